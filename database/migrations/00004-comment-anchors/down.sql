@@ -0,0 +1,9 @@
+ALTER TABLE comments
+  DROP CONSTRAINT comment_anchor_check,
+  DROP COLUMN start_line,
+  DROP COLUMN end_line,
+  DROP COLUMN side,
+  DROP COLUMN context_hash,
+  DROP COLUMN outdated;
+
+DROP TYPE CommentSide;
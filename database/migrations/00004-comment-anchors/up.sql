@@ -0,0 +1,13 @@
+CREATE TYPE CommentSide AS ENUM ('old', 'new');
+
+ALTER TABLE comments
+  ADD COLUMN start_line INTEGER NULL,
+  ADD COLUMN end_line INTEGER NULL,
+  ADD COLUMN side CommentSide NULL,
+  ADD COLUMN context_hash VARCHAR(64) NULL,
+  ADD COLUMN outdated BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD CONSTRAINT comment_anchor_check CHECK (
+    (start_line IS NULL AND end_line IS NULL AND side IS NULL AND context_hash IS NULL)
+    OR (file_path IS NOT NULL AND start_line IS NOT NULL AND end_line IS NOT NULL
+      AND side IS NOT NULL AND context_hash IS NOT NULL AND 1 <= start_line AND start_line <= end_line)
+  );
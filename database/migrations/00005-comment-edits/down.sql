@@ -0,0 +1,9 @@
+DROP TABLE comment_reactions;
+DROP TABLE comment_edits;
+
+ALTER TABLE comments
+  DROP CONSTRAINT comment_resolved_check,
+  DROP COLUMN edited_at,
+  DROP COLUMN resolved,
+  DROP COLUMN resolved_by,
+  DROP COLUMN resolved_at;
@@ -0,0 +1,20 @@
+ALTER TABLE comments
+  ADD COLUMN edited_at TIMESTAMP NULL,
+  ADD COLUMN resolved BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN resolved_by INTEGER NULL REFERENCES users(id) ON DELETE SET NULL,
+  ADD COLUMN resolved_at TIMESTAMP NULL,
+  ADD CONSTRAINT comment_resolved_check CHECK (NOT resolved OR respond_to IS NULL);
+
+CREATE TABLE comment_edits (
+  id SERIAL PRIMARY KEY,
+  comment_id INTEGER NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+  previous_message TEXT NOT NULL,
+  date TIMESTAMP NOT NULL
+);
+
+CREATE TABLE comment_reactions (
+  comment_id INTEGER NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  reaction VARCHAR(32) NOT NULL,
+  PRIMARY KEY (comment_id, user_id, reaction)
+);
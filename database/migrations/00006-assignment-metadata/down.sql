@@ -0,0 +1,12 @@
+ALTER TABLE assignments
+  DROP CONSTRAINT assignment_late_policy_check,
+  DROP CONSTRAINT assignment_dates_check,
+  DROP COLUMN name,
+  DROP COLUMN description,
+  DROP COLUMN release_date,
+  DROP COLUMN due_date,
+  DROP COLUMN late_policy,
+  DROP COLUMN grace_period,
+  DROP COLUMN late_penalty;
+
+DROP TYPE LatePolicy;
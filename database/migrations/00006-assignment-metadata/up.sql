@@ -0,0 +1,16 @@
+CREATE TYPE LatePolicy AS ENUM ('accept', 'flag', 'reject');
+
+ALTER TABLE assignments
+  ADD COLUMN name VARCHAR(255) NOT NULL DEFAULT '',
+  ADD COLUMN description TEXT NOT NULL DEFAULT '',
+  ADD COLUMN release_date TIMESTAMP NULL,
+  ADD COLUMN due_date TIMESTAMP NULL,
+  ADD COLUMN late_policy LatePolicy NOT NULL DEFAULT 'flag',
+  ADD COLUMN grace_period INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN late_penalty INTEGER NOT NULL DEFAULT 0,
+  ADD CONSTRAINT assignment_dates_check CHECK (
+    release_date IS NULL OR due_date IS NULL OR release_date <= due_date
+  ),
+  ADD CONSTRAINT assignment_late_policy_check CHECK (
+    grace_period >= 0 AND late_penalty >= 0 AND late_penalty <= 100
+  );
@@ -0,0 +1 @@
+DROP INDEX repositories_submission_idx;
@@ -0,0 +1,2 @@
+-- A student has at most one submission repository per assignment
+CREATE UNIQUE INDEX repositories_submission_idx ON repositories (assignment_id, owner_id);
@@ -0,0 +1,4 @@
+DROP TABLE grade_criteria;
+DROP TABLE grades;
+DROP TYPE GradeSource;
+DROP TABLE submissions;
@@ -0,0 +1,31 @@
+CREATE TABLE submissions (
+  id SERIAL PRIMARY KEY,
+  repository_id INTEGER NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+  commit_hash VARCHAR(255) NOT NULL,
+  submitted_at TIMESTAMP NOT NULL,
+  late BOOLEAN NOT NULL DEFAULT FALSE,
+  UNIQUE (repository_id, commit_hash)
+);
+
+CREATE TYPE GradeSource AS ENUM ('manual', 'automated');
+
+CREATE TABLE grades (
+  id SERIAL PRIMARY KEY,
+  submission_id INTEGER NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+  score DOUBLE PRECISION NOT NULL,
+  max_score DOUBLE PRECISION NOT NULL,
+  source GradeSource NOT NULL,
+  grader_id INTEGER NULL REFERENCES users(id) ON DELETE SET NULL,
+  date TIMESTAMP NOT NULL,
+  CONSTRAINT grade_score_check CHECK (max_score > 0 AND score >= 0 AND score <= max_score)
+);
+
+CREATE TABLE grade_criteria (
+  id SERIAL PRIMARY KEY,
+  grade_id INTEGER NOT NULL REFERENCES grades(id) ON DELETE CASCADE,
+  name VARCHAR(255) NOT NULL,
+  score DOUBLE PRECISION NOT NULL,
+  max_score DOUBLE PRECISION NOT NULL,
+  comment TEXT NULL,
+  CONSTRAINT grade_criterion_score_check CHECK (max_score > 0 AND score >= 0 AND score <= max_score)
+);
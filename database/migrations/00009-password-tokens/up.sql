@@ -0,0 +1,6 @@
+-- Single use tokens letting a user choose a password, such as invitations of imported students
+CREATE TABLE password_tokens (
+  token_hash VARCHAR(64) PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,3 @@
+DROP TABLE cirun_log_chunks;
+DROP TABLE cirun_steps;
+DROP TYPE StepStatus;
@@ -0,0 +1,25 @@
+CREATE TYPE StepStatus AS ENUM ('pending', 'running', 'success', 'failed', 'timed_out', 'skipped');
+
+-- The steps of the CI runs, as defined by their pipeline
+CREATE TABLE cirun_steps (
+  id SERIAL PRIMARY KEY,
+  cirun_id INTEGER NOT NULL REFERENCES cirun(id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  name VARCHAR(255) NOT NULL,
+  status StepStatus NOT NULL DEFAULT 'pending',
+  started_at TIMESTAMP NULL,
+  finished_at TIMESTAMP NULL,
+  exit_code INTEGER NULL,
+  log_size INTEGER NOT NULL DEFAULT 0,
+  log_truncated BOOLEAN NOT NULL DEFAULT FALSE,
+  UNIQUE (cirun_id, position)
+);
+
+-- The output of the steps, appended while they run
+CREATE TABLE cirun_log_chunks (
+  id SERIAL PRIMARY KEY,
+  step_id INTEGER NOT NULL REFERENCES cirun_steps(id) ON DELETE CASCADE,
+  content TEXT NOT NULL
+);
+
+CREATE INDEX cirun_log_chunks_step_id ON cirun_log_chunks(step_id);
@@ -0,0 +1,4 @@
+-- The CI run which computed an automated grade, each run grading at most once
+ALTER TABLE grades
+  ADD COLUMN cirun_id INTEGER NULL REFERENCES cirun(id) ON DELETE SET NULL,
+  ADD CONSTRAINT grades_cirun_id_key UNIQUE (cirun_id);
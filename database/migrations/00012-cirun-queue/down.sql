@@ -0,0 +1,16 @@
+ALTER TABLE cirun
+  DROP COLUMN created_at,
+  DROP COLUMN started_at,
+  DROP COLUMN finished_at,
+  DROP COLUMN heartbeat_at,
+  DROP COLUMN ref_name,
+  DROP COLUMN triggered_by,
+  DROP COLUMN worker_id,
+  DROP COLUMN attempt;
+
+-- Values can't be removed from an enum, so the type is recreated without the running status
+UPDATE cirun SET status = 'pending' WHERE status = 'running';
+ALTER TYPE Status RENAME TO Status_old;
+CREATE TYPE Status AS ENUM ('success', 'pending', 'cancelled', 'failed');
+ALTER TABLE cirun ALTER COLUMN status TYPE Status USING status::text::Status;
+DROP TYPE Status_old;
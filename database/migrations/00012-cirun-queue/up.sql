@@ -0,0 +1,12 @@
+ALTER TYPE Status ADD VALUE 'running' AFTER 'pending';
+
+-- What queued the runs, and the progress of the worker running them
+ALTER TABLE cirun
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+  ADD COLUMN started_at TIMESTAMP NULL,
+  ADD COLUMN finished_at TIMESTAMP NULL,
+  ADD COLUMN heartbeat_at TIMESTAMP NULL,
+  ADD COLUMN ref_name VARCHAR(255) NULL,
+  ADD COLUMN triggered_by INTEGER NULL REFERENCES users(id) ON DELETE SET NULL,
+  ADD COLUMN worker_id VARCHAR(255) NULL,
+  ADD COLUMN attempt INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TRIGGER cirun_notify ON cirun;
+DROP FUNCTION notify_cirun();
@@ -0,0 +1,18 @@
+-- Wakes the CI workers when a run is queued, and tells the API when a run changes status. The
+-- notifications are only delivered once the transaction commits.
+CREATE FUNCTION notify_cirun() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'UPDATE' AND OLD.status = NEW.status THEN
+    RETURN NEW;
+  END IF;
+  IF NEW.status = 'pending' THEN
+    PERFORM pg_notify('cirun_queue', NEW.id::TEXT);
+  END IF;
+  PERFORM pg_notify('cirun_status', NEW.id || ' ' || NEW.repository_id || ' ' || NEW.status);
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER cirun_notify
+  AFTER INSERT OR UPDATE OF status ON cirun
+  FOR EACH ROW EXECUTE FUNCTION notify_cirun();
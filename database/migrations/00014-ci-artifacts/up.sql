@@ -0,0 +1,15 @@
+-- The files kept by the CI runs, whose content is stored by the workers under its SHA-256
+CREATE TABLE artifacts (
+  id SERIAL PRIMARY KEY,
+  cirun_id INTEGER NOT NULL REFERENCES cirun(id) ON DELETE CASCADE,
+  name VARCHAR(64) NOT NULL,
+  file_name VARCHAR(255) NOT NULL,
+  content_hash VARCHAR(64) NOT NULL,
+  size BIGINT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+  expires_at TIMESTAMP NOT NULL,
+  UNIQUE (cirun_id, name)
+);
+
+CREATE INDEX artifacts_content_hash ON artifacts(content_hash);
+CREATE INDEX artifacts_expires_at ON artifacts(expires_at);
@@ -0,0 +1,13 @@
+-- The branch updates pushed to the submission repositories, kept by the server as the evidence
+-- of when each commit was handed in
+CREATE TABLE pushes (
+  id SERIAL PRIMARY KEY,
+  repository_id INTEGER NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+  user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+  ref_name TEXT NOT NULL,
+  commit_hash VARCHAR(255) NOT NULL,
+  pushed_at TIMESTAMP NOT NULL,
+  late BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX pushes_repository_id ON pushes(repository_id, pushed_at);
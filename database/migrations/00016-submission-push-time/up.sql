@@ -0,0 +1,2 @@
+-- When the submitted commit was pushed, as recorded by the server, to compute the late penalty
+ALTER TABLE submissions ADD COLUMN pushed_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE comments DROP COLUMN anchor_end_line;
+ALTER TABLE comments DROP COLUMN anchor_start_line;
+ALTER TABLE comments DROP COLUMN anchor_commit;
@@ -0,0 +1,5 @@
+-- Where the lines of a line comment were last found once they moved in a later push. The comment
+-- keeps the commit and lines it was made on.
+ALTER TABLE comments ADD COLUMN anchor_commit VARCHAR(255);
+ALTER TABLE comments ADD COLUMN anchor_start_line INT;
+ALTER TABLE comments ADD COLUMN anchor_end_line INT;
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use diesel::{
   r2d2::{ConnectionManager, Pool, PooledConnection},
   PgConnection,
@@ -10,6 +12,18 @@ pub trait ConnectionProvider: Send + Sync {
   fn get_connection(&self) -> Result<Self::Connection, DatabaseError>;
 }
 
+/// Allows a single provider to be shared between multiple services.
+impl<T> ConnectionProvider for Arc<T>
+where
+  T: ConnectionProvider,
+{
+  type Connection = T::Connection;
+
+  fn get_connection(&self) -> Result<Self::Connection, DatabaseError> {
+    self.as_ref().get_connection()
+  }
+}
+
 #[cfg_attr(feature = "mock", faux::create)]
 pub struct ConnectionPool {
   pool: Pool<ConnectionManager<PgConnection>>,
@@ -0,0 +1,235 @@
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, ExpressionMethods, OptionalExtension, PgConnection,
+  QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use std::{ops::DerefMut, time::SystemTime};
+
+use crate::{db_handle::BaseDbHandle, error::DatabaseError};
+
+/// A file kept by a CI run, whose content is stored under its hash by the workers.
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::artifacts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Artifact {
+  pub id: i32,
+  pub cirun_id: i32,
+  /// The name given by the pipeline, unique within the run
+  pub name: String,
+  /// The name of the file when downloaded
+  pub file_name: String,
+  /// The SHA-256 of the content, in hexadecimal
+  pub content_hash: String,
+  /// The size of the content, in bytes
+  pub size: i64,
+  pub created_at: SystemTime,
+  pub expires_at: SystemTime,
+}
+
+impl Artifact {
+  pub fn is_expired(&self, now: SystemTime) -> bool {
+    self.expires_at <= now
+  }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::artifacts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewArtifact<'a> {
+  cirun_id: i32,
+  name: &'a str,
+  file_name: &'a str,
+  content_hash: &'a str,
+  size: i64,
+  created_at: SystemTime,
+  expires_at: SystemTime,
+}
+
+pub trait ArtifactDbHandle {
+  fn create_artifact(
+    &mut self,
+    cirun_id: i32,
+    name: &str,
+    file_name: &str,
+    content_hash: &str,
+    size: i64,
+    expires_at: SystemTime,
+  ) -> Result<Artifact, DatabaseError>;
+
+  fn get_artifact_by_id(&mut self, artifact_id: i32) -> Result<Option<Artifact>, DatabaseError>;
+
+  /// Lists the artifacts of the run, expired or not, in the order they were kept
+  fn list_cirun_artifacts(&mut self, cirun_id: i32) -> Result<Vec<Artifact>, DatabaseError>;
+
+  /// Deletes the artifacts which expired at `now`, returning them
+  fn delete_expired_artifacts(&mut self, now: SystemTime) -> Result<Vec<Artifact>, DatabaseError>;
+
+  /// Lists the hashes of the contents which are still referred to by an artifact
+  fn list_artifact_hashes(&mut self) -> Result<Vec<String>, DatabaseError>;
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> ArtifactDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn create_artifact(
+    &mut self,
+    cirun_id: i32,
+    name: &str,
+    file_name: &str,
+    content_hash: &str,
+    size: i64,
+    expires_at: SystemTime,
+  ) -> Result<Artifact, DatabaseError> {
+    use crate::schema::artifacts;
+
+    let new_artifact = NewArtifact {
+      cirun_id,
+      name,
+      file_name,
+      content_hash,
+      size,
+      created_at: SystemTime::now(),
+      expires_at,
+    };
+
+    diesel::insert_into(artifacts::table)
+      .values(&new_artifact)
+      .returning(Artifact::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_artifact_by_id(&mut self, artifact_id: i32) -> Result<Option<Artifact>, DatabaseError> {
+    use crate::schema::artifacts::dsl;
+
+    dsl::artifacts
+      .filter(dsl::id.eq(artifact_id))
+      .select(Artifact::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_cirun_artifacts(&mut self, cirun_id: i32) -> Result<Vec<Artifact>, DatabaseError> {
+    use crate::schema::artifacts::dsl;
+
+    dsl::artifacts
+      .filter(dsl::cirun_id.eq(cirun_id))
+      .order(dsl::id)
+      .select(Artifact::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn delete_expired_artifacts(&mut self, now: SystemTime) -> Result<Vec<Artifact>, DatabaseError> {
+    use crate::schema::artifacts::dsl;
+
+    diesel::delete(dsl::artifacts.filter(dsl::expires_at.le(now)))
+      .returning(Artifact::as_returning())
+      .get_results(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_artifact_hashes(&mut self) -> Result<Vec<String>, DatabaseError> {
+    use crate::schema::artifacts::dsl;
+
+    dsl::artifacts
+      .select(dsl::content_hash)
+      .distinct()
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, SystemTime};
+
+  use crate::{
+    db_handle::{
+      cirun::CirunDbHandle,
+      repository::{RepositoryDbHandle, Repotype},
+      user::UserDbHandle,
+    },
+    transaction_tests,
+  };
+
+  use super::ArtifactDbHandle;
+
+  transaction_tests! {
+    fn create_artifacts(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let other = tx.create_cirun(repo.id, "other")?;
+
+      let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+      let hash = "a".repeat(64);
+      let binary = tx.create_artifact(cirun.id, "binary", "binary", &hash, 12, expires_at)?;
+      assert_eq!(binary.content_hash, hash);
+      assert_eq!(binary.size, 12);
+      assert_eq!(binary.expires_at, expires_at);
+      // The same content in another run
+      tx.create_artifact(other.id, "binary", "binary", &hash, 12, expires_at)?;
+      // The names are unique within a run
+      assert!(tx.create_artifact(cirun.id, "binary", "binary", &hash, 12, expires_at).is_err());
+    }
+
+    fn list_artifacts(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let other = tx.create_cirun(repo.id, "other")?;
+
+      let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+      let hash = "a".repeat(64);
+      let binary = tx.create_artifact(cirun.id, "binary", "binary", &hash, 12, expires_at)?;
+      tx.create_artifact(cirun.id, "coverage", "coverage.tar.gz", &"b".repeat(64), 3, expires_at)?;
+      tx.create_artifact(other.id, "binary", "binary", &hash, 12, expires_at)?;
+
+      let artifacts = tx.list_cirun_artifacts(cirun.id)?;
+      let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+      assert_eq!(names, vec!["binary", "coverage"]);
+      assert_eq!(tx.get_artifact_by_id(binary.id)?, Some(binary));
+      assert_eq!(tx.list_artifact_hashes()?.len(), 2);
+    }
+
+    fn delete_expired_artifacts(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+
+      let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+      let expired = tx.create_artifact(cirun.id, "old", "old", &"a".repeat(64), 1, now)?;
+      let kept = tx.create_artifact(
+        cirun.id,
+        "new",
+        "new",
+        &"b".repeat(64),
+        1,
+        now + Duration::from_secs(1),
+      )?;
+      assert!(expired.is_expired(now) && !kept.is_expired(now));
+
+      let deleted = tx.delete_expired_artifacts(now)?;
+      assert_eq!(deleted, vec![expired]);
+      assert_eq!(tx.list_cirun_artifacts(cirun.id)?, vec![kept]);
+      assert_eq!(tx.list_artifact_hashes()?, vec!["b".repeat(64)]);
+    }
+
+    fn claim_cirun_removes_previous_artifacts(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      tx.create_artifact(cirun.id, "binary", "binary", &"a".repeat(64), 1, SystemTime::now())?;
+
+      tx.claim_pending_cirun("worker")?.expect("Expected a pending cirun");
+      assert!(tx.list_cirun_artifacts(cirun.id)?.is_empty());
+    }
+
+    fn get_nonexistent_artifact(tx: &mut DbHandle) {
+      assert!(tx.get_artifact_by_id(1)?.is_none());
+    }
+  }
+}
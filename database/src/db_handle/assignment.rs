@@ -1,19 +1,67 @@
 use diesel::{
-  deserialize::Queryable, prelude::Insertable, ExpressionMethods, JoinOnDsl,
-  NullableExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable,
-  SelectableHelper,
+  deserialize::Queryable,
+  dsl::{exists, not},
+  prelude::{AsChangeset, Insertable},
+  BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+  OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
 };
-use std::ops::DerefMut;
+use diesel_derive_enum::DbEnum;
+use std::{ops::DerefMut, time::SystemTime};
 
 use crate::{
   db_handle::BaseDbHandle,
   error::DatabaseError,
-  schema::{assignments, repositories},
+  schema::{assignments, group_students, repositories},
 };
 
 use super::{group::Group, repository::Repository};
 
-#[derive(Queryable, Selectable)]
+/// What happens to the pushes made to a submission repository after the due date.
+#[derive(Debug, DbEnum, PartialEq, Eq, Clone, Copy)]
+#[ExistingTypePath = "crate::schema::sql_types::Latepolicy"]
+pub enum Latepolicy {
+  /// Late pushes are accepted as if they were on time
+  Accept,
+  /// Late pushes are accepted but flagged as late
+  Flag,
+  /// Late pushes are rejected
+  Reject,
+}
+
+/// The descriptive and scheduling part of an assignment, which can be changed after its creation.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone, Debug, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::assignments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(treat_none_as_null = true)]
+pub struct AssignmentMetadata {
+  pub name: String,
+  pub description: String,
+  /// When the base repository becomes visible to the students, immediately if unset
+  pub release_date: Option<SystemTime>,
+  /// When submissions are due, never if unset
+  pub due_date: Option<SystemTime>,
+  pub late_policy: Latepolicy,
+  /// Minutes after the due date during which pushes are still considered on time
+  pub grace_period: i32,
+  /// Percentage of the grade removed per started day of lateness
+  pub late_penalty: i32,
+}
+
+impl Default for AssignmentMetadata {
+  fn default() -> Self {
+    Self {
+      name: String::new(),
+      description: String::new(),
+      release_date: None,
+      due_date: None,
+      late_policy: Latepolicy::Flag,
+      grace_period: 0,
+      late_penalty: 0,
+    }
+  }
+}
+
+#[derive(Queryable, Selectable, Clone, Debug)]
 #[diesel(table_name = crate::schema::assignments)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Assignment {
@@ -22,6 +70,8 @@ pub struct Assignment {
   pub base_repo_id: i32,
   pub test_repo_id: Option<i32>,
   pub correction_repo_id: Option<i32>,
+  #[diesel(embed)]
+  pub metadata: AssignmentMetadata,
 }
 
 #[derive(Insertable)]
@@ -32,6 +82,8 @@ pub struct NewAssignment {
   pub base_repo_id: i32,
   pub test_repo_id: Option<i32>,
   pub correction_repo_id: Option<i32>,
+  #[diesel(embed)]
+  pub metadata: AssignmentMetadata,
 }
 
 pub trait AssignmentDbHandle {
@@ -63,6 +115,19 @@ pub trait AssignmentDbHandle {
     correction_repo_id: i32,
   ) -> Result<Assignment, DatabaseError>;
 
+  /// Creates an assignment along with its metadata
+  fn insert_assignment(
+    &mut self,
+    new_assignment: &NewAssignment,
+  ) -> Result<Assignment, DatabaseError>;
+
+  /// Replaces the metadata of the assignment
+  fn update_assignment_metadata(
+    &mut self,
+    assignment_id: i32,
+    metadata: &AssignmentMetadata,
+  ) -> Result<Assignment, DatabaseError>;
+
   fn get_assignment_by_id(
     &mut self,
     assignment_id: i32,
@@ -90,6 +155,19 @@ pub trait AssignmentDbHandle {
     assignment_id: i32,
   ) -> Result<Vec<Repository>, DatabaseError>;
 
+  /// Lists the assignments using the repository as their base, test or correction repository
+  fn list_repository_assignments(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<Assignment>, DatabaseError>;
+
+  /// Lists the assignments released by `now` with students of their group who don't have a
+  /// submission repository yet
+  fn list_unprovisioned_assignments(
+    &mut self,
+    now: SystemTime,
+  ) -> Result<Vec<Assignment>, DatabaseError>;
+
   fn delete_assignment(&mut self, assignment_id: i32) -> Result<bool, DatabaseError>;
 }
 
@@ -110,13 +188,10 @@ where
       base_repo_id,
       test_repo_id,
       correction_repo_id,
+      metadata: AssignmentMetadata::default(),
     };
 
-    diesel::insert_into(assignments::table)
-      .values(&new_assignment)
-      .returning(Assignment::as_select())
-      .get_result(self.conn.deref_mut())
-      .map_err(DatabaseError::from)
+    self.insert_assignment(&new_assignment)
   }
 }
 
@@ -166,6 +241,32 @@ where
     )
   }
 
+  fn insert_assignment(
+    &mut self,
+    new_assignment: &NewAssignment,
+  ) -> Result<Assignment, DatabaseError> {
+    diesel::insert_into(assignments::table)
+      .values(new_assignment)
+      .returning(Assignment::as_select())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn update_assignment_metadata(
+    &mut self,
+    assignment_id: i32,
+    metadata: &AssignmentMetadata,
+  ) -> Result<Assignment, DatabaseError> {
+    use crate::schema::assignments::dsl;
+
+    diesel::update(assignments::table.filter(dsl::id.eq(assignment_id)))
+      .set(metadata)
+      .returning(Assignment::as_select())
+      .get_result(self.conn.deref_mut())
+      .optional()?
+      .ok_or(DatabaseError::NotFound)
+  }
+
   fn get_assignment_by_id(
     &mut self,
     assignment_id: i32,
@@ -278,6 +379,45 @@ where
       .map_err(DatabaseError::from)
   }
 
+  fn list_repository_assignments(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<Assignment>, DatabaseError> {
+    use crate::schema::assignments::dsl;
+
+    assignments::table
+      .filter(
+        dsl::base_repo_id
+          .eq(repository_id)
+          .or(dsl::test_repo_id.eq(repository_id))
+          .or(dsl::correction_repo_id.eq(repository_id)),
+      )
+      .select(Assignment::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_unprovisioned_assignments(
+    &mut self,
+    now: SystemTime,
+  ) -> Result<Vec<Assignment>, DatabaseError> {
+    use crate::schema::assignments::dsl;
+
+    let provisioned = repositories::table
+      .filter(repositories::assignment_id.eq(dsl::id.nullable()))
+      .filter(repositories::owner_id.eq(group_students::student_id));
+    let unprovisioned_students = group_students::table
+      .filter(group_students::group_id.eq(dsl::group_id))
+      .filter(not(exists(provisioned)));
+    assignments::table
+      .filter(dsl::release_date.is_null().or(dsl::release_date.le(now)))
+      .filter(exists(unprovisioned_students))
+      .order(dsl::id)
+      .select(Assignment::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
   fn delete_assignment(&mut self, assignment_id: i32) -> Result<bool, DatabaseError> {
     use crate::schema::assignments::dsl;
 
@@ -299,7 +439,22 @@ mod tests {
     transaction_tests,
   };
 
-  use super::AssignmentDbHandle;
+  use std::time::{Duration, SystemTime};
+
+  use super::{Assignment, AssignmentDbHandle, AssignmentMetadata, Latepolicy, NewAssignment};
+
+  fn metadata() -> AssignmentMetadata {
+    let release_date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    AssignmentMetadata {
+      name: "Linked lists".to_string(),
+      description: "Implement a linked list".to_string(),
+      release_date: Some(release_date),
+      due_date: Some(release_date + Duration::from_secs(7 * 24 * 3600)),
+      late_policy: Latepolicy::Reject,
+      grace_period: 15,
+      late_penalty: 10,
+    }
+  }
 
   transaction_tests! {
     fn create_assignment_with_invalid_group(tx: &mut DbHandle) {
@@ -388,6 +543,74 @@ mod tests {
       assert_eq!(assignment.correction_repo_id, Some(correction_repo.id));
     }
 
+    fn create_assignment_has_default_metadata(tx: &mut DbHandle) {
+      let group = tx.create_group("test-group", None)?;
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repo = tx.create_repository("test-repo", &Repotype::Default, user.id, None)?;
+      let assignment = tx.create_assignment(group.id, repo.id)?;
+      assert_eq!(assignment.metadata, AssignmentMetadata::default());
+    }
+
+    fn insert_assignment_with_metadata(tx: &mut DbHandle) {
+      let group = tx.create_group("test-group", None)?;
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repo = tx.create_repository("test-repo", &Repotype::Default, user.id, None)?;
+      let assignment = tx.insert_assignment(&NewAssignment {
+        group_id: group.id,
+        base_repo_id: repo.id,
+        test_repo_id: None,
+        correction_repo_id: None,
+        metadata: metadata(),
+      })?;
+      assert_eq!(assignment.metadata, metadata());
+
+      let assignment = tx.get_assignment_by_id(assignment.id)?.expect("Assignment not found");
+      assert_eq!(assignment.metadata, metadata());
+    }
+
+    fn insert_assignment_due_before_release(tx: &mut DbHandle) {
+      let group = tx.create_group("test-group", None)?;
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repo = tx.create_repository("test-repo", &Repotype::Default, user.id, None)?;
+      let mut metadata = metadata();
+      metadata.due_date = Some(metadata.release_date.unwrap() - Duration::from_secs(1));
+      let res = tx.insert_assignment(&NewAssignment {
+        group_id: group.id,
+        base_repo_id: repo.id,
+        test_repo_id: None,
+        correction_repo_id: None,
+        metadata,
+      });
+      assert!(res.is_err());
+    }
+
+    fn update_assignment_metadata(tx: &mut DbHandle) {
+      let group = tx.create_group("test-group", None)?;
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repo = tx.create_repository("test-repo", &Repotype::Default, user.id, None)?;
+      let assignment = tx.insert_assignment(&NewAssignment {
+        group_id: group.id,
+        base_repo_id: repo.id,
+        test_repo_id: None,
+        correction_repo_id: None,
+        metadata: metadata(),
+      })?;
+
+      let updated = AssignmentMetadata {
+        name: "Trees".to_string(),
+        due_date: None,
+        ..metadata()
+      };
+      let assignment = tx.update_assignment_metadata(assignment.id, &updated)?;
+      assert_eq!(assignment.metadata, updated);
+      assert_eq!(assignment.base_repo_id, repo.id);
+    }
+
+    fn update_nonexistent_assignment_metadata(tx: &mut DbHandle) {
+      let res = tx.update_assignment_metadata(1, &metadata());
+      assert!(matches!(res, Err(crate::error::DatabaseError::NotFound)));
+    }
+
     fn get_nonexistent_assignment_by_id(tx: &mut DbHandle) {
       let assignment = tx.get_assignment_by_id(1)?;
       assert!(assignment.is_none());
@@ -455,19 +678,61 @@ mod tests {
       let base_repo = tx.create_repository("base-repo", &Repotype::Default, user.id, None)?;
       let assignment = tx.create_assignment(group.id, base_repo.id)?;
 
-      let repos = vec![
+      // each student has their own submission repository
+      let repos = [
         "repo1",
         "repo2",
         "repo3",
       ];
       repos.iter().for_each(|repo_name| {
-        tx.create_repository(repo_name, &Repotype::Default, user.id, Some(assignment.id)).expect("Error creating repository");
+        let student = tx.create_user(repo_name, repo_name, "password", None).expect("Error creating user");
+        tx.create_repository(repo_name, &Repotype::Default, student.id, Some(assignment.id)).expect("Error creating repository");
       });
 
       let submission_repos = tx.get_assignment_submission_repos(assignment.id)?;
       assert_eq!(submission_repos.len(), repos.len());
     }
 
+    fn list_repository_assignments(tx: &mut DbHandle) {
+      let group = tx.create_group("test-group", None)?;
+      let user = tx.create_user("username", "email", "password", None)?;
+      let base_repo = tx.create_repository("base-repo", &Repotype::Default, user.id, None)?;
+      let test_repo = tx.create_repository("test-repo", &Repotype::Ci, user.id, None)?;
+      let other_repo = tx.create_repository("other-repo", &Repotype::Default, user.id, None)?;
+      let assignment = tx.create_assignment_with_ci(group.id, base_repo.id, test_repo.id)?;
+
+      let assignments = tx.list_repository_assignments(base_repo.id)?;
+      assert_eq!(assignments.len(), 1);
+      assert_eq!(assignments[0].id, assignment.id);
+      let assignments = tx.list_repository_assignments(test_repo.id)?;
+      assert_eq!(assignments.len(), 1);
+      let assignments = tx.list_repository_assignments(other_repo.id)?;
+      assert!(assignments.is_empty());
+    }
+
+    fn list_unprovisioned_assignments(tx: &mut DbHandle) {
+      let now = SystemTime::now();
+      let group = tx.create_group("test-group", None)?;
+      let teacher = tx.create_user("teacher", "teacher@test.com", "password", None)?;
+      let student = tx.create_user("student", "student@test.com", "password", None)?;
+      tx.add_student(group.id, student.id)?;
+      let base_repo = tx.create_repository("base-repo", &Repotype::Default, teacher.id, None)?;
+      let released = tx.create_assignment(group.id, base_repo.id)?;
+      let upcoming = tx.create_assignment(group.id, base_repo.id)?;
+      let upcoming_metadata = AssignmentMetadata {
+        release_date: Some(now + Duration::from_secs(24 * 3600)),
+        ..Default::default()
+      };
+      tx.update_assignment_metadata(upcoming.id, &upcoming_metadata)?;
+      let listed = |assignments: Vec<Assignment>| -> Vec<i32> {
+        assignments.iter().map(|a| a.id).filter(|id| [released.id, upcoming.id].contains(id)).collect()
+      };
+      assert_eq!(listed(tx.list_unprovisioned_assignments(now)?), vec![released.id]);
+
+      tx.create_repository("base-repo-student", &Repotype::Default, student.id, Some(released.id))?;
+      assert!(listed(tx.list_unprovisioned_assignments(now)?).is_empty());
+    }
+
     fn delete_assignment(tx: &mut DbHandle) {
       let group = tx.create_group("test-group", None)?;
       let user = tx.create_user("username", "email", "password", None)?;
@@ -1,22 +1,31 @@
 use diesel::{
-  deserialize::Queryable, prelude::Insertable, ExpressionMethods, OptionalExtension, PgConnection,
-  QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
 };
 use diesel_derive_enum::DbEnum;
-use std::ops::DerefMut;
+use std::{ops::DerefMut, time::SystemTime};
 
 use crate::{db_handle::BaseDbHandle, error::DatabaseError};
 
-#[derive(Debug, DbEnum, PartialEq, Eq)]
+#[derive(Debug, DbEnum, PartialEq, Eq, Clone, Copy)]
 #[ExistingTypePath = "crate::schema::sql_types::Status"]
 pub enum Status {
   Success,
   Pending,
+  /// Claimed by a worker, which sends heartbeats until the run completes
+  Running,
   Cancelled,
   Failed,
 }
 
-#[derive(Queryable, Selectable)]
+impl Status {
+  /// Whether the run reached its final status, the other runs being queued or running
+  pub fn is_completed(&self) -> bool {
+    !matches!(self, Status::Pending | Status::Running)
+  }
+}
+
+#[derive(Queryable, Selectable, Debug, PartialEq)]
 #[diesel(table_name = crate::schema::cirun)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Cirun {
@@ -24,6 +33,20 @@ pub struct Cirun {
   pub repository_id: i32,
   pub commit: String,
   pub status: Status,
+  pub created_at: SystemTime,
+  /// When the latest attempt started
+  pub started_at: Option<SystemTime>,
+  pub finished_at: Option<SystemTime>,
+  /// The last sign of life of the worker running the run
+  pub heartbeat_at: Option<SystemTime>,
+  /// The reference whose update queued the run, such as `refs/heads/main`
+  pub ref_name: Option<String>,
+  /// The user who queued the run
+  pub triggered_by: Option<i32>,
+  /// The worker which ran the latest attempt
+  pub worker_id: Option<String>,
+  /// The number of times a worker claimed the run
+  pub attempt: i32,
 }
 
 #[derive(Insertable)]
@@ -33,6 +56,9 @@ pub struct NewCirun<'a> {
   pub repository_id: i32,
   pub commit: &'a str,
   pub status: &'a Status,
+  pub created_at: SystemTime,
+  pub ref_name: Option<&'a str>,
+  pub triggered_by: Option<i32>,
 }
 
 pub trait CirunDbHandle {
@@ -45,6 +71,15 @@ pub trait CirunDbHandle {
     status: &Status,
   ) -> Result<Cirun, DatabaseError>;
 
+  /// Queues a run for the update of a reference, pushed by the given user
+  fn create_cirun_for_ref(
+    &mut self,
+    repository_id: i32,
+    commit: &str,
+    ref_name: &str,
+    triggered_by: Option<i32>,
+  ) -> Result<Cirun, DatabaseError>;
+
   fn get_cirun_by_id(&mut self, cirun_id: i32) -> Result<Option<Cirun>, DatabaseError>;
 
   fn get_cirun_by_commit(
@@ -57,6 +92,39 @@ pub trait CirunDbHandle {
 
   fn update_cirun_status(&mut self, cirun_id: i32, status: &Status)
     -> Result<Cirun, DatabaseError>;
+
+  /// Marks the oldest pending run as running on the given worker, starting a new attempt, and
+  /// removes the steps and artifacts of its previous attempts. The runs being claimed by other
+  /// connections are skipped.
+  fn claim_pending_cirun(&mut self, worker_id: &str) -> Result<Option<Cirun>, DatabaseError>;
+
+  /// Records that the worker running the attempt is alive. Returns false if the attempt isn't
+  /// running anymore, because the run was cancelled or reaped.
+  fn heartbeat_cirun(&mut self, cirun_id: i32, attempt: i32) -> Result<bool, DatabaseError>;
+
+  /// Completes the attempt with the given status. Returns none if the attempt isn't running
+  /// anymore, in which case the run is left untouched.
+  fn finish_cirun(
+    &mut self,
+    cirun_id: i32,
+    attempt: i32,
+    status: &Status,
+  ) -> Result<Option<Cirun>, DatabaseError>;
+
+  /// Queues a completed run again. Returns none if the run is still pending or running.
+  fn requeue_cirun(&mut self, cirun_id: i32) -> Result<Option<Cirun>, DatabaseError>;
+
+  /// Cancels a pending or running run. Returns none if the run already completed.
+  fn cancel_cirun(&mut self, cirun_id: i32) -> Result<Option<Cirun>, DatabaseError>;
+
+  /// Releases the running runs whose worker didn't send a heartbeat since `cutoff`, and is
+  /// presumably dead. They are queued again, unless they were already attempted `max_attempts`
+  /// times, in which case they fail. Returns the runs released.
+  fn reap_ciruns(
+    &mut self,
+    cutoff: SystemTime,
+    max_attempts: i32,
+  ) -> Result<Vec<Cirun>, DatabaseError>;
 }
 
 #[cfg_attr(feature = "mock", faux::methods(path = "super"))]
@@ -80,6 +148,34 @@ where
       repository_id,
       commit,
       status,
+      created_at: SystemTime::now(),
+      ref_name: None,
+      triggered_by: None,
+    };
+
+    diesel::insert_into(cirun::table)
+      .values(&new_cirun)
+      .returning(Cirun::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn create_cirun_for_ref(
+    &mut self,
+    repository_id: i32,
+    commit: &str,
+    ref_name: &str,
+    triggered_by: Option<i32>,
+  ) -> Result<Cirun, DatabaseError> {
+    use crate::schema::cirun;
+
+    let new_cirun = NewCirun {
+      repository_id,
+      commit,
+      status: &Status::Pending,
+      created_at: SystemTime::now(),
+      ref_name: Some(ref_name),
+      triggered_by,
     };
 
     diesel::insert_into(cirun::table)
@@ -146,6 +242,166 @@ where
       .get_result(self.conn.deref_mut())
       .map_err(DatabaseError::from)
   }
+
+  fn claim_pending_cirun(&mut self, worker_id: &str) -> Result<Option<Cirun>, DatabaseError> {
+    use crate::schema::{artifacts, cirun::dsl, cirun_steps};
+
+    self
+      .conn
+      .deref_mut()
+      .transaction(|conn| {
+        let Some(cirun_id) = dsl::cirun
+          .filter(dsl::status.eq(Status::Pending))
+          .order(dsl::id)
+          .for_no_key_update()
+          .skip_locked()
+          .select(dsl::id)
+          .first::<i32>(conn)
+          .optional()?
+        else {
+          return Ok::<_, diesel::result::Error>(None);
+        };
+
+        diesel::delete(cirun_steps::table.filter(cirun_steps::cirun_id.eq(cirun_id)))
+          .execute(conn)?;
+        diesel::delete(artifacts::table.filter(artifacts::cirun_id.eq(cirun_id))).execute(conn)?;
+        let now = SystemTime::now();
+        diesel::update(dsl::cirun.filter(dsl::id.eq(cirun_id)))
+          .set((
+            dsl::status.eq(Status::Running),
+            dsl::started_at.eq(now),
+            dsl::finished_at.eq(None::<SystemTime>),
+            dsl::heartbeat_at.eq(now),
+            dsl::worker_id.eq(worker_id),
+            dsl::attempt.eq(dsl::attempt + 1),
+          ))
+          .returning(Cirun::as_returning())
+          .get_result(conn)
+          .map(Some)
+      })
+      .map_err(DatabaseError::from)
+  }
+
+  fn heartbeat_cirun(&mut self, cirun_id: i32, attempt: i32) -> Result<bool, DatabaseError> {
+    use crate::schema::cirun::dsl;
+
+    diesel::update(
+      dsl::cirun
+        .filter(dsl::id.eq(cirun_id))
+        .filter(dsl::attempt.eq(attempt))
+        .filter(dsl::status.eq(Status::Running)),
+    )
+    .set(dsl::heartbeat_at.eq(SystemTime::now()))
+    .execute(self.conn.deref_mut())
+    .map(|updated| updated > 0)
+    .map_err(DatabaseError::from)
+  }
+
+  fn finish_cirun(
+    &mut self,
+    cirun_id: i32,
+    attempt: i32,
+    status: &Status,
+  ) -> Result<Option<Cirun>, DatabaseError> {
+    use crate::schema::cirun::dsl;
+
+    diesel::update(
+      dsl::cirun
+        .filter(dsl::id.eq(cirun_id))
+        .filter(dsl::attempt.eq(attempt))
+        .filter(dsl::status.eq(Status::Running)),
+    )
+    .set((
+      dsl::status.eq(status),
+      dsl::finished_at.eq(SystemTime::now()),
+    ))
+    .returning(Cirun::as_returning())
+    .get_result(self.conn.deref_mut())
+    .optional()
+    .map_err(DatabaseError::from)
+  }
+
+  fn requeue_cirun(&mut self, cirun_id: i32) -> Result<Option<Cirun>, DatabaseError> {
+    use crate::schema::cirun::dsl;
+
+    diesel::update(
+      dsl::cirun
+        .filter(dsl::id.eq(cirun_id))
+        .filter(dsl::status.ne_all(vec![Status::Pending, Status::Running])),
+    )
+    .set((
+      dsl::status.eq(Status::Pending),
+      dsl::started_at.eq(None::<SystemTime>),
+      dsl::finished_at.eq(None::<SystemTime>),
+      dsl::heartbeat_at.eq(None::<SystemTime>),
+      dsl::worker_id.eq(None::<String>),
+    ))
+    .returning(Cirun::as_returning())
+    .get_result(self.conn.deref_mut())
+    .optional()
+    .map_err(DatabaseError::from)
+  }
+
+  fn cancel_cirun(&mut self, cirun_id: i32) -> Result<Option<Cirun>, DatabaseError> {
+    use crate::schema::cirun::dsl;
+
+    diesel::update(
+      dsl::cirun
+        .filter(dsl::id.eq(cirun_id))
+        .filter(dsl::status.eq_any(vec![Status::Pending, Status::Running])),
+    )
+    .set((
+      dsl::status.eq(Status::Cancelled),
+      dsl::finished_at.eq(SystemTime::now()),
+    ))
+    .returning(Cirun::as_returning())
+    .get_result(self.conn.deref_mut())
+    .optional()
+    .map_err(DatabaseError::from)
+  }
+
+  fn reap_ciruns(
+    &mut self,
+    cutoff: SystemTime,
+    max_attempts: i32,
+  ) -> Result<Vec<Cirun>, DatabaseError> {
+    use crate::schema::cirun::dsl;
+
+    self
+      .conn
+      .deref_mut()
+      .transaction(|conn| {
+        let mut reaped = diesel::update(
+          dsl::cirun
+            .filter(dsl::status.eq(Status::Running))
+            .filter(dsl::heartbeat_at.lt(cutoff))
+            .filter(dsl::attempt.lt(max_attempts)),
+        )
+        .set((
+          dsl::status.eq(Status::Pending),
+          dsl::heartbeat_at.eq(None::<SystemTime>),
+        ))
+        .returning(Cirun::as_returning())
+        .get_results(conn)?;
+
+        let failed = diesel::update(
+          dsl::cirun
+            .filter(dsl::status.eq(Status::Running))
+            .filter(dsl::heartbeat_at.lt(cutoff))
+            .filter(dsl::attempt.ge(max_attempts)),
+        )
+        .set((
+          dsl::status.eq(Status::Failed),
+          dsl::finished_at.eq(SystemTime::now()),
+        ))
+        .returning(Cirun::as_returning())
+        .get_results(conn)?;
+
+        reaped.extend(failed);
+        Ok::<_, diesel::result::Error>(reaped)
+      })
+      .map_err(DatabaseError::from)
+  }
 }
 
 #[cfg(test)]
@@ -224,7 +480,7 @@ mod tests {
     fn list_repository_ciruns(tx: &mut DbHandle) {
       let user = tx.create_user("user", "email", "password", None)?;
       let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
-      let commits = vec![
+      let commits = [
         "commit1",
         "commit2",
         "commit3",
@@ -245,6 +501,136 @@ mod tests {
       assert_eq!(updated_cirun.status, crate::db_handle::cirun::Status::Success);
     }
 
+    fn create_cirun_for_ref(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun_for_ref(repo.id, "commit", "refs/heads/main", Some(user.id))?;
+      assert_eq!(cirun.status, crate::db_handle::cirun::Status::Pending);
+      assert_eq!(cirun.ref_name.as_deref(), Some("refs/heads/main"));
+      assert_eq!(cirun.triggered_by, Some(user.id));
+      assert_eq!(cirun.attempt, 0);
+      assert!(cirun.started_at.is_none());
+    }
+
+    fn claim_pending_cirun(tx: &mut DbHandle) {
+      use crate::db_handle::cirun::Status;
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      tx.create_cirun_with_status(repo.id, "done", &Status::Success)?;
+      let first = tx.create_cirun(repo.id, "first")?;
+      let second = tx.create_cirun(repo.id, "second")?;
+
+      let claimed = tx.claim_pending_cirun("worker-1")?.expect("Expected a pending cirun");
+      assert_eq!(claimed.id, first.id);
+      assert_eq!(claimed.status, Status::Running);
+      assert_eq!(claimed.worker_id.as_deref(), Some("worker-1"));
+      assert_eq!(claimed.attempt, 1);
+      assert!(claimed.started_at.is_some());
+      assert!(claimed.heartbeat_at.is_some());
+
+      assert_eq!(tx.claim_pending_cirun("worker-2")?.map(|c| c.id), Some(second.id));
+      assert!(tx.claim_pending_cirun("worker-1")?.is_none());
+    }
+
+    fn claim_cirun_removes_previous_steps(tx: &mut DbHandle) {
+      use crate::db_handle::cirun_step::CirunStepDbHandle;
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      tx.create_cirun_steps(cirun.id, &["build", "test"])?;
+
+      tx.claim_pending_cirun("worker")?.expect("Expected a pending cirun");
+      assert!(tx.list_cirun_steps(cirun.id)?.is_empty());
+    }
+
+    fn finish_cirun(tx: &mut DbHandle) {
+      use crate::db_handle::cirun::Status;
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let claimed = tx.claim_pending_cirun("worker")?.expect("Expected a pending cirun");
+
+      assert!(tx.heartbeat_cirun(cirun.id, claimed.attempt)?);
+      // Another attempt can't complete the run
+      assert!(!tx.heartbeat_cirun(cirun.id, claimed.attempt + 1)?);
+      assert!(tx.finish_cirun(cirun.id, claimed.attempt + 1, &Status::Success)?.is_none());
+
+      let finished = tx
+        .finish_cirun(cirun.id, claimed.attempt, &Status::Success)?
+        .expect("The attempt should be running");
+      assert_eq!(finished.status, Status::Success);
+      assert!(finished.finished_at.is_some());
+      assert!(!tx.heartbeat_cirun(cirun.id, claimed.attempt)?);
+    }
+
+    fn cancel_cirun(tx: &mut DbHandle) {
+      use crate::db_handle::cirun::Status;
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let pending = tx.create_cirun(repo.id, "pending")?;
+      let done = tx.create_cirun_with_status(repo.id, "done", &Status::Success)?;
+      let running = tx.create_cirun(repo.id, "running")?;
+      tx.update_cirun_status(running.id, &Status::Running)?;
+
+      let cancelled = tx.cancel_cirun(pending.id)?.expect("The run should be cancelled");
+      assert_eq!(cancelled.status, Status::Cancelled);
+      assert!(cancelled.finished_at.is_some());
+      assert!(tx.cancel_cirun(running.id)?.is_some());
+      assert!(tx.cancel_cirun(done.id)?.is_none());
+      assert!(tx.cancel_cirun(pending.id)?.is_none());
+    }
+
+    fn requeue_cirun(tx: &mut DbHandle) {
+      use crate::db_handle::cirun::Status;
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      assert!(tx.requeue_cirun(cirun.id)?.is_none());
+
+      let claimed = tx.claim_pending_cirun("worker")?.expect("Expected a pending cirun");
+      assert!(tx.requeue_cirun(cirun.id)?.is_none());
+      tx.finish_cirun(cirun.id, claimed.attempt, &Status::Failed)?;
+
+      let requeued = tx.requeue_cirun(cirun.id)?.expect("The run should be queued again");
+      assert_eq!(requeued.status, Status::Pending);
+      assert!(requeued.finished_at.is_none());
+      assert!(requeued.worker_id.is_none());
+      assert_eq!(tx.claim_pending_cirun("worker")?.map(|c| c.attempt), Some(2));
+    }
+
+    fn reap_ciruns(tx: &mut DbHandle) {
+      use crate::db_handle::cirun::Status;
+      use std::time::{Duration, SystemTime};
+
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      tx.claim_pending_cirun("worker")?;
+
+      // The heartbeat is recent enough
+      let past = SystemTime::now() - Duration::from_secs(60);
+      assert!(tx.reap_ciruns(past, 2)?.is_empty());
+
+      let future = SystemTime::now() + Duration::from_secs(60);
+      let reaped = tx.reap_ciruns(future, 2)?;
+      assert_eq!(reaped.len(), 1);
+      assert_eq!(reaped[0].status, Status::Pending);
+      assert_eq!(reaped[0].worker_id.as_deref(), Some("worker"));
+
+      // The second attempt is the last one
+      tx.claim_pending_cirun("worker")?;
+      let reaped = tx.reap_ciruns(future, 2)?;
+      assert_eq!(reaped.len(), 1);
+      assert_eq!(reaped[0].id, cirun.id);
+      assert_eq!(reaped[0].status, Status::Failed);
+      assert_eq!(reaped[0].attempt, 2);
+    }
+
     fn update_nonexistent_cirun_status_fails(tx: &mut DbHandle) {
       let status = tx.update_cirun_status(1, &crate::db_handle::cirun::Status::Success);
       assert!(status.is_err());
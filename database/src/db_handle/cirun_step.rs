@@ -0,0 +1,351 @@
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use diesel_derive_enum::DbEnum;
+use std::{ops::DerefMut, time::SystemTime};
+
+use crate::{db_handle::BaseDbHandle, error::DatabaseError};
+
+#[derive(Debug, DbEnum, PartialEq, Eq, Clone, Copy)]
+#[ExistingTypePath = "crate::schema::sql_types::Stepstatus"]
+pub enum StepStatus {
+  Pending,
+  Running,
+  Success,
+  Failed,
+  TimedOut,
+  /// The step didn't run because an earlier one failed
+  Skipped,
+}
+
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::cirun_steps)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CirunStep {
+  pub id: i32,
+  pub cirun_id: i32,
+  /// The index of the step in the pipeline, starting at 0
+  pub position: i32,
+  pub name: String,
+  pub status: StepStatus,
+  pub started_at: Option<SystemTime>,
+  pub finished_at: Option<SystemTime>,
+  pub exit_code: Option<i32>,
+  /// The size of the stored log, in bytes
+  pub log_size: i32,
+  /// Whether the output exceeded the cap of the worker and was cut
+  pub log_truncated: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::cirun_steps)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewCirunStep<'a> {
+  cirun_id: i32,
+  position: i32,
+  name: &'a str,
+}
+
+/// A part of the log of a step. The chunks of a run are ordered by id.
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::cirun_log_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CirunLogChunk {
+  pub id: i32,
+  pub step_id: i32,
+  pub content: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::cirun_log_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewCirunLogChunk<'a> {
+  step_id: i32,
+  content: &'a str,
+}
+
+pub trait CirunStepDbHandle {
+  /// Creates the pending steps of the run, in the order of the pipeline
+  fn create_cirun_steps(
+    &mut self,
+    cirun_id: i32,
+    names: &[&str],
+  ) -> Result<Vec<CirunStep>, DatabaseError>;
+
+  fn get_cirun_step_by_id(&mut self, step_id: i32) -> Result<Option<CirunStep>, DatabaseError>;
+
+  /// Lists the steps of the run, in the order of the pipeline
+  fn list_cirun_steps(&mut self, cirun_id: i32) -> Result<Vec<CirunStep>, DatabaseError>;
+
+  fn start_cirun_step(
+    &mut self,
+    step_id: i32,
+    started_at: SystemTime,
+  ) -> Result<CirunStep, DatabaseError>;
+
+  fn finish_cirun_step(
+    &mut self,
+    step_id: i32,
+    status: &StepStatus,
+    exit_code: Option<i32>,
+    log_truncated: bool,
+    finished_at: SystemTime,
+  ) -> Result<CirunStep, DatabaseError>;
+
+  /// Marks the steps of the run which haven't started as skipped, returning how many were
+  fn skip_pending_cirun_steps(&mut self, cirun_id: i32) -> Result<usize, DatabaseError>;
+
+  fn append_cirun_step_log(
+    &mut self,
+    step_id: i32,
+    content: &str,
+  ) -> Result<CirunLogChunk, DatabaseError>;
+
+  /// Returns the whole log of the step
+  fn get_cirun_step_log(&mut self, step_id: i32) -> Result<String, DatabaseError>;
+
+  /// Lists the log chunks of every step of the run, in order, starting after the chunk `after`
+  fn list_cirun_log_chunks(
+    &mut self,
+    cirun_id: i32,
+    after: Option<i32>,
+  ) -> Result<Vec<CirunLogChunk>, DatabaseError>;
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> CirunStepDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn create_cirun_steps(
+    &mut self,
+    cirun_id: i32,
+    names: &[&str],
+  ) -> Result<Vec<CirunStep>, DatabaseError> {
+    use crate::schema::cirun_steps;
+
+    let new_steps: Vec<NewCirunStep> = names
+      .iter()
+      .enumerate()
+      .map(|(position, name)| NewCirunStep {
+        cirun_id,
+        position: position as i32,
+        name,
+      })
+      .collect();
+
+    diesel::insert_into(cirun_steps::table)
+      .values(&new_steps)
+      .returning(CirunStep::as_returning())
+      .get_results(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_cirun_step_by_id(&mut self, step_id: i32) -> Result<Option<CirunStep>, DatabaseError> {
+    use crate::schema::cirun_steps::dsl;
+
+    dsl::cirun_steps
+      .filter(dsl::id.eq(step_id))
+      .select(CirunStep::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_cirun_steps(&mut self, cirun_id: i32) -> Result<Vec<CirunStep>, DatabaseError> {
+    use crate::schema::cirun_steps::dsl;
+
+    dsl::cirun_steps
+      .filter(dsl::cirun_id.eq(cirun_id))
+      .order(dsl::position)
+      .select(CirunStep::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn start_cirun_step(
+    &mut self,
+    step_id: i32,
+    started_at: SystemTime,
+  ) -> Result<CirunStep, DatabaseError> {
+    use crate::schema::cirun_steps::dsl;
+
+    diesel::update(dsl::cirun_steps.filter(dsl::id.eq(step_id)))
+      .set((
+        dsl::status.eq(StepStatus::Running),
+        dsl::started_at.eq(started_at),
+      ))
+      .returning(CirunStep::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn finish_cirun_step(
+    &mut self,
+    step_id: i32,
+    status: &StepStatus,
+    exit_code: Option<i32>,
+    log_truncated: bool,
+    finished_at: SystemTime,
+  ) -> Result<CirunStep, DatabaseError> {
+    use crate::schema::cirun_steps::dsl;
+
+    diesel::update(dsl::cirun_steps.filter(dsl::id.eq(step_id)))
+      .set((
+        dsl::status.eq(status),
+        dsl::exit_code.eq(exit_code),
+        dsl::log_truncated.eq(log_truncated),
+        dsl::finished_at.eq(finished_at),
+      ))
+      .returning(CirunStep::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn skip_pending_cirun_steps(&mut self, cirun_id: i32) -> Result<usize, DatabaseError> {
+    use crate::schema::cirun_steps::dsl;
+
+    diesel::update(
+      dsl::cirun_steps
+        .filter(dsl::cirun_id.eq(cirun_id))
+        .filter(dsl::status.eq(StepStatus::Pending)),
+    )
+    .set(dsl::status.eq(StepStatus::Skipped))
+    .execute(self.conn.deref_mut())
+    .map_err(DatabaseError::from)
+  }
+
+  fn append_cirun_step_log(
+    &mut self,
+    step_id: i32,
+    content: &str,
+  ) -> Result<CirunLogChunk, DatabaseError> {
+    use crate::schema::{cirun_log_chunks, cirun_steps::dsl};
+
+    self.conn.deref_mut().transaction(|conn| {
+      diesel::update(dsl::cirun_steps.filter(dsl::id.eq(step_id)))
+        .set(dsl::log_size.eq(dsl::log_size + content.len() as i32))
+        .execute(conn)?;
+
+      diesel::insert_into(cirun_log_chunks::table)
+        .values(&NewCirunLogChunk { step_id, content })
+        .returning(CirunLogChunk::as_returning())
+        .get_result(conn)
+        .map_err(DatabaseError::from)
+    })
+  }
+
+  fn get_cirun_step_log(&mut self, step_id: i32) -> Result<String, DatabaseError> {
+    use crate::schema::cirun_log_chunks::dsl;
+
+    let chunks: Vec<String> = dsl::cirun_log_chunks
+      .filter(dsl::step_id.eq(step_id))
+      .order(dsl::id)
+      .select(dsl::content)
+      .load(self.conn.deref_mut())?;
+    Ok(chunks.concat())
+  }
+
+  fn list_cirun_log_chunks(
+    &mut self,
+    cirun_id: i32,
+    after: Option<i32>,
+  ) -> Result<Vec<CirunLogChunk>, DatabaseError> {
+    use crate::schema::{cirun_log_chunks::dsl, cirun_steps};
+
+    dsl::cirun_log_chunks
+      .inner_join(cirun_steps::table)
+      .filter(cirun_steps::dsl::cirun_id.eq(cirun_id))
+      .filter(dsl::id.gt(after.unwrap_or(0)))
+      .order(dsl::id)
+      .select(CirunLogChunk::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, SystemTime};
+
+  use crate::{
+    db_handle::{
+      cirun::CirunDbHandle,
+      repository::{RepositoryDbHandle, Repotype},
+      user::UserDbHandle,
+    },
+    transaction_tests,
+  };
+
+  use super::{CirunStepDbHandle, StepStatus};
+
+  transaction_tests! {
+    fn create_cirun_steps(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+
+      tx.create_cirun_steps(cirun.id, &["build", "test"])?;
+      let steps = tx.list_cirun_steps(cirun.id)?;
+      let names: Vec<&str> = steps.iter().map(|s| s.name.as_str()).collect();
+      assert_eq!(names, vec!["build", "test"]);
+      assert_eq!(steps[1].position, 1);
+      assert!(steps.iter().all(|s| s.status == StepStatus::Pending && s.started_at.is_none()));
+    }
+
+    fn run_cirun_steps(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let steps = tx.create_cirun_steps(cirun.id, &["build", "test", "lint"])?;
+
+      let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+      let step = tx.start_cirun_step(steps[0].id, start)?;
+      assert_eq!(step.status, StepStatus::Running);
+      assert_eq!(step.started_at, Some(start));
+
+      let end = start + Duration::from_secs(3);
+      tx.finish_cirun_step(steps[0].id, &StepStatus::Success, Some(0), false, end)?;
+      tx.start_cirun_step(steps[1].id, end)?;
+      tx.finish_cirun_step(steps[1].id, &StepStatus::Failed, Some(2), true, end)?;
+      assert_eq!(tx.skip_pending_cirun_steps(cirun.id)?, 1);
+
+      let steps = tx.list_cirun_steps(cirun.id)?;
+      let statuses: Vec<StepStatus> = steps.iter().map(|s| s.status).collect();
+      assert_eq!(statuses, vec![StepStatus::Success, StepStatus::Failed, StepStatus::Skipped]);
+      assert_eq!(steps[1].exit_code, Some(2));
+      assert!(steps[1].log_truncated);
+      assert_eq!(steps[1].finished_at, Some(end));
+    }
+
+    fn cirun_step_logs(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let other = tx.create_cirun(repo.id, "other")?;
+      let steps = tx.create_cirun_steps(cirun.id, &["build", "test"])?;
+      let other_steps = tx.create_cirun_steps(other.id, &["build"])?;
+
+      let first = tx.append_cirun_step_log(steps[0].id, "Compiling\n")?;
+      tx.append_cirun_step_log(other_steps[0].id, "Other\n")?;
+      tx.append_cirun_step_log(steps[1].id, "Testing\n")?;
+      tx.append_cirun_step_log(steps[0].id, "Done\n")?;
+
+      assert_eq!(tx.get_cirun_step_log(steps[0].id)?, "Compiling\nDone\n");
+      let step = tx.get_cirun_step_by_id(steps[0].id)?.expect("Step not found");
+      assert_eq!(step.log_size, 15);
+
+      let chunks = tx.list_cirun_log_chunks(cirun.id, None)?;
+      let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+      assert_eq!(contents, vec!["Compiling\n", "Testing\n", "Done\n"]);
+      let chunks = tx.list_cirun_log_chunks(cirun.id, Some(first.id))?;
+      assert_eq!(chunks.len(), 2);
+    }
+
+    fn get_nonexistent_cirun_step(tx: &mut DbHandle) {
+      assert!(tx.get_cirun_step_by_id(1)?.is_none());
+      assert_eq!(tx.get_cirun_step_log(1)?, "");
+    }
+  }
+}
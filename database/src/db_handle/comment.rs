@@ -1,6 +1,7 @@
 use diesel::{
-  deserialize::Queryable, prelude::Insertable, ExpressionMethods, OptionalExtension, PgConnection,
-  QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+  deserialize::Queryable, prelude::Insertable, BoolExpressionMethods, Connection,
+  ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable,
+  SelectableHelper,
 };
 use diesel_derive_enum::DbEnum;
 use std::ops::DerefMut;
@@ -14,6 +15,25 @@ pub enum Commentauthor {
   Automated,
 }
 
+/// The side of a diff a line comment refers to: `Old` for the parent of the commit, `New` for the
+/// commit itself.
+#[derive(Debug, DbEnum, PartialEq, Eq, Clone, Copy)]
+#[ExistingTypePath = "crate::schema::sql_types::Commentside"]
+pub enum Commentside {
+  Old,
+  New,
+}
+
+/// The lines of a file a comment is attached to, along with a hash of their content used to detect
+/// when they change.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommentAnchor {
+  pub start_line: i32,
+  pub end_line: i32,
+  pub side: Commentside,
+  pub context_hash: String,
+}
+
 #[derive(Debug, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::comments)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -29,6 +49,48 @@ pub struct Comment {
   pub author_type: Commentauthor,
   pub author_id: Option<i32>,
   pub date: std::time::SystemTime,
+
+  pub start_line: Option<i32>,
+  pub end_line: Option<i32>,
+  pub side: Option<Commentside>,
+  pub context_hash: Option<String>,
+  /// Whether the anchored lines changed since the comment was made
+  pub outdated: bool,
+
+  pub edited_at: Option<std::time::SystemTime>,
+  /// Only thread roots can be resolved
+  pub resolved: bool,
+  pub resolved_by: Option<i32>,
+  pub resolved_at: Option<std::time::SystemTime>,
+
+  /// The commit where the lines were last found once they moved, unset if they never did
+  pub anchor_commit: Option<String>,
+  pub anchor_start_line: Option<i32>,
+  pub anchor_end_line: Option<i32>,
+}
+
+impl Comment {
+  /// The lines the comment was made on
+  pub fn anchor(&self) -> Option<CommentAnchor> {
+    Some(CommentAnchor {
+      start_line: self.start_line?,
+      end_line: self.end_line?,
+      side: self.side?,
+      context_hash: self.context_hash.clone()?,
+    })
+  }
+
+  /// The commit and lines the comment currently refers to: where its lines were last found, or
+  /// where it was made if they never moved
+  pub fn current_anchor(&self) -> Option<(&str, CommentAnchor)> {
+    let mut anchor = self.anchor()?;
+    let Some(commit) = &self.anchor_commit else {
+      return Some((&self.commit_hash, anchor));
+    };
+    anchor.start_line = self.anchor_start_line?;
+    anchor.end_line = self.anchor_end_line?;
+    Some((commit, anchor))
+  }
 }
 
 #[derive(Insertable)]
@@ -45,6 +107,41 @@ pub struct NewComment<'a> {
   pub author_type: &'a Commentauthor,
   pub author_id: Option<i32>,
   pub date: &'a std::time::SystemTime,
+
+  pub start_line: Option<i32>,
+  pub end_line: Option<i32>,
+  pub side: Option<&'a Commentside>,
+  pub context_hash: Option<&'a str>,
+}
+
+/// A previous version of the message of a comment, saved when it was edited.
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::comment_edits)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CommentEdit {
+  pub id: i32,
+  pub comment_id: i32,
+  pub previous_message: String,
+  /// When the message was replaced
+  pub date: std::time::SystemTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::comment_edits)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewCommentEdit<'a> {
+  comment_id: i32,
+  previous_message: &'a str,
+  date: &'a std::time::SystemTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::comment_reactions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CommentReaction {
+  pub comment_id: i32,
+  pub user_id: i32,
+  pub reaction: String,
 }
 
 pub trait CommentDbHandle {
@@ -65,6 +162,16 @@ pub trait CommentDbHandle {
     message: &str,
   ) -> Result<Comment, DatabaseError>;
 
+  fn add_line_comment(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    file_path: &str,
+    anchor: &CommentAnchor,
+    author_id: i32,
+    message: &str,
+  ) -> Result<Comment, DatabaseError>;
+
   fn add_ci_comment(
     &mut self,
     repository_id: i32,
@@ -80,6 +187,15 @@ pub trait CommentDbHandle {
     message: &str,
   ) -> Result<Comment, DatabaseError>;
 
+  fn add_ci_line_comment(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    file_path: &str,
+    anchor: &CommentAnchor,
+    message: &str,
+  ) -> Result<Comment, DatabaseError>;
+
   fn add_response_comment(
     &mut self,
     reply_to: i32,
@@ -89,14 +205,71 @@ pub trait CommentDbHandle {
 
   fn get_comment_by_id(&mut self, comment_id: i32) -> Result<Option<Comment>, DatabaseError>;
 
+  /// Lists the comments of a commit, along with the line comments whose lines moved to it. When
+  /// `resolved` is set, only the threads in that state are listed, along with all replies.
   fn list_commit_comments(
     &mut self,
     repository_id: i32,
     commit_hash: &str,
+    resolved: Option<bool>,
+  ) -> Result<Vec<Comment>, DatabaseError>;
+
+  /// Lists the root comments of the threads of the repository, whichever the commit
+  fn list_repository_threads(
+    &mut self,
+    repository_id: i32,
+    resolved: Option<bool>,
   ) -> Result<Vec<Comment>, DatabaseError>;
 
   fn list_response_comments(&mut self, comment_id: i32) -> Result<Vec<Comment>, DatabaseError>;
 
+  /// Lists the line comments of the repository which aren't outdated yet, replies excluded
+  fn list_anchored_comments(&mut self, repository_id: i32) -> Result<Vec<Comment>, DatabaseError>;
+
+  fn set_comment_outdated(
+    &mut self,
+    comment_id: i32,
+    outdated: bool,
+  ) -> Result<Comment, DatabaseError>;
+
+  /// Records that the lines of a line comment were found at another place of a later commit. The
+  /// comment keeps the commit and lines it was made on.
+  fn move_comment_anchor(
+    &mut self,
+    comment_id: i32,
+    commit_hash: &str,
+    start_line: i32,
+    end_line: i32,
+  ) -> Result<Comment, DatabaseError>;
+
+  /// Replaces the message of the comment, keeping the previous one in its history
+  fn edit_comment(&mut self, comment_id: i32, message: &str) -> Result<Comment, DatabaseError>;
+
+  /// Lists the previous messages of the comment, oldest first
+  fn list_comment_edits(&mut self, comment_id: i32) -> Result<Vec<CommentEdit>, DatabaseError>;
+
+  /// Resolves a thread. Fails with `NotFound` if the comment isn't the root of a thread.
+  fn resolve_comment(&mut self, comment_id: i32, user_id: i32) -> Result<Comment, DatabaseError>;
+
+  fn unresolve_comment(&mut self, comment_id: i32) -> Result<Comment, DatabaseError>;
+
+  /// Adds a reaction of the user to the comment, doing nothing if it already exists
+  fn add_reaction(
+    &mut self,
+    comment_id: i32,
+    user_id: i32,
+    reaction: &str,
+  ) -> Result<(), DatabaseError>;
+
+  fn remove_reaction(
+    &mut self,
+    comment_id: i32,
+    user_id: i32,
+    reaction: &str,
+  ) -> Result<(), DatabaseError>;
+
+  fn list_reactions(&mut self, comment_id: i32) -> Result<Vec<CommentReaction>, DatabaseError>;
+
   fn delete_comment(&mut self, comment_id: i32) -> Result<(), DatabaseError>;
 }
 
@@ -136,6 +309,10 @@ where
       author_type: &Commentauthor::User,
       author_id: Some(author_id),
       date: &std::time::SystemTime::now(),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
     };
 
     self.add_comment_inner(new_comment)
@@ -158,6 +335,37 @@ where
       author_type: &Commentauthor::User,
       author_id: Some(author_id),
       date: &std::time::SystemTime::now(),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
+    };
+
+    self.add_comment_inner(new_comment)
+  }
+
+  fn add_line_comment(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    file_path: &str,
+    anchor: &CommentAnchor,
+    author_id: i32,
+    message: &str,
+  ) -> Result<Comment, DatabaseError> {
+    let new_comment = NewComment {
+      repository_id,
+      commit_hash,
+      respond_to: None,
+      file_path: Some(file_path),
+      message,
+      author_type: &Commentauthor::User,
+      author_id: Some(author_id),
+      date: &std::time::SystemTime::now(),
+      start_line: Some(anchor.start_line),
+      end_line: Some(anchor.end_line),
+      side: Some(&anchor.side),
+      context_hash: Some(&anchor.context_hash),
     };
 
     self.add_comment_inner(new_comment)
@@ -178,6 +386,10 @@ where
       author_type: &Commentauthor::Automated,
       author_id: None,
       date: &std::time::SystemTime::now(),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
     };
 
     self.add_comment_inner(new_comment)
@@ -199,6 +411,36 @@ where
       author_type: &Commentauthor::Automated,
       author_id: None,
       date: &std::time::SystemTime::now(),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
+    };
+
+    self.add_comment_inner(new_comment)
+  }
+
+  fn add_ci_line_comment(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    file_path: &str,
+    anchor: &CommentAnchor,
+    message: &str,
+  ) -> Result<Comment, DatabaseError> {
+    let new_comment = NewComment {
+      repository_id,
+      commit_hash,
+      respond_to: None,
+      file_path: Some(file_path),
+      message,
+      author_type: &Commentauthor::Automated,
+      author_id: None,
+      date: &std::time::SystemTime::now(),
+      start_line: Some(anchor.start_line),
+      end_line: Some(anchor.end_line),
+      side: Some(&anchor.side),
+      context_hash: Some(&anchor.context_hash),
     };
 
     self.add_comment_inner(new_comment)
@@ -222,6 +464,10 @@ where
       author_type: &Commentauthor::User,
       author_id: Some(author_id),
       date: &std::time::SystemTime::now(),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
     };
 
     self.add_comment_inner(new_comment)
@@ -242,12 +488,44 @@ where
     &mut self,
     repository_id: i32,
     commit_hash: &str,
+    resolved: Option<bool>,
   ) -> Result<Vec<Comment>, DatabaseError> {
     use crate::schema::comments::dsl;
 
-    dsl::comments
+    let mut query = dsl::comments
       .filter(dsl::repository_id.eq(repository_id))
-      .filter(dsl::commit_hash.eq(commit_hash))
+      .filter(
+        dsl::commit_hash
+          .eq(commit_hash)
+          .or(dsl::anchor_commit.eq(commit_hash)),
+      )
+      .into_boxed();
+    if let Some(resolved) = resolved {
+      query = query.filter(dsl::respond_to.is_not_null().or(dsl::resolved.eq(resolved)));
+    }
+
+    query
+      .select(Comment::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_repository_threads(
+    &mut self,
+    repository_id: i32,
+    resolved: Option<bool>,
+  ) -> Result<Vec<Comment>, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    let mut query = dsl::comments
+      .filter(dsl::repository_id.eq(repository_id))
+      .filter(dsl::respond_to.is_null())
+      .into_boxed();
+    if let Some(resolved) = resolved {
+      query = query.filter(dsl::resolved.eq(resolved));
+    }
+
+    query
       .select(Comment::as_select())
       .load(self.conn.deref_mut())
       .map_err(DatabaseError::from)
@@ -263,6 +541,169 @@ where
       .map_err(DatabaseError::from)
   }
 
+  fn list_anchored_comments(&mut self, repository_id: i32) -> Result<Vec<Comment>, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    dsl::comments
+      .filter(dsl::repository_id.eq(repository_id))
+      .filter(dsl::respond_to.is_null())
+      .filter(dsl::context_hash.is_not_null())
+      .filter(dsl::outdated.eq(false))
+      .select(Comment::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn set_comment_outdated(
+    &mut self,
+    comment_id: i32,
+    outdated: bool,
+  ) -> Result<Comment, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    diesel::update(dsl::comments.find(comment_id))
+      .set(dsl::outdated.eq(outdated))
+      .returning(Comment::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn move_comment_anchor(
+    &mut self,
+    comment_id: i32,
+    commit_hash: &str,
+    start_line: i32,
+    end_line: i32,
+  ) -> Result<Comment, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    diesel::update(dsl::comments.find(comment_id))
+      .set((
+        dsl::anchor_commit.eq(commit_hash),
+        dsl::anchor_start_line.eq(start_line),
+        dsl::anchor_end_line.eq(end_line),
+      ))
+      .returning(Comment::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn edit_comment(&mut self, comment_id: i32, message: &str) -> Result<Comment, DatabaseError> {
+    use crate::schema::{comment_edits, comments::dsl};
+
+    self.conn.deref_mut().transaction(|conn| {
+      let previous = dsl::comments
+        .find(comment_id)
+        .select(dsl::message)
+        .for_update()
+        .first::<String>(conn)
+        .optional()?
+        .ok_or(DatabaseError::NotFound)?;
+      let now = std::time::SystemTime::now();
+
+      diesel::insert_into(comment_edits::table)
+        .values(&NewCommentEdit {
+          comment_id,
+          previous_message: &previous,
+          date: &now,
+        })
+        .execute(conn)?;
+      diesel::update(dsl::comments.find(comment_id))
+        .set((dsl::message.eq(message), dsl::edited_at.eq(Some(now))))
+        .returning(Comment::as_returning())
+        .get_result(conn)
+        .map_err(DatabaseError::from)
+    })
+  }
+
+  fn list_comment_edits(&mut self, comment_id: i32) -> Result<Vec<CommentEdit>, DatabaseError> {
+    use crate::schema::comment_edits::dsl;
+
+    dsl::comment_edits
+      .filter(dsl::comment_id.eq(comment_id))
+      .order((dsl::date.asc(), dsl::id.asc()))
+      .select(CommentEdit::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn resolve_comment(&mut self, comment_id: i32, user_id: i32) -> Result<Comment, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    diesel::update(dsl::comments.find(comment_id))
+      .filter(dsl::respond_to.is_null())
+      .set((
+        dsl::resolved.eq(true),
+        dsl::resolved_by.eq(Some(user_id)),
+        dsl::resolved_at.eq(Some(std::time::SystemTime::now())),
+      ))
+      .returning(Comment::as_returning())
+      .get_result(self.conn.deref_mut())
+      .optional()?
+      .ok_or(DatabaseError::NotFound)
+  }
+
+  fn unresolve_comment(&mut self, comment_id: i32) -> Result<Comment, DatabaseError> {
+    use crate::schema::comments::dsl;
+
+    diesel::update(dsl::comments.find(comment_id))
+      .filter(dsl::respond_to.is_null())
+      .set((
+        dsl::resolved.eq(false),
+        dsl::resolved_by.eq(None::<i32>),
+        dsl::resolved_at.eq(None::<std::time::SystemTime>),
+      ))
+      .returning(Comment::as_returning())
+      .get_result(self.conn.deref_mut())
+      .optional()?
+      .ok_or(DatabaseError::NotFound)
+  }
+
+  fn add_reaction(
+    &mut self,
+    comment_id: i32,
+    user_id: i32,
+    reaction: &str,
+  ) -> Result<(), DatabaseError> {
+    use crate::schema::comment_reactions;
+
+    diesel::insert_into(comment_reactions::table)
+      .values(&CommentReaction {
+        comment_id,
+        user_id,
+        reaction: reaction.to_string(),
+      })
+      .on_conflict_do_nothing()
+      .execute(self.conn.deref_mut())
+      .map(|_| ())
+      .map_err(DatabaseError::from)
+  }
+
+  fn remove_reaction(
+    &mut self,
+    comment_id: i32,
+    user_id: i32,
+    reaction: &str,
+  ) -> Result<(), DatabaseError> {
+    use crate::schema::comment_reactions::dsl;
+
+    diesel::delete(dsl::comment_reactions.find((comment_id, user_id, reaction)))
+      .execute(self.conn.deref_mut())
+      .map(|_| ())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_reactions(&mut self, comment_id: i32) -> Result<Vec<CommentReaction>, DatabaseError> {
+    use crate::schema::comment_reactions::dsl;
+
+    dsl::comment_reactions
+      .filter(dsl::comment_id.eq(comment_id))
+      .order((dsl::reaction.asc(), dsl::user_id.asc()))
+      .select(CommentReaction::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
   fn delete_comment(&mut self, comment_id: i32) -> Result<(), DatabaseError> {
     use crate::schema::comments::dsl::*;
 
@@ -283,7 +724,16 @@ mod tests {
     transaction_tests,
   };
 
-  use super::{CommentDbHandle, Commentauthor};
+  use super::{CommentAnchor, CommentDbHandle, Commentauthor, Commentside};
+
+  fn anchor(start_line: i32, end_line: i32) -> CommentAnchor {
+    CommentAnchor {
+      start_line,
+      end_line,
+      side: Commentside::New,
+      context_hash: "hash".to_string(),
+    }
+  }
 
   transaction_tests! {
     fn add_comment_missing_repository(tx: &mut DbHandle) {
@@ -322,6 +772,24 @@ mod tests {
       assert_eq!(comment.file_path, Some("file".to_string()));
     }
 
+    fn add_line_comment_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment =
+        tx.add_line_comment(repository.id, "commit", "file", &anchor(42, 43), user.id, "message")?;
+      assert_eq!(comment.file_path, Some("file".to_string()));
+      assert_eq!(comment.anchor(), Some(anchor(42, 43)));
+      assert!(!comment.outdated);
+    }
+
+    fn add_line_comment_invalid_range(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let result =
+        tx.add_line_comment(repository.id, "commit", "file", &anchor(43, 42), user.id, "message");
+      result.expect_err("Expected error when the range ends before it starts");
+    }
+
     fn add_ci_comment_success(tx: &mut DbHandle) {
       let user = tx.create_user("username", "email", "password", None)?;
       let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
@@ -345,6 +813,14 @@ mod tests {
       assert_eq!(comment.file_path, Some("file#42-43".to_string()));
     }
 
+    fn add_ci_line_comment_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment = tx.add_ci_line_comment(repository.id, "commit", "file", &anchor(1, 1), "message")?;
+      assert_eq!(comment.author_type, Commentauthor::Automated);
+      assert_eq!(comment.anchor(), Some(anchor(1, 1)));
+    }
+
     fn add_response_comment_missing_reply_to(tx: &mut DbHandle) {
       let user = tx.create_user("username", "email", "password", None)?;
       let result = tx.add_response_comment(0, user.id, "message");
@@ -377,7 +853,7 @@ mod tests {
       let response = tx.add_response_comment(comment.id, user.id, "message")?;
       tx.add_response_comment(comment.id, user.id, "message")?;
       tx.add_response_comment(response.id, user.id, "message")?;
-      let comments = tx.list_commit_comments(repository.id, "commit")?;
+      let comments = tx.list_commit_comments(repository.id, "commit", None)?;
       assert_eq!(comments.len(), 4);
     }
 
@@ -402,7 +878,7 @@ mod tests {
     fn list_commit_comments_empty(tx: &mut DbHandle) {
       let user = tx.create_user("username", "email", "password", None)?;
       let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
-      let comments = tx.list_commit_comments(repository.id, "commit")?;
+      let comments = tx.list_commit_comments(repository.id, "commit", None)?;
       assert!(comments.is_empty());
     }
 
@@ -410,7 +886,7 @@ mod tests {
       let user = tx.create_user("username", "email", "password", None)?;
       let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
       let comment = tx.add_comment(repository.id, "commit", user.id, "message")?;
-      let comments = tx.list_commit_comments(repository.id, "commit")?;
+      let comments = tx.list_commit_comments(repository.id, "commit", None)?;
       assert_eq!(comments.len(), 1);
       assert_eq!(comments[0].id, comment.id);
       assert_eq!(comments[0].repository_id, repository.id);
@@ -428,7 +904,7 @@ mod tests {
       tx.add_ci_file_comment(repository.id, "commit", "file", "message")?;
       tx.add_ci_comment(repository.id, "commit", "message")?;
 
-      let comments = tx.list_commit_comments(repository.id, "commit")?;
+      let comments = tx.list_commit_comments(repository.id, "commit", None)?;
       assert_eq!(comments.len(), 4);
     }
 
@@ -452,6 +928,146 @@ mod tests {
       assert_eq!(comments[0].author_type, Commentauthor::User);
     }
 
+    fn list_anchored_comments_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment =
+        tx.add_line_comment(repository.id, "commit", "file", &anchor(1, 2), user.id, "message")?;
+      let outdated =
+        tx.add_line_comment(repository.id, "commit", "file", &anchor(3, 4), user.id, "message")?;
+      tx.add_response_comment(comment.id, user.id, "message")?;
+      tx.add_file_comment(repository.id, "commit", "file", user.id, "message")?;
+
+      let outdated = tx.set_comment_outdated(outdated.id, true)?;
+      assert!(outdated.outdated);
+
+      let comments = tx.list_anchored_comments(repository.id)?;
+      assert_eq!(comments.len(), 1);
+      assert_eq!(comments[0].id, comment.id);
+    }
+
+    fn move_comment_anchor_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment =
+        tx.add_line_comment(repository.id, "commit", "file", &anchor(1, 2), user.id, "message")?;
+
+      let moved = tx.move_comment_anchor(comment.id, "head", 3, 4)?;
+      assert_eq!(moved.commit_hash, "commit");
+      assert_eq!(moved.anchor(), Some(anchor(1, 2)));
+      assert_eq!(moved.current_anchor(), Some(("head", anchor(3, 4))));
+      assert!(!moved.outdated);
+
+      // Listed on both the commit it was made on and the one its lines moved to
+      for commit in ["commit", "head"] {
+        let comments = tx.list_commit_comments(repository.id, commit, None)?;
+        assert_eq!(comments.len(), 1);
+      }
+    }
+
+    fn set_comment_outdated_missing_comment(tx: &mut DbHandle) {
+      let result = tx.set_comment_outdated(0, true);
+      result.expect_err("Expected error when updating nonexistent comment");
+    }
+
+    fn list_commit_comments_resolved_filter(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let resolved = tx.add_comment(repository.id, "commit", user.id, "message")?;
+      let reply = tx.add_response_comment(resolved.id, user.id, "message")?;
+      let open = tx.add_comment(repository.id, "commit", user.id, "message")?;
+      tx.resolve_comment(resolved.id, user.id)?;
+
+      let comments = tx.list_commit_comments(repository.id, "commit", Some(true))?;
+      let ids: Vec<_> = comments.iter().map(|c| c.id).collect();
+      assert_eq!(ids.len(), 2);
+      assert!(ids.contains(&resolved.id) && ids.contains(&reply.id));
+
+      let comments = tx.list_commit_comments(repository.id, "commit", Some(false))?;
+      let ids: Vec<_> = comments.iter().map(|c| c.id).collect();
+      assert_eq!(ids.len(), 2);
+      assert!(ids.contains(&open.id) && ids.contains(&reply.id));
+    }
+
+    fn list_repository_threads_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let first = tx.add_comment(repository.id, "first", user.id, "message")?;
+      let second = tx.add_comment(repository.id, "second", user.id, "message")?;
+      tx.add_response_comment(first.id, user.id, "message")?;
+      tx.resolve_comment(second.id, user.id)?;
+
+      let threads = tx.list_repository_threads(repository.id, None)?;
+      assert_eq!(threads.len(), 2);
+      let threads = tx.list_repository_threads(repository.id, Some(false))?;
+      assert_eq!(threads.len(), 1);
+      assert_eq!(threads[0].id, first.id);
+    }
+
+    fn edit_comment_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment = tx.add_comment(repository.id, "commit", user.id, "first")?;
+      assert!(comment.edited_at.is_none());
+      tx.edit_comment(comment.id, "second")?;
+      let edited = tx.edit_comment(comment.id, "third")?;
+      assert_eq!(edited.message, "third");
+      assert!(edited.edited_at.is_some());
+
+      let edits = tx.list_comment_edits(comment.id)?;
+      let messages: Vec<_> = edits.iter().map(|e| e.previous_message.as_str()).collect();
+      assert_eq!(messages, vec!["first", "second"]);
+    }
+
+    fn edit_comment_missing_comment(tx: &mut DbHandle) {
+      let result = tx.edit_comment(0, "message");
+      assert!(matches!(result, Err(crate::error::DatabaseError::NotFound)));
+    }
+
+    fn resolve_comment_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment = tx.add_comment(repository.id, "commit", user.id, "message")?;
+      let resolved = tx.resolve_comment(comment.id, user.id)?;
+      assert!(resolved.resolved);
+      assert_eq!(resolved.resolved_by, Some(user.id));
+      assert!(resolved.resolved_at.is_some());
+
+      let unresolved = tx.unresolve_comment(comment.id)?;
+      assert!(!unresolved.resolved);
+      assert_eq!(unresolved.resolved_by, None);
+      assert_eq!(unresolved.resolved_at, None);
+    }
+
+    fn resolve_reply_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment = tx.add_comment(repository.id, "commit", user.id, "message")?;
+      let reply = tx.add_response_comment(comment.id, user.id, "message")?;
+      let result = tx.resolve_comment(reply.id, user.id);
+      assert!(matches!(result, Err(crate::error::DatabaseError::NotFound)));
+    }
+
+    fn reactions_success(tx: &mut DbHandle) {
+      let user = tx.create_user("username", "email", "password", None)?;
+      let repository = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let comment = tx.add_comment(repository.id, "commit", user.id, "message")?;
+      tx.add_reaction(comment.id, user.id, "+1")?;
+      tx.add_reaction(comment.id, user.id, "+1")?;
+      tx.add_reaction(comment.id, user.id, "eyes")?;
+      tx.remove_reaction(comment.id, user.id, "eyes")?;
+
+      let reactions = tx.list_reactions(comment.id)?;
+      assert_eq!(
+        reactions,
+        vec![super::CommentReaction {
+          comment_id: comment.id,
+          user_id: user.id,
+          reaction: "+1".to_string(),
+        }]
+      );
+    }
+
     fn delete_comment_missing_comment(tx: &mut DbHandle) {
       let result = tx.delete_comment(0);
       result.expect("Expected nothing to happen when deleting nonexistent comment");
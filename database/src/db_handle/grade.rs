@@ -0,0 +1,437 @@
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use diesel_derive_enum::DbEnum;
+use std::ops::DerefMut;
+
+use crate::{
+  db_handle::{submission::Submission, BaseDbHandle},
+  error::DatabaseError,
+};
+
+/// Whether a grade was given by a teacher or computed by the CI.
+#[derive(Debug, DbEnum, PartialEq, Eq, Clone, Copy)]
+#[ExistingTypePath = "crate::schema::sql_types::Gradesource"]
+pub enum Gradesource {
+  Manual,
+  Automated,
+}
+
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::grades)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Grade {
+  pub id: i32,
+  pub submission_id: i32,
+  pub score: f64,
+  pub max_score: f64,
+  pub source: Gradesource,
+  /// The teacher who gave the grade, none for automated grades
+  pub grader_id: Option<i32>,
+  pub date: std::time::SystemTime,
+  /// The CI run which computed the grade, none for manual grades
+  pub cirun_id: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::grades)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewGrade<'a> {
+  submission_id: i32,
+  score: f64,
+  max_score: f64,
+  source: &'a Gradesource,
+  grader_id: Option<i32>,
+  date: &'a std::time::SystemTime,
+  cirun_id: Option<i32>,
+}
+
+/// The score of a grade for one criterion of the rubric.
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::grade_criteria)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GradeCriterion {
+  pub id: i32,
+  pub grade_id: i32,
+  pub name: String,
+  pub score: f64,
+  pub max_score: f64,
+  pub comment: Option<String>,
+}
+
+/// A criterion of the rubric, as given when grading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RubricScore {
+  pub name: String,
+  pub score: f64,
+  pub max_score: f64,
+  pub comment: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::grade_criteria)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewGradeCriterion<'a> {
+  grade_id: i32,
+  name: &'a str,
+  score: f64,
+  max_score: f64,
+  comment: Option<&'a str>,
+}
+
+pub trait GradeDbHandle {
+  /// Grades a submission on behalf of a teacher, along with the breakdown of the rubric
+  fn add_grade(
+    &mut self,
+    submission_id: i32,
+    grader_id: i32,
+    score: f64,
+    max_score: f64,
+    rubric: &[RubricScore],
+  ) -> Result<Grade, DatabaseError>;
+
+  /// Grades a submission with the score computed by a CI run. A run can only grade once.
+  fn add_ci_grade(
+    &mut self,
+    submission_id: i32,
+    cirun_id: i32,
+    score: f64,
+    max_score: f64,
+    rubric: &[RubricScore],
+  ) -> Result<Grade, DatabaseError>;
+
+  fn get_grade_by_id(&mut self, grade_id: i32) -> Result<Option<Grade>, DatabaseError>;
+
+  fn get_cirun_grade(&mut self, cirun_id: i32) -> Result<Option<Grade>, DatabaseError>;
+
+  /// Returns the most recent automated grade of the submissions of the repository
+  fn get_latest_ci_grade(&mut self, repository_id: i32) -> Result<Option<Grade>, DatabaseError>;
+
+  /// Lists the automated grades of the submissions of the repository along with their submission,
+  /// oldest first
+  fn list_repository_ci_grades(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<(Grade, Submission)>, DatabaseError>;
+
+  /// Lists the grades of the submission, oldest first
+  fn list_submission_grades(&mut self, submission_id: i32) -> Result<Vec<Grade>, DatabaseError>;
+
+  /// Lists the rubric breakdown of the grade, in the order it was given
+  fn list_grade_criteria(&mut self, grade_id: i32) -> Result<Vec<GradeCriterion>, DatabaseError>;
+
+  fn delete_grade(&mut self, grade_id: i32) -> Result<(), DatabaseError>;
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn add_grade_inner(
+    &mut self,
+    new_grade: NewGrade,
+    rubric: &[RubricScore],
+  ) -> Result<Grade, DatabaseError> {
+    use crate::schema::{grade_criteria, grades};
+
+    self.conn.deref_mut().transaction(|conn| {
+      let grade = diesel::insert_into(grades::table)
+        .values(&new_grade)
+        .returning(Grade::as_returning())
+        .get_result(conn)?;
+
+      let criteria: Vec<NewGradeCriterion> = rubric
+        .iter()
+        .map(|criterion| NewGradeCriterion {
+          grade_id: grade.id,
+          name: &criterion.name,
+          score: criterion.score,
+          max_score: criterion.max_score,
+          comment: criterion.comment.as_deref(),
+        })
+        .collect();
+      diesel::insert_into(grade_criteria::table)
+        .values(&criteria)
+        .execute(conn)?;
+
+      Ok(grade)
+    })
+  }
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> GradeDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn add_grade(
+    &mut self,
+    submission_id: i32,
+    grader_id: i32,
+    score: f64,
+    max_score: f64,
+    rubric: &[RubricScore],
+  ) -> Result<Grade, DatabaseError> {
+    let new_grade = NewGrade {
+      submission_id,
+      score,
+      max_score,
+      source: &Gradesource::Manual,
+      grader_id: Some(grader_id),
+      date: &std::time::SystemTime::now(),
+      cirun_id: None,
+    };
+
+    self.add_grade_inner(new_grade, rubric)
+  }
+
+  fn add_ci_grade(
+    &mut self,
+    submission_id: i32,
+    cirun_id: i32,
+    score: f64,
+    max_score: f64,
+    rubric: &[RubricScore],
+  ) -> Result<Grade, DatabaseError> {
+    let new_grade = NewGrade {
+      submission_id,
+      score,
+      max_score,
+      source: &Gradesource::Automated,
+      grader_id: None,
+      date: &std::time::SystemTime::now(),
+      cirun_id: Some(cirun_id),
+    };
+
+    self.add_grade_inner(new_grade, rubric)
+  }
+
+  fn get_grade_by_id(&mut self, grade_id: i32) -> Result<Option<Grade>, DatabaseError> {
+    use crate::schema::grades::dsl;
+
+    dsl::grades
+      .find(grade_id)
+      .select(Grade::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_cirun_grade(&mut self, cirun_id: i32) -> Result<Option<Grade>, DatabaseError> {
+    use crate::schema::grades::dsl;
+
+    dsl::grades
+      .filter(dsl::cirun_id.eq(cirun_id))
+      .select(Grade::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_latest_ci_grade(&mut self, repository_id: i32) -> Result<Option<Grade>, DatabaseError> {
+    use crate::schema::{grades, submissions};
+
+    grades::table
+      .inner_join(submissions::table)
+      .filter(submissions::repository_id.eq(repository_id))
+      .filter(grades::source.eq(Gradesource::Automated))
+      .order((grades::date.desc(), grades::id.desc()))
+      .select(Grade::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_repository_ci_grades(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<(Grade, Submission)>, DatabaseError> {
+    use crate::schema::{grades, submissions};
+
+    grades::table
+      .inner_join(submissions::table)
+      .filter(submissions::repository_id.eq(repository_id))
+      .filter(grades::source.eq(Gradesource::Automated))
+      .order((grades::date, grades::id))
+      .select((Grade::as_select(), Submission::as_select()))
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_submission_grades(&mut self, submission_id: i32) -> Result<Vec<Grade>, DatabaseError> {
+    use crate::schema::grades::dsl;
+
+    dsl::grades
+      .filter(dsl::submission_id.eq(submission_id))
+      .order(dsl::id)
+      .select(Grade::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_grade_criteria(&mut self, grade_id: i32) -> Result<Vec<GradeCriterion>, DatabaseError> {
+    use crate::schema::grade_criteria::dsl;
+
+    dsl::grade_criteria
+      .filter(dsl::grade_id.eq(grade_id))
+      .order(dsl::id)
+      .select(GradeCriterion::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn delete_grade(&mut self, grade_id: i32) -> Result<(), DatabaseError> {
+    use crate::schema::grades::dsl;
+
+    diesel::delete(dsl::grades.find(grade_id))
+      .execute(self.conn.deref_mut())
+      .map(|_| ())
+      .map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::SystemTime;
+
+  use crate::{
+    db_handle::{
+      cirun::CirunDbHandle,
+      repository::{RepositoryDbHandle, Repotype},
+      submission::SubmissionDbHandle,
+      user::UserDbHandle,
+    },
+    transaction_tests,
+  };
+
+  use super::{GradeDbHandle, Gradesource, RubricScore};
+
+  fn rubric() -> Vec<RubricScore> {
+    vec![
+      RubricScore {
+        name: "Tests".to_string(),
+        score: 8.0,
+        max_score: 10.0,
+        comment: None,
+      },
+      RubricScore {
+        name: "Style".to_string(),
+        score: 4.5,
+        max_score: 5.0,
+        comment: Some("Some functions are too long".to_string()),
+      },
+    ]
+  }
+
+  transaction_tests! {
+    fn add_grade(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+
+      let grade = tx.add_grade(submission.id, user.id, 12.5, 15.0, &rubric())?;
+      assert_eq!(grade.submission_id, submission.id);
+      assert_eq!(grade.score, 12.5);
+      assert_eq!(grade.max_score, 15.0);
+      assert_eq!(grade.source, Gradesource::Manual);
+      assert_eq!(grade.grader_id, Some(user.id));
+      assert_eq!(tx.get_grade_by_id(grade.id)?, Some(grade.clone()));
+
+      let criteria = tx.list_grade_criteria(grade.id)?;
+      let names: Vec<&str> = criteria.iter().map(|c| c.name.as_str()).collect();
+      assert_eq!(names, vec!["Tests", "Style"]);
+      assert_eq!(criteria[1].score, 4.5);
+      assert_eq!(criteria[1].comment.as_deref(), Some("Some functions are too long"));
+    }
+
+    fn add_ci_grade(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+
+      let grade = tx.add_ci_grade(submission.id, cirun.id, 3.0, 4.0, &[])?;
+      assert_eq!(grade.source, Gradesource::Automated);
+      assert_eq!(grade.grader_id, None);
+      assert_eq!(grade.cirun_id, Some(cirun.id));
+      assert!(tx.list_grade_criteria(grade.id)?.is_empty());
+      assert_eq!(tx.get_cirun_grade(cirun.id)?, Some(grade));
+    }
+
+    fn add_ci_grade_twice_for_a_run_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+
+      tx.add_ci_grade(submission.id, cirun.id, 3.0, 4.0, &[])?;
+      assert!(tx.add_ci_grade(submission.id, cirun.id, 4.0, 4.0, &[]).is_err());
+    }
+
+    fn repository_ci_grades(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let other = tx.create_repository("other", &Repotype::Default, user.id, None)?;
+      assert!(tx.get_latest_ci_grade(repo.id)?.is_none());
+      assert!(tx.list_repository_ci_grades(repo.id)?.is_empty());
+
+      let first = tx.create_submission(repo.id, "first", SystemTime::now(), false, None)?;
+      let second = tx.create_submission(repo.id, "second", SystemTime::now(), false, None)?;
+      let elsewhere = tx.create_submission(other.id, "first", SystemTime::now(), false, None)?;
+      let mut grade = |submission_id: i32, commit: &str, score: f64, max_score: f64| {
+        let cirun = tx.create_cirun(repo.id, commit)?;
+        tx.add_ci_grade(submission_id, cirun.id, score, max_score, &[])
+      };
+      let oldest = grade(first.id, "first", 9.0, 10.0)?;
+      let latest = grade(second.id, "second", 3.0, 4.0)?;
+      grade(elsewhere.id, "first", 10.0, 10.0)?;
+      // Manual grades are not taken into account
+      tx.add_grade(second.id, user.id, 4.0, 4.0, &[])?;
+
+      assert_eq!(tx.get_latest_ci_grade(repo.id)?.map(|g| g.id), Some(latest.id));
+      let grades: Vec<(i32, i32)> = tx
+        .list_repository_ci_grades(repo.id)?
+        .iter()
+        .map(|(grade, submission)| (grade.id, submission.id))
+        .collect();
+      assert_eq!(grades, vec![(oldest.id, first.id), (latest.id, second.id)]);
+    }
+
+    fn add_grade_above_max_score_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+
+      assert!(tx.add_grade(submission.id, user.id, 21.0, 20.0, &[]).is_err());
+    }
+
+    fn add_grade_with_invalid_rubric_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+
+      let mut rubric = rubric();
+      rubric[0].score = -1.0;
+      assert!(tx.add_grade(submission.id, user.id, 10.0, 20.0, &rubric).is_err());
+      assert!(tx.list_submission_grades(submission.id)?.is_empty());
+    }
+
+    fn list_and_delete_grades(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+
+      let cirun = tx.create_cirun(repo.id, "commit")?;
+      let first = tx.add_ci_grade(submission.id, cirun.id, 3.0, 4.0, &[])?;
+      let second = tx.add_grade(submission.id, user.id, 4.0, 4.0, &rubric())?;
+      let ids: Vec<i32> = tx.list_submission_grades(submission.id)?.iter().map(|g| g.id).collect();
+      assert_eq!(ids, vec![first.id, second.id]);
+
+      tx.delete_grade(second.id)?;
+      assert!(tx.get_grade_by_id(second.id)?.is_none());
+      assert!(tx.list_grade_criteria(second.id)?.is_empty());
+    }
+  }
+}
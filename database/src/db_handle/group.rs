@@ -12,7 +12,7 @@ use crate::{
 
 use super::{assignment::Assignment, user::User};
 
-#[derive(Queryable, Selectable)]
+#[derive(Queryable, Selectable, Clone, Debug)]
 #[diesel(table_name = crate::schema::groups)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Group {
@@ -247,7 +247,7 @@ mod tests {
     fn list_group_assignments(tx: &mut DbHandle) {
       let user = tx.create_user("user", "email", "password", None)?;
       let group = tx.create_group("test_group", None)?;
-      let assignments = vec![
+      let assignments = [
         "repo1",
         "repo2",
         "repo3",
@@ -279,7 +279,7 @@ mod tests {
     }
 
     fn list_students(tx: &mut DbHandle) {
-      let students = vec![
+      let students = [
         ("student-1", "email-1", "password-1"),
         ("student-2", "email-2", "password-2"),
         ("student-3", "email-3", "password-3"),
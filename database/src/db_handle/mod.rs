@@ -12,11 +12,17 @@ use crate::error::DatabaseError;
 #[cfg(test)]
 pub mod tests;
 
+pub mod artifact;
 pub mod assignment;
 pub mod cirun;
+pub mod cirun_step;
 pub mod comment;
+pub mod grade;
 pub mod group;
+pub mod push;
 pub mod repository;
+pub mod submission;
+pub mod transaction;
 pub mod user;
 
 #[cfg_attr(feature = "mock", faux::create)]
@@ -34,12 +40,13 @@ impl<T> BaseDbHandle<T>
 where
   T: DerefMut<Target = PgConnection>,
 {
-  pub fn run_migrations(&mut self) -> Result<Vec<MigrationVersion>, DatabaseError> {
+  pub fn run_migrations(&mut self) -> Result<Vec<MigrationVersion<'static>>, DatabaseError> {
     const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
     self
       .conn
       .run_pending_migrations(MIGRATIONS)
+      .map(|versions| versions.iter().map(|v| v.as_owned()).collect())
       .map_err(|e: Box<dyn Error + Send + Sync>| DatabaseError::MigrationError(e))
   }
 
@@ -0,0 +1,142 @@
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, ExpressionMethods, PgConnection, QueryDsl,
+  RunQueryDsl, Selectable, SelectableHelper,
+};
+use std::ops::DerefMut;
+
+use crate::{db_handle::BaseDbHandle, error::DatabaseError};
+
+/// A branch update pushed to a submission repository, recorded by the server when it happens so
+/// that the students can't rewrite when their work was handed in.
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::pushes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PushRecord {
+  pub id: i32,
+  pub repository_id: i32,
+  /// The user who pushed, if they still exist
+  pub user_id: Option<i32>,
+  pub ref_name: String,
+  /// The commit the branch pointed to after the push
+  pub commit_hash: String,
+  pub pushed_at: std::time::SystemTime,
+  /// Whether the push was flagged as late by the late policy of the assignment
+  pub late: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::pushes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewPushRecord<'a> {
+  repository_id: i32,
+  user_id: Option<i32>,
+  ref_name: &'a str,
+  commit_hash: &'a str,
+  pushed_at: &'a std::time::SystemTime,
+  late: bool,
+}
+
+pub trait PushDbHandle {
+  fn create_push_record(
+    &mut self,
+    repository_id: i32,
+    user_id: Option<i32>,
+    ref_name: &str,
+    commit_hash: &str,
+    pushed_at: std::time::SystemTime,
+    late: bool,
+  ) -> Result<PushRecord, DatabaseError>;
+
+  /// Lists the pushes to the repository, oldest first
+  fn list_repository_pushes(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<PushRecord>, DatabaseError>;
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> PushDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn create_push_record(
+    &mut self,
+    repository_id: i32,
+    user_id: Option<i32>,
+    ref_name: &str,
+    commit_hash: &str,
+    pushed_at: std::time::SystemTime,
+    late: bool,
+  ) -> Result<PushRecord, DatabaseError> {
+    use crate::schema::pushes;
+
+    diesel::insert_into(pushes::table)
+      .values(&NewPushRecord {
+        repository_id,
+        user_id,
+        ref_name,
+        commit_hash,
+        pushed_at: &pushed_at,
+        late,
+      })
+      .returning(PushRecord::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_repository_pushes(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<PushRecord>, DatabaseError> {
+    use crate::schema::pushes::dsl;
+
+    dsl::pushes
+      .filter(dsl::repository_id.eq(repository_id))
+      .order((dsl::pushed_at, dsl::id))
+      .select(PushRecord::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, SystemTime};
+
+  use crate::{
+    db_handle::{
+      repository::{RepositoryDbHandle, Repotype},
+      user::UserDbHandle,
+    },
+    transaction_tests,
+  };
+
+  use super::PushDbHandle;
+
+  transaction_tests! {
+    fn create_push_record(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let push = tx.create_push_record(repo.id, Some(user.id), "refs/heads/main", "commit", SystemTime::now(), true)?;
+      assert_eq!(push.repository_id, repo.id);
+      assert_eq!(push.user_id, Some(user.id));
+      assert_eq!(push.ref_name, "refs/heads/main");
+      assert_eq!(push.commit_hash, "commit");
+      assert!(push.late);
+    }
+
+    fn list_repository_pushes(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("repo", &Repotype::Default, user.id, None)?;
+      let other = tx.create_repository("other", &Repotype::Default, user.id, None)?;
+
+      let now = SystemTime::now();
+      let second = tx.create_push_record(repo.id, Some(user.id), "refs/heads/main", "second", now, false)?;
+      let first = tx.create_push_record(repo.id, None, "refs/heads/main", "first", now - Duration::from_secs(60), false)?;
+      tx.create_push_record(other.id, Some(user.id), "refs/heads/main", "other", now, false)?;
+
+      let ids: Vec<i32> = tx.list_repository_pushes(repo.id)?.iter().map(|p| p.id).collect();
+      assert_eq!(ids, vec![first.id, second.id]);
+    }
+  }
+}
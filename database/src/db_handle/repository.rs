@@ -9,6 +9,11 @@ use crate::{db_handle::BaseDbHandle, error::DatabaseError};
 
 use super::user::User;
 
+/// The unique constraint on the names of the repositories
+pub const REPOSITORY_NAME_CONSTRAINT: &str = "repositories_name_key";
+/// The unique constraint allowing a single submission repository per student and assignment
+pub const SUBMISSION_REPOSITORY_CONSTRAINT: &str = "repositories_submission_idx";
+
 #[derive(Debug, DbEnum, PartialEq, Eq)]
 #[ExistingTypePath = "crate::schema::sql_types::Repotype"]
 pub enum Repotype {
@@ -155,7 +160,11 @@ where
 mod tests {
   use crate::{
     db_handle::{
-      repository::{RepositoryDbHandle, Repotype},
+      assignment::AssignmentDbHandle,
+      group::GroupDbHandle,
+      repository::{
+        RepositoryDbHandle, Repotype, REPOSITORY_NAME_CONSTRAINT, SUBMISSION_REPOSITORY_CONSTRAINT,
+      },
       user::UserDbHandle,
     },
     error::DatabaseError,
@@ -183,6 +192,29 @@ mod tests {
       assert_eq!(repository.owner_id, user.id);
     }
 
+    fn create_repository_with_taken_name_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("owner", "abc", "abc", None)?;
+
+      tx.create_repository("test-repo", &Repotype::Default, user.id, None)?;
+      let err = tx
+        .create_repository("test-repo", &Repotype::Default, user.id, None)
+        .expect_err("Expected error");
+      assert_eq!(err.unique_constraint(), Some(REPOSITORY_NAME_CONSTRAINT));
+    }
+
+    fn create_second_submission_repository_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("student", "abc", "abc", None)?;
+      let group = tx.create_group("group", None)?;
+      let base = tx.create_repository("base", &Repotype::Default, user.id, None)?;
+      let assignment = tx.create_assignment(group.id, base.id)?;
+
+      tx.create_repository("first", &Repotype::Default, user.id, Some(assignment.id))?;
+      let err = tx
+        .create_repository("second", &Repotype::Default, user.id, Some(assignment.id))
+        .expect_err("Expected error");
+      assert_eq!(err.unique_constraint(), Some(SUBMISSION_REPOSITORY_CONSTRAINT));
+    }
+
     fn get_unknown_repository_by_id(tx: &mut DbHandle) {
       let repository = tx.get_repository_by_id(1)?;
 
@@ -230,7 +262,7 @@ mod tests {
     }
 
     fn list_user_repositories(tx: &mut DbHandle) {
-      let repos = vec![
+      let repos = [
         ("test-repo-1", Repotype::Default),
         ("test-repo-2", Repotype::Default),
         ("test-repo-3", Repotype::Default),
@@ -0,0 +1,239 @@
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, ExpressionMethods, OptionalExtension, PgConnection,
+  QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use std::ops::DerefMut;
+
+use crate::{db_handle::BaseDbHandle, error::DatabaseError};
+
+/// A commit of a submission repository handed in for grading.
+#[derive(Debug, Queryable, Selectable, Clone, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::submissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Submission {
+  pub id: i32,
+  pub repository_id: i32,
+  pub commit_hash: String,
+  pub submitted_at: std::time::SystemTime,
+  /// Whether the submission happened after the due date of the assignment
+  pub late: bool,
+  /// When the commit was pushed, as recorded by the server, if it was
+  pub pushed_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::submissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewSubmission<'a> {
+  repository_id: i32,
+  commit_hash: &'a str,
+  submitted_at: &'a std::time::SystemTime,
+  late: bool,
+  pushed_at: Option<&'a std::time::SystemTime>,
+}
+
+pub trait SubmissionDbHandle {
+  /// Records a submission. A commit can only be submitted once per repository.
+  fn create_submission(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    submitted_at: std::time::SystemTime,
+    late: bool,
+    pushed_at: Option<std::time::SystemTime>,
+  ) -> Result<Submission, DatabaseError>;
+
+  fn get_submission_by_id(
+    &mut self,
+    submission_id: i32,
+  ) -> Result<Option<Submission>, DatabaseError>;
+
+  fn get_submission_by_commit(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+  ) -> Result<Option<Submission>, DatabaseError>;
+
+  /// Lists the submissions of the repository, oldest first
+  fn list_repository_submissions(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<Submission>, DatabaseError>;
+
+  /// Lists the submissions of all the submission repositories of the assignment, oldest first
+  fn list_assignment_submissions(
+    &mut self,
+    assignment_id: i32,
+  ) -> Result<Vec<Submission>, DatabaseError>;
+
+  fn delete_submission(&mut self, submission_id: i32) -> Result<(), DatabaseError>;
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> SubmissionDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn create_submission(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+    submitted_at: std::time::SystemTime,
+    late: bool,
+    pushed_at: Option<std::time::SystemTime>,
+  ) -> Result<Submission, DatabaseError> {
+    use crate::schema::submissions;
+
+    diesel::insert_into(submissions::table)
+      .values(&NewSubmission {
+        repository_id,
+        commit_hash,
+        submitted_at: &submitted_at,
+        late,
+        pushed_at: pushed_at.as_ref(),
+      })
+      .returning(Submission::as_returning())
+      .get_result(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_submission_by_id(
+    &mut self,
+    submission_id: i32,
+  ) -> Result<Option<Submission>, DatabaseError> {
+    use crate::schema::submissions::dsl;
+
+    dsl::submissions
+      .find(submission_id)
+      .select(Submission::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn get_submission_by_commit(
+    &mut self,
+    repository_id: i32,
+    commit_hash: &str,
+  ) -> Result<Option<Submission>, DatabaseError> {
+    use crate::schema::submissions::dsl;
+
+    dsl::submissions
+      .filter(dsl::repository_id.eq(repository_id))
+      .filter(dsl::commit_hash.eq(commit_hash))
+      .select(Submission::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_repository_submissions(
+    &mut self,
+    repository_id: i32,
+  ) -> Result<Vec<Submission>, DatabaseError> {
+    use crate::schema::submissions::dsl;
+
+    dsl::submissions
+      .filter(dsl::repository_id.eq(repository_id))
+      .order((dsl::submitted_at, dsl::id))
+      .select(Submission::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn list_assignment_submissions(
+    &mut self,
+    assignment_id: i32,
+  ) -> Result<Vec<Submission>, DatabaseError> {
+    use crate::schema::{repositories, submissions::dsl};
+
+    dsl::submissions
+      .inner_join(repositories::table)
+      .filter(repositories::assignment_id.eq(assignment_id))
+      .order((dsl::submitted_at, dsl::id))
+      .select(Submission::as_select())
+      .load(self.conn.deref_mut())
+      .map_err(DatabaseError::from)
+  }
+
+  fn delete_submission(&mut self, submission_id: i32) -> Result<(), DatabaseError> {
+    use crate::schema::submissions::dsl;
+
+    diesel::delete(dsl::submissions.find(submission_id))
+      .execute(self.conn.deref_mut())
+      .map(|_| ())
+      .map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, SystemTime};
+
+  use crate::{
+    db_handle::{
+      assignment::AssignmentDbHandle,
+      group::GroupDbHandle,
+      repository::{RepositoryDbHandle, Repotype},
+      user::UserDbHandle,
+    },
+    transaction_tests,
+  };
+
+  use super::SubmissionDbHandle;
+
+  transaction_tests! {
+    fn create_submission(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let now = SystemTime::now();
+      let submission = tx.create_submission(repo.id, "commit", now, true, Some(now - Duration::from_secs(60)))?;
+      assert_eq!(submission.repository_id, repo.id);
+      assert_eq!(submission.commit_hash, "commit");
+      assert!(submission.late);
+      assert!(submission.pushed_at.is_some_and(|pushed_at| pushed_at < now));
+
+      let found = tx.get_submission_by_id(submission.id)?.expect("Submission not found");
+      assert_eq!(found, submission);
+      let found = tx.get_submission_by_commit(repo.id, "commit")?.expect("Submission not found");
+      assert_eq!(found, submission);
+      assert!(tx.get_submission_by_commit(repo.id, "other")?.is_none());
+    }
+
+    fn create_submission_twice_fails(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+      let result = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None);
+      assert!(result.is_err_and(|e| e.is_unique_violation()));
+    }
+
+    fn list_submissions(tx: &mut DbHandle) {
+      let teacher = tx.create_user("teacher", "teacher", "password", None)?;
+      let student = tx.create_user("student", "student", "password", None)?;
+      let group = tx.create_group("group", Some(teacher.id))?;
+      let base = tx.create_repository("base", &Repotype::Default, teacher.id, None)?;
+      let assignment = tx.create_assignment(group.id, base.id)?;
+      let repo = tx.create_repository("repo", &Repotype::Default, student.id, Some(assignment.id))?;
+      let other = tx.create_repository("other", &Repotype::Default, student.id, None)?;
+
+      let now = SystemTime::now();
+      let second = tx.create_submission(repo.id, "second", now, false, None)?;
+      let first = tx.create_submission(repo.id, "first", now - Duration::from_secs(60), false, None)?;
+      tx.create_submission(other.id, "other", now, false, None)?;
+
+      let ids: Vec<i32> = tx.list_repository_submissions(repo.id)?.iter().map(|s| s.id).collect();
+      assert_eq!(ids, vec![first.id, second.id]);
+      let ids: Vec<i32> = tx.list_assignment_submissions(assignment.id)?.iter().map(|s| s.id).collect();
+      assert_eq!(ids, vec![first.id, second.id]);
+    }
+
+    fn delete_submission(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", None)?;
+      let repo = tx.create_repository("name", &Repotype::Default, user.id, None)?;
+      let submission = tx.create_submission(repo.id, "commit", SystemTime::now(), false, None)?;
+      tx.delete_submission(submission.id)?;
+      assert!(tx.get_submission_by_id(submission.id)?.is_none());
+    }
+  }
+}
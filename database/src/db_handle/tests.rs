@@ -32,7 +32,7 @@ impl DerefMut for HandleWrapper {
 
 #[rstest::fixture]
 #[once]
-fn connection_string() -> String {
+pub fn connection_string() -> String {
   dotenvy::dotenv().ok();
   let database_url = std::env::var("DATABASE_URL").unwrap();
 
@@ -47,13 +47,13 @@ fn connection_string() -> String {
 
 #[rstest::fixture]
 pub fn db_handle(connection_string: &str) -> BaseDbHandle<HandleWrapper> {
-  BaseDbHandle::<HandleWrapper>::new(&connection_string).expect("Error creating DbHandle")
+  BaseDbHandle::<HandleWrapper>::new(connection_string).expect("Error creating DbHandle")
 }
 
 #[macro_export]
 macro_rules! transaction_tests {
   {$(fn $name:ident($tx:ident : &mut DbHandle) { $($body:tt)* })*} => {
-    use crate::db_handle::{BaseDbHandle, tests::{db_handle, HandleWrapper}};
+    use $crate::db_handle::{BaseDbHandle, tests::{db_handle, HandleWrapper}};
     $(
       #[rstest::rstest]
       fn $name(db_handle: BaseDbHandle<HandleWrapper>) {
@@ -62,7 +62,7 @@ macro_rules! transaction_tests {
 
         let mut f = move || {
           $($body)*
-          Result::<(), crate::error::DatabaseError>::Ok(())
+          Result::<(), $crate::error::DatabaseError>::Ok(())
         };
 
         let err = f();
@@ -0,0 +1,98 @@
+use diesel::{
+  connection::{AnsiTransactionManager, TransactionManager},
+  PgConnection,
+};
+use std::ops::DerefMut;
+
+use crate::{db_handle::BaseDbHandle, error::DatabaseError};
+
+pub trait TransactionDbHandle {
+  fn begin_transaction(&mut self) -> Result<(), DatabaseError>;
+
+  fn commit_transaction(&mut self) -> Result<(), DatabaseError>;
+
+  fn rollback_transaction(&mut self) -> Result<(), DatabaseError>;
+
+  /// Runs the given closure inside a transaction. The transaction is committed if the closure
+  /// succeeds, and rolled back otherwise. Nested calls use savepoints.
+  fn transaction<R, E, F>(&mut self, f: F) -> Result<R, E>
+  where
+    Self: Sized,
+    E: From<DatabaseError>,
+    F: FnOnce(&mut Self) -> Result<R, E>,
+  {
+    self.begin_transaction()?;
+    match f(self) {
+      Ok(value) => {
+        self.commit_transaction()?;
+        Ok(value)
+      }
+      Err(e) => {
+        self.rollback_transaction()?;
+        Err(e)
+      }
+    }
+  }
+}
+
+#[cfg_attr(feature = "mock", faux::methods(path = "super"))]
+impl<T> TransactionDbHandle for BaseDbHandle<T>
+where
+  T: DerefMut<Target = PgConnection>,
+{
+  fn begin_transaction(&mut self) -> Result<(), DatabaseError> {
+    AnsiTransactionManager::begin_transaction(self.conn.deref_mut()).map_err(DatabaseError::from)
+  }
+
+  fn commit_transaction(&mut self) -> Result<(), DatabaseError> {
+    AnsiTransactionManager::commit_transaction(self.conn.deref_mut()).map_err(DatabaseError::from)
+  }
+
+  fn rollback_transaction(&mut self) -> Result<(), DatabaseError> {
+    AnsiTransactionManager::rollback_transaction(self.conn.deref_mut()).map_err(DatabaseError::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db_handle::{transaction::TransactionDbHandle, user::UserDbHandle},
+    error::DatabaseError,
+    transaction_tests,
+  };
+
+  transaction_tests! {
+    fn transaction_commits_on_success(tx: &mut DbHandle) {
+      let user = tx.transaction(|tx| tx.create_user("username", "email", "password", None))?;
+
+      let found = tx.get_user_by_id(user.id)?;
+      assert!(found.is_some(), "Expected user to be committed");
+    }
+
+    fn transaction_rolls_back_on_error(tx: &mut DbHandle) {
+      let res: Result<(), DatabaseError> = tx.transaction(|tx| {
+        tx.create_user("username", "email", "password", None)?;
+        Err(DatabaseError::NotFound)
+      });
+      assert!(res.is_err());
+
+      let found = tx.get_user_by_username("username")?;
+      assert!(found.is_none(), "Expected user creation to be rolled back");
+    }
+
+    fn nested_transaction_rolls_back_inner_only(tx: &mut DbHandle) {
+      tx.transaction(|tx| {
+        tx.create_user("outer", "outer-email", "password", None)?;
+        let inner: Result<(), DatabaseError> = tx.transaction(|tx| {
+          tx.create_user("inner", "inner-email", "password", None)?;
+          Err(DatabaseError::NotFound)
+        });
+        assert!(inner.is_err());
+        Result::<(), DatabaseError>::Ok(())
+      })?;
+
+      assert!(tx.get_user_by_username("outer")?.is_some());
+      assert!(tx.get_user_by_username("inner")?.is_none());
+    }
+  }
+}
@@ -29,6 +29,15 @@ pub struct NewUser<'a> {
   pub pubkey: &'a Vec<Option<String>>,
 }
 
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::password_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewPasswordToken<'a> {
+  token_hash: &'a str,
+  user_id: i32,
+  expires_at: &'a std::time::SystemTime,
+}
+
 pub trait UserDbHandle {
   fn create_user(
     &mut self,
@@ -44,8 +53,29 @@ pub trait UserDbHandle {
 
   fn get_user_by_email(&mut self, email: &str) -> Result<Option<User>, DatabaseError>;
 
+  /// Finds the user owning the public key, given as its base64 data. The keys of the users are
+  /// either stored as that data alone, or as OpenSSH lines such as `ssh-ed25519 <data> comment`.
+  fn get_user_by_public_key(&mut self, key_data: &str) -> Result<Option<User>, DatabaseError>;
+
   fn insert_user_public_key(&mut self, user_id: i32, pubkey: &str) -> Result<(), DatabaseError>;
 
+  fn set_user_password(&mut self, user_id: i32, password: &str) -> Result<(), DatabaseError>;
+
+  /// Stores the hash of a token letting the user choose a password until `expires_at`
+  fn create_password_token(
+    &mut self,
+    user_id: i32,
+    token_hash: &str,
+    expires_at: std::time::SystemTime,
+  ) -> Result<(), DatabaseError>;
+
+  /// Consumes the token, returning the id of its user if it hasn't expired at `now`
+  fn use_password_token(
+    &mut self,
+    token_hash: &str,
+    now: std::time::SystemTime,
+  ) -> Result<Option<i32>, DatabaseError>;
+
   fn delete_user(&mut self, user_id: i32) -> Result<(), DatabaseError>;
 
   fn list_teaching_groups(&mut self, user_id: i32) -> Result<Vec<Group>, DatabaseError>;
@@ -139,6 +169,26 @@ where
     }
   }
 
+  fn get_user_by_public_key(&mut self, key_data: &str) -> Result<Option<User>, DatabaseError> {
+    use crate::schema::users::dsl;
+    use diesel::{
+      dsl::sql,
+      sql_types::{Bool, Text},
+    };
+
+    let owns_key = sql::<Bool>("EXISTS (SELECT 1 FROM unnest(pubkey) AS key WHERE key = ")
+      .bind::<Text, _>(key_data)
+      .sql(" OR split_part(key, ' ', 2) = ")
+      .bind::<Text, _>(key_data)
+      .sql(")");
+    dsl::users
+      .filter(owns_key)
+      .select(User::as_select())
+      .first(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)
+  }
+
   fn insert_user_public_key(&mut self, user_id: i32, pubkey: &str) -> Result<(), DatabaseError> {
     diesel::sql_query(format!(
       "UPDATE users SET pubkey = array_append(pubkey, '{}') WHERE id = {}",
@@ -149,6 +199,53 @@ where
     .map_err(DatabaseError::from)
   }
 
+  fn set_user_password(&mut self, user_id: i32, password: &str) -> Result<(), DatabaseError> {
+    use crate::schema::users::dsl;
+
+    let updated = diesel::update(dsl::users.find(user_id))
+      .set(dsl::password.eq(password))
+      .execute(self.conn.deref_mut())
+      .map_err(DatabaseError::from)?;
+    match updated {
+      0 => Err(DatabaseError::NotFound),
+      _ => Ok(()),
+    }
+  }
+
+  fn create_password_token(
+    &mut self,
+    user_id: i32,
+    token_hash: &str,
+    expires_at: std::time::SystemTime,
+  ) -> Result<(), DatabaseError> {
+    use crate::schema::password_tokens;
+
+    diesel::insert_into(password_tokens::table)
+      .values(&NewPasswordToken {
+        token_hash,
+        user_id,
+        expires_at: &expires_at,
+      })
+      .execute(self.conn.deref_mut())
+      .map(|_| ())
+      .map_err(DatabaseError::from)
+  }
+
+  fn use_password_token(
+    &mut self,
+    token_hash: &str,
+    now: std::time::SystemTime,
+  ) -> Result<Option<i32>, DatabaseError> {
+    use crate::schema::password_tokens::dsl;
+
+    let token = diesel::delete(dsl::password_tokens.find(token_hash))
+      .returning((dsl::user_id, dsl::expires_at))
+      .get_result::<(i32, std::time::SystemTime)>(self.conn.deref_mut())
+      .optional()
+      .map_err(DatabaseError::from)?;
+    Ok(token.and_then(|(user_id, expires_at)| (now < expires_at).then_some(user_id)))
+  }
+
   fn delete_user(&mut self, user_id: i32) -> Result<(), DatabaseError> {
     use crate::schema::users::dsl::users;
 
@@ -261,6 +358,19 @@ mod tests {
       assert!(user.is_none());
     }
 
+    fn get_user_by_public_key(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "password", Some(vec!["AAAAbase"]))?;
+      tx.insert_user_public_key(user.id, "ssh-ed25519 AAAAline user@host")?;
+      tx.create_user("other", "other", "password", None)?;
+
+      let found = tx.get_user_by_public_key("AAAAbase")?.expect("User not found");
+      assert_eq!(found.id, user.id);
+      let found = tx.get_user_by_public_key("AAAAline")?.expect("User not found");
+      assert_eq!(found.id, user.id);
+      assert!(tx.get_user_by_public_key("ssh-ed25519")?.is_none());
+      assert!(tx.get_user_by_public_key("AAAAunknown")?.is_none());
+    }
+
     fn insert_user_public_key_without_existing(tx: &mut DbHandle) {
       let username = "insert_user_public_key";
       let email = "abc";
@@ -369,18 +479,40 @@ mod tests {
       let password = "abc";
 
       let user = tx.create_user(username, email, password, None)?;
-      let groups = vec![
+      let groups = [
         ("test_group_1", None),
         ("test_group_2", None),
         ("test_group_3", None),
       ];
       groups.iter().for_each(|(name, teacher_id)| {
-        let group = tx.create_group(name, teacher_id.clone()).expect("Error creating group");
+        let group = tx.create_group(name, *teacher_id).expect("Error creating group");
         tx.add_student(group.id, user.id).expect("Error adding student");
       });
       let groups = tx.list_belongs_groups(user.id)?;
 
       assert_eq!(groups.len(), 3);
     }
+
+    fn set_user_password(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "", None)?;
+      tx.set_user_password(user.id, "hash")?;
+      let user = tx.get_user_by_id(user.id)?.expect("User not found");
+      assert_eq!(user.password, "hash");
+      assert!(matches!(tx.set_user_password(user.id + 1, "hash"), Err(DatabaseError::NotFound)));
+    }
+
+    fn use_password_token(tx: &mut DbHandle) {
+      let user = tx.create_user("user", "email", "", None)?;
+      let now = std::time::SystemTime::now();
+      let tomorrow = now + std::time::Duration::from_secs(24 * 3600);
+      tx.create_password_token(user.id, "valid", tomorrow)?;
+      tx.create_password_token(user.id, "expired", now)?;
+
+      assert_eq!(tx.use_password_token("valid", now)?, Some(user.id));
+      // Tokens can only be used once
+      assert_eq!(tx.use_password_token("valid", now)?, None);
+      assert_eq!(tx.use_password_token("expired", now)?, None);
+      assert_eq!(tx.use_password_token("unknown", now)?, None);
+    }
   }
 }
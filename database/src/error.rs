@@ -18,3 +18,27 @@ pub enum DatabaseError {
   #[error("Not found")]
   NotFound,
 }
+
+impl DatabaseError {
+  /// Whether the error was caused by a unique constraint, such as a duplicated name.
+  pub fn is_unique_violation(&self) -> bool {
+    matches!(
+      self,
+      DatabaseError::DieselError(diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::UniqueViolation,
+        _
+      ))
+    )
+  }
+
+  /// The name of the unique constraint violated by the query, if that caused the error.
+  pub fn unique_constraint(&self) -> Option<&str> {
+    match self {
+      DatabaseError::DieselError(diesel::result::Error::DatabaseError(
+        diesel::result::DatabaseErrorKind::UniqueViolation,
+        info,
+      )) => info.constraint_name(),
+      _ => None,
+    }
+  }
+}
@@ -0,0 +1,95 @@
+//! Rows to return from the mocked database handles in tests, with the fields the tests rarely care
+//! about filled in. Tests needing other values override them with the struct update syntax.
+
+use std::time::SystemTime;
+
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind};
+
+use crate::{
+  db_handle::{
+    cirun::{Cirun, Status},
+    repository::{Repository, Repotype},
+    user::User,
+  },
+  error::DatabaseError,
+};
+
+/// A user whose email is derived from its username.
+pub fn user(id: i32, username: &str) -> User {
+  User {
+    id,
+    username: username.to_string(),
+    password: "password".to_string(),
+    email: format!("{}@test.com", username),
+    pubkey: vec![],
+  }
+}
+
+/// A repository named after its id.
+pub fn repository(id: i32, owner_id: i32, assignment_id: Option<i32>) -> Repository {
+  Repository {
+    id,
+    name: format!("repo-{}", id),
+    repo_type: Repotype::Default,
+    owner_id,
+    assignment_id,
+  }
+}
+
+/// A run of the `commit-<id>` commit, created at the epoch and not claimed by any worker.
+pub fn cirun(id: i32, repository_id: i32, status: Status) -> Cirun {
+  Cirun {
+    id,
+    repository_id,
+    commit: format!("commit-{}", id),
+    status,
+    created_at: SystemTime::UNIX_EPOCH,
+    started_at: None,
+    finished_at: None,
+    heartbeat_at: None,
+    ref_name: None,
+    triggered_by: None,
+    worker_id: None,
+    attempt: 0,
+  }
+}
+
+/// The error of a query violating the given unique constraint.
+pub fn unique_violation(constraint: &'static str) -> DatabaseError {
+  DatabaseError::DieselError(diesel::result::Error::DatabaseError(
+    DatabaseErrorKind::UniqueViolation,
+    Box::new(UniqueViolation(constraint)),
+  ))
+}
+
+struct UniqueViolation(&'static str);
+
+impl DatabaseErrorInformation for UniqueViolation {
+  fn message(&self) -> &str {
+    "duplicate key value violates unique constraint"
+  }
+
+  fn details(&self) -> Option<&str> {
+    None
+  }
+
+  fn hint(&self) -> Option<&str> {
+    None
+  }
+
+  fn table_name(&self) -> Option<&str> {
+    None
+  }
+
+  fn column_name(&self) -> Option<&str> {
+    None
+  }
+
+  fn constraint_name(&self) -> Option<&str> {
+    Some(self.0)
+  }
+
+  fn statement_position(&self) -> Option<i32> {
+    None
+  }
+}
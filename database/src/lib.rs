@@ -3,5 +3,8 @@ pub mod schema;
 
 pub mod connection_pool;
 pub mod db_handle;
+#[cfg(feature = "mock")]
+pub mod fixtures;
+pub mod notifications;
 
 pub use db_handle::DbHandle;
@@ -0,0 +1,150 @@
+//! Notifications sent by the database when the CI runs change status.
+//!
+//! A trigger on the `cirun` table notifies `cirun_queue` when a run is queued, which wakes the
+//! workers, and `cirun_status` whenever a run changes status. Notifications are lost while nobody
+//! listens, so the listeners still poll the table from time to time.
+
+use std::time::{Duration, Instant};
+
+use diesel::{Connection, PgConnection, RunQueryDsl};
+
+use crate::{db_handle::cirun::Status, error::DatabaseError};
+
+/// Notified with the id of each run queued, or queued again
+pub const CIRUN_QUEUE_CHANNEL: &str = "cirun_queue";
+/// Notified with the id, the repository and the status of each run changing status
+pub const CIRUN_STATUS_CHANNEL: &str = "cirun_status";
+
+/// How often the connection is checked for notifications while waiting
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CirunNotification {
+  /// A run is waiting for a worker
+  Queued { cirun_id: i32 },
+  StatusChanged {
+    cirun_id: i32,
+    repository_id: i32,
+    status: Status,
+  },
+}
+
+impl CirunNotification {
+  fn parse(channel: &str, payload: &str) -> Option<Self> {
+    match channel {
+      CIRUN_QUEUE_CHANNEL => Some(CirunNotification::Queued {
+        cirun_id: payload.parse().ok()?,
+      }),
+      CIRUN_STATUS_CHANNEL => {
+        let mut parts = payload.split(' ');
+        let cirun_id = parts.next()?.parse().ok()?;
+        let repository_id = parts.next()?.parse().ok()?;
+        let status = match parts.next()? {
+          "success" => Status::Success,
+          "pending" => Status::Pending,
+          "running" => Status::Running,
+          "cancelled" => Status::Cancelled,
+          "failed" => Status::Failed,
+          _ => return None,
+        };
+        Some(CirunNotification::StatusChanged {
+          cirun_id,
+          repository_id,
+          status,
+        })
+      }
+      _ => None,
+    }
+  }
+}
+
+/// A connection of its own listening to the notifications of the runs.
+pub struct CirunListener {
+  conn: PgConnection,
+}
+
+impl CirunListener {
+  pub fn connect(database_url: &str, channels: &[&str]) -> Result<Self, DatabaseError> {
+    let mut conn = PgConnection::establish(database_url)?;
+    for channel in channels {
+      diesel::sql_query(format!("LISTEN {}", channel)).execute(&mut conn)?;
+    }
+    Ok(CirunListener { conn })
+  }
+
+  /// Waits up to `timeout` for notifications, returning the ones received. An error means the
+  /// connection was lost, and the listener should be connected again.
+  ///
+  /// Diesel doesn't expose the socket of the connection, so it is checked at a short interval.
+  /// This only reads what the server already sent, without querying it.
+  pub fn wait(&mut self, timeout: Duration) -> Result<Vec<CirunNotification>, DatabaseError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let mut notifications = Vec::new();
+      for notification in self.conn.notifications_iter() {
+        let notification = notification?;
+        notifications.extend(CirunNotification::parse(
+          &notification.channel,
+          &notification.payload,
+        ));
+      }
+      let now = Instant::now();
+      if !notifications.is_empty() || now >= deadline {
+        return Ok(notifications);
+      }
+      std::thread::sleep(CHECK_INTERVAL.min(deadline - now));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db_handle::tests::connection_string;
+
+  #[test]
+  fn test_parse_notifications() {
+    assert_eq!(
+      CirunNotification::parse(CIRUN_QUEUE_CHANNEL, "12"),
+      Some(CirunNotification::Queued { cirun_id: 12 })
+    );
+    assert_eq!(
+      CirunNotification::parse(CIRUN_STATUS_CHANNEL, "12 3 cancelled"),
+      Some(CirunNotification::StatusChanged {
+        cirun_id: 12,
+        repository_id: 3,
+        status: Status::Cancelled
+      })
+    );
+    assert_eq!(
+      CirunNotification::parse(CIRUN_STATUS_CHANNEL, "12 3 unknown"),
+      None
+    );
+    assert_eq!(CirunNotification::parse("other", "12"), None);
+  }
+
+  #[rstest::rstest]
+  fn test_listen_to_notifications(connection_string: &str) {
+    let mut listener =
+      CirunListener::connect(connection_string, &[CIRUN_STATUS_CHANNEL]).expect("Unable to listen");
+    assert_eq!(listener.wait(Duration::from_millis(10)).unwrap(), vec![]);
+
+    let mut conn = PgConnection::establish(connection_string).unwrap();
+    diesel::sql_query(format!("NOTIFY {}, '1 2 running'", CIRUN_STATUS_CHANNEL))
+      .execute(&mut conn)
+      .unwrap();
+    // Not listened to
+    diesel::sql_query(format!("NOTIFY {}, '1'", CIRUN_QUEUE_CHANNEL))
+      .execute(&mut conn)
+      .unwrap();
+
+    assert_eq!(
+      listener.wait(Duration::from_secs(5)).unwrap(),
+      vec![CirunNotification::StatusChanged {
+        cirun_id: 1,
+        repository_id: 2,
+        status: Status::Running
+      }]
+    );
+  }
+}
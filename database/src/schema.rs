@@ -5,6 +5,18 @@ pub mod sql_types {
   #[diesel(postgres_type(name = "commentauthor"))]
   pub struct Commentauthor;
 
+  #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+  #[diesel(postgres_type(name = "commentside"))]
+  pub struct Commentside;
+
+  #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+  #[diesel(postgres_type(name = "gradesource"))]
+  pub struct Gradesource;
+
+  #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+  #[diesel(postgres_type(name = "latepolicy"))]
+  pub struct Latepolicy;
+
   #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
   #[diesel(postgres_type(name = "repotype"))]
   pub struct Repotype;
@@ -12,15 +24,46 @@ pub mod sql_types {
   #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
   #[diesel(postgres_type(name = "status"))]
   pub struct Status;
+
+  #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+  #[diesel(postgres_type(name = "stepstatus"))]
+  pub struct Stepstatus;
 }
 
 diesel::table! {
+    artifacts (id) {
+        id -> Int4,
+        cirun_id -> Int4,
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 255]
+        file_name -> Varchar,
+        #[max_length = 64]
+        content_hash -> Varchar,
+        size -> Int8,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Latepolicy;
+
     assignments (id) {
         id -> Int4,
         group_id -> Int4,
         base_repo_id -> Int4,
         test_repo_id -> Nullable<Int4>,
         correction_repo_id -> Nullable<Int4>,
+        #[max_length = 255]
+        name -> Varchar,
+        description -> Text,
+        release_date -> Nullable<Timestamp>,
+        due_date -> Nullable<Timestamp>,
+        late_policy -> Latepolicy,
+        grace_period -> Int4,
+        late_penalty -> Int4,
     }
 }
 
@@ -33,12 +76,50 @@ diesel::table! {
         repository_id -> Int4,
         commit -> Text,
         status -> Status,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        heartbeat_at -> Nullable<Timestamp>,
+        #[max_length = 255]
+        ref_name -> Nullable<Varchar>,
+        triggered_by -> Nullable<Int4>,
+        #[max_length = 255]
+        worker_id -> Nullable<Varchar>,
+        attempt -> Int4,
+    }
+}
+
+diesel::table! {
+    cirun_log_chunks (id) {
+        id -> Int4,
+        step_id -> Int4,
+        content -> Text,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Stepstatus;
+
+    cirun_steps (id) {
+        id -> Int4,
+        cirun_id -> Int4,
+        position -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        status -> Stepstatus,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        exit_code -> Nullable<Int4>,
+        log_size -> Int4,
+        log_truncated -> Bool,
     }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Commentauthor;
+    use super::sql_types::Commentside;
 
     comments (id) {
         id -> Int4,
@@ -52,6 +133,66 @@ diesel::table! {
         author_type -> Commentauthor,
         author_id -> Nullable<Int4>,
         date -> Timestamp,
+        start_line -> Nullable<Int4>,
+        end_line -> Nullable<Int4>,
+        side -> Nullable<Commentside>,
+        #[max_length = 64]
+        context_hash -> Nullable<Varchar>,
+        outdated -> Bool,
+        edited_at -> Nullable<Timestamp>,
+        resolved -> Bool,
+        resolved_by -> Nullable<Int4>,
+        resolved_at -> Nullable<Timestamp>,
+        #[max_length = 255]
+        anchor_commit -> Nullable<Varchar>,
+        anchor_start_line -> Nullable<Int4>,
+        anchor_end_line -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    comment_edits (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        previous_message -> Text,
+        date -> Timestamp,
+    }
+}
+
+diesel::table! {
+    comment_reactions (comment_id, user_id, reaction) {
+        comment_id -> Int4,
+        user_id -> Int4,
+        #[max_length = 32]
+        reaction -> Varchar,
+    }
+}
+
+diesel::table! {
+    grade_criteria (id) {
+        id -> Int4,
+        grade_id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        score -> Float8,
+        max_score -> Float8,
+        comment -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Gradesource;
+
+    grades (id) {
+        id -> Int4,
+        submission_id -> Int4,
+        score -> Float8,
+        max_score -> Float8,
+        source -> Gradesource,
+        grader_id -> Nullable<Int4>,
+        date -> Timestamp,
+        cirun_id -> Nullable<Int4>,
     }
 }
 
@@ -70,6 +211,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    password_tokens (token_hash) {
+        #[max_length = 64]
+        token_hash -> Varchar,
+        user_id -> Int4,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pushes (id) {
+        id -> Int4,
+        repository_id -> Int4,
+        user_id -> Nullable<Int4>,
+        ref_name -> Text,
+        #[max_length = 255]
+        commit_hash -> Varchar,
+        pushed_at -> Timestamp,
+        late -> Bool,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Repotype;
@@ -84,6 +247,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    submissions (id) {
+        id -> Int4,
+        repository_id -> Int4,
+        #[max_length = 255]
+        commit_hash -> Varchar,
+        submitted_at -> Timestamp,
+        late -> Bool,
+        pushed_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -94,21 +269,46 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(artifacts -> cirun (cirun_id));
 diesel::joinable!(assignments -> groups (group_id));
 diesel::joinable!(cirun -> repositories (repository_id));
+diesel::joinable!(cirun -> users (triggered_by));
+diesel::joinable!(cirun_log_chunks -> cirun_steps (step_id));
+diesel::joinable!(cirun_steps -> cirun (cirun_id));
+diesel::joinable!(comment_edits -> comments (comment_id));
+diesel::joinable!(comment_reactions -> comments (comment_id));
+diesel::joinable!(comment_reactions -> users (user_id));
 diesel::joinable!(comments -> repositories (repository_id));
 diesel::joinable!(comments -> users (author_id));
+diesel::joinable!(grade_criteria -> grades (grade_id));
+diesel::joinable!(grades -> cirun (cirun_id));
+diesel::joinable!(grades -> submissions (submission_id));
+diesel::joinable!(grades -> users (grader_id));
 diesel::joinable!(group_students -> groups (group_id));
 diesel::joinable!(group_students -> users (student_id));
 diesel::joinable!(groups -> users (teacher_id));
+diesel::joinable!(password_tokens -> users (user_id));
+diesel::joinable!(pushes -> repositories (repository_id));
+diesel::joinable!(pushes -> users (user_id));
 diesel::joinable!(repositories -> users (owner_id));
+diesel::joinable!(submissions -> repositories (repository_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+  artifacts,
   assignments,
   cirun,
+  cirun_log_chunks,
+  cirun_steps,
+  comment_edits,
+  comment_reactions,
   comments,
+  grade_criteria,
+  grades,
   group_students,
   groups,
+  password_tokens,
+  pushes,
   repositories,
+  submissions,
   users,
 );
@@ -12,6 +12,8 @@ pub enum GitProcessError {
   RepositoryNotFoundError,
   #[error("Permission denied error")]
   PermissionDeniedError,
+  #[error("Push rejected: {0}")]
+  PushRejectedError(String),
   #[error("IO error: {0}")]
   IoError(#[from] std::io::Error),
 }
@@ -24,6 +26,7 @@ impl GitProcessError {
       GitProcessError::ParseFailureError(_) => "Invalid command",
       GitProcessError::RepositoryNotFoundError => "Repository not found",
       GitProcessError::PermissionDeniedError => "Permission denied",
+      GitProcessError::PushRejectedError(message) => message,
       GitProcessError::IoError(_) => "IO error",
     }
   }
@@ -52,7 +55,11 @@ mod tests {
       "Permission denied"
     );
     assert_eq!(
-      GitProcessError::IoError(std::io::Error::new(std::io::ErrorKind::Other, "test")).message(),
+      GitProcessError::PushRejectedError("Too late".to_string()).message(),
+      "Too late"
+    );
+    assert_eq!(
+      GitProcessError::IoError(std::io::Error::other("test")).message(),
       "IO error"
     );
   }
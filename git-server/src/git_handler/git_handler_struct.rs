@@ -1,4 +1,8 @@
-use std::{marker::PhantomData, process::Stdio};
+use std::{
+  marker::PhantomData,
+  process::Stdio,
+  sync::{Arc, Mutex},
+};
 
 use log::debug;
 use ssh_server::{
@@ -11,9 +15,10 @@ use tokio::{io::AsyncWrite, process::Command};
 use crate::{
   error::GitProcessError,
   get_permission,
-  git_process::GitProcess,
+  git_process::{GitProcess, OnSuccess},
   is_command_allowed, parse_command,
-  repository::{Repository, RepositoryProvider},
+  push::{PushParser, PushRecorder},
+  repository::{PushCheck, Repository, RepositoryPermission, RepositoryProvider},
   GitHandlerConfig,
 };
 
@@ -60,10 +65,21 @@ where
       .ok_or(GitProcessError::RepositoryNotFoundError)?;
 
     let permission = get_permission(&command)?;
+    let is_push = permission == RepositoryPermission::Write;
     if !repository.has_permission(user, permission) {
       return Err(GitProcessError::PermissionDeniedError);
     }
 
+    let notice = if is_push {
+      match repository.check_push(user) {
+        PushCheck::Accept => None,
+        PushCheck::Notify(message) => Some(message),
+        PushCheck::Reject(message) => return Err(GitProcessError::PushRejectedError(message)),
+      }
+    } else {
+      None
+    };
+
     // based on config, use git command or use binaries
     let mut process = if self.config.use_git_command {
       let mut cmd = Command::new("git");
@@ -73,6 +89,14 @@ where
       Command::new(&command)
     };
 
+    if is_push {
+      // Lets the clients send options with `git push -o`
+      process
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "receive.advertisePushOptions")
+        .env("GIT_CONFIG_VALUE_0", "true");
+    }
+
     debug!("Starting process: {}", &command);
 
     let mut process = process
@@ -83,7 +107,17 @@ where
 
     let stdin = process.stdin.take().unwrap();
 
-    GitProcess::forward_output(process, handle, channel_id);
+    if !is_push {
+      GitProcess::forward_output(process, handle, channel_id, notice, None);
+      return Ok(Box::pin(stdin));
+    }
+
+    // The request is read on its way to git, to tell the repository what the push updated
+    let parser = Arc::new(Mutex::new(PushParser::default()));
+    let stdin = PushRecorder::new(stdin, parser.clone());
+    let on_success: OnSuccess =
+      Box::new(move || repository.after_push(parser.lock().unwrap().push()));
+    GitProcess::forward_output(process, handle, channel_id, notice, Some(on_success));
 
     Ok(Box::pin(stdin))
   }
@@ -143,6 +177,7 @@ mod tests {
     let repo_provider = SimpleRepositoryProvider {
       find_repository: false,
       has_permission: false,
+      reject_push: None,
     };
     let handler = GitHandler::new(config, repo_provider);
 
@@ -166,6 +201,7 @@ mod tests {
     let repo_provider = SimpleRepositoryProvider {
       find_repository: false,
       has_permission: false,
+      reject_push: None,
     };
     let handler = GitHandler::new(config, repo_provider);
 
@@ -189,6 +225,7 @@ mod tests {
     let repo_provider = SimpleRepositoryProvider {
       find_repository: true,
       has_permission: false,
+      reject_push: None,
     };
     let handler = GitHandler::new(config, repo_provider);
 
@@ -203,4 +240,33 @@ mod tests {
       result
     );
   }
+
+  #[test]
+  fn when_push_rejected_then_reject_with_message() {
+    let config = GitHandlerConfig {
+      use_git_command: false,
+    };
+    let repo_provider = SimpleRepositoryProvider {
+      find_repository: true,
+      has_permission: true,
+      reject_push: Some("The deadline has passed".to_string()),
+    };
+    let handler = GitHandler::new(config, repo_provider);
+
+    let user = SimpleUser;
+    let handle = MockHandle;
+    let channel_id = 0;
+
+    let result = handler.handle(
+      &user,
+      handle,
+      channel_id,
+      "git-receive-pack '/path/to/repo'",
+    );
+    assert!(
+      matches!(&result, HandlerResult::Rejected(message) if message == "The deadline has passed"),
+      "Expected HandlerResult::Rejected, got {:?}",
+      result
+    );
+  }
 }
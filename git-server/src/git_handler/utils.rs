@@ -70,8 +70,8 @@ mod tests {
 
   #[test]
   fn test_is_command_allowed() {
-    assert_eq!(is_command_allowed("git-upload-pack"), true);
-    assert_eq!(is_command_allowed("git-receive-pack"), true);
-    assert_eq!(is_command_allowed("invalid-command"), false);
+    assert!(is_command_allowed("git-upload-pack"));
+    assert!(is_command_allowed("git-receive-pack"));
+    assert!(!is_command_allowed("invalid-command"));
   }
 }
@@ -3,6 +3,12 @@ use russh::CryptoVec;
 use ssh_server::wrapper::HandleWrapper;
 use tokio::{io::AsyncReadExt, process::Child};
 
+/// Stream used to send messages to the client, displayed by git alongside its own output.
+const STDERR: u32 = 1;
+
+/// Called once the process has exited successfully, returning a message to send to the client.
+pub(crate) type OnSuccess = Box<dyn FnOnce() -> Option<String> + Send + Sync>;
+
 /// A struct representing a git process.
 ///
 /// This struct is used to forward the output of the git process to the client.
@@ -14,6 +20,8 @@ where
   process: Child,
   handle: HW,
   channel_id: CId,
+  notice: Option<String>,
+  on_success: Option<OnSuccess>,
 }
 
 impl<CId, HW> GitProcess<CId, HW>
@@ -22,11 +30,23 @@ where
   HW: HandleWrapper<ChannelId = CId> + Sync + Send + 'static,
 {
   /// Forwards the output of the git process to the client.
-  pub(crate) fn forward_output(process: Child, handle: HW, channel_id: CId) {
+  ///
+  /// The notice, if any, is sent to the client before the output, and `on_success` is called
+  /// once the process has exited successfully, on a thread where blocking is allowed. The message
+  /// it returns is sent after the output.
+  pub(crate) fn forward_output(
+    process: Child,
+    handle: HW,
+    channel_id: CId,
+    notice: Option<String>,
+    on_success: Option<OnSuccess>,
+  ) {
     let git_process = GitProcess {
       process,
       handle,
       channel_id,
+      notice,
+      on_success,
     };
     git_process.forward_output_inner();
   }
@@ -36,6 +56,10 @@ where
     let mut git_stdout = self.process.stdout.take().unwrap();
 
     tokio::spawn(async move {
+      if let Some(notice) = self.notice.take() {
+        self.stderr(&notice).await?;
+      }
+
       const BUF_SIZE: usize = 1024 * 32;
       let mut buf = [0u8; BUF_SIZE];
       loop {
@@ -57,6 +81,17 @@ where
         })?
         .code()
         .unwrap_or(128) as u32;
+      if status == 0 {
+        if let Some(on_success) = self.on_success.take() {
+          // The callback queries the database and runs git, which would block the runtime
+          let message = tokio::task::spawn_blocking(on_success).await.map_err(|e| {
+            error!("Error running the post-push callback: {}", e);
+          })?;
+          if let Some(message) = message {
+            self.stderr(&message).await?;
+          }
+        }
+      }
       self.exit_status(status).await?;
 
       self.eof().await?;
@@ -83,6 +118,18 @@ where
     })?;
     Ok(())
   }
+  /// Sends a message to the client, on its own line.
+  async fn stderr(&self, message: &str) -> Result<(), ()> {
+    let buf = CryptoVec::from_slice(format!("{}\n", message.trim_end()).as_bytes());
+    self
+      .handle
+      .extended_data(self.channel_id, STDERR, buf)
+      .await
+      .map_err(|_| {
+        error!("Failed to write message to channel");
+      })?;
+    Ok(())
+  }
   /// Sets the exit status of the process.
   async fn exit_status(&self, status: u32) -> Result<(), ()> {
     self
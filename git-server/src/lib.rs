@@ -2,6 +2,8 @@ pub mod error;
 mod git_handler;
 mod git_handler_config;
 pub(crate) mod git_process;
+pub mod objects;
+pub mod push;
 pub mod repository;
 
 pub use crate::git_handler::*;
@@ -0,0 +1,927 @@
+use std::path::Path;
+
+use git2::{
+  BranchType, Delta, DiffFindOptions, DiffLineType, ErrorCode, ObjectType, Oid, Patch, Repository,
+  Sort,
+};
+
+use super::{
+  Blob, BlobContent, CommitInfo, CommitPage, Diff, DiffHunk, DiffLine, DiffLineKind, DiffOptions,
+  EntryKind, FileDiff, FileStatus, GitRef, ObjectsError, RefKind, Signature, TreeEntry,
+};
+
+/// Read-only view over the objects of a repository on disk.
+pub struct GitObjects {
+  repo: Repository,
+}
+
+impl GitObjects {
+  /// Opens the repository stored at the given path, bare or not.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ObjectsError> {
+    match Repository::open(path) {
+      Ok(repo) => Ok(GitObjects { repo }),
+      Err(e) if e.code() == ErrorCode::NotFound => Err(ObjectsError::RepositoryNotFound),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  /// Lists the branches and the tags of the repository, sorted by name.
+  pub fn list_refs(&self) -> Result<Vec<GitRef>, ObjectsError> {
+    let mut refs = Vec::new();
+
+    for branch in self.repo.branches(Some(BranchType::Local))? {
+      let (branch, _) = branch?;
+      let (Some(name), Ok(commit)) = (branch.name()?, branch.get().peel_to_commit()) else {
+        continue;
+      };
+      refs.push(GitRef {
+        name: name.to_string(),
+        kind: RefKind::Branch,
+        target: commit.id().to_string(),
+      });
+    }
+
+    self.repo.tag_foreach(|oid, name| {
+      let name = String::from_utf8_lossy(name);
+      // Annotated tags point to a tag object, which in turn points to the commit
+      let target = self
+        .repo
+        .find_object(oid, None)
+        .and_then(|o| o.peel_to_commit())
+        .map(|c| c.id())
+        .unwrap_or(oid);
+      refs.push(GitRef {
+        name: name.trim_start_matches("refs/tags/").to_string(),
+        kind: RefKind::Tag,
+        target: target.to_string(),
+      });
+      true
+    })?;
+
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(refs)
+  }
+
+  /// Resolves a revision (branch, tag, commit id...) to the id of a commit.
+  pub fn resolve(&self, rev: &str) -> Result<String, ObjectsError> {
+    Ok(self.find_commit(rev)?.id().to_string())
+  }
+
+  /// Whether `ancestor` is part of the history of `rev`, or is `rev` itself. Unknown revisions
+  /// are not part of any history.
+  pub fn is_ancestor(&self, ancestor: &str, rev: &str) -> Result<bool, ObjectsError> {
+    let (Ok(ancestor), Ok(commit)) = (self.find_commit(ancestor), self.find_commit(rev)) else {
+      return Ok(false);
+    };
+    Ok(ancestor.id() == commit.id() || self.repo.graph_descendant_of(commit.id(), ancestor.id())?)
+  }
+
+  /// Lists the entries of the directory at `path` for the given revision. Directories come first,
+  /// then files, each sorted by name.
+  pub fn read_tree(&self, rev: &str, path: &str) -> Result<Vec<TreeEntry>, ObjectsError> {
+    let path = normalize_path(path);
+    let root = self.find_commit(rev)?.tree()?;
+    let tree = if path.is_empty() {
+      root
+    } else {
+      let entry = root
+        .get_path(Path::new(path))
+        .map_err(|_| ObjectsError::PathNotFound(path.to_string()))?;
+      if entry.kind() != Some(ObjectType::Tree) {
+        return Err(ObjectsError::NotATree(path.to_string()));
+      }
+      self.repo.find_tree(entry.id())?
+    };
+
+    let mut entries = Vec::new();
+    for entry in tree.iter() {
+      let name = String::from_utf8_lossy(entry.name_bytes()).to_string();
+      let (kind, size) = match entry.kind() {
+        Some(ObjectType::Tree) => (EntryKind::Tree, None),
+        Some(ObjectType::Blob) => (
+          EntryKind::Blob,
+          Some(self.repo.find_blob(entry.id())?.size()),
+        ),
+        // Submodules are stored as commits of another repository
+        _ => (EntryKind::Submodule, None),
+      };
+      entries.push(TreeEntry {
+        path: join_path(path, &name),
+        name,
+        kind,
+        mode: entry.filemode(),
+        id: entry.id().to_string(),
+        size,
+      });
+    }
+
+    entries.sort_by(|a, b| {
+      (a.kind != EntryKind::Tree)
+        .cmp(&(b.kind != EntryKind::Tree))
+        .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(entries)
+  }
+
+  /// Reads the file at `path` for the given revision. The content is only returned for text files
+  /// no larger than `max_size` bytes.
+  pub fn read_blob(&self, rev: &str, path: &str, max_size: usize) -> Result<Blob, ObjectsError> {
+    let path = normalize_path(path);
+    let entry = self
+      .find_commit(rev)?
+      .tree()?
+      .get_path(Path::new(path))
+      .map_err(|_| ObjectsError::PathNotFound(path.to_string()))?;
+    if entry.kind() != Some(ObjectType::Blob) {
+      return Err(ObjectsError::NotABlob(path.to_string()));
+    }
+
+    let blob = self.repo.find_blob(entry.id())?;
+    let content = if blob.size() > max_size {
+      BlobContent::TooLarge
+    } else if blob.is_binary() {
+      BlobContent::Binary
+    } else {
+      match std::str::from_utf8(blob.content()) {
+        Ok(text) => BlobContent::Text(text.to_string()),
+        Err(_) => BlobContent::Binary,
+      }
+    };
+
+    Ok(Blob {
+      id: blob.id().to_string(),
+      path: path.to_string(),
+      size: blob.size(),
+      content,
+    })
+  }
+
+  /// Lists the commits reachable from the revision, newest first, skipping the first `skip` ones.
+  pub fn log(&self, rev: &str, skip: usize, limit: usize) -> Result<CommitPage, ObjectsError> {
+    let start = self.find_commit(rev)?;
+
+    let mut walk = self.repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    walk.push(start.id())?;
+
+    let mut commits = Vec::new();
+    let mut has_more = false;
+    for oid in walk.skip(skip) {
+      if commits.len() == limit {
+        has_more = true;
+        break;
+      }
+      commits.push(self.commit_info(oid?)?);
+    }
+
+    Ok(CommitPage { commits, has_more })
+  }
+
+  /// Computes the changes described by `spec`: either a single revision, compared to its first
+  /// parent, a `base..head` range, or a `base...head` range compared to their merge base. Files
+  /// past the limits of the options are left out, and the diff is flagged as truncated.
+  pub fn diff(&self, spec: &str, options: &DiffOptions) -> Result<Diff, ObjectsError> {
+    if let Some((base, head)) = split_range(spec, "...")? {
+      let base = self.find_commit(base)?;
+      let head = self.find_commit(head)?;
+      let merge_base = self
+        .repo
+        .merge_base(base.id(), head.id())
+        .map_err(|_| ObjectsError::InvalidRange(format!("{} (no common ancestor)", spec)))?;
+      let merge_base = self.repo.find_commit(merge_base)?;
+      return self.diff_commits(Some(&merge_base), &head, options);
+    }
+    match split_range(spec, "..")? {
+      Some((base, head)) => self.diff_range(base, head, options),
+      None => {
+        let commit = self.find_commit(spec)?;
+        let base = match commit.parent_ids().next() {
+          Some(parent) => Some(self.repo.find_commit(parent)?),
+          None => None,
+        };
+        self.diff_commits(base.as_ref(), &commit, options)
+      }
+    }
+  }
+
+  /// Computes the changes between the trees of two revisions.
+  pub fn diff_range(
+    &self,
+    base: &str,
+    head: &str,
+    options: &DiffOptions,
+  ) -> Result<Diff, ObjectsError> {
+    let base = self.find_commit(base)?;
+    let head = self.find_commit(head)?;
+    self.diff_commits(Some(&base), &head, options)
+  }
+
+  fn diff_commits(
+    &self,
+    base: Option<&git2::Commit<'_>>,
+    head: &git2::Commit<'_>,
+    options: &DiffOptions,
+  ) -> Result<Diff, ObjectsError> {
+    let base_tree = base.map(|c| c.tree()).transpose()?;
+    let head_tree = head.tree()?;
+
+    let mut diff_options = git2::DiffOptions::new();
+    diff_options
+      .context_lines(options.context_lines)
+      .ignore_whitespace(options.ignore_whitespace);
+    let mut diff = self.repo.diff_tree_to_tree(
+      base_tree.as_ref(),
+      Some(&head_tree),
+      Some(&mut diff_options),
+    )?;
+    if options.detect_renames {
+      diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    }
+
+    let mut files = Vec::new();
+    let mut size = 0;
+    let mut truncated = false;
+    for index in 0..diff.deltas().len() {
+      let Some(file) = file_diff(&diff, index)? else {
+        continue;
+      };
+      size += file
+        .hunks
+        .iter()
+        .flat_map(|hunk| &hunk.lines)
+        .map(|line| line.content.len())
+        .sum::<usize>();
+      if files.len() == options.max_files || size > options.max_size {
+        truncated = true;
+        break;
+      }
+      files.push(file);
+    }
+
+    Ok(Diff {
+      base: base.map(|c| c.id().to_string()),
+      head: head.id().to_string(),
+      files,
+      truncated,
+    })
+  }
+
+  /// Hashes the content of the lines `start..=end` (starting at 1) of the file at `path`, so
+  /// that they can be found again in later revisions with `find_lines`.
+  pub fn hash_lines(
+    &self,
+    rev: &str,
+    path: &str,
+    start: usize,
+    end: usize,
+  ) -> Result<String, ObjectsError> {
+    let content = self.read_text(rev, path)?;
+    let lines: Vec<_> = content.lines().collect();
+    if start == 0 || start > end || end > lines.len() {
+      return Err(ObjectsError::InvalidLineRange(start, end));
+    }
+    Ok(hash_lines(&lines[start - 1..end]))
+  }
+
+  /// Looks for `count` consecutive lines of the file at `path` matching a hash computed by
+  /// `hash_lines`. Returns the first line of the closest match to `hint`, if any.
+  pub fn find_lines(
+    &self,
+    rev: &str,
+    path: &str,
+    hash: &str,
+    count: usize,
+    hint: usize,
+  ) -> Result<Option<usize>, ObjectsError> {
+    let content = match self.read_text(rev, path) {
+      Ok(content) => content,
+      Err(ObjectsError::PathNotFound(_) | ObjectsError::NotABlob(_)) => return Ok(None),
+      Err(e) => return Err(e),
+    };
+    let lines: Vec<_> = content.lines().collect();
+    if count == 0 || count > lines.len() {
+      return Ok(None);
+    }
+
+    Ok(
+      lines
+        .windows(count)
+        .enumerate()
+        .filter(|(_, window)| hash_lines(window) == hash)
+        .map(|(i, _)| i + 1)
+        .min_by_key(|start| start.abs_diff(hint)),
+    )
+  }
+
+  fn read_text(&self, rev: &str, path: &str) -> Result<String, ObjectsError> {
+    let blob = self.read_blob(rev, path, usize::MAX)?;
+    match blob.content {
+      BlobContent::Text(content) => Ok(content),
+      _ => Err(ObjectsError::NotABlob(path.to_string())),
+    }
+  }
+
+  fn commit_info(&self, oid: Oid) -> Result<CommitInfo, ObjectsError> {
+    let commit = self.repo.find_commit(oid)?;
+    let signature = |s: git2::Signature| Signature {
+      name: String::from_utf8_lossy(s.name_bytes()).to_string(),
+      email: String::from_utf8_lossy(s.email_bytes()).to_string(),
+      time: s.when().seconds(),
+    };
+
+    Ok(CommitInfo {
+      id: commit.id().to_string(),
+      parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+      author: signature(commit.author()),
+      committer: signature(commit.committer()),
+      summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).to_string(),
+      message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+    })
+  }
+
+  fn find_commit(&self, rev: &str) -> Result<git2::Commit<'_>, ObjectsError> {
+    let not_found = || ObjectsError::RevisionNotFound(rev.to_string());
+    // Revisions starting with a dash could be mistaken for options by other git tools
+    if rev.is_empty() || rev.starts_with('-') {
+      return Err(not_found());
+    }
+
+    self
+      .repo
+      .revparse_single(rev)
+      .and_then(|o| o.peel_to_commit())
+      .map_err(|_| not_found())
+  }
+}
+
+/// Splits a `base..head` range on the separator, refusing ranges with a missing side.
+fn split_range<'a>(
+  spec: &'a str,
+  separator: &str,
+) -> Result<Option<(&'a str, &'a str)>, ObjectsError> {
+  match spec.split_once(separator) {
+    Some((base, head)) if base.is_empty() || head.is_empty() => Err(ObjectsError::InvalidRange(
+      format!("{} (both sides of the range are required)", spec),
+    )),
+    range => Ok(range),
+  }
+}
+
+fn hash_lines(lines: &[&str]) -> String {
+  let content = lines.join("\n");
+  Oid::hash_object(ObjectType::Blob, content.as_bytes())
+    .map(|oid| oid.to_string())
+    .unwrap_or_default()
+}
+
+/// Builds the diff of the file at `index`, or `None` when it has no visible change left, which
+/// happens when only whitespace changed and whitespace is ignored.
+fn file_diff(diff: &git2::Diff<'_>, index: usize) -> Result<Option<FileDiff>, ObjectsError> {
+  let Some(delta) = diff.get_delta(index) else {
+    return Ok(None);
+  };
+  let status = match delta.status() {
+    Delta::Added => FileStatus::Added,
+    Delta::Deleted => FileStatus::Deleted,
+    Delta::Renamed => FileStatus::Renamed,
+    Delta::Copied => FileStatus::Copied,
+    Delta::Typechange => FileStatus::TypeChanged,
+    _ => FileStatus::Modified,
+  };
+  let path = |file: git2::DiffFile<'_>| {
+    file
+      .path_bytes()
+      .map(|p| String::from_utf8_lossy(p).to_string())
+  };
+  let old_path = (status != FileStatus::Added)
+    .then(|| path(delta.old_file()))
+    .flatten();
+  let new_path = (status != FileStatus::Deleted)
+    .then(|| path(delta.new_file()))
+    .flatten();
+
+  let mut file = FileDiff {
+    old_path,
+    new_path,
+    status,
+    binary: false,
+    additions: 0,
+    deletions: 0,
+    hunks: Vec::new(),
+  };
+
+  let patch = Patch::from_diff(diff, index)?;
+  match patch {
+    Some(patch) if !patch.delta().flags().is_binary() => {
+      for hunk_index in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_index)?;
+        let mut lines = Vec::with_capacity(line_count);
+        for line_index in 0..line_count {
+          let line = patch.line_in_hunk(hunk_index, line_index)?;
+          let kind = match line.origin_value() {
+            DiffLineType::Addition => DiffLineKind::Addition,
+            DiffLineType::Deletion => DiffLineKind::Deletion,
+            DiffLineType::Context => DiffLineKind::Context,
+            // End of file markers are not actual lines
+            _ => continue,
+          };
+          match kind {
+            DiffLineKind::Addition => file.additions += 1,
+            DiffLineKind::Deletion => file.deletions += 1,
+            DiffLineKind::Context => {}
+          }
+          let content = String::from_utf8_lossy(line.content());
+          lines.push(DiffLine {
+            kind,
+            old_line: line.old_lineno(),
+            new_line: line.new_lineno(),
+            content: content.trim_end_matches(['\n', '\r']).to_string(),
+          });
+        }
+        file.hunks.push(DiffHunk {
+          header: String::from_utf8_lossy(hunk.header())
+            .trim_end()
+            .to_string(),
+          old_start: hunk.old_start(),
+          old_lines: hunk.old_lines(),
+          new_start: hunk.new_start(),
+          new_lines: hunk.new_lines(),
+          lines,
+        });
+      }
+    }
+    _ => file.binary = true,
+  }
+
+  if status == FileStatus::Modified && !file.binary && file.hunks.is_empty() {
+    return Ok(None);
+  }
+  Ok(Some(file))
+}
+
+fn normalize_path(path: &str) -> &str {
+  path.trim_matches('/')
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+  if parent.is_empty() {
+    name.to_string()
+  } else {
+    format!("{}/{}", parent, name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use git2::{Signature as GitSignature, Time};
+  use tempfile::TempDir;
+
+  /// Commits the given files on top of `main`, replacing the whole tree
+  fn commit(repo: &Repository, files: &[(&str, &[u8])], message: &str, time: i64) -> Oid {
+    let mut index = git2::Index::new().unwrap();
+    for (path, content) in files.iter() {
+      let oid = repo.blob(content).unwrap();
+      let entry = git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: content.len() as u32,
+        id: oid,
+        flags: 0,
+        flags_extended: 0,
+        path: path.as_bytes().to_vec(),
+      };
+      index.add(&entry).unwrap();
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+    let signature = GitSignature::new("John", "john@example.com", &Time::new(time, 0)).unwrap();
+    let parent = repo
+      .find_reference("refs/heads/main")
+      .ok()
+      .map(|r| r.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+    repo
+      .commit(
+        Some("refs/heads/main"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+      )
+      .unwrap()
+  }
+
+  /// Creates a bare repository with the following history on `main`:
+  /// - "Initial commit": `README.md`
+  /// - "Add sources": `src/main.rs`, `image.bin`, tagged `v1` (annotated)
+  fn fixture() -> (TempDir, Vec<Oid>) {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let repo = Repository::init_bare(dir.path()).expect("Unable to init repository");
+
+    let commits = vec![
+      commit(
+        &repo,
+        &[("README.md", b"# Readme\n")],
+        "Initial commit",
+        1000,
+      ),
+      commit(
+        &repo,
+        &[
+          ("README.md", b"# Readme\n"),
+          ("src/main.rs", b"fn main() {}\n"),
+          ("image.bin", &[0, 159, 146, 150]),
+        ],
+        "Add sources\n\nWith details",
+        1001,
+      ),
+    ];
+
+    let signature = GitSignature::new("John", "john@example.com", &Time::new(2000, 0)).unwrap();
+    let target = repo.find_object(commits[1], None).unwrap();
+    repo
+      .tag("v1", &target, &signature, "Version 1", false)
+      .unwrap();
+    repo.set_head("refs/heads/main").unwrap();
+
+    (dir, commits)
+  }
+
+  #[test]
+  fn test_open_missing_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let res = GitObjects::open(dir.path().join("missing"));
+    assert!(matches!(res, Err(ObjectsError::RepositoryNotFound)));
+  }
+
+  #[test]
+  fn test_list_refs() {
+    let (dir, commits) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let refs = objects.list_refs().unwrap();
+    assert_eq!(
+      refs,
+      vec![
+        GitRef {
+          name: "main".to_string(),
+          kind: RefKind::Branch,
+          target: commits[1].to_string(),
+        },
+        GitRef {
+          name: "v1".to_string(),
+          kind: RefKind::Tag,
+          target: commits[1].to_string(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_resolve() {
+    let (dir, commits) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    assert_eq!(objects.resolve("main").unwrap(), commits[1].to_string());
+    assert_eq!(objects.resolve("v1").unwrap(), commits[1].to_string());
+    assert_eq!(objects.resolve("HEAD~1").unwrap(), commits[0].to_string());
+    assert!(matches!(
+      objects.resolve("unknown"),
+      Err(ObjectsError::RevisionNotFound(_))
+    ));
+    assert!(matches!(
+      objects.resolve("--all"),
+      Err(ObjectsError::RevisionNotFound(_))
+    ));
+  }
+
+  #[test]
+  fn test_is_ancestor() {
+    let (dir, commits) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+    let (first, second) = (commits[0].to_string(), commits[1].to_string());
+
+    assert!(objects.is_ancestor(&first, "main").unwrap());
+    assert!(objects.is_ancestor(&second, "main").unwrap());
+    assert!(!objects.is_ancestor(&second, &first).unwrap());
+    assert!(!objects.is_ancestor("unknown", "main").unwrap());
+  }
+
+  #[test]
+  fn test_read_tree() {
+    let (dir, _) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let entries = objects.read_tree("main", "").unwrap();
+    let names: Vec<_> = entries.iter().map(|e| (e.path.as_str(), e.kind)).collect();
+    assert_eq!(
+      names,
+      vec![
+        ("src", EntryKind::Tree),
+        ("README.md", EntryKind::Blob),
+        ("image.bin", EntryKind::Blob),
+      ]
+    );
+    assert_eq!(entries[1].size, Some(9));
+
+    let entries = objects.read_tree("main", "/src/").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, "src/main.rs");
+
+    assert!(matches!(
+      objects.read_tree("main", "README.md"),
+      Err(ObjectsError::NotATree(_))
+    ));
+    assert!(matches!(
+      objects.read_tree("HEAD~1", "src"),
+      Err(ObjectsError::PathNotFound(_))
+    ));
+  }
+
+  #[test]
+  fn test_read_blob() {
+    let (dir, _) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let blob = objects.read_blob("main", "src/main.rs", 1024).unwrap();
+    assert_eq!(
+      blob.content,
+      BlobContent::Text("fn main() {}\n".to_string())
+    );
+    assert_eq!(blob.size, 13);
+
+    let blob = objects.read_blob("main", "image.bin", 1024).unwrap();
+    assert_eq!(blob.content, BlobContent::Binary);
+
+    let blob = objects.read_blob("main", "src/main.rs", 4).unwrap();
+    assert_eq!(blob.content, BlobContent::TooLarge);
+
+    assert!(matches!(
+      objects.read_blob("main", "src", 1024),
+      Err(ObjectsError::NotABlob(_))
+    ));
+  }
+
+  #[test]
+  fn test_log() {
+    let (dir, commits) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let page = objects.log("main", 0, 1).unwrap();
+    assert!(page.has_more);
+    assert_eq!(page.commits.len(), 1);
+    let commit = &page.commits[0];
+    assert_eq!(commit.id, commits[1].to_string());
+    assert_eq!(commit.parents, vec![commits[0].to_string()]);
+    assert_eq!(commit.summary, "Add sources");
+    assert_eq!(commit.message, "Add sources\n\nWith details");
+    assert_eq!(commit.author.name, "John");
+    assert_eq!(commit.author.time, 1001);
+
+    let page = objects.log("main", 1, 1).unwrap();
+    assert!(!page.has_more);
+    assert_eq!(page.commits[0].id, commits[0].to_string());
+
+    let page = objects.log("main", 2, 1).unwrap();
+    assert!(page.commits.is_empty());
+    assert!(!page.has_more);
+  }
+
+  #[test]
+  fn test_diff_commit() {
+    let (dir, commits) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let diff = objects.diff("main", &DiffOptions::default()).unwrap();
+    assert_eq!(diff.base, Some(commits[0].to_string()));
+    assert_eq!(diff.head, commits[1].to_string());
+    let files: Vec<_> = diff
+      .files
+      .iter()
+      .map(|f| (f.new_path.as_deref().unwrap(), f.status, f.binary))
+      .collect();
+    assert_eq!(
+      files,
+      vec![
+        ("image.bin", FileStatus::Added, true),
+        ("src/main.rs", FileStatus::Added, false),
+      ]
+    );
+    assert_eq!(diff.files[1].old_path, None);
+    assert_eq!(diff.files[1].additions, 1);
+    assert_eq!(
+      diff.files[1].hunks[0].lines,
+      vec![DiffLine {
+        kind: DiffLineKind::Addition,
+        old_line: None,
+        new_line: Some(1),
+        content: "fn main() {}".to_string(),
+      }]
+    );
+
+    // The root commit is compared to an empty tree
+    let diff = objects.diff("main~1", &DiffOptions::default()).unwrap();
+    assert_eq!(diff.base, None);
+    assert_eq!(diff.files.len(), 1);
+    assert_eq!(diff.files[0].status, FileStatus::Added);
+  }
+
+  #[test]
+  fn test_diff_limits() {
+    let (dir, _) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+    let paths = |diff: &Diff| -> Vec<String> {
+      diff
+        .files
+        .iter()
+        .filter_map(|f| f.new_path.clone())
+        .collect()
+    };
+
+    let diff = objects.diff("main", &DiffOptions::default()).unwrap();
+    assert!(!diff.truncated);
+    let options = DiffOptions {
+      max_files: 2,
+      max_size: 12,
+      ..Default::default()
+    };
+    let diff = objects.diff("main", &options).unwrap();
+    assert_eq!(paths(&diff), vec!["image.bin", "src/main.rs"]);
+    assert!(!diff.truncated);
+
+    let options = DiffOptions {
+      max_files: 1,
+      ..Default::default()
+    };
+    let diff = objects.diff("main", &options).unwrap();
+    assert_eq!(paths(&diff), vec!["image.bin"]);
+    assert!(diff.truncated);
+
+    // The lines of src/main.rs are over the size limit
+    let options = DiffOptions {
+      max_size: 11,
+      ..Default::default()
+    };
+    let diff = objects.diff("main", &options).unwrap();
+    assert_eq!(paths(&diff), vec!["image.bin"]);
+    assert!(diff.truncated);
+  }
+
+  #[test]
+  fn test_diff_range() {
+    let (dir, commits) = fixture();
+    let repo = Repository::open(dir.path()).unwrap();
+    let source = b"fn main() {\n  println!(\"Hello\");\n}\n\nfn helper() {}\n";
+    let head = commit(
+      &repo,
+      &[
+        ("README.md", b"# Readme  \n"),
+        ("src/app.rs", b"fn main() {}\n"),
+        ("lib.rs", source),
+      ],
+      "Rework",
+      1002,
+    );
+    let objects = GitObjects::open(dir.path()).unwrap();
+    let range = format!("{}..{}", commits[1], head);
+
+    let diff = objects.diff(&range, &DiffOptions::default()).unwrap();
+    let files: Vec<_> = diff
+      .files
+      .iter()
+      .map(|f| (f.old_path.as_deref(), f.new_path.as_deref(), f.status))
+      .collect();
+    assert_eq!(
+      files,
+      vec![
+        (Some("README.md"), Some("README.md"), FileStatus::Modified),
+        (Some("image.bin"), None, FileStatus::Deleted),
+        (None, Some("lib.rs"), FileStatus::Added),
+        (Some("src/main.rs"), Some("src/app.rs"), FileStatus::Renamed),
+      ]
+    );
+    let lines = &diff.files[2].hunks[0].lines;
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[4].new_line, Some(5));
+    assert_eq!(lines[4].content, "fn helper() {}");
+
+    let options = DiffOptions {
+      ignore_whitespace: true,
+      detect_renames: false,
+      ..Default::default()
+    };
+    let diff = objects.diff(&range, &options).unwrap();
+    let files: Vec<_> = diff.files.iter().map(|f| f.status).collect();
+    assert_eq!(
+      files,
+      vec![
+        FileStatus::Deleted,
+        FileStatus::Added,
+        FileStatus::Added,
+        FileStatus::Deleted,
+      ]
+    );
+
+    assert!(matches!(
+      objects.diff("main..unknown", &options),
+      Err(ObjectsError::RevisionNotFound(_))
+    ));
+  }
+
+  #[test]
+  fn test_diff_merge_base_range() {
+    let (dir, commits) = fixture();
+    let repo = Repository::open(dir.path()).unwrap();
+    // A side branch forking from the initial commit
+    let signature = GitSignature::new("John", "john@example.com", &Time::new(1003, 0)).unwrap();
+    let blob = repo.blob(b"Side\n").unwrap();
+    let mut tree = repo.treebuilder(None).unwrap();
+    tree.insert("side.txt", blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let initial = repo.find_commit(commits[0]).unwrap();
+    let side = repo
+      .commit(None, &signature, &signature, "Side", &tree, &[&initial])
+      .unwrap();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    // Only the changes of `main` since the fork, ignoring the side branch
+    let diff = objects
+      .diff(&format!("{}...main", side), &DiffOptions::default())
+      .unwrap();
+    assert_eq!(diff.base, Some(commits[0].to_string()));
+    assert_eq!(diff.head, commits[1].to_string());
+    let files: Vec<_> = diff.files.iter().map(|f| f.new_path.as_deref()).collect();
+    assert_eq!(files, vec![Some("image.bin"), Some("src/main.rs")]);
+
+    // Compared to the side branch itself
+    let diff = objects
+      .diff(&format!("{}..main", side), &DiffOptions::default())
+      .unwrap();
+    assert_eq!(diff.base, Some(side.to_string()));
+  }
+
+  #[test]
+  fn test_diff_empty_range_sides() {
+    let (dir, _) = fixture();
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    for spec in ["..main", "main..", "...main", "main...", ".."] {
+      assert!(
+        matches!(
+          objects.diff(spec, &DiffOptions::default()),
+          Err(ObjectsError::InvalidRange(_))
+        ),
+        "{}",
+        spec
+      );
+    }
+  }
+
+  #[test]
+  fn test_hash_and_find_lines() {
+    let (dir, commits) = fixture();
+    let repo = Repository::open(dir.path()).unwrap();
+    let objects = GitObjects::open(dir.path()).unwrap();
+    let hash = objects.hash_lines("main", "src/main.rs", 1, 1).unwrap();
+    assert!(matches!(
+      objects.hash_lines("main", "src/main.rs", 1, 2),
+      Err(ObjectsError::InvalidLineRange(1, 2))
+    ));
+
+    commit(
+      &repo,
+      &[("src/main.rs", b"// Moved\n\nfn main() {}\n\nfn main() {}\n")],
+      "Move main",
+      1002,
+    );
+    let head = "main";
+    assert_eq!(
+      objects
+        .find_lines(head, "src/main.rs", &hash, 1, 1)
+        .unwrap(),
+      Some(3)
+    );
+    assert_eq!(
+      objects
+        .find_lines(head, "src/main.rs", &hash, 1, 5)
+        .unwrap(),
+      Some(5)
+    );
+    assert_eq!(
+      objects.find_lines(head, "README.md", &hash, 1, 1).unwrap(),
+      None
+    );
+    assert_eq!(
+      objects
+        .find_lines(&commits[1].to_string(), "src/main.rs", &hash, 1, 1)
+        .unwrap(),
+      Some(1)
+    );
+  }
+}
@@ -0,0 +1,10 @@
+//! Read-only access to the objects stored in a repository, allowing to browse its content without
+//! going through the git protocol.
+
+mod git_objects;
+mod objects_error;
+mod objects_types;
+
+pub use git_objects::*;
+pub use objects_error::*;
+pub use objects_types::*;
@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+/// Errors that can occur while reading the objects of a repository.
+#[derive(Error, Debug)]
+pub enum ObjectsError {
+  #[error("Repository not found")]
+  RepositoryNotFound,
+  #[error("Revision not found: {0}")]
+  RevisionNotFound(String),
+  #[error("Path not found: {0}")]
+  PathNotFound(String),
+  #[error("Not a directory: {0}")]
+  NotATree(String),
+  #[error("Not a file: {0}")]
+  NotABlob(String),
+  #[error("Invalid range: {0}")]
+  InvalidRange(String),
+  #[error("Invalid line range: {0}-{1}")]
+  InvalidLineRange(usize, usize),
+  #[error("Git error: {0}")]
+  GitError(#[from] git2::Error),
+}
+
+impl ObjectsError {
+  /// Whether the error comes from the request rather than from the repository itself.
+  pub fn is_not_found(&self) -> bool {
+    matches!(
+      self,
+      ObjectsError::RepositoryNotFound
+        | ObjectsError::RevisionNotFound(_)
+        | ObjectsError::PathNotFound(_)
+    )
+  }
+}
@@ -0,0 +1,173 @@
+/// The kind of a reference.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RefKind {
+  Branch,
+  Tag,
+}
+
+/// A branch or a tag, along with the commit it points to.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GitRef {
+  /// The short name of the reference, such as `main` for `refs/heads/main`
+  pub name: String,
+  pub kind: RefKind,
+  pub target: String,
+}
+
+/// The kind of an entry of a tree.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EntryKind {
+  Tree,
+  Blob,
+  Submodule,
+}
+
+/// An entry of a tree: either a file, a directory or a submodule.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TreeEntry {
+  pub name: String,
+  /// The path of the entry from the root of the repository
+  pub path: String,
+  pub kind: EntryKind,
+  pub mode: i32,
+  pub id: String,
+  /// The size of the file, only set for blobs
+  pub size: Option<usize>,
+}
+
+/// The content of a blob, as it can be displayed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BlobContent {
+  Text(String),
+  Binary,
+  /// The blob exceeds the maximum size requested
+  TooLarge,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Blob {
+  pub id: String,
+  pub path: String,
+  pub size: usize,
+  pub content: BlobContent,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Signature {
+  pub name: String,
+  pub email: String,
+  /// Seconds since the unix epoch
+  pub time: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommitInfo {
+  pub id: String,
+  pub parents: Vec<String>,
+  pub author: Signature,
+  pub committer: Signature,
+  /// The first line of the message
+  pub summary: String,
+  pub message: String,
+}
+
+/// A page of the commit log.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommitPage {
+  pub commits: Vec<CommitInfo>,
+  /// Whether more commits follow this page
+  pub has_more: bool,
+}
+
+/// Options used when computing a diff.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DiffOptions {
+  /// Ignore changes in the amount of whitespace
+  pub ignore_whitespace: bool,
+  /// Report renamed files as such rather than as a deletion and an addition
+  pub detect_renames: bool,
+  /// The number of unchanged lines shown around each change
+  pub context_lines: u32,
+  /// The number of files after which the remaining ones are left out
+  pub max_files: usize,
+  /// The total size of the lines after which the remaining files are left out
+  pub max_size: usize,
+}
+
+impl Default for DiffOptions {
+  fn default() -> Self {
+    Self {
+      ignore_whitespace: false,
+      detect_renames: true,
+      context_lines: 3,
+      max_files: usize::MAX,
+      max_size: usize::MAX,
+    }
+  }
+}
+
+/// How a file changed between the two sides of a diff.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileStatus {
+  Added,
+  Deleted,
+  Modified,
+  Renamed,
+  Copied,
+  /// The file changed kind, for instance from a regular file to a symlink
+  TypeChanged,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DiffLineKind {
+  Context,
+  Addition,
+  Deletion,
+}
+
+/// A line of a hunk. Line numbers start at 1 and are only set on the side the line exists in.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DiffLine {
+  pub kind: DiffLineKind,
+  pub old_line: Option<u32>,
+  pub new_line: Option<u32>,
+  /// The content of the line, without its line ending
+  pub content: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DiffHunk {
+  /// The `@@ -a,b +c,d @@` header of the hunk
+  pub header: String,
+  pub old_start: u32,
+  pub old_lines: u32,
+  pub new_start: u32,
+  pub new_lines: u32,
+  pub lines: Vec<DiffLine>,
+}
+
+/// The changes made to a single file.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileDiff {
+  /// The path before the change, unset for added files
+  pub old_path: Option<String>,
+  /// The path after the change, unset for deleted files
+  pub new_path: Option<String>,
+  pub status: FileStatus,
+  /// Binary files are reported without hunks
+  pub binary: bool,
+  pub additions: usize,
+  pub deletions: usize,
+  pub hunks: Vec<DiffHunk>,
+}
+
+/// The changes between two commits.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diff {
+  /// The commit the changes are computed from, unset for a root commit
+  pub base: Option<String>,
+  pub head: String,
+  pub files: Vec<FileDiff>,
+  /// Whether files were left out, once over the limits of the options
+  pub truncated: bool,
+}
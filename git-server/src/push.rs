@@ -0,0 +1,257 @@
+use std::{
+  io,
+  pin::Pin,
+  sync::{Arc, Mutex},
+  task::{Context, Poll},
+};
+
+use tokio::io::AsyncWrite;
+
+/// A reference updated by a push, as requested by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+  pub old: String,
+  pub new: String,
+  /// The full name of the reference, such as `refs/heads/main`
+  pub name: String,
+}
+
+impl RefUpdate {
+  /// The name of the branch, if the reference is a branch
+  pub fn branch(&self) -> Option<&str> {
+    self.name.strip_prefix("refs/heads/")
+  }
+
+  pub fn is_delete(&self) -> bool {
+    self.new.bytes().all(|b| b == b'0')
+  }
+}
+
+/// What a client sent along with a push, read from the start of its request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Push {
+  pub updates: Vec<RefUpdate>,
+  /// The options given with `git push -o`
+  pub options: Vec<String>,
+}
+
+impl Push {
+  pub fn has_option(&self, option: &str) -> bool {
+    self.options.iter().any(|o| o == option)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+  Commands,
+  Options,
+  Done,
+}
+
+/// Reads the commands and the push options at the start of a `git-receive-pack` request, made of
+/// pkt-lines. The pack which follows isn't kept.
+#[derive(Debug)]
+pub(crate) struct PushParser {
+  stage: Stage,
+  /// The start of a pkt-line which didn't fully arrive yet
+  pending: Vec<u8>,
+  /// Whether the client announced push options after the commands
+  has_options: bool,
+  push: Push,
+}
+
+impl Default for PushParser {
+  fn default() -> Self {
+    PushParser {
+      stage: Stage::Commands,
+      pending: Vec::new(),
+      has_options: false,
+      push: Push::default(),
+    }
+  }
+}
+
+impl PushParser {
+  pub(crate) fn push(&self) -> &Push {
+    &self.push
+  }
+
+  pub(crate) fn feed(&mut self, bytes: &[u8]) {
+    if self.stage == Stage::Done {
+      return;
+    }
+    self.pending.extend_from_slice(bytes);
+
+    let mut start = 0;
+    while self.stage != Stage::Done && self.pending.len() >= start + 4 {
+      let length = std::str::from_utf8(&self.pending[start..start + 4])
+        .ok()
+        .and_then(|length| usize::from_str_radix(length, 16).ok());
+      match length {
+        Some(0) => {
+          start += 4;
+          self.stage = match self.stage {
+            Stage::Commands if self.has_options => Stage::Options,
+            _ => Stage::Done,
+          };
+        }
+        Some(length) if length > 4 => {
+          if self.pending.len() < start + length {
+            break;
+          }
+          let line = self.pending[start + 4..start + length].to_vec();
+          start += length;
+          self.line(&line);
+        }
+        // Not a request this parser understands
+        _ => self.stage = Stage::Done,
+      }
+    }
+
+    if self.stage == Stage::Done {
+      self.pending = Vec::new();
+    } else {
+      self.pending.drain(..start);
+    }
+  }
+
+  fn line(&mut self, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    match self.stage {
+      Stage::Commands => {
+        // The first command carries the capabilities of the client
+        let (command, capabilities) = line.split_once('\0').unwrap_or((line, ""));
+        if capabilities.split(' ').any(|c| c == "push-options") {
+          self.has_options = true;
+        }
+        let mut parts = command.splitn(3, ' ');
+        if let (Some(old), Some(new), Some(name)) = (parts.next(), parts.next(), parts.next()) {
+          let is_id = |id: &str| id.len() >= 40 && id.bytes().all(|b| b.is_ascii_hexdigit());
+          if is_id(old) && is_id(new) {
+            self.push.updates.push(RefUpdate {
+              old: old.to_string(),
+              new: new.to_string(),
+              name: name.to_string(),
+            });
+          }
+        }
+      }
+      Stage::Options => self.push.options.push(line.to_string()),
+      Stage::Done => {}
+    }
+  }
+}
+
+/// Forwards a push request to git, recording its commands and options along the way.
+pub(crate) struct PushRecorder<W> {
+  inner: W,
+  parser: Arc<Mutex<PushParser>>,
+}
+
+impl<W> PushRecorder<W> {
+  pub(crate) fn new(inner: W, parser: Arc<Mutex<PushParser>>) -> Self {
+    PushRecorder { inner, parser }
+  }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for PushRecorder<W> {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    let this = self.get_mut();
+    let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+    if let Poll::Ready(Ok(written)) = &poll {
+      this.parser.lock().unwrap().feed(&buf[..*written]);
+    }
+    poll
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pkt_line(line: &str) -> String {
+    format!("{:04x}{}", line.len() + 4, line)
+  }
+
+  fn update(old: char, new: char, name: &str) -> RefUpdate {
+    RefUpdate {
+      old: old.to_string().repeat(40),
+      new: new.to_string().repeat(40),
+      name: name.to_string(),
+    }
+  }
+
+  #[test]
+  fn test_parse_push_with_options() {
+    let request = [
+      pkt_line(&format!(
+        "{} {} refs/heads/main\0report-status push-options side-band-64k\n",
+        "a".repeat(40),
+        "b".repeat(40)
+      )),
+      pkt_line(&format!(
+        "{} {} refs/heads/old\n",
+        "c".repeat(40),
+        "0".repeat(40)
+      )),
+      "0000".to_string(),
+      pkt_line("ci.skip\n"),
+      pkt_line("other"),
+      "0000".to_string(),
+      "PACK0000".to_string(),
+    ]
+    .concat();
+
+    // The request arrives in small pieces, cutting the pkt-lines
+    let mut parser = PushParser::default();
+    for chunk in request.as_bytes().chunks(7) {
+      parser.feed(chunk);
+    }
+
+    let push = parser.push();
+    assert_eq!(
+      push.updates,
+      vec![
+        update('a', 'b', "refs/heads/main"),
+        update('c', '0', "refs/heads/old")
+      ]
+    );
+    assert_eq!(push.options, vec!["ci.skip", "other"]);
+    assert!(push.has_option("ci.skip"));
+    assert_eq!(push.updates[0].branch(), Some("main"));
+    assert!(!push.updates[0].is_delete());
+    assert!(push.updates[1].is_delete());
+  }
+
+  #[test]
+  fn test_parse_push_without_options() {
+    let request = [
+      pkt_line(&format!(
+        "{} {} refs/tags/v1\0report-status\n",
+        "0".repeat(40),
+        "a".repeat(40)
+      )),
+      "0000".to_string(),
+      // Part of the pack, which would look like options
+      pkt_line("ci.skip"),
+    ]
+    .concat();
+
+    let mut parser = PushParser::default();
+    parser.feed(request.as_bytes());
+
+    let push = parser.push();
+    assert_eq!(push.updates, vec![update('0', 'a', "refs/tags/v1")]);
+    assert_eq!(push.updates[0].branch(), None);
+    assert!(push.options.is_empty());
+  }
+}
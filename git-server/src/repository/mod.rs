@@ -10,3 +10,14 @@ pub enum RepositoryPermission {
   Read,
   Write,
 }
+
+/// Whether a push can go ahead, checked once the user is known to have the write permission.
+#[derive(Debug, PartialEq)]
+pub enum PushCheck {
+  /// The push is accepted
+  Accept,
+  /// The push is accepted, and the message is shown to the client
+  Notify(String),
+  /// The push is refused, and the message is shown to the client
+  Reject(String),
+}
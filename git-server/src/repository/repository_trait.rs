@@ -1,6 +1,8 @@
 use ssh_server::user::User;
 
-use super::RepositoryPermission;
+use crate::push::Push;
+
+use super::{PushCheck, RepositoryPermission};
 
 /// Trait representing a repository.
 pub trait Repository: Sync + Send + 'static {
@@ -11,4 +13,16 @@ pub trait Repository: Sync + Send + 'static {
 
   /// Returns the path of this repository on disk. This is the path used by the git command to access the repository, not necessarily the path given by the user.
   fn get_path(&self) -> &str;
+
+  /// Checks a push before it starts, on top of the write permission. Accepts every push by default.
+  fn check_push(&self, _user: &Self::User) -> PushCheck {
+    PushCheck::Accept
+  }
+
+  /// Called once a push has been received successfully, with the updates and options the client
+  /// sent. The message returned, if any, is shown to the client. It runs on a thread dedicated to
+  /// blocking work, so it can query the database or run git.
+  fn after_push(&self, _push: &Push) -> Option<String> {
+    None
+  }
 }
@@ -1,11 +1,13 @@
 use async_trait::async_trait;
 use ssh_server::{user::User, wrapper::HandleWrapper};
 
-use crate::repository::{Repository, RepositoryProvider};
+use crate::repository::{PushCheck, Repository, RepositoryProvider};
 
 pub struct SimpleRepositoryProvider {
   pub find_repository: bool,
   pub has_permission: bool,
+  /// Message used to reject pushes, if any
+  pub reject_push: Option<String>,
 }
 
 impl RepositoryProvider for SimpleRepositoryProvider {
@@ -15,14 +17,18 @@ impl RepositoryProvider for SimpleRepositoryProvider {
 
   fn find_repository(&self, _user: &Self::User, path: &str) -> Option<Self::Repository> {
     if self.find_repository {
-      Some(SimpleRepository(self.has_permission, path.to_string()))
+      Some(SimpleRepository(
+        self.has_permission,
+        path.to_string(),
+        self.reject_push.clone(),
+      ))
     } else {
       None
     }
   }
 }
 
-pub struct SimpleRepository(pub bool, pub String);
+pub struct SimpleRepository(pub bool, pub String, pub Option<String>);
 
 impl Repository for SimpleRepository {
   type User = SimpleUser;
@@ -38,6 +44,13 @@ impl Repository for SimpleRepository {
   fn get_path(&self) -> &str {
     self.1.as_str()
   }
+
+  fn check_push(&self, _user: &Self::User) -> PushCheck {
+    match &self.2 {
+      Some(message) => PushCheck::Reject(message.clone()),
+      None => PushCheck::Accept,
+    }
+  }
 }
 
 pub struct SimpleUser;
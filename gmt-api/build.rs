@@ -9,16 +9,29 @@ pub use src::security;
 pub use src::services;
 
 use database::connection_pool::ConnectionPool;
-use src::services::make_service;
+use gmt_common::{
+  artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+};
+use src::services::{
+  cirun_service::events::CirunEvents, make_service, repository_service::CloneUrls,
+};
 
 fn main() {
   dotenvy::dotenv().ok();
 
   let db = ConnectionPool::faux();
+  let storage = RepositoryStorage::faux();
 
   std::fs::create_dir_all("openapi").unwrap();
 
-  let api_service = (make_service(db)).server("http://localhost:3001");
+  let api_service = (make_service(
+    db,
+    storage,
+    ArtifactStorage::new_from_env(),
+    CloneUrls::new_from_env(),
+    CirunEvents::default(),
+  ))
+  .server("http://localhost:3001");
   let specs = api_service.spec();
   std::fs::write("openapi/main_service.json", specs).unwrap();
 }
@@ -1,6 +1,9 @@
 use database::connection_pool::ConnectionPool;
+use gmt_common::{
+  artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+};
 use poem::{listener::TcpListener, middleware::Cors, EndpointExt, Route};
-use services::make_service;
+use services::{cirun_service::events::CirunEvents, make_service, repository_service::CloneUrls};
 use swagger::add_swagger_ui;
 
 pub mod error;
@@ -18,7 +21,18 @@ async fn main() -> Result<(), std::io::Error> {
     .run_migrations()
     .expect("Failed to run migrations");
 
-  let mut api_service = make_service(connection_pool);
+  let storage = RepositoryStorage::new_from_env();
+
+  let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL not set");
+  let cirun_events = CirunEvents::listen(database_url);
+
+  let mut api_service = make_service(
+    connection_pool,
+    storage,
+    ArtifactStorage::new_from_env(),
+    CloneUrls::new_from_env(),
+    cirun_events,
+  );
   let mut app = Route::new();
 
   (api_service, app) = add_swagger_ui(api_service, app);
@@ -1,5 +1,4 @@
 use jwt::VerifyWithKey;
-use log::error;
 use poem_openapi::{auth::ApiKey, ApiResponse, SecurityScheme};
 use sha2::digest::InvalidLength;
 
@@ -52,7 +51,7 @@ mod tests {
       email: "john.doe@example.com".to_string(),
     };
 
-    Token::new(Header::default(), UserToken::from(user))
+    Token::new(Header::default(), user)
       .sign_with_key(&key)
       .expect("Unable to sign token")
       .into()
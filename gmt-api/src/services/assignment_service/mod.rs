@@ -0,0 +1,899 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::{Assignment, NewAssignment},
+    group::Group,
+    repository::{Repository, Repotype},
+  },
+};
+use gmt_common::{
+  deadlines::is_released,
+  permissions::{is_group_member, is_teacher},
+  repositories::{
+    provisioning::provision_assignment,
+    repository_storage::{is_valid_repository_name, RepositoryStorage},
+  },
+};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+
+use crate::{security::gmt_token::GmtToken, services::repository_service::RepositoryResponse};
+
+pub mod structs;
+
+pub use structs::*;
+
+#[OpenApi]
+impl<DbPool, Db> AssignmentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the assignments of all the groups the user teaches or belongs to. Students only see
+  /// the assignments which have been released.
+  #[oai(path = "/assignments", method = "get")]
+  async fn list_assignments(
+    &self,
+    token: GmtToken,
+  ) -> Result<Json<Vec<AssignmentResponse>>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let mut groups = db.list_teaching_groups(user.user_id)?;
+    for group in db.list_belongs_groups(user.user_id)? {
+      if !groups.iter().any(|g| g.id == group.id) {
+        groups.push(group);
+      }
+    }
+
+    let now = SystemTime::now();
+    let mut assignments = Vec::new();
+    for group in groups {
+      let teacher = is_teacher(&group, user.user_id);
+      assignments.extend(
+        db.list_group_assignments(group.id)?
+          .into_iter()
+          .filter(|a| teacher || is_released(&a.metadata, now)),
+      );
+    }
+
+    Ok(Json(
+      assignments
+        .into_iter()
+        .map(AssignmentResponse::from)
+        .collect(),
+    ))
+  }
+
+  /// Creates an assignment for a group, along with its repositories.
+  ///
+  /// The database rows and the repositories on disk are created together: if any of them fails,
+  /// nothing is kept. If the assignment is already released, the repositories of the students are
+  /// created as well.
+  #[oai(path = "/assignments", method = "post")]
+  async fn create_assignment(
+    &self,
+    token: GmtToken,
+    req: Json<CreateAssignmentRequest>,
+  ) -> Result<Json<AssignmentResponse>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = db
+      .get_group_by_id(req.group_id)?
+      .ok_or(AssignmentError::NotFound("Group".into()))?;
+    if !is_teacher(&group, user.user_id) {
+      return Err(AssignmentError::Forbidden);
+    }
+    validate_repository_names(&req)?;
+    let metadata = req.metadata.validate()?;
+
+    let mut created = Vec::new();
+    let assignment = db.transaction(|db| {
+      let mut create = |name: &str, repo_type: &Repotype| {
+        create_repository(
+          db,
+          &self.storage,
+          name,
+          repo_type,
+          user.user_id,
+          &mut created,
+        )
+      };
+
+      let base_repo = create(&req.base_repo_name, &Repotype::Default)?;
+      let test_repo = match &req.test_repo_name {
+        Some(name) => Some(create(name, &Repotype::Ci)?),
+        None => None,
+      };
+      let correction_repo = match &req.correction_repo_name {
+        Some(name) => Some(create(name, &Repotype::Default)?),
+        None => None,
+      };
+
+      let assignment = db.insert_assignment(&NewAssignment {
+        group_id: group.id,
+        base_repo_id: base_repo.id,
+        test_repo_id: test_repo.map(|r| r.id),
+        correction_repo_id: correction_repo.map(|r| r.id),
+        metadata,
+      })?;
+      Ok::<_, AssignmentError>(assignment)
+    });
+
+    if assignment.is_err() {
+      for name in created {
+        if let Err(e) = self.storage.delete_repository(&name) {
+          log::warn!("Unable to remove repository {} after failure: {}", name, e);
+        }
+      }
+    }
+    let assignment = assignment?;
+    self.provision(&mut db, &assignment);
+
+    Ok(Json(assignment.into()))
+  }
+
+  #[oai(path = "/assignments/:id", method = "get")]
+  async fn get_assignment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<AssignmentResponse>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (assignment, group) = find_assignment(&mut db, id.0)?;
+    if !is_group_member(&mut db, &group, user.user_id)? {
+      return Err(AssignmentError::Forbidden);
+    }
+    if !is_teacher(&group, user.user_id) && !is_released(&assignment.metadata, SystemTime::now()) {
+      return Err(AssignmentError::NotFound("Assignment".into()));
+    }
+
+    Ok(Json(assignment.into()))
+  }
+
+  /// Replaces the name, description, dates and late policy of the assignment. Releasing the
+  /// assignment creates the repositories of the students.
+  #[oai(path = "/assignments/:id", method = "put")]
+  async fn update_assignment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<AssignmentMetadataRequest>,
+  ) -> Result<Json<AssignmentResponse>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (assignment, group) = find_assignment(&mut db, id.0)?;
+    if !is_teacher(&group, user.user_id) {
+      return Err(AssignmentError::Forbidden);
+    }
+    let metadata = req.validate()?;
+
+    let assignment = db.update_assignment_metadata(assignment.id, &metadata)?;
+    self.provision(&mut db, &assignment);
+
+    Ok(Json(assignment.into()))
+  }
+
+  /// Deletes the assignment. Its repositories are kept, and submissions are detached from it.
+  #[oai(path = "/assignments/:id", method = "delete")]
+  async fn delete_assignment(&self, token: GmtToken, id: Path<i32>) -> Result<(), AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (assignment, group) = find_assignment(&mut db, id.0)?;
+    if !is_teacher(&group, user.user_id) {
+      return Err(AssignmentError::Forbidden);
+    }
+
+    if !db.delete_assignment(assignment.id)? {
+      return Err(AssignmentError::NotFound("Assignment".into()));
+    }
+    Ok(())
+  }
+
+  /// Creates the missing repositories of the students, forked from the base repository. Returns
+  /// the repositories which were created.
+  #[oai(path = "/assignments/:id/repositories", method = "post")]
+  async fn provision_submissions(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<RepositoryResponse>>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (assignment, group) = find_assignment(&mut db, id.0)?;
+    if !is_teacher(&group, user.user_id) {
+      return Err(AssignmentError::Forbidden);
+    }
+    if !is_released(&assignment.metadata, SystemTime::now()) {
+      return Err(AssignmentError::BadRequest(
+        "The assignment has not been released yet".into(),
+      ));
+    }
+
+    let repositories = provision_assignment(&mut db, &self.storage, &assignment, SystemTime::now())
+      .map_err(|e| {
+        log::error!("Unable to provision assignment {}: {}", assignment.id, e);
+        AssignmentError::InternalServerError
+      })?;
+    Ok(Json(
+      repositories
+        .into_iter()
+        .map(RepositoryResponse::from)
+        .collect(),
+    ))
+  }
+
+  /// Lists the submission repositories of the assignment. Students only see their own.
+  #[oai(path = "/assignments/:id/repositories", method = "get")]
+  async fn list_submissions(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<RepositoryResponse>>, AssignmentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (assignment, group) = find_assignment(&mut db, id.0)?;
+    if !is_group_member(&mut db, &group, user.user_id)? {
+      return Err(AssignmentError::Forbidden);
+    }
+
+    let teacher = is_teacher(&group, user.user_id);
+    let repositories = db.get_assignment_submission_repos(assignment.id)?;
+
+    Ok(Json(
+      repositories
+        .into_iter()
+        .filter(|r| teacher || r.owner_id == user.user_id)
+        .map(RepositoryResponse::from)
+        .collect(),
+    ))
+  }
+}
+
+impl<DbPool, Db> AssignmentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Creates the repositories of the students if the assignment is released. Failures are only
+  /// logged, the repositories can be provisioned again later on.
+  fn provision(&self, db: &mut Db, assignment: &Assignment) {
+    match provision_assignment(db, &self.storage, assignment, SystemTime::now()) {
+      Ok(created) if !created.is_empty() => log::info!(
+        "Provisioned {} repositories for assignment {}",
+        created.len(),
+        assignment.id
+      ),
+      Ok(_) => {}
+      Err(e) => log::warn!("Unable to provision assignment {}: {}", assignment.id, e),
+    }
+  }
+}
+
+fn find_assignment<Db: DbType>(
+  db: &mut Db,
+  assignment_id: i32,
+) -> Result<(Assignment, Group), AssignmentError> {
+  let assignment = db
+    .get_assignment_by_id(assignment_id)?
+    .ok_or(AssignmentError::NotFound("Assignment".into()))?;
+  let group = db
+    .get_group_by_id(assignment.group_id)?
+    .ok_or(AssignmentError::NotFound("Group".into()))?;
+  Ok((assignment, group))
+}
+
+fn validate_repository_names(req: &CreateAssignmentRequest) -> Result<(), AssignmentError> {
+  let names = [
+    Some(&req.base_repo_name),
+    req.test_repo_name.as_ref(),
+    req.correction_repo_name.as_ref(),
+  ];
+  let names: Vec<&String> = names.into_iter().flatten().collect();
+
+  if let Some(name) = names.iter().find(|n| !is_valid_repository_name(n)) {
+    return Err(AssignmentError::BadRequest(
+      format!("Invalid repository name: {}", name).into(),
+    ));
+  }
+  for (i, name) in names.iter().enumerate() {
+    if names[i + 1..].contains(name) {
+      return Err(AssignmentError::BadRequest(
+        "Repository names must be different".into(),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Creates the repository row and the bare repository on disk. Created repositories are recorded
+/// so they can be cleaned up if the transaction fails.
+fn create_repository<Db: DbType>(
+  db: &mut Db,
+  storage: &RepositoryStorage,
+  name: &str,
+  repo_type: &Repotype,
+  owner_id: i32,
+  created: &mut Vec<String>,
+) -> Result<Repository, AssignmentError> {
+  let repository = db
+    .create_repository(name, repo_type, owner_id, None)
+    .map_err(|e| {
+      if e.is_unique_violation() {
+        AssignmentError::Conflict(format!("Repository {} already exists", name).into())
+      } else {
+        e.into()
+      }
+    })?;
+
+  storage.create_repository(name).map_err(|e| {
+    if e.kind() == std::io::ErrorKind::AlreadyExists {
+      AssignmentError::Conflict(format!("Repository {} already exists", name).into())
+    } else {
+      e.into()
+    }
+  })?;
+  created.push(name.to_string());
+
+  Ok(repository)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::test_utils::{repository, user, valid_token};
+  use chrono::{DateTime, Utc};
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      assignment::{AssignmentMetadata, Latepolicy},
+      user::User,
+    },
+    error::DatabaseError,
+    DbHandle,
+  };
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+  use std::{path::PathBuf, time::Duration};
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn client<F>(storage: RepositoryStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      faux::when!(db.rollback_transaction).then(|_| Ok(()));
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      AssignmentService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage)),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  /// A storage recording the repositories created and deleted
+  fn storage(
+    created: Arc<Mutex<Vec<String>>>,
+    deleted: Arc<Mutex<Vec<String>>>,
+  ) -> RepositoryStorage {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.create_repository).then(move |name| {
+      created.lock().unwrap().push(name.to_string());
+      Ok(PathBuf::from(name))
+    });
+    faux::when!(storage.delete_repository).then(move |name| {
+      deleted.lock().unwrap().push(name.to_string());
+      Ok(())
+    });
+    storage
+  }
+
+  fn group(id: i32, teacher_id: Option<i32>) -> Group {
+    Group {
+      id,
+      name: format!("group-{}", id),
+      teacher_id,
+    }
+  }
+
+  fn assignment(id: i32, group_id: i32) -> Assignment {
+    Assignment {
+      id,
+      group_id,
+      base_repo_id: 10,
+      test_repo_id: None,
+      correction_repo_id: None,
+      metadata: AssignmentMetadata::default(),
+    }
+  }
+
+  fn unreleased_assignment(id: i32, group_id: i32) -> Assignment {
+    let mut assignment = assignment(id, group_id);
+    assignment.metadata.release_date =
+      Some(SystemTime::now() + Duration::from_secs(365 * 24 * 3600));
+    assignment
+  }
+
+  fn metadata_request(name: &str) -> AssignmentMetadataRequest {
+    AssignmentMetadataRequest {
+      name: name.to_string(),
+      description: None,
+      release_date: None,
+      due_date: None,
+      late_policy: None,
+      grace_period: None,
+      late_penalty: None,
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_assignments(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.list_teaching_groups(USER_ID)).then(|_| Ok(vec![group(1, Some(USER_ID))]));
+      faux::when!(db.list_belongs_groups(USER_ID)).then(|_| Ok(vec![group(2, Some(OTHER_ID))]));
+      faux::when!(db.list_group_assignments)
+        .then(|id| Ok(vec![assignment(id, id), unreleased_assignment(id + 10, id)]));
+    });
+
+    let resp = client
+      .get("/assignments")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let assignments = resp
+      .json()
+      .await
+      .value()
+      .deserialize::<Vec<AssignmentResponse>>();
+    let ids: Vec<i32> = assignments.iter().map(|a| a.id).collect();
+    assert_eq!(ids, vec![1, 11, 2]);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_assignment(valid_token: String) {
+    let created = Arc::new(Mutex::new(Vec::new()));
+    let deleted = Arc::new(Mutex::new(Vec::new()));
+    let client = client(storage(created.clone(), deleted.clone()), |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.create_repository).then(|(name, repo_type, owner_id, assignment_id)| {
+        assert_eq!(owner_id, USER_ID);
+        assert_eq!(assignment_id, None);
+        let id = if name == "base" { 10 } else { 11 };
+        let mut repo = repository(id, owner_id, None);
+        repo.repo_type = if *repo_type == Repotype::Ci {
+          Repotype::Ci
+        } else {
+          Repotype::Default
+        };
+        Ok(repo)
+      });
+      faux::when!(db.insert_assignment).then(|new_assignment| {
+        Ok(Assignment {
+          id: 1,
+          group_id: new_assignment.group_id,
+          base_repo_id: new_assignment.base_repo_id,
+          test_repo_id: new_assignment.test_repo_id,
+          correction_repo_id: new_assignment.correction_repo_id,
+          metadata: new_assignment.metadata.clone(),
+        })
+      });
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+    });
+    let due_date = "2024-01-15T23:59:00Z".parse::<DateTime<Utc>>().unwrap();
+
+    let resp = client
+      .post("/assignments")
+      .header("Authorization", valid_token)
+      .body_json(&CreateAssignmentRequest {
+        group_id: 1,
+        base_repo_name: "base".to_string(),
+        test_repo_name: Some("tests".to_string()),
+        correction_repo_name: None,
+        metadata: AssignmentMetadataRequest {
+          description: Some("Implement a linked list".to_string()),
+          due_date: Some(due_date),
+          late_policy: Some(LatePolicy::Reject),
+          grace_period: Some(10),
+          ..metadata_request(" Linked lists ")
+        },
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let assignment = resp
+      .json()
+      .await
+      .value()
+      .deserialize::<AssignmentResponse>();
+    assert_eq!(
+      assignment,
+      AssignmentResponse {
+        id: 1,
+        group_id: 1,
+        base_repo_id: 10,
+        test_repo_id: Some(11),
+        correction_repo_id: None,
+        name: "Linked lists".to_string(),
+        description: "Implement a linked list".to_string(),
+        release_date: None,
+        due_date: Some(due_date),
+        late_policy: LatePolicy::Reject,
+        grace_period: 10,
+        late_penalty: 0,
+      }
+    );
+    assert_eq!(*created.lock().unwrap(), vec!["base", "tests"]);
+    assert!(deleted.lock().unwrap().is_empty());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_assignment_failure_removes_repositories(valid_token: String) {
+    let created = Arc::new(Mutex::new(Vec::new()));
+    let deleted = Arc::new(Mutex::new(Vec::new()));
+    let client = client(storage(created.clone(), deleted.clone()), |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.create_repository).then(|(name, _, owner_id, _)| {
+        if name == "base" {
+          Ok(repository(10, owner_id, None))
+        } else {
+          Err(DatabaseError::NotFound)
+        }
+      });
+      faux::when!(db.commit_transaction).then(|_| panic!("Transaction should not be committed"));
+    });
+
+    let resp = client
+      .post("/assignments")
+      .header("Authorization", valid_token)
+      .body_json(&CreateAssignmentRequest {
+        group_id: 1,
+        base_repo_name: "base".to_string(),
+        test_repo_name: None,
+        correction_repo_name: Some("correction".to_string()),
+        metadata: metadata_request("assignment"),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(*created.lock().unwrap(), vec!["base"]);
+    assert_eq!(*deleted.lock().unwrap(), vec!["base"]);
+  }
+
+  #[rstest]
+  #[case("base", Some("base"), None)]
+  #[case("../base", None, None)]
+  #[case("base", None, Some(""))]
+  #[tokio::test]
+  async fn test_create_assignment_invalid_names(
+    valid_token: String,
+    #[case] base: &str,
+    #[case] test: Option<&str>,
+    #[case] correction: Option<&str>,
+  ) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+    });
+
+    let resp = client
+      .post("/assignments")
+      .header("Authorization", valid_token)
+      .body_json(&CreateAssignmentRequest {
+        group_id: 1,
+        base_repo_name: base.to_string(),
+        test_repo_name: test.map(str::to_string),
+        correction_repo_name: correction.map(str::to_string),
+        metadata: metadata_request("assignment"),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+  }
+
+  #[rstest]
+  #[case::empty_name(AssignmentMetadataRequest { ..metadata_request("  ") })]
+  #[case::negative_grace_period(AssignmentMetadataRequest { grace_period: Some(-1), ..metadata_request("a") })]
+  #[case::penalty_too_high(AssignmentMetadataRequest { late_penalty: Some(101), ..metadata_request("a") })]
+  #[case::due_before_release(AssignmentMetadataRequest {
+    release_date: Some("2024-01-15T00:00:00Z".parse().unwrap()),
+    due_date: Some("2024-01-14T00:00:00Z".parse().unwrap()),
+    ..metadata_request("a")
+  })]
+  #[tokio::test]
+  async fn test_create_assignment_invalid_metadata(
+    valid_token: String,
+    #[case] metadata: AssignmentMetadataRequest,
+  ) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+    });
+
+    let resp = client
+      .post("/assignments")
+      .header("Authorization", valid_token)
+      .body_json(&CreateAssignmentRequest {
+        group_id: 1,
+        base_repo_name: "base".to_string(),
+        test_repo_name: None,
+        correction_repo_name: None,
+        metadata,
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_assignment_not_teacher(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(OTHER_ID)))));
+    });
+
+    let resp = client
+      .post("/assignments")
+      .header("Authorization", valid_token)
+      .body_json(&CreateAssignmentRequest {
+        group_id: 1,
+        base_repo_name: "base".to_string(),
+        test_repo_name: None,
+        correction_repo_name: None,
+        metadata: metadata_request("assignment"),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), vec![], false, StatusCode::OK)]
+  #[case(Some(OTHER_ID), vec![user(USER_ID, "user-1")], false, StatusCode::OK)]
+  #[case(Some(OTHER_ID), vec![], false, StatusCode::FORBIDDEN)]
+  #[case::unreleased_teacher(Some(USER_ID), vec![], true, StatusCode::OK)]
+  #[case::unreleased_student(Some(OTHER_ID), vec![user(USER_ID, "user-1")], true, StatusCode::NOT_FOUND)]
+  #[tokio::test]
+  async fn test_get_assignment(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] students: Vec<User>,
+    #[case] unreleased: bool,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(move |_| {
+        Ok(Some(if unreleased {
+          unreleased_assignment(1, 1)
+        } else {
+          assignment(1, 1)
+        }))
+      });
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      let students = students.clone();
+      faux::when!(db.list_students(1)).then(move |_| Ok(students.clone()));
+    });
+
+    let resp = client
+      .get("/assignments/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_nonexistent_assignment(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(|_| Ok(None));
+    });
+
+    let resp = client
+      .get("/assignments/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), StatusCode::OK)]
+  #[case(Some(OTHER_ID), StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_update_assignment(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(|_| Ok(Some(assignment(1, 1))));
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.update_assignment_metadata).then(|(id, metadata)| {
+        assert_eq!(metadata.late_policy, Latepolicy::Accept);
+        assert_eq!(metadata.late_penalty, 20);
+        Ok(Assignment {
+          metadata: metadata.clone(),
+          ..assignment(id, 1)
+        })
+      });
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .put("/assignments/1")
+      .header("Authorization", valid_token)
+      .body_json(&AssignmentMetadataRequest {
+        late_policy: Some(LatePolicy::Accept),
+        late_penalty: Some(20),
+        ..metadata_request("Renamed")
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let assignment = resp
+        .json()
+        .await
+        .value()
+        .deserialize::<AssignmentResponse>();
+      assert_eq!(assignment.name, "Renamed");
+      assert_eq!(assignment.late_policy, LatePolicy::Accept);
+    }
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), StatusCode::OK)]
+  #[case(Some(OTHER_ID), StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_delete_assignment(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(|_| Ok(Some(assignment(1, 1))));
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.delete_assignment(1)).then(|_| Ok(true));
+    });
+
+    let resp = client
+      .delete("/assignments/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::teacher(Some(USER_ID), false, StatusCode::OK)]
+  #[case::not_teacher(Some(OTHER_ID), false, StatusCode::FORBIDDEN)]
+  #[case::unreleased(Some(USER_ID), true, StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_provision_submissions(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] unreleased: bool,
+    #[case] expected: StatusCode,
+  ) {
+    let forks = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = RepositoryStorage::faux();
+    let recorded = forks.clone();
+    faux::when!(storage.fork_repository).then(move |(source, name)| {
+      recorded
+        .lock()
+        .unwrap()
+        .push(format!("{} -> {}", source, name));
+      Ok(PathBuf::from(name))
+    });
+
+    let client = client(storage, move |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(move |_| {
+        Ok(Some(if unreleased {
+          unreleased_assignment(1, 1)
+        } else {
+          assignment(1, 1)
+        }))
+      });
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![user(3, "user-3"), user(4, "user-4")]));
+      faux::when!(db.get_assignment_submission_repos(1))
+        .then(|_| Ok(vec![repository(20, 3, Some(1))]));
+      faux::when!(db.get_repository_by_id(10)).then(|_| {
+        let mut repo = repository(10, USER_ID, None);
+        repo.name = "base".to_string();
+        Ok(Some(repo))
+      });
+      faux::when!(db.get_repository_by_name).then(|_| Ok(None));
+      faux::when!(db.create_repository).then(|(name, _, owner_id, assignment_id)| {
+        let mut repo = repository(21, owner_id, assignment_id);
+        repo.name = name.to_string();
+        Ok(repo)
+      });
+    });
+
+    let resp = client
+      .post("/assignments/1/repositories")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let repositories = resp
+        .json()
+        .await
+        .value()
+        .deserialize::<Vec<RepositoryResponse>>();
+      assert_eq!(repositories.len(), 1);
+      assert_eq!(repositories[0].name, "base-user-4");
+      assert_eq!(*forks.lock().unwrap(), vec!["base -> base-user-4"]);
+    } else {
+      assert!(forks.lock().unwrap().is_empty());
+    }
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), vec![], vec![20, 21])]
+  #[case(Some(OTHER_ID), vec![user(USER_ID, "user-1")], vec![20])]
+  #[tokio::test]
+  async fn test_list_submissions(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] students: Vec<User>,
+    #[case] expected: Vec<i32>,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      faux::when!(db.get_assignment_by_id(1)).then(|_| Ok(Some(assignment(1, 1))));
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      let students = students.clone();
+      faux::when!(db.list_students(1)).then(move |_| Ok(students.clone()));
+      faux::when!(db.get_assignment_submission_repos(1)).then(|_| {
+        Ok(vec![
+          repository(20, USER_ID, Some(1)),
+          repository(21, 3, Some(1)),
+        ])
+      });
+    });
+
+    let resp = client
+      .get("/assignments/1/repositories")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let owners = |id| if id == 20 { USER_ID } else { 3 };
+    resp
+      .assert_json(
+        expected
+          .into_iter()
+          .map(|id| RepositoryResponse::from(repository(id, owners(id), Some(1))))
+          .collect::<Vec<_>>(),
+      )
+      .await;
+  }
+}
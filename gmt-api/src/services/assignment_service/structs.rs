@@ -0,0 +1,235 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::{Assignment, AssignmentDbHandle, AssignmentMetadata, Latepolicy},
+    group::GroupDbHandle,
+    repository::RepositoryDbHandle,
+    transaction::TransactionDbHandle,
+    user::UserDbHandle,
+  },
+  error::DatabaseError,
+};
+use gmt_common::repositories::repository_storage::RepositoryStorage;
+use poem_openapi::{ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::structs::StringResponse;
+
+pub trait DbType:
+  AssignmentDbHandle
+  + GroupDbHandle
+  + RepositoryDbHandle
+  + TransactionDbHandle
+  + UserDbHandle
+  + 'static
+{
+}
+impl<T> DbType for T where
+  T: AssignmentDbHandle
+    + GroupDbHandle
+    + RepositoryDbHandle
+    + TransactionDbHandle
+    + UserDbHandle
+    + 'static
+{
+}
+
+pub struct AssignmentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+}
+
+impl<DbPool, Db> AssignmentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>) -> Self {
+    Self { db, storage }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LatePolicy {
+  /// Late pushes are accepted as if they were on time
+  Accept,
+  /// Late pushes are accepted but flagged as late
+  #[default]
+  Flag,
+  /// Late pushes are rejected
+  Reject,
+}
+
+impl From<Latepolicy> for LatePolicy {
+  fn from(policy: Latepolicy) -> Self {
+    match policy {
+      Latepolicy::Accept => LatePolicy::Accept,
+      Latepolicy::Flag => LatePolicy::Flag,
+      Latepolicy::Reject => LatePolicy::Reject,
+    }
+  }
+}
+
+impl From<LatePolicy> for Latepolicy {
+  fn from(policy: LatePolicy) -> Self {
+    match policy {
+      LatePolicy::Accept => Latepolicy::Accept,
+      LatePolicy::Flag => Latepolicy::Flag,
+      LatePolicy::Reject => Latepolicy::Reject,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Clone)]
+pub struct AssignmentMetadataRequest {
+  pub name: String,
+  pub description: Option<String>,
+  /// When the base repository becomes visible to the students, immediately if unset
+  pub release_date: Option<DateTime<Utc>>,
+  /// When submissions are due, never if unset
+  pub due_date: Option<DateTime<Utc>>,
+  /// What happens to pushes made after the due date, `flag` by default
+  pub late_policy: Option<LatePolicy>,
+  /// Minutes after the due date during which pushes are still considered on time
+  pub grace_period: Option<i32>,
+  /// Percentage of the grade removed per started day of lateness
+  pub late_penalty: Option<i32>,
+}
+
+impl AssignmentMetadataRequest {
+  /// Checks the request, returning the metadata to store
+  pub fn validate(&self) -> Result<AssignmentMetadata, AssignmentError> {
+    let name = self.name.trim();
+    if name.is_empty() || name.len() > 255 {
+      return Err(AssignmentError::BadRequest(
+        "The name must be between 1 and 255 characters long".into(),
+      ));
+    }
+    if let (Some(release), Some(due)) = (self.release_date, self.due_date) {
+      if due < release {
+        return Err(AssignmentError::BadRequest(
+          "The due date must be after the release date".into(),
+        ));
+      }
+    }
+    let grace_period = self.grace_period.unwrap_or(0);
+    if grace_period < 0 {
+      return Err(AssignmentError::BadRequest(
+        "The grace period can't be negative".into(),
+      ));
+    }
+    let late_penalty = self.late_penalty.unwrap_or(0);
+    if !(0..=100).contains(&late_penalty) {
+      return Err(AssignmentError::BadRequest(
+        "The late penalty must be between 0 and 100".into(),
+      ));
+    }
+
+    Ok(AssignmentMetadata {
+      name: name.to_string(),
+      description: self.description.clone().unwrap_or_default(),
+      release_date: self.release_date.map(Into::into),
+      due_date: self.due_date.map(Into::into),
+      late_policy: self.late_policy.unwrap_or_default().into(),
+      grace_period,
+      late_penalty,
+    })
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct CreateAssignmentRequest {
+  pub group_id: i32,
+  /// Name of the repository holding the template code for the students
+  pub base_repo_name: String,
+  /// Name of the repository holding the pipeline definition
+  pub test_repo_name: Option<String>,
+  /// Name of the repository holding the teacher's correction
+  pub correction_repo_name: Option<String>,
+  #[oai(flatten)]
+  #[serde(flatten)]
+  pub metadata: AssignmentMetadataRequest,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct AssignmentResponse {
+  pub id: i32,
+  pub group_id: i32,
+  pub base_repo_id: i32,
+  pub test_repo_id: Option<i32>,
+  pub correction_repo_id: Option<i32>,
+  pub name: String,
+  pub description: String,
+  pub release_date: Option<DateTime<Utc>>,
+  pub due_date: Option<DateTime<Utc>>,
+  pub late_policy: LatePolicy,
+  pub grace_period: i32,
+  pub late_penalty: i32,
+}
+
+impl From<Assignment> for AssignmentResponse {
+  fn from(assignment: Assignment) -> Self {
+    let metadata = assignment.metadata;
+    Self {
+      id: assignment.id,
+      group_id: assignment.group_id,
+      base_repo_id: assignment.base_repo_id,
+      test_repo_id: assignment.test_repo_id,
+      correction_repo_id: assignment.correction_repo_id,
+      name: metadata.name,
+      description: metadata.description,
+      release_date: metadata.release_date.map(Into::into),
+      due_date: metadata.due_date.map(Into::into),
+      late_policy: metadata.late_policy.into(),
+      grace_period: metadata.grace_period,
+      late_penalty: metadata.late_penalty,
+    }
+  }
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum AssignmentError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("Only the teacher of the group can perform this action")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 409)]
+  #[error("{0}")]
+  Conflict(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, AssignmentError, InternalServerError);
+error_from!(std::io::Error, AssignmentError, InternalServerError);
+
+impl From<TokenError> for AssignmentError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => AssignmentError::Unauthorized,
+      TokenError::InternalServerError => AssignmentError::InternalServerError,
+    }
+  }
+}
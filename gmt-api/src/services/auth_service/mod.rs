@@ -1,7 +1,10 @@
-use std::sync::{Arc, Mutex};
+use std::{
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
 
 use database::connection_pool::ConnectionProvider;
-use gmt_common::password::PasswordAuth;
+use gmt_common::password::{hash_token, PasswordAuth};
 use hmac::{Hmac, Mac};
 use jwt::{Header, SignWithKey, Token};
 use poem_openapi::{
@@ -82,6 +85,32 @@ where
     }))
   }
 
+  /// Sets the password of a user using the token of an invitation, then logs the user in. A token
+  /// can only be used once.
+  #[oai(path = "/reset-password", method = "post")]
+  async fn reset_password(
+    &self,
+    req: Json<ResetPasswordRequest>,
+  ) -> Result<Json<LoginResponse>, AuthenticationError> {
+    let mut db = self.db.get_connection()?;
+
+    let user_id = db
+      .use_password_token(&hash_token(&req.token), SystemTime::now())?
+      .ok_or(AuthenticationError::Unauthorized)?;
+    db.set_user_password(user_id, &Pass::generate_hash(&req.password))?;
+    let user = db
+      .get_user_by_id(user_id)?
+      .ok_or(AuthenticationError::InternalServerError)?;
+
+    let key = get_secret_key()?;
+
+    let token = Token::new(Header::default(), UserToken::from(user)).sign_with_key(&key)?;
+
+    Ok(Json(LoginResponse {
+      token: token.as_str().to_string(),
+    }))
+  }
+
   /// Returns a \n separated list of keys
   #[oai(path = "/keys/:username", method = "get")]
   async fn keys(&self, username: Path<String>) -> Result<PlainText<String>, PubKeysError> {
@@ -244,6 +273,47 @@ mod tests {
     }
   }
 
+  #[rstest]
+  #[case::valid(Some(1), Ok(()))]
+  #[case::invalid(None, Err(AuthenticationError::Unauthorized))]
+  #[tokio::test]
+  async fn test_reset_password(
+    #[case] user_id: Option<i32>,
+    #[case] expected: Result<(), AuthenticationError>,
+  ) {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut user_handle = DbHandle::faux();
+      faux::when!(user_handle.use_password_token).then(move |(token_hash, _)| {
+        assert_eq!(token_hash, hash_token("token"));
+        Ok(user_id)
+      });
+      faux::when!(user_handle.set_user_password).then(|(user_id, hash)| {
+        assert_eq!(user_id, 1);
+        assert!(PasswordAuthImpl::verify_password("new password", hash));
+        Ok(())
+      });
+      faux::when!(user_handle.get_user_by_id(1)).then(|_| Ok(Some(get_user())));
+      Ok(user_handle)
+    });
+    let auth_service: AuthService<ConnectionPool, DbHandle, _> =
+      AuthService::<ConnectionPool, DbHandle, PasswordAuthImpl>::new(pool);
+
+    let res = auth_service
+      .reset_password(Json(ResetPasswordRequest {
+        token: "token".to_string(),
+        password: "new password".to_string(),
+      }))
+      .await;
+    match expected {
+      Ok(_) => assert!(res.is_ok()),
+      Err(e) => {
+        let err = res.expect_err("Expected error");
+        assert_eq!(err, e);
+      }
+    }
+  }
+
   #[rstest]
   fn test_get_secret_key() {
     let key = get_secret_key();
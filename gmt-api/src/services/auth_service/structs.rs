@@ -70,6 +70,13 @@ pub struct SignUpRequest {
   pub password: String,
 }
 
+#[derive(Object, Deserialize, Serialize)]
+pub struct ResetPasswordRequest {
+  /// The token received with the invitation
+  pub token: String,
+  pub password: String,
+}
+
 #[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
 pub enum AuthenticationError {
   #[oai(status = 403)]
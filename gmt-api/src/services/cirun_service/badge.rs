@@ -0,0 +1,89 @@
+use database::db_handle::cirun::Status;
+use jwt::{SignWithKey, VerifyWithKey};
+use serde::{Deserialize, Serialize};
+
+use crate::services::auth_service::get_secret_key;
+
+/// Rough width of a character of the 11px Verdana font used by the badges
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+
+/// The claims of the badge keys. The badges are embedded as images, which can't send an
+/// authentication header, so their URL holds a key signed for the repository instead.
+#[derive(Serialize, Deserialize)]
+struct BadgeClaims {
+  badge_repository_id: i32,
+}
+
+/// Signs the key giving access to the badge of the repository
+pub fn sign_badge_key(repository_id: i32) -> Option<String> {
+  let claims = BadgeClaims {
+    badge_repository_id: repository_id,
+  };
+  claims.sign_with_key(&get_secret_key().ok()?).ok()
+}
+
+/// Whether the key was signed for the badge of the repository
+pub fn verify_badge_key(key: &str, repository_id: i32) -> bool {
+  let Ok(secret) = get_secret_key() else {
+    return false;
+  };
+  let claims: Result<BadgeClaims, _> = key.verify_with_key(&secret);
+  claims.is_ok_and(|claims| claims.badge_repository_id == repository_id)
+}
+
+/// Returns the text and color displayed on the badge for the given status, `None` meaning the
+/// repository has no run yet.
+pub fn badge_message(status: Option<&Status>) -> (&'static str, &'static str) {
+  match status {
+    Some(Status::Success) => ("passing", "#4c1"),
+    Some(Status::Failed) => ("failing", "#e05d44"),
+    Some(Status::Pending) => ("pending", "#dfb317"),
+    Some(Status::Running) => ("running", "#dfb317"),
+    Some(Status::Cancelled) => ("cancelled", "#9f9f9f"),
+    None => ("unknown", "#9f9f9f"),
+  }
+}
+
+/// Renders a flat, shields-like badge
+pub fn render_badge(label: &str, message: &str, color: &str) -> String {
+  let label_width = label.len() * CHAR_WIDTH + PADDING;
+  let message_width = message.len() * CHAR_WIDTH + PADDING;
+  let width = label_width + message_width;
+
+  format!(
+    r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}"><title>{label}: {message}</title><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{label_x}" y="14">{label}</text><text x="{message_x}" y="14">{message}</text></g></svg>"##,
+    label_x = label_width / 2,
+    message_x = label_width + message_width / 2,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_render_badge() {
+    let (message, color) = badge_message(Some(&Status::Success));
+    let svg = render_badge("ci", message, color);
+
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains(r#"aria-label="ci: passing""#));
+    assert!(svg.contains(r##"fill="#4c1""##));
+    assert!(svg.contains(r#"width="83""#));
+  }
+
+  #[test]
+  fn test_badge_key() {
+    let key = sign_badge_key(1).expect("Unable to sign key");
+
+    assert!(verify_badge_key(&key, 1));
+    assert!(!verify_badge_key(&key, 2));
+    assert!(!verify_badge_key("invalid", 1));
+  }
+
+  #[test]
+  fn test_badge_message_without_run() {
+    assert_eq!(badge_message(None), ("unknown", "#9f9f9f"));
+  }
+}
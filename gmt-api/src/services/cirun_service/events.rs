@@ -0,0 +1,185 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Arc,
+  time::Duration,
+};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::cirun::Cirun,
+  notifications::{CirunListener, CirunNotification, CIRUN_STATUS_CHANNEL},
+};
+use futures_util::Stream;
+use log::warn;
+use tokio::{
+  sync::broadcast::{self, error::RecvError},
+  time::Instant,
+};
+
+use super::{CirunError, CirunResponse, CirunStatus, DbType};
+
+/// How often the runs are polled, in case a notification was missed
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Forwards the status changes notified by the database to the streams of the API.
+#[derive(Clone)]
+pub struct CirunEvents {
+  sender: broadcast::Sender<CirunNotification>,
+}
+
+/// Events which are never notified, leaving the streams to poll the database.
+impl Default for CirunEvents {
+  fn default() -> Self {
+    let (sender, _) = broadcast::channel(256);
+    CirunEvents { sender }
+  }
+}
+
+impl CirunEvents {
+  /// Listens to the status changes of the runs in the given database, on a thread of its own.
+  pub fn listen(database_url: String) -> Self {
+    let events = CirunEvents::default();
+    let forwarded = events.clone();
+    std::thread::spawn(move || loop {
+      match CirunListener::connect(&database_url, &[CIRUN_STATUS_CHANNEL]) {
+        Ok(mut listener) => loop {
+          match listener.wait(POLL_INTERVAL) {
+            Ok(notifications) => notifications
+              .into_iter()
+              .for_each(|notification| forwarded.notify(notification)),
+            Err(e) => {
+              warn!("Stopped listening to the CI runs: {}", e);
+              break;
+            }
+          }
+        },
+        Err(e) => warn!("Unable to listen to the CI runs: {}", e),
+      }
+      std::thread::sleep(RECONNECT_DELAY);
+    });
+    events
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<CirunNotification> {
+    self.sender.subscribe()
+  }
+
+  pub(crate) fn notify(&self, notification: CirunNotification) {
+    // Nobody may be following the runs
+    self.sender.send(notification).ok();
+  }
+}
+
+/// Follows the status of the runs of a repository, refreshing a run when notified of its changes
+/// and polling them all from time to time.
+struct StatusStream<DbPool> {
+  db: Arc<DbPool>,
+  repository_id: i32,
+  notifications: broadcast::Receiver<CirunNotification>,
+  known: HashMap<i32, CirunStatus>,
+  events: VecDeque<CirunResponse>,
+  polled: bool,
+  next_poll: Instant,
+}
+
+impl<DbPool, Db> StatusStream<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db>,
+  Db: DbType,
+{
+  /// Queues the runs whose status changed since they were last seen. The runs completed before
+  /// the first poll aren't sent.
+  fn update(&mut self, cirun: Cirun) {
+    let status = CirunStatus::from(cirun.status);
+    let changed = match self.known.insert(cirun.id, status) {
+      Some(previous) => previous != status,
+      None => self.polled || !cirun.status.is_completed(),
+    };
+    if changed {
+      self.events.push_back(cirun.into());
+    }
+  }
+
+  fn poll(&mut self) -> Result<(), CirunError> {
+    let mut db = self.db.get_connection()?;
+    let mut ciruns = db.list_repository_ciruns(self.repository_id)?;
+    ciruns.sort_by_key(|c| c.id);
+    ciruns.into_iter().for_each(|cirun| self.update(cirun));
+    self.polled = true;
+    self.next_poll = Instant::now() + POLL_INTERVAL;
+    Ok(())
+  }
+
+  fn refresh(&mut self, cirun_id: i32) -> Result<(), CirunError> {
+    let mut db = self.db.get_connection()?;
+    if let Some(cirun) = db.get_cirun_by_id(cirun_id)? {
+      self.update(cirun);
+    }
+    Ok(())
+  }
+
+  /// Waits for the next notification about the repository, or for the next poll.
+  async fn wait(&mut self) -> Result<(), CirunError> {
+    match tokio::time::timeout_at(self.next_poll, self.notifications.recv()).await {
+      Ok(Ok(CirunNotification::StatusChanged {
+        cirun_id,
+        repository_id,
+        ..
+      }))
+        if repository_id == self.repository_id =>
+      {
+        self.refresh(cirun_id)
+      }
+      Ok(Ok(_)) => Ok(()),
+      // Some notifications were missed
+      Ok(Err(RecvError::Lagged(_))) => self.poll(),
+      Ok(Err(RecvError::Closed)) => {
+        tokio::time::sleep_until(self.next_poll).await;
+        self.poll()
+      }
+      Err(_) => self.poll(),
+    }
+  }
+}
+
+/// Streams the runs of the repository each time one is queued or changes status, starting with
+/// the runs which haven't completed yet.
+pub fn status_stream<DbPool, Db>(
+  db: Arc<DbPool>,
+  repository_id: i32,
+  notifications: broadcast::Receiver<CirunNotification>,
+) -> impl Stream<Item = CirunResponse> + Send + 'static
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+{
+  let stream = StatusStream {
+    db,
+    repository_id,
+    notifications,
+    known: HashMap::new(),
+    events: VecDeque::new(),
+    polled: false,
+    next_poll: Instant::now(),
+  };
+
+  futures_util::stream::unfold(stream, |mut stream| async move {
+    loop {
+      if let Some(event) = stream.events.pop_front() {
+        return Some((event, stream));
+      }
+      let result = match stream.polled {
+        true => stream.wait().await,
+        false => stream.poll(),
+      };
+      if let Err(e) = result {
+        warn!(
+          "Unable to follow the runs of repository {}: {}",
+          stream.repository_id, e
+        );
+        return None;
+      }
+    }
+  })
+}
@@ -0,0 +1,98 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use database::connection_pool::ConnectionProvider;
+use futures_util::Stream;
+use log::warn;
+
+use super::{CirunError, CirunLogEvent, CirunStepResponse, DbType};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Follows the steps and the log of a run by polling the database, until the run completes.
+struct LogStream<DbPool> {
+  db: Arc<DbPool>,
+  cirun_id: i32,
+  steps: Vec<CirunStepResponse>,
+  last_chunk: Option<i32>,
+  events: VecDeque<CirunLogEvent>,
+  polled: bool,
+  completed: bool,
+}
+
+impl<DbPool, Db> LogStream<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db>,
+  Db: DbType,
+{
+  /// Queues the changes since the previous poll.
+  fn poll(&mut self) -> Result<(), CirunError> {
+    let mut db = self.db.get_connection()?;
+
+    // The status is read first, since a completed run has all its steps and logs stored
+    let cirun = db
+      .get_cirun_by_id(self.cirun_id)?
+      .ok_or(CirunError::NotFound("CI run".into()))?;
+
+    for step in db.list_cirun_steps(self.cirun_id)? {
+      let step = CirunStepResponse::from(step);
+      match self.steps.iter_mut().find(|known| known.id == step.id) {
+        Some(known) if *known == step => continue,
+        Some(known) => *known = step.clone(),
+        None => self.steps.push(step.clone()),
+      }
+      self.events.push_back(CirunLogEvent::Step(step));
+    }
+
+    for chunk in db.list_cirun_log_chunks(self.cirun_id, self.last_chunk)? {
+      self.last_chunk = Some(chunk.id);
+      self.events.push_back(CirunLogEvent::Log(chunk.into()));
+    }
+
+    if cirun.status.is_completed() {
+      self.events.push_back(CirunLogEvent::End(cirun.into()));
+      self.completed = true;
+    }
+    Ok(())
+  }
+}
+
+/// Streams the steps of the run and their log, starting after the chunk `after`. The stream ends
+/// once the run completes.
+pub fn log_stream<DbPool, Db>(
+  db: Arc<DbPool>,
+  cirun_id: i32,
+  after: Option<i32>,
+) -> impl Stream<Item = CirunLogEvent> + Send + 'static
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+{
+  let stream = LogStream {
+    db,
+    cirun_id,
+    steps: Vec::new(),
+    last_chunk: after,
+    events: VecDeque::new(),
+    polled: false,
+    completed: false,
+  };
+
+  futures_util::stream::unfold(stream, |mut stream| async move {
+    loop {
+      if let Some(event) = stream.events.pop_front() {
+        return Some((event, stream));
+      }
+      if stream.completed {
+        return None;
+      }
+      if stream.polled {
+        tokio::time::sleep(POLL_INTERVAL).await;
+      }
+      stream.polled = true;
+      if let Err(e) = stream.poll() {
+        warn!("Unable to follow the log of run {}: {}", stream.cirun_id, e);
+        return None;
+      }
+    }
+  })
+}
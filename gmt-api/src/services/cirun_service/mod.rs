@@ -0,0 +1,1010 @@
+use std::{
+  cmp::Reverse,
+  sync::{Arc, Mutex},
+  time::{Duration, SystemTime},
+};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{cirun::Cirun, repository::Repository},
+};
+use futures_util::{stream::BoxStream, StreamExt};
+use gmt_common::{
+  permissions::{can_read_repository, can_write_repository},
+  repositories::repository_storage::is_valid_branch_name,
+};
+use poem::{web::sse::Event, Body};
+use poem_openapi::{
+  param::{Path, Query},
+  payload::{Binary, EventStream, Json, PlainText},
+  types::ToJSON,
+  OpenApi,
+};
+
+use crate::security::gmt_token::GmtToken;
+
+use self::{
+  badge::{badge_message, render_badge, sign_badge_key, verify_badge_key},
+  events::status_stream,
+  logs::log_stream,
+};
+
+pub mod badge;
+pub mod events;
+pub mod logs;
+pub mod structs;
+
+pub use structs::*;
+
+#[OpenApi]
+impl<DbPool, Db> CirunService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the CI runs of the repository, latest first
+  #[oai(path = "/repositories/:id/ciruns", method = "get")]
+  async fn list_ciruns(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<CirunResponse>>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let mut ciruns = db.list_repository_ciruns(repository.id)?;
+    ciruns.sort_by_key(|c| Reverse(c.id));
+    Ok(Json(ciruns.into_iter().map(CirunResponse::from).collect()))
+  }
+
+  /// Streams the runs of the repository as server-sent events, each time one is queued or changes
+  /// status. The runs which haven't completed yet are sent first.
+  #[oai(path = "/repositories/:id/ciruns/events", method = "get")]
+  async fn stream_repository_ciruns(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<EventStream<BoxStream<'static, CirunResponse>>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let stream = status_stream(self.db.clone(), repository.id, self.events.subscribe()).boxed();
+    Ok(
+      EventStream::new(stream)
+        .keep_alive(Duration::from_secs(15))
+        .to_event(|cirun| Event::message(cirun.to_json_string()).event_type("status")),
+    )
+  }
+
+  #[oai(path = "/ciruns/:id", method = "get")]
+  async fn get_cirun(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CirunResponse>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    Ok(Json(cirun.into()))
+  }
+
+  /// Cancels a run which hasn't completed yet. A running run stops before its next step.
+  #[oai(path = "/ciruns/:id/cancel", method = "post")]
+  async fn cancel_cirun(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CirunResponse>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_write_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let cirun = db
+      .cancel_cirun(cirun.id)?
+      .ok_or(CirunError::Conflict("CI run is already completed".into()))?;
+    Ok(Json(cirun.into()))
+  }
+
+  /// Queues a completed run again
+  #[oai(path = "/ciruns/:id/rerun", method = "post")]
+  async fn rerun_cirun(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CirunResponse>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_write_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let cirun = db
+      .requeue_cirun(cirun.id)?
+      .ok_or(CirunError::Conflict("CI run hasn't completed yet".into()))?;
+    Ok(Json(cirun.into()))
+  }
+
+  /// Lists the steps of the run, in the order of the pipeline
+  #[oai(path = "/ciruns/:id/steps", method = "get")]
+  async fn list_cirun_steps(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<CirunStepResponse>>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let steps = db.list_cirun_steps(cirun.id)?;
+    Ok(Json(
+      steps.into_iter().map(CirunStepResponse::from).collect(),
+    ))
+  }
+
+  /// Returns the log of a step of the run, as stored so far
+  #[oai(path = "/ciruns/:id/steps/:step_id/log", method = "get")]
+  async fn get_cirun_step_log(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    step_id: Path<i32>,
+  ) -> Result<PlainText<String>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+    let step = db
+      .get_cirun_step_by_id(step_id.0)?
+      .filter(|step| step.cirun_id == cirun.id)
+      .ok_or(CirunError::NotFound("Step".into()))?;
+
+    Ok(PlainText(db.get_cirun_step_log(step.id)?))
+  }
+
+  /// Streams the steps of the run and their log as server-sent events, until the run completes.
+  /// A client reconnecting can resume the log after the id of the last chunk it received.
+  #[oai(path = "/ciruns/:id/logs", method = "get")]
+  async fn stream_cirun_logs(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    after: Query<Option<i32>>,
+  ) -> Result<EventStream<BoxStream<'static, CirunLogEvent>>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let stream = log_stream(self.db.clone(), cirun.id, after.0).boxed();
+    Ok(
+      EventStream::new(stream)
+        .keep_alive(Duration::from_secs(15))
+        .to_event(|event| Event::message(event.to_json_string()).event_type(event.kind())),
+    )
+  }
+
+  /// Lists the artifacts kept by the run which haven't expired yet
+  #[oai(path = "/ciruns/:id/artifacts", method = "get")]
+  async fn list_cirun_artifacts(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<ArtifactResponse>>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let now = SystemTime::now();
+    let artifacts = db.list_cirun_artifacts(cirun.id)?;
+    Ok(Json(
+      artifacts
+        .into_iter()
+        .filter(|artifact| !artifact.is_expired(now))
+        .map(ArtifactResponse::from)
+        .collect(),
+    ))
+  }
+
+  /// Downloads an artifact of the run, as long as it hasn't expired
+  #[oai(path = "/ciruns/:id/artifacts/:artifact_id", method = "get")]
+  async fn download_cirun_artifact(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    artifact_id: Path<i32>,
+  ) -> Result<ArtifactDownloadResponse, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (cirun, repository) = find_cirun(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+    let artifact = db
+      .get_artifact_by_id(artifact_id.0)?
+      .filter(|artifact| artifact.cirun_id == cirun.id && !artifact.is_expired(SystemTime::now()))
+      .ok_or(CirunError::NotFound("Artifact".into()))?;
+
+    // The file may have been purged since the artifact was listed
+    let path = self
+      .artifacts
+      .get_path(&artifact.content_hash)
+      .ok_or(CirunError::NotFound("Artifact".into()))?;
+    let file = match std::fs::File::open(path) {
+      Ok(file) => tokio::fs::File::from_std(file),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        return Err(CirunError::NotFound("Artifact".into()))
+      }
+      Err(e) => return Err(e.into()),
+    };
+
+    Ok(ArtifactDownloadResponse::Artifact(
+      Binary(Body::from_async_read(file)),
+      format!("attachment; filename=\"{}\"", artifact.file_name),
+    ))
+  }
+
+  /// Returns the key giving access to the badge of the repository without authentication, so it
+  /// can be embedded as an image
+  #[oai(path = "/repositories/:id/badge/key", method = "get")]
+  async fn get_badge_key(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<BadgeKeyResponse>, CirunError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CirunError::Forbidden);
+    }
+
+    let key = sign_badge_key(repository.id).ok_or(CirunError::InternalServerError)?;
+    Ok(Json(BadgeKeyResponse { key }))
+  }
+
+  /// Renders the status of the run for the head of the branch as an SVG badge. Without a branch,
+  /// the latest run of the repository is used. The badge is accessed with the key of the
+  /// repository rather than an authentication token.
+  #[oai(path = "/repositories/:id/badge", method = "get")]
+  async fn get_badge(
+    &self,
+    id: Path<i32>,
+    key: Query<String>,
+    branch: Query<Option<String>>,
+  ) -> Result<BadgeResponse, CirunError> {
+    if !verify_badge_key(&key.0, id.0) {
+      return Err(CirunError::Forbidden);
+    }
+    if let Some(branch) = &branch.0 {
+      if !is_valid_branch_name(branch) {
+        return Err(CirunError::BadRequest(
+          format!("Invalid branch name: {}", branch).into(),
+        ));
+      }
+    }
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    let cirun = match &branch.0 {
+      Some(branch) => match self.storage.resolve_branch(&repository.name, branch)? {
+        Some(commit) => db.get_cirun_by_commit(repository.id, &commit)?,
+        None => None,
+      },
+      None => db
+        .list_repository_ciruns(repository.id)?
+        .into_iter()
+        .max_by_key(|c| c.id),
+    };
+
+    let (message, color) = badge_message(cirun.as_ref().map(|c| &c.status));
+    Ok(BadgeResponse::Badge(
+      PlainText(render_badge("ci", message, color)),
+      "no-cache".to_string(),
+    ))
+  }
+}
+
+fn find_repository<Db: DbType>(db: &mut Db, repository_id: i32) -> Result<Repository, CirunError> {
+  db.get_repository_by_id(repository_id)?
+    .ok_or(CirunError::NotFound("Repository".into()))
+}
+
+fn find_cirun<Db: DbType>(db: &mut Db, cirun_id: i32) -> Result<(Cirun, Repository), CirunError> {
+  let cirun = db
+    .get_cirun_by_id(cirun_id)?
+    .ok_or(CirunError::NotFound("CI run".into()))?;
+  let repository = find_repository(db, cirun.repository_id)?;
+  Ok((cirun, repository))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::events::CirunEvents;
+  use super::*;
+  use crate::services::test_utils::{cirun, repository, valid_token};
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      artifact::Artifact,
+      cirun::Status,
+      cirun_step::{CirunLogChunk, CirunStep, StepStatus},
+    },
+    notifications::CirunNotification,
+    DbHandle,
+  };
+  use gmt_common::{
+    artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+  };
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::{types::ParseFromJSON, OpenApiService};
+  use rstest::rstest;
+  use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+  };
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn client<F>(storage: RepositoryStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    client_with_events(storage, CirunEvents::default(), setup)
+  }
+
+  fn client_with_events<F>(
+    storage: RepositoryStorage,
+    events: CirunEvents,
+    setup: F,
+  ) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let artifacts = ArtifactStorage::new(PathBuf::from("artifacts"));
+    service_client(storage, artifacts, events, setup)
+  }
+
+  fn client_with_artifacts<F>(artifacts: ArtifactStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    service_client(
+      RepositoryStorage::faux(),
+      artifacts,
+      CirunEvents::default(),
+      setup,
+    )
+  }
+
+  fn service_client<F>(
+    storage: RepositoryStorage,
+    artifacts: ArtifactStorage,
+    events: CirunEvents,
+    setup: F,
+  ) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      CirunService::<ConnectionPool, DbHandle>::new(
+        pool,
+        Arc::new(storage),
+        Arc::new(artifacts),
+        events,
+      ),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn step(cirun_id: i32, status: StepStatus) -> CirunStep {
+    CirunStep {
+      id: 1,
+      cirun_id,
+      position: 0,
+      name: "test".to_string(),
+      status,
+      started_at: None,
+      finished_at: None,
+      exit_code: None,
+      log_size: 0,
+      log_truncated: false,
+    }
+  }
+
+  fn chunk(id: i32, content: &str) -> CirunLogChunk {
+    CirunLogChunk {
+      id,
+      step_id: 1,
+      content: content.to_string(),
+    }
+  }
+
+  fn artifact(id: i32, cirun_id: i32, content_hash: &str, expires_at: SystemTime) -> Artifact {
+    Artifact {
+      id,
+      cirun_id,
+      name: format!("artifact-{}", id),
+      file_name: format!("artifact-{}.html", id),
+      content_hash: content_hash.to_string(),
+      size: 6,
+      created_at: SystemTime::UNIX_EPOCH,
+      expires_at,
+    }
+  }
+
+  /// Sets up a repository owned by `owner_id`, with no assignment using it
+  fn setup_repository(db: &mut DbHandle, owner_id: i32) {
+    faux::when!(db.get_repository_by_id(1)).then(move |_| Ok(Some(repository(1, owner_id, None))));
+    faux::when!(db.list_repository_assignments(1)).then(|_| Ok(vec![]));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_ciruns(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      setup_repository(db, USER_ID);
+      faux::when!(db.list_repository_ciruns(1)).then(|_| {
+        Ok(vec![
+          cirun(1, 1, Status::Success),
+          cirun(2, 1, Status::Pending),
+        ])
+      });
+    });
+
+    let resp = client
+      .get("/repositories/1/ciruns")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    assert_eq!(
+      resp
+        .json()
+        .await
+        .value()
+        .deserialize::<Vec<CirunResponse>>(),
+      vec![
+        CirunResponse::from(cirun(2, 1, Status::Pending)),
+        CirunResponse::from(cirun(1, 1, Status::Success)),
+      ]
+    );
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_get_cirun(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+    });
+
+    let resp = client
+      .get("/ciruns/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::pending(USER_ID, true, StatusCode::OK)]
+  #[case::completed(USER_ID, false, StatusCode::CONFLICT)]
+  #[case::stranger(OTHER_ID, true, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_cancel_cirun(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] cancellable: bool,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Running))));
+      faux::when!(db.cancel_cirun(1))
+        .then(move |id| Ok(cancellable.then(|| cirun(id, 1, Status::Cancelled))));
+    });
+
+    let resp = client
+      .post("/ciruns/1/cancel")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      assert_eq!(
+        resp.json().await.value().deserialize::<CirunResponse>(),
+        CirunResponse::from(cirun(1, 1, Status::Cancelled))
+      );
+    }
+  }
+
+  #[rstest]
+  #[case::completed(USER_ID, true, StatusCode::OK)]
+  #[case::running(USER_ID, false, StatusCode::CONFLICT)]
+  #[case::stranger(OTHER_ID, true, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_rerun_cirun(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] completed: bool,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+      faux::when!(db.requeue_cirun(1))
+        .then(move |id| Ok(completed.then(|| cirun(id, 1, Status::Pending))));
+    });
+
+    let resp = client
+      .post("/ciruns/1/rerun")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      assert_eq!(
+        resp.json().await.value().deserialize::<CirunResponse>(),
+        CirunResponse::from(cirun(1, 1, Status::Pending))
+      );
+    }
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_list_cirun_steps(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+      faux::when!(db.list_cirun_steps(1)).then(|_| Ok(vec![step(1, StepStatus::Failed)]));
+    });
+
+    let resp = client
+      .get("/ciruns/1/steps")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      resp
+        .assert_json(vec![CirunStepResponse::from(step(1, StepStatus::Failed))])
+        .await;
+    }
+  }
+
+  #[rstest]
+  #[case::step_of_the_run(1, StatusCode::OK)]
+  #[case::step_of_another_run(2, StatusCode::NOT_FOUND)]
+  #[tokio::test]
+  async fn test_get_cirun_step_log(
+    valid_token: String,
+    #[case] step_cirun_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, USER_ID);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+      faux::when!(db.get_cirun_step_by_id(1))
+        .then(move |_| Ok(Some(step(step_cirun_id, StepStatus::Failed))));
+      faux::when!(db.get_cirun_step_log(1)).then(|_| Ok("assertion failed\n".to_string()));
+    });
+
+    let resp = client
+      .get("/ciruns/1/steps/1/log")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      resp.assert_text("assertion failed\n").await;
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_stream_cirun_logs(valid_token: String) {
+    // The run completes after the first poll of the log
+    let polls = Arc::new(AtomicUsize::new(0));
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, USER_ID);
+      let polled = polls.clone();
+      faux::when!(db.get_cirun_by_id(1)).then(move |_| {
+        Ok(Some(match polled.load(Ordering::SeqCst) {
+          0 => cirun(1, 1, Status::Pending),
+          _ => cirun(1, 1, Status::Success),
+        }))
+      });
+      let polled = polls.clone();
+      faux::when!(db.list_cirun_steps(1)).then(move |_| {
+        Ok(vec![match polled.load(Ordering::SeqCst) {
+          0 => step(1, StepStatus::Running),
+          _ => step(1, StepStatus::Success),
+        }])
+      });
+      let polled = polls.clone();
+      faux::when!(db.list_cirun_log_chunks).then(move |(cirun_id, after)| {
+        assert_eq!(cirun_id, 1);
+        polled.fetch_add(1, Ordering::SeqCst);
+        Ok(match after {
+          None => vec![chunk(1, "Running tests\n")],
+          Some(_) => vec![chunk(2, "All tests passed\n")],
+        })
+      });
+    });
+
+    let resp = client
+      .get("/ciruns/1/logs")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/event-stream");
+    let body = resp.0.into_body().into_string().await.unwrap();
+
+    let events: Vec<(String, CirunLogEvent)> = body
+      .split("\n\n")
+      .filter(|event| event.contains("data: "))
+      .map(|event| {
+        let field = |name: &str| {
+          event
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .unwrap()
+            .to_string()
+        };
+        let data = CirunLogEvent::parse_from_json_string(&field("data: ")).expect("Invalid event");
+        (field("event: "), data)
+      })
+      .collect();
+    assert_eq!(
+      events,
+      vec![
+        (
+          "step".to_string(),
+          CirunLogEvent::Step(step(1, StepStatus::Running).into())
+        ),
+        (
+          "log".to_string(),
+          CirunLogEvent::Log(chunk(1, "Running tests\n").into())
+        ),
+        (
+          "step".to_string(),
+          CirunLogEvent::Step(step(1, StepStatus::Success).into())
+        ),
+        (
+          "log".to_string(),
+          CirunLogEvent::Log(chunk(2, "All tests passed\n").into())
+        ),
+        (
+          "end".to_string(),
+          CirunLogEvent::End(cirun(1, 1, Status::Success).into())
+        ),
+      ]
+    );
+  }
+
+  /// Reads the next status event of a stream, keeping what was received after it
+  async fn next_status_event<S, B>(body: &mut S, received: &mut String) -> CirunResponse
+  where
+    S: futures_util::Stream<Item = Result<B, std::io::Error>> + Unpin,
+    B: AsRef<[u8]>,
+  {
+    loop {
+      if let Some(end) = received.find("\n\n") {
+        let event: String = received.drain(..end + 2).collect();
+        assert!(event.contains("event: status"));
+        let data = event
+          .lines()
+          .find_map(|line| line.strip_prefix("data: "))
+          .expect("Event without data");
+        return CirunResponse::parse_from_json_string(data).expect("Invalid event");
+      }
+      let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+        .await
+        .expect("No event received")
+        .unwrap()
+        .unwrap();
+      received.push_str(&String::from_utf8_lossy(chunk.as_ref()));
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_stream_repository_ciruns(valid_token: String) {
+    let events = CirunEvents::default();
+    let client = client_with_events(RepositoryStorage::faux(), events.clone(), |db| {
+      setup_repository(db, USER_ID);
+      faux::when!(db.list_repository_ciruns(1)).then(|_| {
+        Ok(vec![
+          cirun(2, 1, Status::Running),
+          cirun(1, 1, Status::Success),
+        ])
+      });
+      faux::when!(db.get_cirun_by_id(3)).then(|_| Ok(Some(cirun(3, 1, Status::Pending))));
+    });
+
+    let resp = client
+      .get("/repositories/1/ciruns/events")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/event-stream");
+    let mut body = Box::pin(resp.0.into_body().into_bytes_stream());
+    let mut received = String::new();
+
+    // Only the runs which haven't completed are sent at first
+    assert_eq!(
+      next_status_event(&mut body, &mut received).await,
+      cirun(2, 1, Status::Running).into()
+    );
+
+    // The runs of other repositories are ignored
+    events.notify(CirunNotification::StatusChanged {
+      cirun_id: 4,
+      repository_id: 2,
+      status: Status::Pending,
+    });
+    events.notify(CirunNotification::StatusChanged {
+      cirun_id: 3,
+      repository_id: 1,
+      status: Status::Pending,
+    });
+    assert_eq!(
+      next_status_event(&mut body, &mut received).await,
+      cirun(3, 1, Status::Pending).into()
+    );
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_stream_repository_ciruns_forbidden(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      setup_repository(db, OTHER_ID);
+    });
+
+    let resp = client
+      .get("/repositories/1/ciruns/events")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_get_badge_key(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+    });
+
+    let resp = client
+      .get("/repositories/1/badge/key")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let key = resp.json().await.value().deserialize::<BadgeKeyResponse>();
+      assert!(verify_badge_key(&key.key, 1));
+    }
+  }
+
+  #[rstest]
+  #[case::branch(Some("main"), "failing")]
+  #[case::unknown_branch(Some("other"), "unknown")]
+  #[case::latest(None, "passing")]
+  #[tokio::test]
+  async fn test_get_badge(#[case] branch: Option<&str>, #[case] expected: &str) {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.resolve_branch("repo-1", "main"))
+      .then(|_| Ok(Some("commit-1".to_string())));
+    faux::when!(storage.resolve_branch("repo-1", "other")).then(|_| Ok(None));
+    let client = client(storage, |db| {
+      setup_repository(db, USER_ID);
+      faux::when!(db.get_cirun_by_commit(1, "commit-1"))
+        .then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+      faux::when!(db.list_repository_ciruns(1)).then(|_| {
+        Ok(vec![
+          cirun(2, 1, Status::Success),
+          cirun(1, 1, Status::Failed),
+        ])
+      });
+    });
+
+    // Without any authentication header, as the images embedding the badge
+    let mut req = client
+      .get("/repositories/1/badge")
+      .query("key", &sign_badge_key(1).unwrap());
+    if let Some(branch) = branch {
+      req = req.query("branch", &branch);
+    }
+    let resp = req.send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("image/svg+xml");
+    let body = resp.0.into_body().into_string().await.unwrap();
+    assert!(
+      body.contains(&format!("ci: {}", expected)),
+      "Unexpected badge: {}",
+      body
+    );
+  }
+
+  #[rstest]
+  #[case::other_repository(sign_badge_key(2).unwrap(), "main", StatusCode::FORBIDDEN)]
+  #[case::invalid_key("invalid".to_string(), "main", StatusCode::FORBIDDEN)]
+  #[case::revision(sign_badge_key(1).unwrap(), "main~3", StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_get_badge_refused(
+    #[case] key: String,
+    #[case] branch: &str,
+    #[case] expected: StatusCode,
+  ) {
+    // Neither the storage nor the database are reached
+    let client = client(RepositoryStorage::faux(), |_| {});
+
+    let resp = client
+      .get("/repositories/1/badge")
+      .query("key", &key)
+      .query("branch", &branch)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_list_cirun_artifacts(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let later = SystemTime::now() + Duration::from_secs(3600);
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Success))));
+      faux::when!(db.list_cirun_artifacts(1)).then(move |_| {
+        Ok(vec![
+          artifact(1, 1, "hash", later),
+          // Not purged yet
+          artifact(2, 1, "hash", SystemTime::UNIX_EPOCH),
+        ])
+      });
+    });
+
+    let resp = client
+      .get("/ciruns/1/artifacts")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      assert_eq!(
+        resp
+          .json()
+          .await
+          .value()
+          .deserialize::<Vec<ArtifactResponse>>(),
+        vec![ArtifactResponse::from(artifact(1, 1, "hash", later))]
+      );
+    }
+  }
+
+  #[rstest]
+  #[case::artifact_of_the_run(1, USER_ID, StatusCode::OK)]
+  #[case::artifact_of_another_run(2, USER_ID, StatusCode::NOT_FOUND)]
+  #[case::expired(3, USER_ID, StatusCode::NOT_FOUND)]
+  #[case::purged(4, USER_ID, StatusCode::NOT_FOUND)]
+  #[case::stranger(1, OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_download_cirun_artifact(
+    valid_token: String,
+    #[case] artifact_id: i32,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let dir = tempfile::tempdir().unwrap();
+    let artifacts = ArtifactStorage::new(dir.path().join("artifacts"));
+    let file = dir.path().join("index.html");
+    std::fs::write(&file, "<html>").unwrap();
+    let (hash, _) = artifacts.store(&file).unwrap();
+
+    let later = SystemTime::now() + Duration::from_secs(3600);
+    let client = client_with_artifacts(artifacts, move |db| {
+      setup_repository(db, owner_id);
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(cirun(1, 1, Status::Success))));
+      let hash = hash.clone();
+      faux::when!(db.get_artifact_by_id).then(move |id| {
+        Ok(Some(match id {
+          1 => artifact(1, 1, &hash, later),
+          2 => artifact(2, 2, &hash, later),
+          3 => artifact(3, 1, &hash, SystemTime::UNIX_EPOCH),
+          _ => artifact(id, 1, &"0".repeat(64), later),
+        }))
+      });
+    });
+
+    let resp = client
+      .get(format!("/ciruns/1/artifacts/{}", artifact_id))
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      resp.assert_content_type("application/octet-stream");
+      resp.assert_header(
+        "Content-Disposition",
+        "attachment; filename=\"artifact-1.html\"",
+      );
+      resp.assert_text("<html>").await;
+    }
+  }
+}
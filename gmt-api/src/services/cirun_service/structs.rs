@@ -0,0 +1,327 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    artifact::{Artifact, ArtifactDbHandle},
+    assignment::AssignmentDbHandle,
+    cirun::{Cirun, CirunDbHandle, Status},
+    cirun_step::{CirunLogChunk, CirunStep, CirunStepDbHandle, StepStatus},
+    group::GroupDbHandle,
+    repository::RepositoryDbHandle,
+  },
+  error::DatabaseError,
+};
+use gmt_common::{
+  artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+};
+use poem::Body;
+use poem_openapi::{
+  payload::{Binary, PlainText},
+  ApiResponse, Enum, Object, Union,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::{super::structs::StringResponse, events::CirunEvents};
+
+pub trait DbType:
+  ArtifactDbHandle
+  + AssignmentDbHandle
+  + CirunDbHandle
+  + CirunStepDbHandle
+  + GroupDbHandle
+  + RepositoryDbHandle
+  + 'static
+{
+}
+impl<T> DbType for T where
+  T: ArtifactDbHandle
+    + AssignmentDbHandle
+    + CirunDbHandle
+    + CirunStepDbHandle
+    + GroupDbHandle
+    + RepositoryDbHandle
+    + 'static
+{
+}
+
+pub struct CirunService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Shared with the log streams, which outlive the requests
+  pub db: Arc<DbPool>,
+  pub storage: Arc<RepositoryStorage>,
+  /// The artifacts kept by the workers
+  pub artifacts: Arc<ArtifactStorage>,
+  /// The status changes notified by the database
+  pub events: CirunEvents,
+}
+
+impl<DbPool, Db> CirunService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(
+    db: DbPool,
+    storage: Arc<RepositoryStorage>,
+    artifacts: Arc<ArtifactStorage>,
+    events: CirunEvents,
+  ) -> Self {
+    Self {
+      db: Arc::new(db),
+      storage,
+      artifacts,
+      events,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CirunStatus {
+  Success,
+  Pending,
+  Running,
+  Cancelled,
+  Failed,
+}
+
+impl From<Status> for CirunStatus {
+  fn from(status: Status) -> Self {
+    match status {
+      Status::Success => CirunStatus::Success,
+      Status::Pending => CirunStatus::Pending,
+      Status::Running => CirunStatus::Running,
+      Status::Cancelled => CirunStatus::Cancelled,
+      Status::Failed => CirunStatus::Failed,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CirunResponse {
+  pub id: i32,
+  pub repository_id: i32,
+  pub commit: String,
+  pub status: CirunStatus,
+  pub created_at: DateTime<Utc>,
+  /// When the latest attempt started
+  pub started_at: Option<DateTime<Utc>>,
+  pub finished_at: Option<DateTime<Utc>>,
+  /// The reference whose update queued the run, such as `refs/heads/main`
+  pub ref_name: Option<String>,
+  /// The user who queued the run
+  pub triggered_by: Option<i32>,
+  /// The worker which ran the latest attempt
+  pub worker_id: Option<String>,
+  /// The number of times a worker started the run
+  pub attempt: i32,
+}
+
+impl From<Cirun> for CirunResponse {
+  fn from(cirun: Cirun) -> Self {
+    Self {
+      id: cirun.id,
+      repository_id: cirun.repository_id,
+      commit: cirun.commit,
+      status: cirun.status.into(),
+      created_at: cirun.created_at.into(),
+      started_at: cirun.started_at.map(DateTime::from),
+      finished_at: cirun.finished_at.map(DateTime::from),
+      ref_name: cirun.ref_name,
+      triggered_by: cirun.triggered_by,
+      worker_id: cirun.worker_id,
+      attempt: cirun.attempt,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CirunStepStatus {
+  Pending,
+  Running,
+  Success,
+  Failed,
+  TimedOut,
+  Skipped,
+}
+
+impl From<StepStatus> for CirunStepStatus {
+  fn from(status: StepStatus) -> Self {
+    match status {
+      StepStatus::Pending => CirunStepStatus::Pending,
+      StepStatus::Running => CirunStepStatus::Running,
+      StepStatus::Success => CirunStepStatus::Success,
+      StepStatus::Failed => CirunStepStatus::Failed,
+      StepStatus::TimedOut => CirunStepStatus::TimedOut,
+      StepStatus::Skipped => CirunStepStatus::Skipped,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct CirunStepResponse {
+  pub id: i32,
+  pub position: i32,
+  pub name: String,
+  pub status: CirunStepStatus,
+  pub started_at: Option<DateTime<Utc>>,
+  pub finished_at: Option<DateTime<Utc>>,
+  pub exit_code: Option<i32>,
+  /// The size of the log, in bytes
+  pub log_size: i32,
+  /// Whether the output of the step exceeded the size limit and was cut
+  pub log_truncated: bool,
+}
+
+impl From<CirunStep> for CirunStepResponse {
+  fn from(step: CirunStep) -> Self {
+    Self {
+      id: step.id,
+      position: step.position,
+      name: step.name,
+      status: step.status.into(),
+      started_at: step.started_at.map(DateTime::from),
+      finished_at: step.finished_at.map(DateTime::from),
+      exit_code: step.exit_code,
+      log_size: step.log_size,
+      log_truncated: step.log_truncated,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CirunLogChunkResponse {
+  /// Increases along the log of the run, to resume the stream after it
+  pub id: i32,
+  pub step_id: i32,
+  pub content: String,
+}
+
+impl From<CirunLogChunk> for CirunLogChunkResponse {
+  fn from(chunk: CirunLogChunk) -> Self {
+    Self {
+      id: chunk.id,
+      step_id: chunk.step_id,
+      content: chunk.content,
+    }
+  }
+}
+
+/// An event of the log stream of a run.
+#[derive(Union, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[oai(discriminator_name = "type")]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CirunLogEvent {
+  /// A step was created or changed status
+  #[oai(mapping = "step")]
+  Step(CirunStepResponse),
+  /// Some output of a step
+  #[oai(mapping = "log")]
+  Log(CirunLogChunkResponse),
+  /// The run completed, this is the last event of the stream
+  #[oai(mapping = "end")]
+  End(CirunResponse),
+}
+
+impl CirunLogEvent {
+  pub fn kind(&self) -> &'static str {
+    match self {
+      CirunLogEvent::Step(_) => "step",
+      CirunLogEvent::Log(_) => "log",
+      CirunLogEvent::End(_) => "end",
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct ArtifactResponse {
+  pub id: i32,
+  pub cirun_id: i32,
+  /// The name given by the pipeline
+  pub name: String,
+  /// The name of the downloaded file, directories being archived as `.tar.gz`
+  pub file_name: String,
+  /// The size of the file, in bytes
+  pub size: i64,
+  pub created_at: DateTime<Utc>,
+  /// When the artifact is deleted
+  pub expires_at: DateTime<Utc>,
+}
+
+impl From<Artifact> for ArtifactResponse {
+  fn from(artifact: Artifact) -> Self {
+    Self {
+      id: artifact.id,
+      cirun_id: artifact.cirun_id,
+      name: artifact.name,
+      file_name: artifact.file_name,
+      size: artifact.size,
+      created_at: artifact.created_at.into(),
+      expires_at: artifact.expires_at.into(),
+    }
+  }
+}
+
+#[derive(ApiResponse)]
+pub enum ArtifactDownloadResponse {
+  #[oai(status = 200, content_type = "application/octet-stream")]
+  Artifact(Binary<Body>, #[oai(header = "Content-Disposition")] String),
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct BadgeKeyResponse {
+  /// Given as the `key` query parameter of the badge, which doesn't need an authentication header
+  pub key: String,
+}
+
+#[derive(ApiResponse)]
+pub enum BadgeResponse {
+  #[oai(status = 200, content_type = "image/svg+xml")]
+  Badge(PlainText<String>, #[oai(header = "Cache-Control")] String),
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CirunError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("You do not have access to this repository")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 409)]
+  #[error("{0}")]
+  Conflict(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, CirunError, InternalServerError);
+error_from!(std::io::Error, CirunError, InternalServerError);
+
+impl From<TokenError> for CirunError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => CirunError::Unauthorized,
+      TokenError::InternalServerError => CirunError::InternalServerError,
+    }
+  }
+}
@@ -0,0 +1,399 @@
+use std::sync::{Arc, Mutex};
+
+use database::{connection_pool::ConnectionProvider, db_handle::repository::Repository};
+use git_server::objects::{DiffOptions, GitObjects};
+use gmt_common::permissions::can_read_repository;
+use poem_openapi::{
+  param::{Path, Query},
+  payload::Json,
+  OpenApi,
+};
+
+use crate::security::gmt_token::GmtToken;
+
+pub mod structs;
+
+pub use structs::*;
+
+/// Blobs larger than this are reported as too large instead of being served
+pub const MAX_BLOB_SIZE: usize = 1024 * 1024;
+/// Diffs are truncated past this number of files
+pub const MAX_DIFF_FILES: usize = 300;
+/// Diffs are truncated once their lines add up to more than this
+pub const MAX_DIFF_SIZE: usize = 1024 * 1024;
+const DEFAULT_PER_PAGE: u32 = 30;
+const MAX_PER_PAGE: u32 = 100;
+const MAX_CONTEXT_LINES: u32 = 100;
+
+#[OpenApi]
+impl<DbPool, Db> CodeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the branches and tags of the repository
+  #[oai(path = "/repositories/:id/refs", method = "get")]
+  async fn list_refs(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<RefResponse>>, CodeError> {
+    let objects = self.open_objects(token, id.0)?;
+
+    let refs = objects.list_refs()?;
+    Ok(Json(refs.into_iter().map(RefResponse::from).collect()))
+  }
+
+  /// Lists the content of a directory. Defaults to the root of `HEAD`.
+  #[oai(path = "/repositories/:id/tree", method = "get")]
+  async fn get_tree(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    rev: Query<Option<String>>,
+    path: Query<Option<String>>,
+  ) -> Result<Json<TreeResponse>, CodeError> {
+    let objects = self.open_objects(token, id.0)?;
+    let rev = rev.0.unwrap_or_else(|| "HEAD".to_string());
+    let path = path.0.unwrap_or_default();
+
+    let commit = objects.resolve(&rev)?;
+    let entries = objects.read_tree(&commit, &path)?;
+    Ok(Json(TreeResponse {
+      commit,
+      path,
+      entries: entries.into_iter().map(TreeEntryResponse::from).collect(),
+    }))
+  }
+
+  /// Reads a file. Binary files and files larger than 1 MiB are returned without content.
+  #[oai(path = "/repositories/:id/blob", method = "get")]
+  async fn get_blob(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    rev: Query<Option<String>>,
+    path: Query<String>,
+  ) -> Result<Json<BlobResponse>, CodeError> {
+    let objects = self.open_objects(token, id.0)?;
+    let rev = rev.0.unwrap_or_else(|| "HEAD".to_string());
+
+    let commit = objects.resolve(&rev)?;
+    let blob = objects.read_blob(&commit, &path.0, MAX_BLOB_SIZE)?;
+    Ok(Json(BlobResponse::new(commit, blob)))
+  }
+
+  /// Lists the commits reachable from a revision, latest first. Pages start at 1.
+  #[oai(path = "/repositories/:id/commits", method = "get")]
+  async fn list_commits(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    rev: Query<Option<String>>,
+    page: Query<Option<u32>>,
+    per_page: Query<Option<u32>>,
+  ) -> Result<Json<CommitLogResponse>, CodeError> {
+    let page = page.0.unwrap_or(1);
+    let per_page = per_page.0.unwrap_or(DEFAULT_PER_PAGE);
+    if page == 0 {
+      return Err(CodeError::BadRequest("Pages start at 1".into()));
+    }
+    if per_page == 0 || per_page > MAX_PER_PAGE {
+      return Err(CodeError::BadRequest(
+        format!("per_page must be between 1 and {}", MAX_PER_PAGE).into(),
+      ));
+    }
+
+    let objects = self.open_objects(token, id.0)?;
+    let rev = rev.0.unwrap_or_else(|| "HEAD".to_string());
+
+    let skip = (page - 1) as usize * per_page as usize;
+    let log = objects.log(&rev, skip, per_page as usize)?;
+    Ok(Json(CommitLogResponse {
+      commits: log.commits.into_iter().map(CommitResponse::from).collect(),
+      page,
+      per_page,
+      has_more: log.has_more,
+    }))
+  }
+
+  /// Lists the changes made by a commit, or between two revisions with a `base..head` range or a
+  /// `base...head` range starting from their merge base.
+  /// Line numbers of the head side can be used to comment the files of the `head` commit.
+  /// Diffs of more than 300 files or 1 MiB of lines are truncated.
+  #[oai(path = "/repositories/:id/diff", method = "get")]
+  async fn get_diff(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    rev: Query<String>,
+    ignore_whitespace: Query<Option<bool>>,
+    detect_renames: Query<Option<bool>>,
+    context_lines: Query<Option<u32>>,
+  ) -> Result<Json<DiffResponse>, CodeError> {
+    let defaults = DiffOptions::default();
+    let options = DiffOptions {
+      ignore_whitespace: ignore_whitespace.0.unwrap_or(defaults.ignore_whitespace),
+      detect_renames: detect_renames.0.unwrap_or(defaults.detect_renames),
+      context_lines: context_lines.0.unwrap_or(defaults.context_lines),
+      max_files: MAX_DIFF_FILES,
+      max_size: MAX_DIFF_SIZE,
+    };
+    if options.context_lines > MAX_CONTEXT_LINES {
+      return Err(CodeError::BadRequest(
+        format!("context_lines must be at most {}", MAX_CONTEXT_LINES).into(),
+      ));
+    }
+
+    let objects = self.open_objects(token, id.0)?;
+
+    let diff = objects.diff(&rev.0, &options)?;
+    Ok(Json(diff.into()))
+  }
+}
+
+impl<DbPool, Db> CodeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Opens the objects of the repository, after checking the user can read it
+  fn open_objects(&self, token: GmtToken, repository_id: i32) -> Result<GitObjects, CodeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, repository_id)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(CodeError::Forbidden);
+    }
+
+    Ok(GitObjects::open(self.storage.get_path(&repository.name))?)
+  }
+}
+
+fn find_repository<Db: DbType>(db: &mut Db, repository_id: i32) -> Result<Repository, CodeError> {
+  db.get_repository_by_id(repository_id)?
+    .ok_or(CodeError::NotFound("Repository".into()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::test_utils::{git_fixture, valid_token};
+  use database::{connection_pool::ConnectionPool, db_handle::repository::Repotype, DbHandle};
+  use gmt_common::repositories::repository_storage::RepositoryStorage;
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+  use tempfile::TempDir;
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn client(fixture: &TempDir, owner_id: i32) -> TestClient<Route> {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.get_repository_by_id).then(move |id| {
+        Ok((id == 1).then(|| Repository {
+          id: 1,
+          name: "repo".to_string(),
+          repo_type: Repotype::Default,
+          owner_id,
+          assignment_id: None,
+        }))
+      });
+      faux::when!(db.list_repository_assignments(1)).then(|_| Ok(vec![]));
+      Ok(db)
+    });
+    let mut storage = RepositoryStorage::faux();
+    let path = fixture.path().join("repo.git");
+    faux::when!(storage.get_path("repo")).then(move |_| path.clone());
+
+    let service = OpenApiService::new(
+      CodeService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage)),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn fixture() -> TempDir {
+    git_fixture(&[
+      ("README.md", b"# Hello\n"),
+      ("src/main.rs", b"fn main() {}\n"),
+      ("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0"),
+    ])
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, 1, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, 1, StatusCode::FORBIDDEN)]
+  #[case::unknown(USER_ID, 2, StatusCode::NOT_FOUND)]
+  #[tokio::test]
+  async fn test_list_refs(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] repository_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let fixture = fixture();
+    let client = client(&fixture, owner_id);
+
+    let resp = client
+      .get(format!("/repositories/{}/refs", repository_id))
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let refs = resp.json().await.value().deserialize::<Vec<RefResponse>>();
+      assert_eq!(refs.len(), 1);
+      assert_eq!(refs[0].name, "main");
+      assert_eq!(refs[0].kind, RefKindResponse::Branch);
+    }
+  }
+
+  #[rstest]
+  #[case::root(None, StatusCode::OK, vec!["src", "README.md", "logo.png"])]
+  #[case::directory(Some("src"), StatusCode::OK, vec!["main.rs"])]
+  #[case::missing(Some("docs"), StatusCode::NOT_FOUND, vec![])]
+  #[case::file(Some("README.md"), StatusCode::BAD_REQUEST, vec![])]
+  #[tokio::test]
+  async fn test_get_tree(
+    valid_token: String,
+    #[case] path: Option<&str>,
+    #[case] expected: StatusCode,
+    #[case] names: Vec<&str>,
+  ) {
+    let fixture = fixture();
+    let client = client(&fixture, USER_ID);
+
+    let mut req = client
+      .get("/repositories/1/tree")
+      .header("Authorization", valid_token);
+    if let Some(path) = path {
+      req = req.query("path", &path);
+    }
+    let resp = req.send().await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let tree = resp.json().await.value().deserialize::<TreeResponse>();
+      let entries: Vec<_> = tree.entries.iter().map(|e| e.name.as_str()).collect();
+      assert_eq!(entries, names);
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_blob(valid_token: String) {
+    let fixture = fixture();
+    let client = client(&fixture, USER_ID);
+
+    let resp = client
+      .get("/repositories/1/blob")
+      .query("path", &"src/main.rs")
+      .header("Authorization", valid_token.clone())
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let blob = resp.json().await.value().deserialize::<BlobResponse>();
+    assert_eq!(blob.content.as_deref(), Some("fn main() {}\n"));
+    assert!(!blob.binary);
+
+    let resp = client
+      .get("/repositories/1/blob")
+      .query("path", &"logo.png")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let blob = resp.json().await.value().deserialize::<BlobResponse>();
+    assert!(blob.binary);
+    assert_eq!(blob.content, None);
+  }
+
+  #[rstest]
+  #[case::first_page(Some(1), Some(10), StatusCode::OK)]
+  #[case::zero_page(Some(0), None, StatusCode::BAD_REQUEST)]
+  #[case::too_many(None, Some(1000), StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_list_commits(
+    valid_token: String,
+    #[case] page: Option<u32>,
+    #[case] per_page: Option<u32>,
+    #[case] expected: StatusCode,
+  ) {
+    let fixture = fixture();
+    let client = client(&fixture, USER_ID);
+
+    let mut req = client
+      .get("/repositories/1/commits")
+      .header("Authorization", valid_token);
+    if let Some(page) = page {
+      req = req.query("page", &page);
+    }
+    if let Some(per_page) = per_page {
+      req = req.query("per_page", &per_page);
+    }
+    let resp = req.send().await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let log = resp.json().await.value().deserialize::<CommitLogResponse>();
+      assert_eq!(log.commits.len(), 1);
+      assert_eq!(log.commits[0].summary, "Initial commit");
+      assert!(!log.has_more);
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_unknown_revision(valid_token: String) {
+    let fixture = fixture();
+    let client = client(&fixture, USER_ID);
+
+    let resp = client
+      .get("/repositories/1/tree")
+      .query("rev", &"unknown")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[rstest]
+  #[case::commit("HEAD", None, StatusCode::OK, 3)]
+  #[case::range("HEAD..main", None, StatusCode::OK, 0)]
+  #[case::unknown("HEAD..unknown", None, StatusCode::NOT_FOUND, 0)]
+  #[case::too_much_context("HEAD", Some(1000), StatusCode::BAD_REQUEST, 0)]
+  #[tokio::test]
+  async fn test_get_diff(
+    valid_token: String,
+    #[case] rev: &str,
+    #[case] context_lines: Option<u32>,
+    #[case] expected: StatusCode,
+    #[case] file_count: usize,
+  ) {
+    let fixture = fixture();
+    let client = client(&fixture, USER_ID);
+
+    let mut req = client
+      .get("/repositories/1/diff")
+      .query("rev", &rev)
+      .query("ignore_whitespace", &true)
+      .header("Authorization", valid_token);
+    if let Some(context_lines) = context_lines {
+      req = req.query("context_lines", &context_lines);
+    }
+    let resp = req.send().await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let diff = resp.json().await.value().deserialize::<DiffResponse>();
+      assert_eq!(diff.files.len(), file_count);
+      assert!(!diff.truncated);
+    }
+  }
+}
@@ -0,0 +1,396 @@
+use std::sync::{Arc, Mutex};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::AssignmentDbHandle, group::GroupDbHandle, repository::RepositoryDbHandle,
+  },
+  error::DatabaseError,
+};
+use git_server::objects::{
+  Blob, BlobContent, CommitInfo, Diff, DiffHunk, DiffLine, DiffLineKind, EntryKind, FileDiff,
+  FileStatus, GitRef, ObjectsError, RefKind, Signature, TreeEntry,
+};
+use gmt_common::repositories::repository_storage::RepositoryStorage;
+use poem_openapi::{ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::structs::StringResponse;
+
+pub trait DbType: AssignmentDbHandle + GroupDbHandle + RepositoryDbHandle + 'static {}
+impl<T> DbType for T where T: AssignmentDbHandle + GroupDbHandle + RepositoryDbHandle + 'static {}
+
+pub struct CodeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+}
+
+impl<DbPool, Db> CodeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>) -> Self {
+    Self { db, storage }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RefKindResponse {
+  Branch,
+  Tag,
+}
+
+impl From<RefKind> for RefKindResponse {
+  fn from(kind: RefKind) -> Self {
+    match kind {
+      RefKind::Branch => RefKindResponse::Branch,
+      RefKind::Tag => RefKindResponse::Tag,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct RefResponse {
+  pub name: String,
+  pub kind: RefKindResponse,
+  /// The commit the reference points to
+  pub target: String,
+}
+
+impl From<GitRef> for RefResponse {
+  fn from(git_ref: GitRef) -> Self {
+    Self {
+      name: git_ref.name,
+      kind: git_ref.kind.into(),
+      target: git_ref.target,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKindResponse {
+  Tree,
+  Blob,
+  Submodule,
+}
+
+impl From<EntryKind> for EntryKindResponse {
+  fn from(kind: EntryKind) -> Self {
+    match kind {
+      EntryKind::Tree => EntryKindResponse::Tree,
+      EntryKind::Blob => EntryKindResponse::Blob,
+      EntryKind::Submodule => EntryKindResponse::Submodule,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct TreeEntryResponse {
+  pub name: String,
+  pub path: String,
+  pub kind: EntryKindResponse,
+  pub mode: i32,
+  pub id: String,
+  pub size: Option<u64>,
+}
+
+impl From<TreeEntry> for TreeEntryResponse {
+  fn from(entry: TreeEntry) -> Self {
+    Self {
+      name: entry.name,
+      path: entry.path,
+      kind: entry.kind.into(),
+      mode: entry.mode,
+      id: entry.id,
+      size: entry.size.map(|s| s as u64),
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct TreeResponse {
+  /// The commit the tree was read from
+  pub commit: String,
+  pub path: String,
+  pub entries: Vec<TreeEntryResponse>,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct BlobResponse {
+  /// The commit the blob was read from
+  pub commit: String,
+  pub id: String,
+  pub path: String,
+  pub size: u64,
+  pub binary: bool,
+  /// Whether the blob exceeds the maximum size served by the API
+  pub too_large: bool,
+  /// The content of the file, only set for text files
+  pub content: Option<String>,
+}
+
+impl BlobResponse {
+  pub fn new(commit: String, blob: Blob) -> Self {
+    let (binary, too_large, content) = match blob.content {
+      BlobContent::Text(content) => (false, false, Some(content)),
+      BlobContent::Binary => (true, false, None),
+      BlobContent::TooLarge => (false, true, None),
+    };
+    Self {
+      commit,
+      id: blob.id,
+      path: blob.path,
+      size: blob.size as u64,
+      binary,
+      too_large,
+      content,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct SignatureResponse {
+  pub name: String,
+  pub email: String,
+  /// Seconds since the unix epoch
+  pub time: i64,
+}
+
+impl From<Signature> for SignatureResponse {
+  fn from(signature: Signature) -> Self {
+    Self {
+      name: signature.name,
+      email: signature.email,
+      time: signature.time,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CommitResponse {
+  pub id: String,
+  pub parents: Vec<String>,
+  pub author: SignatureResponse,
+  pub committer: SignatureResponse,
+  pub summary: String,
+  pub message: String,
+}
+
+impl From<CommitInfo> for CommitResponse {
+  fn from(commit: CommitInfo) -> Self {
+    Self {
+      id: commit.id,
+      parents: commit.parents,
+      author: commit.author.into(),
+      committer: commit.committer.into(),
+      summary: commit.summary,
+      message: commit.message,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CommitLogResponse {
+  pub commits: Vec<CommitResponse>,
+  pub page: u32,
+  pub per_page: u32,
+  pub has_more: bool,
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatusResponse {
+  Added,
+  Deleted,
+  Modified,
+  Renamed,
+  Copied,
+  TypeChanged,
+}
+
+impl From<FileStatus> for FileStatusResponse {
+  fn from(status: FileStatus) -> Self {
+    match status {
+      FileStatus::Added => FileStatusResponse::Added,
+      FileStatus::Deleted => FileStatusResponse::Deleted,
+      FileStatus::Modified => FileStatusResponse::Modified,
+      FileStatus::Renamed => FileStatusResponse::Renamed,
+      FileStatus::Copied => FileStatusResponse::Copied,
+      FileStatus::TypeChanged => FileStatusResponse::TypeChanged,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKindResponse {
+  Context,
+  Addition,
+  Deletion,
+}
+
+impl From<DiffLineKind> for DiffLineKindResponse {
+  fn from(kind: DiffLineKind) -> Self {
+    match kind {
+      DiffLineKind::Context => DiffLineKindResponse::Context,
+      DiffLineKind::Addition => DiffLineKindResponse::Addition,
+      DiffLineKind::Deletion => DiffLineKindResponse::Deletion,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct DiffLineResponse {
+  pub kind: DiffLineKindResponse,
+  /// The line number in the base version of the file, unset for additions
+  pub old_line: Option<u32>,
+  /// The line number in the head version of the file, unset for deletions
+  pub new_line: Option<u32>,
+  pub content: String,
+}
+
+impl From<DiffLine> for DiffLineResponse {
+  fn from(line: DiffLine) -> Self {
+    Self {
+      kind: line.kind.into(),
+      old_line: line.old_line,
+      new_line: line.new_line,
+      content: line.content,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct DiffHunkResponse {
+  pub header: String,
+  pub old_start: u32,
+  pub old_lines: u32,
+  pub new_start: u32,
+  pub new_lines: u32,
+  pub lines: Vec<DiffLineResponse>,
+}
+
+impl From<DiffHunk> for DiffHunkResponse {
+  fn from(hunk: DiffHunk) -> Self {
+    Self {
+      header: hunk.header,
+      old_start: hunk.old_start,
+      old_lines: hunk.old_lines,
+      new_start: hunk.new_start,
+      new_lines: hunk.new_lines,
+      lines: hunk.lines.into_iter().map(DiffLineResponse::from).collect(),
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct FileDiffResponse {
+  pub old_path: Option<String>,
+  pub new_path: Option<String>,
+  pub status: FileStatusResponse,
+  pub binary: bool,
+  pub additions: u64,
+  pub deletions: u64,
+  pub hunks: Vec<DiffHunkResponse>,
+}
+
+impl From<FileDiff> for FileDiffResponse {
+  fn from(file: FileDiff) -> Self {
+    Self {
+      old_path: file.old_path,
+      new_path: file.new_path,
+      status: file.status.into(),
+      binary: file.binary,
+      additions: file.additions as u64,
+      deletions: file.deletions as u64,
+      hunks: file.hunks.into_iter().map(DiffHunkResponse::from).collect(),
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct DiffResponse {
+  /// The commit the changes are computed from, unset for a root commit
+  pub base: Option<String>,
+  /// The commit comments on this diff should be attached to
+  pub head: String,
+  pub files: Vec<FileDiffResponse>,
+  /// Whether the remaining files were left out, the diff being too large
+  pub truncated: bool,
+}
+
+impl From<Diff> for DiffResponse {
+  fn from(diff: Diff) -> Self {
+    Self {
+      base: diff.base,
+      head: diff.head,
+      files: diff.files.into_iter().map(FileDiffResponse::from).collect(),
+      truncated: diff.truncated,
+    }
+  }
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CodeError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("You do not have access to this repository")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, CodeError, InternalServerError);
+
+impl From<ObjectsError> for CodeError {
+  fn from(e: ObjectsError) -> Self {
+    match e {
+      ObjectsError::RepositoryNotFound => CodeError::NotFound("Repository".into()),
+      ObjectsError::RevisionNotFound(rev) => {
+        CodeError::NotFound(format!("Revision {}", rev).into())
+      }
+      ObjectsError::PathNotFound(path) => CodeError::NotFound(format!("Path {}", path).into()),
+      ObjectsError::NotATree(_)
+      | ObjectsError::NotABlob(_)
+      | ObjectsError::InvalidRange(_)
+      | ObjectsError::InvalidLineRange(..) => CodeError::BadRequest(e.to_string().into()),
+      ObjectsError::GitError(e) => {
+        log::error!("Unable to read repository: {}", e);
+        CodeError::InternalServerError
+      }
+    }
+  }
+}
+
+impl From<TokenError> for CodeError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => CodeError::Unauthorized,
+      TokenError::InternalServerError => CodeError::InternalServerError,
+    }
+  }
+}
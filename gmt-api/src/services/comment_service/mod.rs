@@ -0,0 +1,977 @@
+use std::sync::{Arc, Mutex};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    comment::{Comment, CommentAnchor, Commentside},
+    repository::Repository,
+  },
+};
+use git_server::objects::GitObjects;
+use gmt_common::permissions::can_write_repository;
+use poem_openapi::{
+  param::{Path, Query},
+  payload::Json,
+  OpenApi,
+};
+
+use crate::security::gmt_token::GmtToken;
+
+pub mod structs;
+
+pub use structs::*;
+
+/// Only the people allowed to push to a repository (its owner and the group's teacher) take part
+/// in its review.
+#[OpenApi]
+impl<DbPool, Db> CommentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the comment threads of a commit, optionally restricted to a single file or to resolved
+  /// or unresolved threads
+  #[oai(path = "/repositories/:id/commits/:commit/comments", method = "get")]
+  async fn list_comments(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    commit: Path<String>,
+    file_path: Query<Option<String>>,
+    resolved: Query<Option<bool>>,
+  ) -> Result<Json<Vec<CommentResponse>>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    validate_commit_hash(&commit)?;
+
+    let mut roots: Vec<Comment> = db
+      .list_commit_comments(repository.id, &commit, resolved.0)?
+      .into_iter()
+      .filter(|c| c.respond_to.is_none())
+      .filter(|c| file_path.is_none() || c.file_path == file_path.0)
+      .collect();
+    sort_comments(&mut roots);
+
+    let threads = roots
+      .into_iter()
+      .map(|c| build_thread(&mut db, c))
+      .collect::<Result<_, _>>()?;
+    Ok(Json(threads))
+  }
+
+  /// Lists the comment threads of the whole repository, oldest first. Listing unresolved threads
+  /// gives the feedback which hasn't been addressed yet.
+  #[oai(path = "/repositories/:id/threads", method = "get")]
+  async fn list_threads(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    resolved: Query<Option<bool>>,
+  ) -> Result<Json<Vec<CommentResponse>>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+
+    let mut roots = db.list_repository_threads(repository.id, resolved.0)?;
+    sort_comments(&mut roots);
+
+    let threads = roots
+      .into_iter()
+      .map(|c| build_thread(&mut db, c))
+      .collect::<Result<_, _>>()?;
+    Ok(Json(threads))
+  }
+
+  /// Comments on a commit, on one of its files or on a range of lines of a file
+  #[oai(path = "/repositories/:id/commits/:commit/comments", method = "post")]
+  async fn post_comment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    commit: Path<String>,
+    req: Json<PostCommentRequest>,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    validate_commit_hash(&commit)?;
+    validate_message(&req.message)?;
+
+    let anchor = self.build_anchor(&repository, &commit, &req)?;
+    let comment = match (&req.file_path, anchor) {
+      (Some(file_path), Some(anchor)) => db.add_line_comment(
+        repository.id,
+        &commit,
+        file_path,
+        &anchor,
+        user.user_id,
+        &req.message,
+      )?,
+      (Some(file_path), None) => db.add_file_comment(
+        repository.id,
+        &commit,
+        file_path,
+        user.user_id,
+        &req.message,
+      )?,
+      (None, _) => db.add_comment(repository.id, &commit, user.user_id, &req.message)?,
+    };
+    Ok(Json(comment.into()))
+  }
+
+  #[oai(path = "/comments/:id/replies", method = "post")]
+  async fn reply(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<ReplyRequest>,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    let repository = find_repository(&mut db, comment.repository_id)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    validate_message(&req.message)?;
+
+    let reply = db.add_response_comment(comment.id, user.user_id, &req.message)?;
+    Ok(Json(reply.into()))
+  }
+
+  /// Edits one of the user's own comments. The previous message is kept in its history.
+  #[oai(path = "/comments/:id", method = "patch")]
+  async fn edit_comment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<EditCommentRequest>,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    if comment.author_id != Some(user.user_id) {
+      return Err(CommentError::Forbidden);
+    }
+    validate_message(&req.message)?;
+
+    let comment = db.edit_comment(comment.id, &req.message)?;
+    Ok(Json(with_reactions(&mut db, comment)?))
+  }
+
+  /// Lists the previous messages of a comment, oldest first
+  #[oai(path = "/comments/:id/history", method = "get")]
+  async fn get_comment_history(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<CommentEditResponse>>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    let repository = find_repository(&mut db, comment.repository_id)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+
+    let edits = db.list_comment_edits(comment.id)?;
+    Ok(Json(
+      edits.into_iter().map(CommentEditResponse::from).collect(),
+    ))
+  }
+
+  /// Marks a thread as resolved. Only the root comment of a thread can be resolved.
+  #[oai(path = "/comments/:id/resolve", method = "post")]
+  async fn resolve_comment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    self.set_resolved(token, id.0, true)
+  }
+
+  #[oai(path = "/comments/:id/unresolve", method = "post")]
+  async fn unresolve_comment(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    self.set_resolved(token, id.0, false)
+  }
+
+  #[oai(path = "/comments/:id/reactions/:reaction", method = "put")]
+  async fn add_reaction(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    reaction: Path<String>,
+  ) -> Result<(), CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    let repository = find_repository(&mut db, comment.repository_id)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    validate_reaction(&reaction)?;
+
+    db.add_reaction(comment.id, user.user_id, &reaction)?;
+    Ok(())
+  }
+
+  #[oai(path = "/comments/:id/reactions/:reaction", method = "delete")]
+  async fn remove_reaction(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    reaction: Path<String>,
+  ) -> Result<(), CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    let repository = find_repository(&mut db, comment.repository_id)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    db.remove_reaction(comment.id, user.user_id, &reaction)?;
+    Ok(())
+  }
+
+  /// Deletes one of the user's own comments, along with its replies
+  #[oai(path = "/comments/:id", method = "delete")]
+  async fn delete_comment(&self, token: GmtToken, id: Path<i32>) -> Result<(), CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, id.0)?;
+    if comment.author_id != Some(user.user_id) {
+      return Err(CommentError::Forbidden);
+    }
+
+    db.delete_comment(comment.id)?;
+    Ok(())
+  }
+}
+
+impl<DbPool, Db> CommentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  fn set_resolved(
+    &self,
+    token: GmtToken,
+    comment_id: i32,
+    resolved: bool,
+  ) -> Result<Json<CommentResponse>, CommentError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let comment = find_comment(&mut db, comment_id)?;
+    let repository = find_repository(&mut db, comment.repository_id)?;
+    ensure_participant(&mut db, &repository, user.user_id)?;
+    if comment.respond_to.is_some() {
+      return Err(CommentError::BadRequest(
+        "Only the first comment of a thread can be resolved".into(),
+      ));
+    }
+
+    let comment = if resolved {
+      db.resolve_comment(comment.id, user.user_id)?
+    } else {
+      db.unresolve_comment(comment.id)?
+    };
+    Ok(Json(build_thread(&mut db, comment)?))
+  }
+
+  /// Computes the anchor of a line comment, hashing the lines it refers to so that they can be
+  /// tracked across pushes
+  fn build_anchor(
+    &self,
+    repository: &Repository,
+    commit: &str,
+    req: &PostCommentRequest,
+  ) -> Result<Option<CommentAnchor>, CommentError> {
+    let Some(start_line) = req.start_line else {
+      if req.end_line.is_some() || req.side.is_some() {
+        return Err(CommentError::BadRequest(
+          "A line range requires a start line".into(),
+        ));
+      }
+      return Ok(None);
+    };
+    let Some(file_path) = &req.file_path else {
+      return Err(CommentError::BadRequest(
+        "A line range requires a file path".into(),
+      ));
+    };
+    let end_line = req.end_line.unwrap_or(start_line);
+    if start_line < 1 || end_line < start_line {
+      return Err(CommentError::BadRequest("Invalid line range".into()));
+    }
+
+    let side: Commentside = req.side.unwrap_or(CommentSide::New).into();
+    let rev = match side {
+      Commentside::Old => format!("{}^", commit),
+      Commentside::New => commit.to_string(),
+    };
+    let objects = GitObjects::open(self.storage.get_path(&repository.name))?;
+    let context_hash =
+      objects.hash_lines(&rev, file_path, start_line as usize, end_line as usize)?;
+
+    Ok(Some(CommentAnchor {
+      start_line,
+      end_line,
+      side,
+      context_hash,
+    }))
+  }
+}
+
+fn find_repository<Db: DbType>(
+  db: &mut Db,
+  repository_id: i32,
+) -> Result<Repository, CommentError> {
+  db.get_repository_by_id(repository_id)?
+    .ok_or(CommentError::NotFound("Repository".into()))
+}
+
+fn find_comment<Db: DbType>(db: &mut Db, comment_id: i32) -> Result<Comment, CommentError> {
+  db.get_comment_by_id(comment_id)?
+    .ok_or(CommentError::NotFound("Comment".into()))
+}
+
+fn ensure_participant<Db: DbType>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+) -> Result<(), CommentError> {
+  if can_write_repository(db, repository, user_id)? {
+    Ok(())
+  } else {
+    Err(CommentError::Forbidden)
+  }
+}
+
+/// Only full lowercase SHA-1 or SHA-256 ids are accepted, as comments are matched by their exact
+/// commit
+fn validate_commit_hash(commit: &str) -> Result<(), CommentError> {
+  let is_lower_hex = |c: char| c.is_ascii_digit() || ('a'..='f').contains(&c);
+  if matches!(commit.len(), 40 | 64) && commit.chars().all(is_lower_hex) {
+    Ok(())
+  } else {
+    Err(CommentError::BadRequest("Invalid commit hash".into()))
+  }
+}
+
+fn validate_message(message: &str) -> Result<(), CommentError> {
+  if message.trim().is_empty() {
+    Err(CommentError::BadRequest("Comment message is empty".into()))
+  } else {
+    Ok(())
+  }
+}
+
+fn validate_reaction(reaction: &str) -> Result<(), CommentError> {
+  if (1..=32).contains(&reaction.chars().count()) && !reaction.chars().any(char::is_whitespace) {
+    Ok(())
+  } else {
+    Err(CommentError::BadRequest("Invalid reaction".into()))
+  }
+}
+
+fn sort_comments(comments: &mut [Comment]) {
+  comments.sort_by(|a, b| a.date.cmp(&b.date).then(a.id.cmp(&b.id)));
+}
+
+/// Fetches the reactions to the comment, grouped by reaction
+fn with_reactions<Db: DbType>(
+  db: &mut Db,
+  comment: Comment,
+) -> Result<CommentResponse, CommentError> {
+  let mut reactions: Vec<ReactionResponse> = Vec::new();
+  for reaction in db.list_reactions(comment.id)? {
+    match reactions.last_mut() {
+      Some(last) if last.reaction == reaction.reaction => last.user_ids.push(reaction.user_id),
+      _ => reactions.push(ReactionResponse {
+        reaction: reaction.reaction,
+        user_ids: vec![reaction.user_id],
+      }),
+    }
+  }
+
+  Ok(CommentResponse {
+    reactions,
+    ..comment.into()
+  })
+}
+
+/// Recursively fetches the replies made to the comment
+fn build_thread<Db: DbType>(
+  db: &mut Db,
+  comment: Comment,
+) -> Result<CommentResponse, CommentError> {
+  let mut replies = db.list_response_comments(comment.id)?;
+  sort_comments(&mut replies);
+
+  let replies = replies
+    .into_iter()
+    .map(|reply| build_thread(db, reply))
+    .collect::<Result<_, _>>()?;
+  Ok(CommentResponse {
+    replies,
+    ..with_reactions(db, comment)?
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::test_utils::{git_fixture, repository, valid_token};
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::comment::{CommentEdit, CommentReaction, Commentauthor},
+    DbHandle,
+  };
+  use gmt_common::repositories::repository_storage::RepositoryStorage;
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+  use std::time::{Duration, SystemTime};
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+  const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+  fn client<F>(setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    client_with_repository(std::path::PathBuf::from("/nonexistent/repo.git"), setup)
+  }
+
+  /// Creates a client whose repository is stored at `path`
+  fn client_with_repository<F>(path: std::path::PathBuf, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.get_path("repo-1")).then(move |_| path.clone());
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      CommentService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage)),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn comment(id: i32, respond_to: Option<i32>, file_path: Option<&str>) -> Comment {
+    Comment {
+      id,
+      repository_id: 1,
+      commit_hash: COMMIT.to_string(),
+      respond_to,
+      file_path: file_path.map(str::to_string),
+      message: format!("message {}", id),
+      author_type: Commentauthor::User,
+      author_id: Some(USER_ID),
+      date: SystemTime::UNIX_EPOCH + Duration::from_secs(id as u64),
+      start_line: None,
+      end_line: None,
+      side: None,
+      context_hash: None,
+      outdated: false,
+      edited_at: None,
+      resolved: false,
+      resolved_by: None,
+      resolved_at: None,
+      anchor_commit: None,
+      anchor_start_line: None,
+      anchor_end_line: None,
+    }
+  }
+
+  fn thread(id: i32, file_path: Option<&str>, replies: Vec<CommentResponse>) -> CommentResponse {
+    CommentResponse {
+      replies,
+      ..comment(id, None, file_path).into()
+    }
+  }
+
+  #[rstest]
+  #[case::all(None, vec![1, 2])]
+  #[case::file(Some("src/main.rs"), vec![2])]
+  #[tokio::test]
+  async fn test_list_comments(
+    valid_token: String,
+    #[case] file_path: Option<&'static str>,
+    #[case] expected: Vec<i32>,
+  ) {
+    let client = client(|db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.list_reactions).then(|_| Ok(vec![]));
+      faux::when!(db.list_commit_comments).then(|(repository_id, commit, resolved)| {
+        assert_eq!(repository_id, 1);
+        assert_eq!(commit, COMMIT);
+        assert_eq!(resolved, None);
+        Ok(vec![
+          comment(2, None, Some("src/main.rs")),
+          comment(3, Some(1), None),
+          comment(1, None, None),
+        ])
+      });
+      faux::when!(db.list_response_comments).then(|id| match id {
+        1 => Ok(vec![comment(3, Some(1), None)]),
+        3 => Ok(vec![comment(4, Some(3), None)]),
+        _ => Ok(vec![]),
+      });
+    });
+
+    let path = format!("/repositories/1/commits/{}/comments", COMMIT);
+    let mut req = client.get(&path).header("Authorization", valid_token);
+    if let Some(file_path) = file_path {
+      req = req.query("file_path", &file_path);
+    }
+    let resp = req.send().await;
+    resp.assert_status_is_ok();
+
+    let nested = || {
+      vec![CommentResponse {
+        replies: vec![comment(4, Some(3), None).into()],
+        ..comment(3, Some(1), None).into()
+      }]
+    };
+    let threads: Vec<CommentResponse> = expected
+      .into_iter()
+      .map(|id| match id {
+        1 => thread(1, None, nested()),
+        _ => thread(id, Some("src/main.rs"), vec![]),
+      })
+      .collect();
+    // Dates are compared once parsed, as their serialized format differs from serde's
+    let body: Vec<CommentResponse> = resp.json().await.value().deserialize();
+    assert_eq!(body, threads);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_comments_forbidden(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, OTHER_ID, None))));
+    });
+
+    let resp = client
+      .get(format!("/repositories/1/commits/{}/comments", COMMIT))
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+  }
+
+  #[rstest]
+  #[case::commit(None)]
+  #[case::file(Some("src/main.rs"))]
+  #[tokio::test]
+  async fn test_post_comment(valid_token: String, #[case] file_path: Option<&'static str>) {
+    let client = client(move |db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.add_comment(1, COMMIT, USER_ID, "message 1"))
+        .then(|_| Ok(comment(1, None, None)));
+      faux::when!(db.add_file_comment(1, COMMIT, "src/main.rs", USER_ID, "message 1"))
+        .then(|_| Ok(comment(1, None, Some("src/main.rs"))));
+    });
+
+    let resp = client
+      .post(format!("/repositories/1/commits/{}/comments", COMMIT))
+      .header("Authorization", valid_token)
+      .body_json(&PostCommentRequest {
+        message: "message 1".to_string(),
+        file_path: file_path.map(str::to_string),
+        start_line: None,
+        end_line: None,
+        side: None,
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let body: CommentResponse = resp.json().await.value().deserialize();
+    assert_eq!(body, thread(1, file_path, vec![]));
+  }
+
+  #[rstest]
+  #[case::invalid_commit("not-a-commit", "message")]
+  #[case::abbreviated_commit("0123456789ab", "message")]
+  #[case::uppercase_commit("0123456789ABCDEF0123456789ABCDEF01234567", "message")]
+  #[case::empty_message(COMMIT, "  ")]
+  #[tokio::test]
+  async fn test_post_invalid_comment(
+    valid_token: String,
+    #[case] commit: &str,
+    #[case] message: &str,
+  ) {
+    let client = client(|db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+    });
+
+    let resp = client
+      .post(format!("/repositories/1/commits/{}/comments", commit))
+      .header("Authorization", valid_token)
+      .body_json(&PostCommentRequest {
+        message: message.to_string(),
+        file_path: None,
+        start_line: None,
+        end_line: None,
+        side: None,
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+  }
+
+  #[rstest]
+  #[case::single_line(Some(2), None, StatusCode::OK)]
+  #[case::range(Some(1), Some(3), StatusCode::OK)]
+  #[case::past_end(Some(3), Some(4), StatusCode::BAD_REQUEST)]
+  #[case::reversed(Some(3), Some(1), StatusCode::BAD_REQUEST)]
+  #[case::missing_start(None, Some(1), StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_post_line_comment(
+    valid_token: String,
+    #[case] start_line: Option<i32>,
+    #[case] end_line: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let fixture = git_fixture(&[("src/main.rs", b"fn main() {\n  todo!()\n}\n")]);
+    let client = client_with_repository(fixture.path().join("repo.git"), |db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.add_line_comment).then(
+        |(repository_id, commit, file_path, anchor, author_id, message)| {
+          assert_eq!(anchor.side, Commentside::New);
+          assert_eq!(anchor.context_hash.len(), 40);
+          Ok(Comment {
+            repository_id,
+            commit_hash: commit.to_string(),
+            file_path: Some(file_path.to_string()),
+            message: message.to_string(),
+            author_id: Some(author_id),
+            start_line: Some(anchor.start_line),
+            end_line: Some(anchor.end_line),
+            side: Some(anchor.side),
+            context_hash: Some(anchor.context_hash.clone()),
+            ..comment(1, None, None)
+          })
+        },
+      );
+    });
+    let commit = std::process::Command::new("git")
+      .arg("--git-dir")
+      .arg(fixture.path().join("repo.git"))
+      .args(["rev-parse", "HEAD"])
+      .output()
+      .unwrap();
+    let commit = String::from_utf8_lossy(&commit.stdout).trim().to_string();
+
+    let resp = client
+      .post(format!("/repositories/1/commits/{}/comments", commit))
+      .header("Authorization", valid_token)
+      .body_json(&PostCommentRequest {
+        message: "message".to_string(),
+        file_path: Some("src/main.rs".to_string()),
+        start_line,
+        end_line,
+        side: None,
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let body: CommentResponse = resp.json().await.value().deserialize();
+      assert_eq!(body.start_line, start_line);
+      assert_eq!(body.end_line, end_line.or(start_line));
+      assert_eq!(body.side, Some(CommentSide::New));
+      assert!(!body.outdated);
+    }
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_reply(valid_token: String, #[case] owner_id: i32, #[case] expected: StatusCode) {
+    let client = client(move |db| {
+      faux::when!(db.get_comment_by_id(1)).then(|_| Ok(Some(comment(1, None, None))));
+      faux::when!(db.get_repository_by_id(1))
+        .then(move |_| Ok(Some(repository(1, owner_id, None))));
+      faux::when!(db.add_response_comment(1, USER_ID, "message 2"))
+        .then(|_| Ok(comment(2, Some(1), None)));
+    });
+
+    let resp = client
+      .post("/comments/1/replies")
+      .header("Authorization", valid_token)
+      .body_json(&ReplyRequest {
+        message: "message 2".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::own(Some(USER_ID), StatusCode::OK)]
+  #[case::other(Some(OTHER_ID), StatusCode::FORBIDDEN)]
+  #[case::automated(None, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_delete_comment(
+    valid_token: String,
+    #[case] author_id: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_comment_by_id(1)).then(move |_| {
+        Ok(Some(Comment {
+          author_id,
+          ..comment(1, None, None)
+        }))
+      });
+      faux::when!(db.delete_comment(1)).then(|_| Ok(()));
+    });
+
+    let resp = client
+      .delete("/comments/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::all(None)]
+  #[case::unresolved(Some(false))]
+  #[tokio::test]
+  async fn test_list_threads(valid_token: String, #[case] resolved: Option<bool>) {
+    let client = client(move |db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.list_repository_threads).then(move |(repository_id, filter)| {
+        assert_eq!(repository_id, 1);
+        assert_eq!(filter, resolved);
+        Ok(vec![comment(2, None, None), comment(1, None, None)])
+      });
+      faux::when!(db.list_response_comments).then(|_| Ok(vec![]));
+      faux::when!(db.list_reactions).then(|id| {
+        Ok(match id {
+          1 => vec![
+            CommentReaction {
+              comment_id: 1,
+              user_id: USER_ID,
+              reaction: "+1".to_string(),
+            },
+            CommentReaction {
+              comment_id: 1,
+              user_id: OTHER_ID,
+              reaction: "+1".to_string(),
+            },
+          ],
+          _ => vec![],
+        })
+      });
+    });
+
+    let mut req = client
+      .get("/repositories/1/threads")
+      .header("Authorization", valid_token);
+    if let Some(resolved) = resolved {
+      req = req.query("resolved", &resolved);
+    }
+    let resp = req.send().await;
+    resp.assert_status_is_ok();
+    let body: Vec<CommentResponse> = resp.json().await.value().deserialize();
+    let ids: Vec<_> = body.iter().map(|c| c.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(
+      body[0].reactions,
+      vec![ReactionResponse {
+        reaction: "+1".to_string(),
+        user_ids: vec![USER_ID, OTHER_ID],
+      }]
+    );
+  }
+
+  #[rstest]
+  #[case::own(Some(USER_ID), "new message", StatusCode::OK)]
+  #[case::other(Some(OTHER_ID), "new message", StatusCode::FORBIDDEN)]
+  #[case::empty(Some(USER_ID), " ", StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_edit_comment(
+    valid_token: String,
+    #[case] author_id: Option<i32>,
+    #[case] message: &str,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_comment_by_id(1)).then(move |_| {
+        Ok(Some(Comment {
+          author_id,
+          ..comment(1, None, None)
+        }))
+      });
+      faux::when!(db.edit_comment).then(|(id, message)| {
+        Ok(Comment {
+          message: message.to_string(),
+          edited_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(10)),
+          ..comment(id, None, None)
+        })
+      });
+      faux::when!(db.list_reactions).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .patch("/comments/1")
+      .header("Authorization", valid_token)
+      .body_json(&EditCommentRequest {
+        message: message.to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let body: CommentResponse = resp.json().await.value().deserialize();
+      assert_eq!(body.message, message);
+      assert!(body.edited_at.is_some());
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_comment_history(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_comment_by_id(1)).then(|_| Ok(Some(comment(1, None, None))));
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.list_comment_edits(1)).then(|_| {
+        Ok(vec![CommentEdit {
+          id: 1,
+          comment_id: 1,
+          previous_message: "first".to_string(),
+          date: SystemTime::UNIX_EPOCH,
+        }])
+      });
+    });
+
+    let resp = client
+      .get("/comments/1/history")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let body: Vec<CommentEditResponse> = resp.json().await.value().deserialize();
+    assert_eq!(
+      body,
+      vec![CommentEditResponse {
+        message: "first".to_string(),
+        date: SystemTime::UNIX_EPOCH.into(),
+      }]
+    );
+  }
+
+  #[rstest]
+  #[case::resolve_root("resolve", USER_ID, None, StatusCode::OK)]
+  #[case::unresolve_root("unresolve", USER_ID, None, StatusCode::OK)]
+  #[case::reply("resolve", USER_ID, Some(2), StatusCode::BAD_REQUEST)]
+  #[case::stranger("resolve", OTHER_ID, None, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_resolve_comment(
+    valid_token: String,
+    #[case] action: &str,
+    #[case] owner_id: i32,
+    #[case] respond_to: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_comment_by_id(1)).then(move |_| Ok(Some(comment(1, respond_to, None))));
+      faux::when!(db.get_repository_by_id(1))
+        .then(move |_| Ok(Some(repository(1, owner_id, None))));
+      faux::when!(db.resolve_comment(1, USER_ID)).then(|_| {
+        Ok(Comment {
+          resolved: true,
+          resolved_by: Some(USER_ID),
+          resolved_at: Some(SystemTime::UNIX_EPOCH),
+          ..comment(1, None, None)
+        })
+      });
+      faux::when!(db.unresolve_comment(1)).then(|_| Ok(comment(1, None, None)));
+      faux::when!(db.list_response_comments).then(|_| Ok(vec![]));
+      faux::when!(db.list_reactions).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .post(format!("/comments/1/{}", action))
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let body: CommentResponse = resp.json().await.value().deserialize();
+      assert_eq!(body.resolved, action == "resolve");
+    }
+  }
+
+  #[rstest]
+  #[case::valid("+1", StatusCode::OK)]
+  #[case::whitespace("thumbs%20up", StatusCode::BAD_REQUEST)]
+  #[tokio::test]
+  async fn test_add_reaction(
+    valid_token: String,
+    #[case] reaction: &str,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(|db| {
+      faux::when!(db.get_comment_by_id(1)).then(|_| Ok(Some(comment(1, None, None))));
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(Some(repository(1, USER_ID, None))));
+      faux::when!(db.add_reaction(1, USER_ID, "+1")).then(|_| Ok(()));
+    });
+
+    let resp = client
+      .put(format!("/comments/1/reactions/{}", reaction))
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_remove_reaction(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_comment_by_id(1)).then(|_| Ok(Some(comment(1, None, None))));
+      faux::when!(db.get_repository_by_id(1))
+        .then(move |_| Ok(Some(repository(1, owner_id, None))));
+      faux::when!(db.remove_reaction(1, USER_ID, "+1")).then(|_| Ok(()));
+    });
+
+    let resp = client
+      .delete("/comments/1/reactions/+1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+}
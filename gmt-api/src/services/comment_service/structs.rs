@@ -0,0 +1,246 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::AssignmentDbHandle,
+    comment::{Comment, CommentDbHandle, CommentEdit, Commentauthor, Commentside},
+    group::GroupDbHandle,
+    repository::RepositoryDbHandle,
+  },
+  error::DatabaseError,
+};
+use git_server::objects::ObjectsError;
+use gmt_common::repositories::repository_storage::RepositoryStorage;
+use poem_openapi::{ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::structs::StringResponse;
+
+pub trait DbType:
+  AssignmentDbHandle + CommentDbHandle + GroupDbHandle + RepositoryDbHandle + 'static
+{
+}
+impl<T> DbType for T where
+  T: AssignmentDbHandle + CommentDbHandle + GroupDbHandle + RepositoryDbHandle + 'static
+{
+}
+
+pub struct CommentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+}
+
+impl<DbPool, Db> CommentService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>) -> Self {
+    Self { db, storage }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct PostCommentRequest {
+  pub message: String,
+  /// When set, the comment is attached to this file instead of the whole commit
+  pub file_path: Option<String>,
+  /// The first line the comment refers to, as numbered in the diff of the commit. Requires
+  /// `file_path`.
+  pub start_line: Option<i32>,
+  /// The last line the comment refers to, defaults to `start_line`
+  pub end_line: Option<i32>,
+  /// The side of the diff the lines are on, defaults to `new`
+  pub side: Option<CommentSide>,
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct ReplyRequest {
+  pub message: String,
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct EditCommentRequest {
+  pub message: String,
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CommentAuthorType {
+  User,
+  Automated,
+}
+
+impl From<Commentauthor> for CommentAuthorType {
+  fn from(author: Commentauthor) -> Self {
+    match author {
+      Commentauthor::User => CommentAuthorType::User,
+      Commentauthor::Automated => CommentAuthorType::Automated,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CommentSide {
+  /// The lines of the parent of the commit
+  Old,
+  /// The lines of the commit itself
+  New,
+}
+
+impl From<Commentside> for CommentSide {
+  fn from(side: Commentside) -> Self {
+    match side {
+      Commentside::Old => CommentSide::Old,
+      Commentside::New => CommentSide::New,
+    }
+  }
+}
+
+impl From<CommentSide> for Commentside {
+  fn from(side: CommentSide) -> Self {
+    match side {
+      CommentSide::Old => Commentside::Old,
+      CommentSide::New => Commentside::New,
+    }
+  }
+}
+
+/// The users who reacted to a comment in the same way
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct ReactionResponse {
+  pub reaction: String,
+  pub user_ids: Vec<i32>,
+}
+
+/// A previous message of a comment
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CommentEditResponse {
+  pub message: String,
+  /// When the message was replaced
+  pub date: DateTime<Utc>,
+}
+
+impl From<CommentEdit> for CommentEditResponse {
+  fn from(edit: CommentEdit) -> Self {
+    Self {
+      message: edit.previous_message,
+      date: edit.date.into(),
+    }
+  }
+}
+
+/// A comment along with the whole thread of replies made to it
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct CommentResponse {
+  pub id: i32,
+  pub repository_id: i32,
+  /// The commit the comment was made on
+  pub commit_hash: String,
+  pub file_path: Option<String>,
+  pub message: String,
+  pub author_type: CommentAuthorType,
+  /// Missing for automated comments, or when the author has been deleted
+  pub author_id: Option<i32>,
+  pub date: DateTime<Utc>,
+  pub start_line: Option<i32>,
+  pub end_line: Option<i32>,
+  pub side: Option<CommentSide>,
+  /// Whether the lines the comment refers to changed since it was made
+  pub outdated: bool,
+  /// The later commit the lines were last found in once they moved, unset if they never did
+  pub anchor_commit: Option<String>,
+  /// Where the lines start in `anchor_commit`
+  pub anchor_start_line: Option<i32>,
+  pub anchor_end_line: Option<i32>,
+  pub edited_at: Option<DateTime<Utc>>,
+  /// Only set on the root of a thread
+  pub resolved: bool,
+  pub resolved_by: Option<i32>,
+  pub resolved_at: Option<DateTime<Utc>>,
+  pub reactions: Vec<ReactionResponse>,
+  pub replies: Vec<CommentResponse>,
+}
+
+impl From<Comment> for CommentResponse {
+  fn from(comment: Comment) -> Self {
+    Self {
+      id: comment.id,
+      repository_id: comment.repository_id,
+      commit_hash: comment.commit_hash,
+      file_path: comment.file_path,
+      message: comment.message,
+      author_type: comment.author_type.into(),
+      author_id: comment.author_id,
+      date: comment.date.into(),
+      start_line: comment.start_line,
+      end_line: comment.end_line,
+      side: comment.side.map(CommentSide::from),
+      outdated: comment.outdated,
+      anchor_commit: comment.anchor_commit,
+      anchor_start_line: comment.anchor_start_line,
+      anchor_end_line: comment.anchor_end_line,
+      edited_at: comment.edited_at.map(DateTime::from),
+      resolved: comment.resolved,
+      resolved_by: comment.resolved_by,
+      resolved_at: comment.resolved_at.map(DateTime::from),
+      reactions: Vec::new(),
+      replies: Vec::new(),
+    }
+  }
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CommentError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("Only the owner of the repository and the group's teacher can review it")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, CommentError, InternalServerError);
+
+impl From<ObjectsError> for CommentError {
+  fn from(e: ObjectsError) -> Self {
+    match e {
+      ObjectsError::GitError(e) => {
+        log::error!("Unable to read repository: {}", e);
+        CommentError::InternalServerError
+      }
+      e => CommentError::BadRequest(e.to_string().into()),
+    }
+  }
+}
+
+impl From<TokenError> for CommentError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => CommentError::Unauthorized,
+      TokenError::InternalServerError => CommentError::InternalServerError,
+    }
+  }
+}
@@ -0,0 +1,931 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::Latepolicy, grade::Grade, repository::Repository, submission::Submission,
+  },
+};
+use git_server::objects::GitObjects;
+use gmt_common::{
+  deadlines::{find_commit_push, late_penalty, penalized_score},
+  gradebook::build_gradebook,
+  permissions::{can_read_repository, can_write_repository, is_teacher},
+};
+use poem_openapi::{
+  param::Path,
+  payload::{Json, PlainText},
+  OpenApi,
+};
+
+use crate::security::gmt_token::GmtToken;
+
+pub mod structs;
+
+pub use structs::*;
+
+#[OpenApi]
+impl<DbPool, Db> GradeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the submissions of the repository, oldest first
+  #[oai(path = "/repositories/:id/submissions", method = "get")]
+  async fn list_submissions(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<SubmissionResponse>>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    let submissions = db.list_repository_submissions(repository.id)?;
+    Ok(Json(
+      submissions
+        .into_iter()
+        .map(SubmissionResponse::from)
+        .collect(),
+    ))
+  }
+
+  /// Submits a commit of a submission repository for grading. Commits pushed after the due date
+  /// are flagged as late, or refused if the assignment doesn't accept late submissions, unless the
+  /// teacher submits on behalf of the student. The time of the submission itself doesn't matter.
+  #[oai(path = "/repositories/:id/submissions", method = "post")]
+  async fn create_submission(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    request: Json<CreateSubmissionRequest>,
+  ) -> Result<Json<SubmissionResponse>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_write_repository(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+    let Some(assignment_id) = repository.assignment_id else {
+      return Err(GradeError::BadRequest(
+        "Only submission repositories can be submitted".into(),
+      ));
+    };
+    let assignment = db
+      .get_assignment_by_id(assignment_id)?
+      .ok_or(GradeError::NotFound("Assignment".into()))?;
+
+    let objects = GitObjects::open(self.storage.get_path(&repository.name))?;
+    let commit = objects.resolve(request.revision.as_deref().unwrap_or("HEAD"))?;
+    if db
+      .get_submission_by_commit(repository.id, &commit)?
+      .is_some()
+    {
+      return Err(GradeError::Conflict(
+        "This commit was already submitted".into(),
+      ));
+    }
+
+    // The server recorded whether the commit was pushed late
+    let pushes = db.list_repository_pushes(repository.id)?;
+    let push = find_commit_push(&pushes, &objects, &commit)?;
+    let late = push.is_some_and(|push| push.late);
+    if late
+      && assignment.metadata.late_policy == Latepolicy::Reject
+      && repository.owner_id == user.user_id
+    {
+      return Err(GradeError::Conflict(
+        "This commit was pushed late, and late submissions are not accepted".into(),
+      ));
+    }
+    let submission = db.create_submission(
+      repository.id,
+      &commit,
+      SystemTime::now(),
+      late,
+      push.map(|push| push.pushed_at),
+    )?;
+    Ok(Json(submission.into()))
+  }
+
+  #[oai(path = "/submissions/:id", method = "get")]
+  async fn get_submission(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<SubmissionResponse>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (submission, repository) = find_submission(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    Ok(Json(submission.into()))
+  }
+
+  /// Lists the grades of the submission along with their rubric, oldest first
+  #[oai(path = "/submissions/:id/grades", method = "get")]
+  async fn list_grades(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<GradeResponse>>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (submission, repository) = find_submission(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    let late_penalty = submission_late_penalty(&mut db, &submission, &repository)?;
+    let mut grades = Vec::new();
+    for grade in db.list_submission_grades(submission.id)? {
+      grades.push(grade_response(&mut db, grade, late_penalty)?);
+    }
+    Ok(Json(grades))
+  }
+
+  /// Returns the best and the latest scores computed by the CI for the submissions of the
+  /// repository, along with their rubric
+  #[oai(path = "/repositories/:id/ci-scores", method = "get")]
+  async fn get_ci_scores(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<CiScoresResponse>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    let mut grades = Vec::new();
+    for (grade, submission) in db.list_repository_ci_grades(repository.id)? {
+      let late_penalty = submission_late_penalty(&mut db, &submission, &repository)?;
+      grades.push((grade, late_penalty));
+    }
+    // Grades are ranked once penalized, so that pushing late can't make the result better. They
+    // are listed oldest first, so that the most recent one wins ties.
+    let share = |(grade, late_penalty): &(Grade, i32)| {
+      penalized_score(grade.score, *late_penalty) / grade.max_score
+    };
+    let best = grades.iter().fold(None, |best, grade| match best {
+      Some(best) if share(best) > share(grade) => Some(best),
+      _ => Some(grade),
+    });
+
+    let mut response = |grade: Option<&(Grade, i32)>| match grade {
+      Some((grade, late_penalty)) => {
+        grade_response(&mut db, grade.clone(), *late_penalty).map(Some)
+      }
+      None => Ok(None),
+    };
+    Ok(Json(CiScoresResponse {
+      best: response(best)?,
+      latest: response(grades.last())?,
+    }))
+  }
+
+  /// Grades the submission, only the teacher of the group can do it
+  #[oai(path = "/submissions/:id/grades", method = "post")]
+  async fn create_grade(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    request: Json<CreateGradeRequest>,
+  ) -> Result<Json<GradeResponse>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let (submission, repository) = find_submission(&mut db, id.0)?;
+    if !is_assignment_teacher(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    let (score, max_score, rubric) = request.validate()?;
+    let grade = db.add_grade(submission.id, user.user_id, score, max_score, &rubric)?;
+    let late_penalty = submission_late_penalty(&mut db, &submission, &repository)?;
+    Ok(Json(grade_response(&mut db, grade, late_penalty)?))
+  }
+
+  #[oai(path = "/grades/:id", method = "delete")]
+  async fn delete_grade(&self, token: GmtToken, id: Path<i32>) -> Result<(), GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let grade = db
+      .get_grade_by_id(id.0)?
+      .ok_or(GradeError::NotFound("Grade".into()))?;
+    let (_, repository) = find_submission(&mut db, grade.submission_id)?;
+    if !is_assignment_teacher(&mut db, &repository, user.user_id)? {
+      return Err(GradeError::Forbidden);
+    }
+
+    db.delete_grade(grade.id)?;
+    Ok(())
+  }
+
+  /// Exports the grades of the students of the group for each assignment, only the teacher of the
+  /// group can do it
+  #[oai(path = "/groups/:id/gradebook", method = "get")]
+  async fn get_gradebook(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<GradebookResponse>, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    check_group_teacher(&mut db, id.0, user.user_id)?;
+
+    let gradebook = build_gradebook(&mut db, id.0)?;
+    Ok(Json(GradebookResponse::new(id.0, gradebook)))
+  }
+
+  /// Exports the gradebook as CSV, with the score, score once the late penalty removed, maximum
+  /// score, late flag and CI status of each assignment in this order
+  #[oai(path = "/groups/:id/gradebook.csv", method = "get")]
+  async fn get_gradebook_csv(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<CsvResponse, GradeError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    check_group_teacher(&mut db, id.0, user.user_id)?;
+
+    let gradebook = build_gradebook(&mut db, id.0)?;
+    Ok(CsvResponse::Csv(
+      PlainText(gradebook.to_csv()),
+      format!("attachment; filename=\"gradebook-{}.csv\"", id.0),
+    ))
+  }
+}
+
+fn check_group_teacher<Db: DbType>(
+  db: &mut Db,
+  group_id: i32,
+  user_id: i32,
+) -> Result<(), GradeError> {
+  let group = db
+    .get_group_by_id(group_id)?
+    .ok_or(GradeError::NotFound("Group".into()))?;
+  match is_teacher(&group, user_id) {
+    true => Ok(()),
+    false => Err(GradeError::Forbidden),
+  }
+}
+
+fn find_repository<Db: DbType>(db: &mut Db, repository_id: i32) -> Result<Repository, GradeError> {
+  db.get_repository_by_id(repository_id)?
+    .ok_or(GradeError::NotFound("Repository".into()))
+}
+
+fn grade_response<Db: DbType>(
+  db: &mut Db,
+  grade: Grade,
+  late_penalty: i32,
+) -> Result<GradeResponse, GradeError> {
+  let criteria = db.list_grade_criteria(grade.id)?;
+  Ok(GradeResponse::new(grade, criteria, late_penalty))
+}
+
+/// The percentage removed from the scores of the submission by the late penalty of its assignment
+fn submission_late_penalty<Db: DbType>(
+  db: &mut Db,
+  submission: &Submission,
+  repository: &Repository,
+) -> Result<i32, GradeError> {
+  let assignment = match repository.assignment_id {
+    Some(assignment_id) if submission.late => db.get_assignment_by_id(assignment_id)?,
+    _ => None,
+  };
+  Ok(assignment.map_or(0, |assignment| {
+    late_penalty(&assignment.metadata, submission)
+  }))
+}
+
+fn find_submission<Db: DbType>(
+  db: &mut Db,
+  submission_id: i32,
+) -> Result<(Submission, Repository), GradeError> {
+  let submission = db
+    .get_submission_by_id(submission_id)?
+    .ok_or(GradeError::NotFound("Submission".into()))?;
+  let repository = find_repository(db, submission.repository_id)?;
+  Ok((submission, repository))
+}
+
+/// Whether the user is the teacher of the group the repository was submitted to
+fn is_assignment_teacher<Db: DbType>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+) -> Result<bool, GradeError> {
+  let Some(assignment_id) = repository.assignment_id else {
+    return Ok(false);
+  };
+  Ok(
+    db.get_assignment_group(assignment_id)?
+      .is_some_and(|group| is_teacher(&group, user_id)),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::{
+    cirun_service::CirunStatus,
+    test_utils::{cirun, git_fixture, repository, user, valid_token},
+  };
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      assignment::{Assignment, AssignmentMetadata},
+      cirun::Status,
+      grade::{Grade, GradeCriterion, Gradesource},
+      group::Group,
+      push::PushRecord,
+    },
+    DbHandle,
+  };
+  use gmt_common::repositories::repository_storage::RepositoryStorage;
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+  use std::time::Duration;
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn client<F>(storage: RepositoryStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      GradeService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage)),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn submission(id: i32, late: bool) -> Submission {
+    Submission {
+      id,
+      repository_id: 1,
+      commit_hash: format!("commit-{}", id),
+      submitted_at: SystemTime::UNIX_EPOCH,
+      late,
+      pushed_at: None,
+    }
+  }
+
+  fn grade(id: i32) -> Grade {
+    Grade {
+      id,
+      submission_id: 1,
+      score: 12.5,
+      max_score: 15.0,
+      source: Gradesource::Manual,
+      grader_id: Some(USER_ID),
+      date: SystemTime::UNIX_EPOCH,
+      cirun_id: None,
+    }
+  }
+
+  fn ci_grade(id: i32, score: f64) -> Grade {
+    Grade {
+      id,
+      submission_id: 1,
+      score,
+      max_score: 10.0,
+      source: Gradesource::Automated,
+      grader_id: None,
+      date: SystemTime::UNIX_EPOCH,
+      cirun_id: Some(id),
+    }
+  }
+
+  fn criterion(grade_id: i32, name: &str, score: f64, max_score: f64) -> GradeCriterion {
+    GradeCriterion {
+      id: 1,
+      grade_id,
+      name: name.to_string(),
+      score,
+      max_score,
+      comment: None,
+    }
+  }
+
+  /// Sets up the submission repository of `owner_id` for an assignment of a group taught by
+  /// `teacher_id`, with the given metadata
+  fn setup_repository(
+    db: &mut DbHandle,
+    owner_id: i32,
+    teacher_id: i32,
+    metadata: AssignmentMetadata,
+  ) {
+    faux::when!(db.get_repository_by_id(1))
+      .then(move |_| Ok(Some(repository(1, owner_id, Some(1)))));
+    faux::when!(db.get_assignment_by_id(1)).then(move |_| {
+      Ok(Some(Assignment {
+        id: 1,
+        group_id: 1,
+        base_repo_id: 2,
+        test_repo_id: None,
+        correction_repo_id: None,
+        metadata: metadata.clone(),
+      }))
+    });
+    faux::when!(db.get_assignment_group(1)).then(move |_| {
+      Ok(Some(Group {
+        id: 1,
+        name: "group".to_string(),
+        teacher_id: Some(teacher_id),
+      }))
+    });
+    faux::when!(db.list_repository_assignments(1)).then(|_| Ok(vec![]));
+  }
+
+  #[rstest]
+  #[case::pushed_on_time(USER_ID, Latepolicy::Flag, Some(false), Some(false))]
+  #[case::pushed_late(USER_ID, Latepolicy::Flag, Some(true), Some(true))]
+  #[case::never_pushed(USER_ID, Latepolicy::Flag, None, Some(false))]
+  #[case::rejected(USER_ID, Latepolicy::Reject, Some(true), None)]
+  #[case::rejected_by_teacher(OTHER_ID, Latepolicy::Reject, Some(true), Some(true))]
+  #[tokio::test]
+  async fn test_create_submission(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] late_policy: Latepolicy,
+    #[case] pushed_late: Option<bool>,
+    #[case] late: Option<bool>,
+  ) {
+    let fixture = git_fixture(&[("README.md", b"# Hello\n")]);
+    let mut storage = RepositoryStorage::faux();
+    let path = fixture.path().join("repo.git");
+    let head = GitObjects::open(&path).unwrap().resolve("main").unwrap();
+    faux::when!(storage.get_path("repo-1")).then(move |_| path.clone());
+
+    // The assignment was due yesterday, only the time of the push matters
+    let metadata = AssignmentMetadata {
+      due_date: Some(SystemTime::now() - Duration::from_secs(24 * 3600)),
+      late_policy,
+      ..Default::default()
+    };
+    let client = client(storage, move |db| {
+      setup_repository(db, owner_id, USER_ID, metadata.clone());
+      faux::when!(db.get_submission_by_commit).then(|_| Ok(None));
+      let head = head.clone();
+      faux::when!(db.list_repository_pushes(1)).then(move |_| {
+        Ok(
+          pushed_late
+            .map(|late| PushRecord {
+              id: 1,
+              repository_id: 1,
+              user_id: Some(owner_id),
+              ref_name: "refs/heads/main".to_string(),
+              commit_hash: head.clone(),
+              pushed_at: SystemTime::UNIX_EPOCH,
+              late,
+            })
+            .into_iter()
+            .collect(),
+        )
+      });
+      faux::when!(db.create_submission).then(move |(repository_id, commit, _, late, pushed_at)| {
+        assert_eq!(pushed_at.is_some(), pushed_late.is_some());
+        Ok(Submission {
+          repository_id,
+          commit_hash: commit.to_string(),
+          late,
+          ..submission(1, late)
+        })
+      });
+    });
+
+    let resp = client
+      .post("/repositories/1/submissions")
+      .header("Authorization", valid_token)
+      .body_json(&CreateSubmissionRequest {
+        revision: Some("main".to_string()),
+      })
+      .send()
+      .await;
+    match late {
+      Some(late) => {
+        resp.assert_status_is_ok();
+        let submission = resp
+          .json()
+          .await
+          .value()
+          .deserialize::<SubmissionResponse>();
+        assert_eq!(submission.late, late);
+        assert_eq!(submission.commit.len(), 40);
+      }
+      None => resp.assert_status(StatusCode::CONFLICT),
+    }
+  }
+
+  #[rstest]
+  #[case::stranger(OTHER_ID, "main", false, StatusCode::FORBIDDEN)]
+  #[case::unknown_revision(USER_ID, "other", false, StatusCode::NOT_FOUND)]
+  #[case::already_submitted(USER_ID, "main", true, StatusCode::CONFLICT)]
+  #[tokio::test]
+  async fn test_create_submission_fails(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] revision: &str,
+    #[case] submitted: bool,
+    #[case] expected: StatusCode,
+  ) {
+    let fixture = git_fixture(&[("README.md", b"# Hello\n")]);
+    let mut storage = RepositoryStorage::faux();
+    let path = fixture.path().join("repo.git");
+    faux::when!(storage.get_path("repo-1")).then(move |_| path.clone());
+
+    let client = client(storage, move |db| {
+      setup_repository(db, owner_id, OTHER_ID, AssignmentMetadata::default());
+      faux::when!(db.get_submission_by_commit)
+        .then(move |_| Ok(submitted.then(|| submission(1, false))));
+    });
+
+    let resp = client
+      .post("/repositories/1/submissions")
+      .header("Authorization", valid_token)
+      .body_json(&CreateSubmissionRequest {
+        revision: Some(revision.to_string()),
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::on_time(false, 0, 12.5)]
+  // Pushed a day and a half after the due date, with 20% removed per started day
+  #[case::late(true, 40, 7.5)]
+  #[tokio::test]
+  async fn test_list_grades(
+    valid_token: String,
+    #[case] late: bool,
+    #[case] late_penalty: i32,
+    #[case] penalized_score: f64,
+  ) {
+    let due_date = SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 3600);
+    let client = client(RepositoryStorage::faux(), move |db| {
+      let metadata = AssignmentMetadata {
+        due_date: Some(due_date),
+        late_policy: Latepolicy::Flag,
+        late_penalty: 20,
+        ..Default::default()
+      };
+      setup_repository(db, USER_ID, OTHER_ID, metadata);
+      faux::when!(db.get_submission_by_id(1)).then(move |_| {
+        Ok(Some(Submission {
+          pushed_at: Some(due_date + Duration::from_secs(36 * 3600)),
+          ..submission(1, late)
+        }))
+      });
+      faux::when!(db.list_submission_grades(1)).then(|_| Ok(vec![grade(1)]));
+      faux::when!(db.list_grade_criteria(1)).then(|_| {
+        Ok(vec![
+          criterion(1, "Tests", 8.0, 10.0),
+          criterion(1, "Style", 4.5, 5.0),
+        ])
+      });
+    });
+
+    let resp = client
+      .get("/submissions/1/grades")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let grades = resp
+      .json()
+      .await
+      .value()
+      .deserialize::<Vec<GradeResponse>>();
+    assert_eq!(
+      grades,
+      vec![GradeResponse::new(
+        grade(1),
+        vec![
+          criterion(1, "Tests", 8.0, 10.0),
+          criterion(1, "Style", 4.5, 5.0),
+        ],
+        late_penalty
+      )]
+    );
+    assert_eq!(grades[0].penalized_score, penalized_score);
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_get_ci_scores(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, owner_id, OTHER_ID, AssignmentMetadata::default());
+      faux::when!(db.list_repository_ci_grades(1)).then(|_| {
+        Ok(vec![
+          (ci_grade(1, 9.0), submission(1, false)),
+          (ci_grade(2, 6.0), submission(2, false)),
+        ])
+      });
+      faux::when!(db.list_grade_criteria)
+        .then(|grade_id| Ok(vec![criterion(grade_id, "Tests", 5.0, 5.0)]));
+    });
+
+    let resp = client
+      .get("/repositories/1/ci-scores")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected != StatusCode::OK {
+      return;
+    }
+    let scores = resp.json().await.value().deserialize::<CiScoresResponse>();
+    assert_eq!(
+      scores,
+      CiScoresResponse {
+        best: Some(GradeResponse::new(
+          ci_grade(1, 9.0),
+          vec![criterion(1, "Tests", 5.0, 5.0)],
+          0
+        )),
+        latest: Some(GradeResponse::new(
+          ci_grade(2, 6.0),
+          vec![criterion(2, "Tests", 5.0, 5.0)],
+          0
+        )),
+      }
+    );
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_ci_scores_late_penalty(valid_token: String) {
+    let due_date = SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 3600);
+    let client = client(RepositoryStorage::faux(), move |db| {
+      let metadata = AssignmentMetadata {
+        due_date: Some(due_date),
+        late_policy: Latepolicy::Flag,
+        late_penalty: 30,
+        ..Default::default()
+      };
+      setup_repository(db, USER_ID, OTHER_ID, metadata);
+      // The late push has the higher raw score, but only 6.3 once penalized
+      faux::when!(db.list_repository_ci_grades(1)).then(move |_| {
+        Ok(vec![
+          (ci_grade(1, 7.0), submission(1, false)),
+          (
+            ci_grade(2, 9.0),
+            Submission {
+              pushed_at: Some(due_date + Duration::from_secs(3600)),
+              ..submission(2, true)
+            },
+          ),
+        ])
+      });
+      faux::when!(db.list_grade_criteria).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .get("/repositories/1/ci-scores")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let scores = resp.json().await.value().deserialize::<CiScoresResponse>();
+    assert_eq!(
+      scores,
+      CiScoresResponse {
+        best: Some(GradeResponse::new(ci_grade(1, 7.0), vec![], 0)),
+        latest: Some(GradeResponse::new(ci_grade(2, 9.0), vec![], 30)),
+      }
+    );
+    assert_eq!(scores.latest.unwrap().penalized_score, 6.3);
+  }
+
+  fn grade_request(score: Option<f64>, rubric: &[(&str, f64, f64)]) -> CreateGradeRequest {
+    CreateGradeRequest {
+      score,
+      max_score: score.map(|_| 15.0),
+      rubric: rubric
+        .iter()
+        .map(|(name, score, max_score)| RubricCriterion {
+          name: name.to_string(),
+          score: *score,
+          max_score: *max_score,
+          comment: None,
+        })
+        .collect(),
+    }
+  }
+
+  #[rstest]
+  #[case::teacher(USER_ID, grade_request(Some(12.5), &[]), StatusCode::OK)]
+  #[case::rubric(
+    USER_ID,
+    grade_request(None, &[("Tests", 8.0, 10.0), ("Style", 4.5, 5.0)]),
+    StatusCode::OK
+  )]
+  #[case::student(OTHER_ID, grade_request(Some(12.5), &[]), StatusCode::FORBIDDEN)]
+  #[case::above_max(USER_ID, grade_request(Some(16.0), &[]), StatusCode::BAD_REQUEST)]
+  #[case::empty(USER_ID, grade_request(None, &[]), StatusCode::BAD_REQUEST)]
+  #[case::invalid_rubric(
+    USER_ID,
+    grade_request(Some(12.5), &[("Tests", -1.0, 10.0)]),
+    StatusCode::BAD_REQUEST
+  )]
+  #[tokio::test]
+  async fn test_create_grade(
+    valid_token: String,
+    #[case] teacher_id: i32,
+    #[case] request: CreateGradeRequest,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, OTHER_ID, teacher_id, AssignmentMetadata::default());
+      faux::when!(db.get_submission_by_id(1)).then(|_| Ok(Some(submission(1, false))));
+      faux::when!(db.add_grade).then(|(submission_id, grader_id, score, max_score, _)| {
+        assert_eq!((submission_id, grader_id), (1, USER_ID));
+        assert_eq!((score, max_score), (12.5, 15.0));
+        Ok(grade(1))
+      });
+      faux::when!(db.list_grade_criteria(1)).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .post("/submissions/1/grades")
+      .header("Authorization", valid_token)
+      .body_json(&request)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[case::teacher(USER_ID, StatusCode::OK)]
+  #[case::student(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_delete_grade(
+    valid_token: String,
+    #[case] teacher_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_repository(db, OTHER_ID, teacher_id, AssignmentMetadata::default());
+      faux::when!(db.get_submission_by_id(1)).then(|_| Ok(Some(submission(1, false))));
+      faux::when!(db.get_grade_by_id(1)).then(|_| Ok(Some(grade(1))));
+      faux::when!(db.delete_grade(1)).then(|_| Ok(()));
+    });
+
+    let resp = client
+      .delete("/grades/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  /// Sets up a group taught by `teacher_id` with one assignment removing 20% per day of lateness,
+  /// and one student who submitted late and was graded
+  fn setup_gradebook(db: &mut DbHandle, teacher_id: i32) {
+    faux::when!(db.get_group_by_id(1)).then(move |_| {
+      Ok(Some(Group {
+        id: 1,
+        name: "group".to_string(),
+        teacher_id: Some(teacher_id),
+      }))
+    });
+    faux::when!(db.list_group_assignments(1)).then(|_| {
+      Ok(vec![Assignment {
+        id: 1,
+        group_id: 1,
+        base_repo_id: 2,
+        test_repo_id: None,
+        correction_repo_id: None,
+        metadata: AssignmentMetadata {
+          name: "Lists".to_string(),
+          late_penalty: 20,
+          ..Default::default()
+        },
+      }])
+    });
+    faux::when!(db.list_students(1)).then(|_| Ok(vec![user(OTHER_ID, "alice")]));
+    faux::when!(db.list_assignment_submissions(1)).then(|_| Ok(vec![submission(1, true)]));
+    faux::when!(db.get_assignment_submission_repos(1))
+      .then(|_| Ok(vec![repository(1, OTHER_ID, Some(1))]));
+    faux::when!(db.list_submission_grades(1)).then(|_| Ok(vec![grade(1)]));
+    faux::when!(db.get_cirun_by_commit(1, "commit-1"))
+      .then(|_| Ok(Some(cirun(1, 1, Status::Failed))));
+  }
+
+  #[rstest]
+  #[case::teacher(USER_ID, StatusCode::OK)]
+  #[case::stranger(OTHER_ID, StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_get_gradebook(
+    valid_token: String,
+    #[case] teacher_id: i32,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(RepositoryStorage::faux(), move |db| {
+      setup_gradebook(db, teacher_id)
+    });
+
+    let resp = client
+      .get("/groups/1/gradebook")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      let gradebook = resp.json().await.value().deserialize::<GradebookResponse>();
+      assert_eq!(
+        gradebook,
+        GradebookResponse {
+          group_id: 1,
+          assignments: vec![GradebookAssignment {
+            id: 1,
+            name: "Lists".to_string(),
+            due_date: None,
+          }],
+          students: vec![GradebookStudent {
+            id: OTHER_ID,
+            username: "alice".to_string(),
+            email: "alice@test.com".to_string(),
+            entries: vec![GradebookEntryResponse {
+              assignment_id: 1,
+              repository_id: Some(1),
+              submission_id: Some(1),
+              commit: Some("commit-1".to_string()),
+              submitted_at: Some(SystemTime::UNIX_EPOCH.into()),
+              late: Some(true),
+              score: Some(12.5),
+              penalized_score: Some(10.0),
+              max_score: Some(15.0),
+              ci_status: Some(CirunStatus::Failed),
+            }],
+          }],
+        }
+      );
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_gradebook_csv(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| setup_gradebook(db, USER_ID));
+
+    let resp = client
+      .get("/groups/1/gradebook.csv")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/csv; charset=utf-8");
+    resp.assert_header(
+      "Content-Disposition",
+      "attachment; filename=\"gradebook-1.csv\"",
+    );
+    resp
+      .assert_text(
+        "student_id,username,email,Lists #1 score,Lists #1 penalized_score,Lists #1 max_score,\
+        Lists #1 late,Lists #1 ci_status\r\n\
+        2,alice,alice@test.com,12.5,10,15,true,failed\r\n",
+      )
+      .await;
+  }
+}
@@ -0,0 +1,399 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::{Assignment, AssignmentDbHandle},
+    cirun::CirunDbHandle,
+    grade::{Grade, GradeCriterion, GradeDbHandle, Gradesource, RubricScore},
+    group::GroupDbHandle,
+    push::PushDbHandle,
+    repository::RepositoryDbHandle,
+    submission::{Submission, SubmissionDbHandle},
+  },
+  error::DatabaseError,
+};
+use git_server::objects::ObjectsError;
+use gmt_common::{
+  deadlines::penalized_score,
+  gradebook::{Gradebook, GradebookEntry},
+  repositories::repository_storage::RepositoryStorage,
+};
+use poem_openapi::{payload::PlainText, ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::{cirun_service::CirunStatus, structs::StringResponse};
+
+pub trait DbType:
+  AssignmentDbHandle
+  + CirunDbHandle
+  + GradeDbHandle
+  + GroupDbHandle
+  + PushDbHandle
+  + RepositoryDbHandle
+  + SubmissionDbHandle
+  + 'static
+{
+}
+impl<T> DbType for T where
+  T: AssignmentDbHandle
+    + CirunDbHandle
+    + GradeDbHandle
+    + GroupDbHandle
+    + PushDbHandle
+    + RepositoryDbHandle
+    + SubmissionDbHandle
+    + 'static
+{
+}
+
+pub struct GradeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+}
+
+impl<DbPool, Db> GradeService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>) -> Self {
+    Self { db, storage }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct CreateSubmissionRequest {
+  /// The revision to submit, such as a branch or a commit, `HEAD` if unset
+  pub revision: Option<String>,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct SubmissionResponse {
+  pub id: i32,
+  pub repository_id: i32,
+  pub commit: String,
+  pub submitted_at: DateTime<Utc>,
+  pub late: bool,
+}
+
+impl From<Submission> for SubmissionResponse {
+  fn from(submission: Submission) -> Self {
+    Self {
+      id: submission.id,
+      repository_id: submission.repository_id,
+      commit: submission.commit_hash,
+      submitted_at: submission.submitted_at.into(),
+      late: submission.late,
+    }
+  }
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum GradeSource {
+  /// Given by the teacher
+  Manual,
+  /// Computed from the CI results
+  Automated,
+}
+
+impl From<Gradesource> for GradeSource {
+  fn from(source: Gradesource) -> Self {
+    match source {
+      Gradesource::Manual => GradeSource::Manual,
+      Gradesource::Automated => GradeSource::Automated,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct RubricCriterion {
+  pub name: String,
+  pub score: f64,
+  pub max_score: f64,
+  pub comment: Option<String>,
+}
+
+impl From<GradeCriterion> for RubricCriterion {
+  fn from(criterion: GradeCriterion) -> Self {
+    Self {
+      name: criterion.name,
+      score: criterion.score,
+      max_score: criterion.max_score,
+      comment: criterion.comment,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct CreateGradeRequest {
+  /// The score of the submission, the sum of the rubric if unset
+  pub score: Option<f64>,
+  /// The maximum score, the sum of the rubric if unset
+  pub max_score: Option<f64>,
+  #[oai(default)]
+  #[serde(default)]
+  pub rubric: Vec<RubricCriterion>,
+}
+
+/// Checks that the score is within `0..=max_score`.
+fn validate_score(what: &str, score: f64, max_score: f64) -> Result<(), GradeError> {
+  if !max_score.is_finite() || max_score <= 0.0 {
+    return Err(GradeError::BadRequest(
+      format!("The maximum score of {} must be positive", what).into(),
+    ));
+  }
+  if !(0.0..=max_score).contains(&score) {
+    return Err(GradeError::BadRequest(
+      format!("The score of {} must be between 0 and {}", what, max_score).into(),
+    ));
+  }
+  Ok(())
+}
+
+impl CreateGradeRequest {
+  /// Checks the request, returning the score, the maximum score and the rubric to store
+  pub fn validate(&self) -> Result<(f64, f64, Vec<RubricScore>), GradeError> {
+    let mut rubric = Vec::new();
+    for criterion in &self.rubric {
+      let name = criterion.name.trim();
+      if name.is_empty() || name.len() > 255 {
+        return Err(GradeError::BadRequest(
+          "The name of a criterion must be between 1 and 255 characters long".into(),
+        ));
+      }
+      validate_score(name, criterion.score, criterion.max_score)?;
+      rubric.push(RubricScore {
+        name: name.to_string(),
+        score: criterion.score,
+        max_score: criterion.max_score,
+        comment: criterion.comment.clone(),
+      });
+    }
+
+    let score = self
+      .score
+      .unwrap_or_else(|| rubric.iter().map(|c| c.score).sum());
+    let max_score = self
+      .max_score
+      .unwrap_or_else(|| rubric.iter().map(|c| c.max_score).sum());
+    validate_score("the submission", score, max_score)?;
+
+    Ok((score, max_score, rubric))
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GradeResponse {
+  pub id: i32,
+  pub submission_id: i32,
+  pub score: f64,
+  pub max_score: f64,
+  /// The percentage of the score removed as the submission was late
+  pub late_penalty: i32,
+  /// The score once the late penalty removed
+  pub penalized_score: f64,
+  pub source: GradeSource,
+  pub grader_id: Option<i32>,
+  pub date: DateTime<Utc>,
+  /// The CI run which computed the grade, for automated grades
+  pub cirun_id: Option<i32>,
+  pub rubric: Vec<RubricCriterion>,
+}
+
+impl GradeResponse {
+  pub fn new(grade: Grade, criteria: Vec<GradeCriterion>, late_penalty: i32) -> Self {
+    Self {
+      id: grade.id,
+      submission_id: grade.submission_id,
+      score: grade.score,
+      max_score: grade.max_score,
+      late_penalty,
+      penalized_score: penalized_score(grade.score, late_penalty),
+      source: grade.source.into(),
+      grader_id: grade.grader_id,
+      date: grade.date.into(),
+      cirun_id: grade.cirun_id,
+      rubric: criteria.into_iter().map(RubricCriterion::from).collect(),
+    }
+  }
+}
+
+/// The scores computed by the CI for the submissions of a repository
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct CiScoresResponse {
+  /// The score with the highest share of its maximum once the late penalty removed, the most recent
+  /// one in case of a tie
+  pub best: Option<GradeResponse>,
+  /// The most recent score
+  pub latest: Option<GradeResponse>,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GradebookAssignment {
+  pub id: i32,
+  pub name: String,
+  pub due_date: Option<DateTime<Utc>>,
+}
+
+impl From<&Assignment> for GradebookAssignment {
+  fn from(assignment: &Assignment) -> Self {
+    Self {
+      id: assignment.id,
+      name: assignment.metadata.name.clone(),
+      due_date: assignment.metadata.due_date.map(Into::into),
+    }
+  }
+}
+
+/// The work of a student on an assignment, every field being empty when there is nothing yet
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GradebookEntryResponse {
+  pub assignment_id: i32,
+  pub repository_id: Option<i32>,
+  /// The latest submission of the student
+  pub submission_id: Option<i32>,
+  pub commit: Option<String>,
+  pub submitted_at: Option<DateTime<Utc>>,
+  pub late: Option<bool>,
+  /// The latest grade of the latest submission
+  pub score: Option<f64>,
+  /// The latest grade once the late penalty of the assignment removed
+  pub penalized_score: Option<f64>,
+  pub max_score: Option<f64>,
+  pub ci_status: Option<CirunStatus>,
+}
+
+impl GradebookEntryResponse {
+  fn new(assignment_id: i32, entry: GradebookEntry) -> Self {
+    let penalized_score = entry.penalized_score();
+    let submission = entry.submission;
+    Self {
+      assignment_id,
+      repository_id: entry.repository_id,
+      submission_id: submission.as_ref().map(|s| s.id),
+      commit: submission.as_ref().map(|s| s.commit_hash.clone()),
+      submitted_at: submission.as_ref().map(|s| s.submitted_at.into()),
+      late: submission.as_ref().map(|s| s.late),
+      score: entry.grade.as_ref().map(|g| g.score),
+      penalized_score,
+      max_score: entry.grade.as_ref().map(|g| g.max_score),
+      ci_status: entry.ci_status.map(CirunStatus::from),
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GradebookStudent {
+  pub id: i32,
+  pub username: String,
+  pub email: String,
+  /// The entries of the student, in the same order as the assignments
+  pub entries: Vec<GradebookEntryResponse>,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GradebookResponse {
+  pub group_id: i32,
+  /// The assignments of the group, by id
+  pub assignments: Vec<GradebookAssignment>,
+  /// The students of the group, by username
+  pub students: Vec<GradebookStudent>,
+}
+
+impl GradebookResponse {
+  pub fn new(group_id: i32, gradebook: Gradebook) -> Self {
+    let assignments: Vec<GradebookAssignment> = gradebook
+      .assignments
+      .iter()
+      .map(GradebookAssignment::from)
+      .collect();
+    let students = gradebook
+      .rows
+      .into_iter()
+      .map(|row| GradebookStudent {
+        id: row.student.id,
+        username: row.student.username,
+        email: row.student.email,
+        entries: assignments
+          .iter()
+          .zip(row.entries)
+          .map(|(assignment, entry)| GradebookEntryResponse::new(assignment.id, entry))
+          .collect(),
+      })
+      .collect();
+    Self {
+      group_id,
+      assignments,
+      students,
+    }
+  }
+}
+
+#[derive(ApiResponse)]
+pub enum CsvResponse {
+  #[oai(status = 200, content_type = "text/csv; charset=utf-8")]
+  Csv(
+    PlainText<String>,
+    #[oai(header = "Content-Disposition")] String,
+  ),
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum GradeError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("You do not have access to this submission")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 409)]
+  #[error("{0}")]
+  Conflict(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, GradeError, InternalServerError);
+
+impl From<ObjectsError> for GradeError {
+  fn from(e: ObjectsError) -> Self {
+    match e {
+      ObjectsError::RepositoryNotFound => GradeError::NotFound("Repository".into()),
+      ObjectsError::RevisionNotFound(rev) => {
+        GradeError::NotFound(format!("Revision {}", rev).into())
+      }
+      e => {
+        log::error!("Unable to read repository: {}", e);
+        GradeError::InternalServerError
+      }
+    }
+  }
+}
+
+impl From<TokenError> for GradeError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => GradeError::Unauthorized,
+      TokenError::InternalServerError => GradeError::InternalServerError,
+    }
+  }
+}
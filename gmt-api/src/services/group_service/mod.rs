@@ -0,0 +1,792 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::SystemTime,
+};
+
+use database::{connection_pool::ConnectionProvider, db_handle::group::Group};
+use gmt_common::{
+  deadlines::is_released,
+  permissions::{is_group_member, is_teacher},
+  repositories::provisioning::provision_student,
+  student_import::{import_students, ImportOutcome},
+};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+
+use crate::{security::gmt_token::GmtToken, services::assignment_service::AssignmentResponse};
+
+pub mod structs;
+
+pub use structs::*;
+
+#[OpenApi]
+impl<DbPool, Db> GroupService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the groups the user teaches or belongs to
+  #[oai(path = "/groups", method = "get")]
+  async fn list_groups(&self, token: GmtToken) -> Result<Json<Vec<GroupResponse>>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let mut groups = db.list_teaching_groups(user.user_id)?;
+    for group in db.list_belongs_groups(user.user_id)? {
+      if !groups.iter().any(|g| g.id == group.id) {
+        groups.push(group);
+      }
+    }
+
+    Ok(Json(groups.into_iter().map(GroupResponse::from).collect()))
+  }
+
+  /// Creates a new group, taught by the user
+  #[oai(path = "/groups", method = "post")]
+  async fn create_group(
+    &self,
+    token: GmtToken,
+    req: Json<CreateGroupRequest>,
+  ) -> Result<Json<GroupResponse>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = db
+      .create_group(&req.name, Some(user.user_id))
+      .map_err(|e| {
+        if e.is_unique_violation() {
+          GroupError::Conflict("Group name already exists".into())
+        } else {
+          e.into()
+        }
+      })?;
+
+    Ok(Json(group.into()))
+  }
+
+  #[oai(path = "/groups/:id", method = "get")]
+  async fn get_group(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<GroupResponse>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_member(&mut db, &group, user.user_id)?;
+
+    Ok(Json(group.into()))
+  }
+
+  /// Changes the teacher of the group. Only the current teacher can do so.
+  #[oai(path = "/groups/:id", method = "patch")]
+  async fn update_group(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<UpdateGroupRequest>,
+  ) -> Result<Json<GroupResponse>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_teacher(&group, user.user_id)?;
+
+    if let Some(teacher_id) = req.teacher_id {
+      db.get_user_by_id(teacher_id)?
+        .ok_or(GroupError::NotFound("Teacher".into()))?;
+    }
+    let group = db.set_teacher(group.id, req.teacher_id)?;
+
+    Ok(Json(group.into()))
+  }
+
+  #[oai(path = "/groups/:id", method = "delete")]
+  async fn delete_group(&self, token: GmtToken, id: Path<i32>) -> Result<(), GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_teacher(&group, user.user_id)?;
+
+    if !db.delete_group(group.id) {
+      return Err(GroupError::InternalServerError);
+    }
+    Ok(())
+  }
+
+  #[oai(path = "/groups/:id/students", method = "get")]
+  async fn list_students(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<StudentResponse>>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_member(&mut db, &group, user.user_id)?;
+
+    let students = db.list_students(group.id)?;
+    Ok(Json(
+      students.into_iter().map(StudentResponse::from).collect(),
+    ))
+  }
+
+  /// Adds an existing user to the group, using its username. The student gets a repository for
+  /// each of the assignments already released.
+  #[oai(path = "/groups/:id/students", method = "post")]
+  async fn add_student(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<AddStudentRequest>,
+  ) -> Result<Json<StudentResponse>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_teacher(&group, user.user_id)?;
+
+    let student = db
+      .get_user_by_username(&req.username)?
+      .ok_or(GroupError::NotFound("Student".into()))?;
+    if db
+      .list_students(group.id)?
+      .iter()
+      .any(|s| s.id == student.id)
+    {
+      return Err(GroupError::Conflict(
+        "Student already belongs to the group".into(),
+      ));
+    }
+    db.add_student(group.id, student.id)?;
+
+    let now = SystemTime::now();
+    for assignment in db.list_group_assignments(group.id)? {
+      if let Err(e) = provision_student(&mut db, &self.storage, &assignment, &student, now) {
+        log::warn!(
+          "Unable to provision assignment {} for user {}: {}",
+          assignment.id,
+          student.id,
+          e
+        );
+      }
+    }
+
+    Ok(Json(student.into()))
+  }
+
+  /// Imports students from a CSV file. Unknown users are created along with an invitation token to
+  /// choose their password, and known ones are added to the group. Invalid lines are reported and
+  /// ignored, while the others are imported all at once. The students get a repository for each of
+  /// the assignments already released.
+  #[oai(path = "/groups/:id/students/import", method = "post")]
+  async fn import_students(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+    req: Json<ImportStudentsRequest>,
+  ) -> Result<Json<Vec<ImportRowResponse>>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_teacher(&group, user.user_id)?;
+
+    let now = SystemTime::now();
+    let report = import_students(&mut db, group.id, &req.csv, now)?;
+
+    let assignments = db.list_group_assignments(group.id)?;
+    for row in &report {
+      let student_id = match row.outcome {
+        ImportOutcome::Created { user_id, .. } | ImportOutcome::Linked { user_id } => user_id,
+        ImportOutcome::Failed { .. } => continue,
+      };
+      let Some(student) = db.get_user_by_id(student_id)? else {
+        continue;
+      };
+      for assignment in &assignments {
+        if let Err(e) = provision_student(&mut db, &self.storage, assignment, &student, now) {
+          log::warn!(
+            "Unable to provision assignment {} for user {}: {}",
+            assignment.id,
+            student.id,
+            e
+          );
+        }
+      }
+    }
+
+    Ok(Json(
+      report.into_iter().map(ImportRowResponse::from).collect(),
+    ))
+  }
+
+  /// Lists the assignments of the group. Students only see the assignments which have been released.
+  #[oai(path = "/groups/:id/assignments", method = "get")]
+  async fn list_assignments(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<Vec<AssignmentResponse>>, GroupError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let group = find_group(&mut db, id.0)?;
+    ensure_member(&mut db, &group, user.user_id)?;
+
+    let teacher = is_teacher(&group, user.user_id);
+    let now = SystemTime::now();
+    let assignments = db.list_group_assignments(group.id)?;
+    Ok(Json(
+      assignments
+        .into_iter()
+        .filter(|a| teacher || is_released(&a.metadata, now))
+        .map(AssignmentResponse::from)
+        .collect(),
+    ))
+  }
+}
+
+fn find_group<Db: DbType>(db: &mut Db, group_id: i32) -> Result<Group, GroupError> {
+  db.get_group_by_id(group_id)?
+    .ok_or(GroupError::NotFound("Group".into()))
+}
+
+fn ensure_teacher(group: &Group, user_id: i32) -> Result<(), GroupError> {
+  if !is_teacher(group, user_id) {
+    return Err(GroupError::Forbidden);
+  }
+  Ok(())
+}
+
+fn ensure_member<Db: DbType>(db: &mut Db, group: &Group, user_id: i32) -> Result<(), GroupError> {
+  if !is_group_member(db, group, user_id)? {
+    return Err(GroupError::Forbidden);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::test_utils::{user, valid_token};
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      assignment::{Assignment, AssignmentMetadata},
+      repository::{Repository, Repotype},
+      user::User,
+    },
+    DbHandle,
+  };
+  use gmt_common::repositories::repository_storage::RepositoryStorage;
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn client<F>(setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    client_with_storage(RepositoryStorage::faux(), setup)
+  }
+
+  fn client_with_storage<F>(storage: RepositoryStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      GroupService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage)),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn group(id: i32, teacher_id: Option<i32>) -> Group {
+    Group {
+      id,
+      name: format!("group-{}", id),
+      teacher_id,
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_groups_missing_token() {
+    let client = client(|_| {});
+
+    let resp = client.get("/groups").send().await;
+    resp.assert_status(StatusCode::UNAUTHORIZED);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_groups(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.list_teaching_groups(USER_ID))
+        .then(|_| Ok(vec![group(1, Some(USER_ID)), group(2, Some(USER_ID))]));
+      faux::when!(db.list_belongs_groups(USER_ID))
+        .then(|_| Ok(vec![group(2, Some(USER_ID)), group(3, Some(OTHER_ID))]));
+    });
+
+    let resp = client
+      .get("/groups")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(vec![
+        GroupResponse::from(group(1, Some(USER_ID))),
+        GroupResponse::from(group(2, Some(USER_ID))),
+        GroupResponse::from(group(3, Some(OTHER_ID))),
+      ])
+      .await;
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_group_sets_teacher(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.create_group("new-group", Some(USER_ID))).then(|(name, teacher_id)| {
+        Ok(Group {
+          id: 1,
+          name: name.to_string(),
+          teacher_id,
+        })
+      });
+    });
+
+    let resp = client
+      .post("/groups")
+      .header("Authorization", valid_token)
+      .body_json(&CreateGroupRequest {
+        name: "new-group".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(GroupResponse {
+        id: 1,
+        name: "new-group".to_string(),
+        teacher_id: Some(USER_ID),
+      })
+      .await;
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), vec![], StatusCode::OK)]
+  #[case(Some(OTHER_ID), vec![user(USER_ID, "user-1")], StatusCode::OK)]
+  #[case(Some(OTHER_ID), vec![user(3, "user-3")], StatusCode::FORBIDDEN)]
+  #[case(None, vec![], StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_get_group_permissions(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] students: Vec<User>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      let students = students.clone();
+      faux::when!(db.list_students(1)).then(move |_| Ok(students.clone()));
+    });
+
+    let resp = client
+      .get("/groups/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_nonexistent_group(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(None));
+    });
+
+    let resp = client
+      .get("/groups/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_update_group_not_teacher(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(OTHER_ID)))));
+    });
+
+    let resp = client
+      .patch("/groups/1")
+      .header("Authorization", valid_token)
+      .body_json(&UpdateGroupRequest {
+        teacher_id: Some(USER_ID),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_update_group_teacher(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.get_user_by_id(OTHER_ID)).then(|_| Ok(Some(user(OTHER_ID, "user-2"))));
+      faux::when!(db.set_teacher(1, Some(OTHER_ID)))
+        .then(|(id, teacher_id)| Ok(group(id, teacher_id)));
+    });
+
+    let resp = client
+      .patch("/groups/1")
+      .header("Authorization", valid_token)
+      .body_json(&UpdateGroupRequest {
+        teacher_id: Some(OTHER_ID),
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(GroupResponse::from(group(1, Some(OTHER_ID))))
+      .await;
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_update_group_unknown_teacher(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.get_user_by_id(OTHER_ID)).then(|_| Ok(None));
+    });
+
+    let resp = client
+      .patch("/groups/1")
+      .header("Authorization", valid_token)
+      .body_json(&UpdateGroupRequest {
+        teacher_id: Some(OTHER_ID),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), StatusCode::OK)]
+  #[case(Some(OTHER_ID), StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_delete_group(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.delete_group(1)).then(|_| true);
+    });
+
+    let resp = client
+      .delete("/groups/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_students(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![user(2, "user-2"), user(3, "user-3")]));
+    });
+
+    let resp = client
+      .get("/groups/1/students")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(vec![
+        StudentResponse::from(user(2, "user-2")),
+        StudentResponse::from(user(3, "user-3")),
+      ])
+      .await;
+  }
+
+  #[rstest]
+  #[case(Some(USER_ID), Some(user(OTHER_ID, "user-2")), vec![], StatusCode::OK)]
+  #[case(Some(OTHER_ID), Some(user(3, "user-3")), vec![], StatusCode::FORBIDDEN)]
+  #[case(Some(USER_ID), None, vec![], StatusCode::NOT_FOUND)]
+  #[case(Some(USER_ID), Some(user(OTHER_ID, "user-2")), vec![user(OTHER_ID, "user-2")], StatusCode::CONFLICT)]
+  #[tokio::test]
+  async fn test_add_student(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] student: Option<User>,
+    #[case] students: Vec<User>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      let student = student.clone();
+      faux::when!(db.get_user_by_username).then(move |_| Ok(student.clone()));
+      let students = students.clone();
+      faux::when!(db.list_students(1)).then(move |_| Ok(students.clone()));
+      faux::when!(db.add_student(1, OTHER_ID)).then(|_| Ok(()));
+      faux::when!(db.list_group_assignments(1)).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .post("/groups/1/students")
+      .header("Authorization", valid_token)
+      .body_json(&AddStudentRequest {
+        username: "student".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_add_student_provisions_released_assignments(valid_token: String) {
+    let forks = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = RepositoryStorage::faux();
+    let recorded = forks.clone();
+    faux::when!(storage.fork_repository).then(move |(source, name)| {
+      recorded
+        .lock()
+        .unwrap()
+        .push((source.to_string(), name.to_string()));
+      Ok(std::path::PathBuf::from(name))
+    });
+
+    let next_year = SystemTime::now() + std::time::Duration::from_secs(365 * 24 * 3600);
+    let client = client_with_storage(storage, move |db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.get_user_by_username).then(|_| Ok(Some(user(OTHER_ID, "user-2"))));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+      faux::when!(db.add_student(1, OTHER_ID)).then(|_| Ok(()));
+      faux::when!(db.list_group_assignments(1))
+        .then(move |_| Ok(vec![assignment(1, None), assignment(2, Some(next_year))]));
+      faux::when!(db.get_assignment_submission_repos(1)).then(|_| Ok(vec![]));
+      faux::when!(db.get_repository_by_id(2)).then(|_| {
+        Ok(Some(Repository {
+          id: 2,
+          name: "base".to_string(),
+          repo_type: Repotype::Default,
+          owner_id: USER_ID,
+          assignment_id: None,
+        }))
+      });
+      faux::when!(db.get_repository_by_name).then(|_| Ok(None));
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      faux::when!(db.create_repository).then(|(name, repo_type, owner_id, assignment_id)| {
+        assert_eq!(owner_id, OTHER_ID);
+        assert_eq!(assignment_id, Some(1));
+        Ok(Repository {
+          id: 20,
+          name: name.to_string(),
+          repo_type: if *repo_type == Repotype::Ci {
+            Repotype::Ci
+          } else {
+            Repotype::Default
+          },
+          owner_id,
+          assignment_id,
+        })
+      });
+    });
+
+    let resp = client
+      .post("/groups/1/students")
+      .header("Authorization", valid_token)
+      .body_json(&AddStudentRequest {
+        username: "student".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    assert_eq!(
+      *forks.lock().unwrap(),
+      vec![("base".to_string(), format!("base-user-{}", OTHER_ID))]
+    );
+  }
+
+  #[rstest]
+  #[case::teacher(Some(USER_ID), StatusCode::OK)]
+  #[case::not_teacher(Some(OTHER_ID), StatusCode::FORBIDDEN)]
+  #[tokio::test]
+  async fn test_import_students_permissions(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] expected: StatusCode,
+  ) {
+    let client = client(move |db| {
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+      faux::when!(db.list_group_assignments(1)).then(|_| Ok(vec![]));
+    });
+
+    let resp = client
+      .post("/groups/1/students/import")
+      .header("Authorization", valid_token)
+      .body_json(&ImportStudentsRequest {
+        csv: "username,email\n".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_import_students(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+      faux::when!(db.get_user_by_username).then(|username| match username {
+        "user-2" => Ok(Some(user(OTHER_ID, "user-2"))),
+        _ => Ok(None),
+      });
+      faux::when!(db.get_user_by_email).then(|_| Ok(None));
+      faux::when!(db.create_user).then(|(username, email, _, _)| {
+        Ok(User {
+          email: email.to_string(),
+          ..user(3, username)
+        })
+      });
+      faux::when!(db.create_password_token).then(|_| Ok(()));
+      faux::when!(db.add_student).then(|_| Ok(()));
+      faux::when!(db.list_group_assignments(1)).then(|_| Ok(vec![]));
+      faux::when!(db.get_user_by_id).then(|id| Ok(Some(user(id, &format!("user-{}", id)))));
+    });
+
+    let resp = client
+      .post("/groups/1/students/import")
+      .header("Authorization", valid_token)
+      .body_json(&ImportStudentsRequest {
+        csv: "user-2,user-2@test.com\nnew,new@test.com\nbroken\n".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+
+    let report = resp
+      .json()
+      .await
+      .value()
+      .deserialize::<Vec<ImportRowResponse>>();
+    let statuses: Vec<(u64, ImportStatus, Option<i32>)> = report
+      .iter()
+      .map(|r| (r.line, r.status, r.user_id))
+      .collect();
+    assert_eq!(
+      statuses,
+      vec![
+        (1, ImportStatus::Linked, Some(OTHER_ID)),
+        (2, ImportStatus::Created, Some(3)),
+        (3, ImportStatus::Failed, None),
+      ]
+    );
+    assert!(report[1].token.is_some());
+    assert!(report[2].reason.is_some());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_import_students_invalid_csv(valid_token: String) {
+    let client = client(|db| {
+      faux::when!(db.get_group_by_id(1)).then(|_| Ok(Some(group(1, Some(USER_ID)))));
+    });
+
+    let resp = client
+      .post("/groups/1/students/import")
+      .header("Authorization", valid_token)
+      .body_json(&ImportStudentsRequest {
+        csv: "user,\"unterminated".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+  }
+
+  fn assignment(id: i32, release_date: Option<SystemTime>) -> Assignment {
+    Assignment {
+      id,
+      group_id: 1,
+      base_repo_id: 2,
+      test_repo_id: Some(3),
+      correction_repo_id: None,
+      metadata: AssignmentMetadata {
+        name: "Assignment".to_string(),
+        release_date,
+        ..Default::default()
+      },
+    }
+  }
+
+  #[rstest]
+  #[case::teacher(Some(USER_ID), vec![1, 2])]
+  #[case::student(Some(OTHER_ID), vec![1])]
+  #[tokio::test]
+  async fn test_list_assignments(
+    valid_token: String,
+    #[case] teacher_id: Option<i32>,
+    #[case] expected: Vec<i32>,
+  ) {
+    let next_year = SystemTime::now() + std::time::Duration::from_secs(365 * 24 * 3600);
+    let client = client(move |db| {
+      faux::when!(db.get_group_by_id(1)).then(move |_| Ok(Some(group(1, teacher_id))));
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![user(USER_ID, "user-1")]));
+      faux::when!(db.list_group_assignments(1))
+        .then(move |_| Ok(vec![assignment(1, None), assignment(2, Some(next_year))]));
+    });
+
+    let resp = client
+      .get("/groups/1/assignments")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    let assignments = resp
+      .json()
+      .await
+      .value()
+      .deserialize::<Vec<AssignmentResponse>>();
+    assert_eq!(
+      assignments,
+      expected
+        .into_iter()
+        .map(|id| AssignmentResponse::from(assignment(id, (id == 2).then_some(next_year))))
+        .collect::<Vec<_>>()
+    );
+  }
+}
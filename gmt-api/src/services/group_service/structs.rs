@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::AssignmentDbHandle,
+    group::{Group, GroupDbHandle},
+    repository::RepositoryDbHandle,
+    transaction::TransactionDbHandle,
+    user::{User, UserDbHandle},
+  },
+  error::DatabaseError,
+};
+use gmt_common::{
+  repositories::repository_storage::RepositoryStorage,
+  student_import::{ImportError, ImportOutcome, ImportRow},
+};
+use poem_openapi::{ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::structs::StringResponse;
+
+pub trait DbType:
+  AssignmentDbHandle
+  + GroupDbHandle
+  + RepositoryDbHandle
+  + TransactionDbHandle
+  + UserDbHandle
+  + 'static
+{
+}
+impl<T> DbType for T where
+  T: AssignmentDbHandle
+    + GroupDbHandle
+    + RepositoryDbHandle
+    + TransactionDbHandle
+    + UserDbHandle
+    + 'static
+{
+}
+
+pub struct GroupService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+}
+
+impl<DbPool, Db> GroupService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>) -> Self {
+    Self { db, storage }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct CreateGroupRequest {
+  pub name: String,
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct UpdateGroupRequest {
+  /// The new teacher of the group. `null` removes the current teacher.
+  pub teacher_id: Option<i32>,
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct AddStudentRequest {
+  pub username: String,
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct ImportStudentsRequest {
+  /// One student per line: username, email and an optional public key. A header line starting
+  /// with `username` is ignored.
+  pub csv: String,
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+  /// A new user was created and added to the group
+  Created,
+  /// An existing user was added to the group
+  Linked,
+  /// The line was ignored
+  Failed,
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct ImportRowResponse {
+  pub line: u64,
+  pub username: String,
+  pub email: String,
+  pub status: ImportStatus,
+  pub user_id: Option<i32>,
+  /// The token the new user has to use to choose a password
+  pub token: Option<String>,
+  /// Why the line was ignored
+  pub reason: Option<String>,
+}
+
+impl From<ImportRow> for ImportRowResponse {
+  fn from(row: ImportRow) -> Self {
+    let (status, user_id, token, reason) = match row.outcome {
+      ImportOutcome::Created { user_id, token } => {
+        (ImportStatus::Created, Some(user_id), Some(token), None)
+      }
+      ImportOutcome::Linked { user_id } => (ImportStatus::Linked, Some(user_id), None, None),
+      ImportOutcome::Failed { reason } => (ImportStatus::Failed, None, None, Some(reason)),
+    };
+    Self {
+      line: row.line as u64,
+      username: row.username,
+      email: row.email,
+      status,
+      user_id,
+      token,
+      reason,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct GroupResponse {
+  pub id: i32,
+  pub name: String,
+  pub teacher_id: Option<i32>,
+}
+
+impl From<Group> for GroupResponse {
+  fn from(group: Group) -> Self {
+    Self {
+      id: group.id,
+      name: group.name,
+      teacher_id: group.teacher_id,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct StudentResponse {
+  pub id: i32,
+  pub username: String,
+  pub email: String,
+}
+
+impl From<User> for StudentResponse {
+  fn from(user: User) -> Self {
+    Self {
+      id: user.id,
+      username: user.username,
+      email: user.email,
+    }
+  }
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum GroupError {
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("Only the teacher of the group can perform this action")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 409)]
+  #[error("{0}")]
+  Conflict(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, GroupError, InternalServerError);
+
+impl From<TokenError> for GroupError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => GroupError::Unauthorized,
+      TokenError::InternalServerError => GroupError::InternalServerError,
+    }
+  }
+}
+
+impl From<ImportError> for GroupError {
+  fn from(e: ImportError) -> Self {
+    match e {
+      ImportError::DatabaseError(_) => GroupError::InternalServerError,
+      ImportError::InvalidCsv(_) => GroupError::BadRequest(e.to_string().into()),
+    }
+  }
+}
@@ -3,39 +3,88 @@ use std::sync::{Arc, Mutex};
 use database::{
   connection_pool::ConnectionProvider,
   db_handle::{
-    assignment::AssignmentDbHandle, cirun::CirunDbHandle, comment::CommentDbHandle,
-    group::GroupDbHandle, repository::RepositoryDbHandle, user::UserDbHandle,
+    artifact::ArtifactDbHandle, assignment::AssignmentDbHandle, cirun::CirunDbHandle,
+    cirun_step::CirunStepDbHandle, comment::CommentDbHandle, grade::GradeDbHandle,
+    group::GroupDbHandle, push::PushDbHandle, repository::RepositoryDbHandle,
+    submission::SubmissionDbHandle, transaction::TransactionDbHandle, user::UserDbHandle,
   },
 };
-use gmt_common::password::PasswordAuthImpl;
+use gmt_common::{
+  artifact_storage::ArtifactStorage, password::PasswordAuthImpl,
+  repositories::repository_storage::RepositoryStorage,
+};
 use poem_openapi::{OpenApi, OpenApiService};
 
-use self::{auth_service::AuthService, hello_service::HelloService};
+use self::{
+  assignment_service::AssignmentService,
+  auth_service::AuthService,
+  cirun_service::{events::CirunEvents, CirunService},
+  code_service::CodeService,
+  comment_service::CommentService,
+  grade_service::GradeService,
+  group_service::GroupService,
+  hello_service::HelloService,
+  repository_service::{CloneUrls, RepositoryService},
+};
 
+pub mod assignment_service;
 pub mod auth_service;
+pub mod cirun_service;
+pub mod code_service;
+pub mod comment_service;
+pub mod grade_service;
+pub mod group_service;
 pub mod hello_service;
+pub mod repository_service;
 
 pub mod structs;
 
 #[cfg(test)]
 pub mod test_utils;
 
-pub fn make_service<DbPool, Db>(db: DbPool) -> OpenApiService<impl OpenApi, ()>
+pub fn make_service<DbPool, Db>(
+  db: DbPool,
+  storage: RepositoryStorage,
+  artifacts: ArtifactStorage,
+  clone_urls: CloneUrls,
+  cirun_events: CirunEvents,
+) -> OpenApiService<impl OpenApi, ()>
 where
   Arc<Mutex<Db>>: 'static + Send + Sync,
   DbPool: ConnectionProvider<Connection = Db> + 'static,
   Db: 'static
+    + ArtifactDbHandle
     + AssignmentDbHandle
     + CirunDbHandle
+    + CirunStepDbHandle
     + CommentDbHandle
+    + GradeDbHandle
     + GroupDbHandle
+    + PushDbHandle
     + RepositoryDbHandle
+    + SubmissionDbHandle
+    + TransactionDbHandle
     + UserDbHandle,
 {
+  let db = Arc::new(db);
+  let storage = Arc::new(storage);
+
   OpenApiService::new(
     (
       HelloService,
-      AuthService::<DbPool, Db, PasswordAuthImpl>::new(db),
+      AuthService::<Arc<DbPool>, Db, PasswordAuthImpl>::new(db.clone()),
+      GroupService::<Arc<DbPool>, Db>::new(db.clone(), storage.clone()),
+      AssignmentService::<Arc<DbPool>, Db>::new(db.clone(), storage.clone()),
+      RepositoryService::<Arc<DbPool>, Db>::new(db.clone(), storage.clone(), clone_urls),
+      CommentService::<Arc<DbPool>, Db>::new(db.clone(), storage.clone()),
+      CirunService::<Arc<DbPool>, Db>::new(
+        db.clone(),
+        storage.clone(),
+        Arc::new(artifacts),
+        cirun_events,
+      ),
+      GradeService::<Arc<DbPool>, Db>::new(db.clone(), storage.clone()),
+      CodeService::<Arc<DbPool>, Db>::new(db, storage),
     ),
     "Git Mentor APIs",
     "1.0",
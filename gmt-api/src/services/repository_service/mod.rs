@@ -0,0 +1,463 @@
+use std::sync::{Arc, Mutex};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::repository::{Repository, Repotype},
+};
+use gmt_common::{
+  permissions::{can_read_repository, can_write_repository},
+  repositories::repository_storage::is_valid_repository_name,
+};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+
+use crate::security::gmt_token::GmtToken;
+
+pub mod structs;
+
+pub use structs::*;
+
+#[OpenApi]
+impl<DbPool, Db> RepositoryService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  /// Lists the repositories owned by the user
+  #[oai(path = "/repositories", method = "get")]
+  async fn list_repositories(
+    &self,
+    token: GmtToken,
+  ) -> Result<Json<Vec<RepositoryResponse>>, RepositoryError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repositories = db.list_user_repositories(user.user_id)?;
+    Ok(Json(
+      repositories
+        .into_iter()
+        .map(RepositoryResponse::from)
+        .collect(),
+    ))
+  }
+
+  /// Creates a personal repository, not linked to any assignment
+  #[oai(path = "/repositories", method = "post")]
+  async fn create_repository(
+    &self,
+    token: GmtToken,
+    req: Json<CreateRepositoryRequest>,
+  ) -> Result<Json<RepositoryDetailsResponse>, RepositoryError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    if !is_valid_repository_name(&req.name) {
+      return Err(RepositoryError::BadRequest(
+        format!("Invalid repository name: {}", req.name).into(),
+      ));
+    }
+    let conflict = || RepositoryError::Conflict("Repository name already exists".into());
+
+    let mut created = false;
+    let repository = db.transaction(|db| {
+      let repository = db
+        .create_repository(&req.name, &Repotype::Default, user.user_id, None)
+        .map_err(|e| {
+          if e.is_unique_violation() {
+            conflict()
+          } else {
+            e.into()
+          }
+        })?;
+      self.storage.create_repository(&req.name).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AlreadyExists {
+          conflict()
+        } else {
+          e.into()
+        }
+      })?;
+      created = true;
+      Ok::<_, RepositoryError>(repository)
+    });
+
+    // The commit can fail once the repository is on disk, which would block the name for good
+    if repository.is_err() && created {
+      if let Err(e) = self.storage.delete_repository(&req.name) {
+        log::warn!(
+          "Unable to remove repository {} after failure: {}",
+          req.name,
+          e
+        );
+      }
+    }
+    let repository = repository?;
+
+    let owner = db.get_repository_owner(repository.id)?;
+    Ok(Json(RepositoryDetailsResponse::new(
+      repository,
+      owner,
+      &self.clone_urls,
+    )))
+  }
+
+  #[oai(path = "/repositories/:id", method = "get")]
+  async fn get_repository(
+    &self,
+    token: GmtToken,
+    id: Path<i32>,
+  ) -> Result<Json<RepositoryDetailsResponse>, RepositoryError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_read_repository(&mut db, &repository, user.user_id)? {
+      return Err(RepositoryError::Forbidden);
+    }
+
+    let owner = db.get_repository_owner(repository.id)?;
+    Ok(Json(RepositoryDetailsResponse::new(
+      repository,
+      owner,
+      &self.clone_urls,
+    )))
+  }
+
+  /// Deletes the repository, both from the database and from the disk. Repositories used by an
+  /// assignment and the submission repositories of the students can't be deleted.
+  #[oai(path = "/repositories/:id", method = "delete")]
+  async fn delete_repository(&self, token: GmtToken, id: Path<i32>) -> Result<(), RepositoryError> {
+    let user = token.get_user()?;
+    let mut db = self.db.get_connection()?;
+
+    let repository = find_repository(&mut db, id.0)?;
+    if !can_write_repository(&mut db, &repository, user.user_id)? {
+      return Err(RepositoryError::Forbidden);
+    }
+    // Deleting it would remove the submissions and grades of the student
+    if repository.assignment_id.is_some() {
+      return Err(RepositoryError::Conflict(
+        "Submission repositories can't be deleted".into(),
+      ));
+    }
+    if !db.list_repository_assignments(repository.id)?.is_empty() {
+      return Err(RepositoryError::Conflict(
+        "Repository is used by an assignment".into(),
+      ));
+    }
+
+    db.transaction(|db| {
+      if !db.delete_repository(repository.id) {
+        return Err(RepositoryError::InternalServerError);
+      }
+      self.storage.delete_repository(&repository.name)?;
+      Ok(())
+    })
+  }
+}
+
+fn find_repository<Db: DbType>(
+  db: &mut Db,
+  repository_id: i32,
+) -> Result<Repository, RepositoryError> {
+  db.get_repository_by_id(repository_id)?
+    .ok_or(RepositoryError::NotFound("Repository".into()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::test_utils::{repository, user, valid_token};
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      assignment::{Assignment, AssignmentMetadata},
+      group::Group,
+    },
+    error::DatabaseError,
+    DbHandle,
+  };
+  use gmt_common::repositories::repository_storage::RepositoryStorage;
+  use poem::{http::StatusCode, test::TestClient, Route};
+  use poem_openapi::OpenApiService;
+  use rstest::rstest;
+  use std::{io, path::PathBuf};
+
+  const USER_ID: i32 = 1;
+  const OTHER_ID: i32 = 2;
+
+  fn clone_urls() -> CloneUrls {
+    CloneUrls {
+      ssh: "ssh://git@example.com:2222".to_string(),
+      http: "https://example.com/git/".to_string(),
+    }
+  }
+
+  fn client<F>(storage: RepositoryStorage, setup: F) -> TestClient<Route>
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      faux::when!(db.rollback_transaction).then(|_| Ok(()));
+      setup(&mut db);
+      Ok(db)
+    });
+    let service = OpenApiService::new(
+      RepositoryService::<ConnectionPool, DbHandle>::new(pool, Arc::new(storage), clone_urls()),
+      "",
+      "",
+    );
+
+    TestClient::new(Route::new().nest("/", service))
+  }
+
+  fn assignment(id: i32, base_repo_id: i32) -> Assignment {
+    Assignment {
+      id,
+      group_id: 1,
+      base_repo_id,
+      test_repo_id: None,
+      correction_repo_id: None,
+      metadata: AssignmentMetadata::default(),
+    }
+  }
+
+  #[test]
+  fn test_clone_urls() {
+    let urls = clone_urls();
+    assert_eq!(urls.ssh_url("repo"), "ssh://git@example.com:2222/repo.git");
+    assert_eq!(urls.http_url("repo"), "https://example.com/git/repo.git");
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_list_repositories(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.list_user_repositories(USER_ID)).then(|_| {
+        Ok(vec![
+          repository(1, USER_ID, None),
+          repository(2, USER_ID, Some(3)),
+        ])
+      });
+    });
+
+    let resp = client
+      .get("/repositories")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(vec![
+        RepositoryResponse::from(repository(1, USER_ID, None)),
+        RepositoryResponse::from(repository(2, USER_ID, Some(3))),
+      ])
+      .await;
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_repository(valid_token: String) {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.create_repository("repo-1")).then(|name| Ok(PathBuf::from(name)));
+    let client = client(storage, |db| {
+      faux::when!(db.create_repository).then(|(name, _, owner_id, assignment_id)| {
+        assert_eq!(name, "repo-1");
+        assert_eq!(assignment_id, None);
+        Ok(repository(1, owner_id, None))
+      });
+      faux::when!(db.get_repository_owner(1)).then(|_| Ok(user(USER_ID, "user-1")));
+    });
+
+    let resp = client
+      .post("/repositories")
+      .header("Authorization", valid_token)
+      .body_json(&CreateRepositoryRequest {
+        name: "repo-1".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp
+      .assert_json(RepositoryDetailsResponse::new(
+        repository(1, USER_ID, None),
+        user(USER_ID, "user-1"),
+        &clone_urls(),
+      ))
+      .await;
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_repository_invalid_name(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |_| {});
+
+    let resp = client
+      .post("/repositories")
+      .header("Authorization", valid_token)
+      .body_json(&CreateRepositoryRequest {
+        name: "../repo".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_repository_existing_on_disk(valid_token: String) {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.create_repository)
+      .then(|_| Err(io::Error::new(io::ErrorKind::AlreadyExists, "exists")));
+    let client = client(storage, |db| {
+      faux::when!(db.create_repository)
+        .then(|(_, _, owner_id, _)| Ok(repository(1, owner_id, None)));
+      faux::when!(db.commit_transaction).then(|_| panic!("Transaction should not be committed"));
+    });
+
+    let resp = client
+      .post("/repositories")
+      .header("Authorization", valid_token)
+      .body_json(&CreateRepositoryRequest {
+        name: "repo-1".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::CONFLICT);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_create_repository_commit_failure(valid_token: String) {
+    let deleted = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.create_repository).then(|name| Ok(PathBuf::from(name)));
+    let removed = deleted.clone();
+    faux::when!(storage.delete_repository).then(move |name| {
+      removed.lock().unwrap().push(name.to_string());
+      Ok(())
+    });
+    let client = client(storage, |db| {
+      faux::when!(db.create_repository)
+        .then(|(_, _, owner_id, _)| Ok(repository(1, owner_id, None)));
+      faux::when!(db.commit_transaction).then(|_| Err(DatabaseError::NotFound));
+    });
+
+    let resp = client
+      .post("/repositories")
+      .header("Authorization", valid_token)
+      .body_json(&CreateRepositoryRequest {
+        name: "repo-1".to_string(),
+      })
+      .send()
+      .await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(*deleted.lock().unwrap(), vec!["repo-1"]);
+  }
+
+  #[rstest]
+  #[case::owner(repository(1, USER_ID, None), vec![], StatusCode::OK)]
+  #[case::stranger(repository(1, OTHER_ID, None), vec![], StatusCode::FORBIDDEN)]
+  #[case::submission_teacher(repository(1, OTHER_ID, Some(1)), vec![], StatusCode::OK)]
+  #[case::base_repo_student(repository(1, OTHER_ID, None), vec![assignment(1, 1)], StatusCode::OK)]
+  #[tokio::test]
+  async fn test_get_repository(
+    valid_token: String,
+    #[case] repo: Repository,
+    #[case] assignments: Vec<Assignment>,
+    #[case] expected: StatusCode,
+  ) {
+    let owner_id = repo.owner_id;
+    let assignment_id = repo.assignment_id;
+    let client = client(RepositoryStorage::faux(), move |db| {
+      faux::when!(db.get_repository_by_id(1))
+        .then(move |_| Ok(Some(repository(1, owner_id, assignment_id))));
+      faux::when!(db.get_repository_owner(1))
+        .then(move |_| Ok(user(owner_id, &format!("user-{}", owner_id))));
+      // The submission's group is taught by the user, while the base repository's group only
+      // has the user as a student
+      faux::when!(db.get_assignment_group(1)).then(|_| {
+        Ok(Some(Group {
+          id: 2,
+          name: "group".to_string(),
+          teacher_id: Some(USER_ID),
+        }))
+      });
+      let assignments = assignments.clone();
+      faux::when!(db.list_repository_assignments(1)).then(move |_| Ok(assignments.clone()));
+      faux::when!(db.get_group_by_id(1)).then(|_| {
+        Ok(Some(Group {
+          id: 1,
+          name: "group".to_string(),
+          teacher_id: Some(OTHER_ID),
+        }))
+      });
+      faux::when!(db.list_students(1)).then(|_| Ok(vec![user(USER_ID, "user-1")]));
+    });
+
+    let resp = client
+      .get("/repositories/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+    if expected == StatusCode::OK {
+      resp
+        .assert_json(RepositoryDetailsResponse::new(
+          repository(1, owner_id, assignment_id),
+          user(owner_id, &format!("user-{}", owner_id)),
+          &clone_urls(),
+        ))
+        .await;
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_get_nonexistent_repository(valid_token: String) {
+    let client = client(RepositoryStorage::faux(), |db| {
+      faux::when!(db.get_repository_by_id(1)).then(|_| Ok(None));
+    });
+
+    let resp = client
+      .get("/repositories/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[rstest]
+  #[case::owner(USER_ID, None, vec![], StatusCode::OK)]
+  #[case::not_owner(OTHER_ID, None, vec![], StatusCode::FORBIDDEN)]
+  #[case::used_by_assignment(USER_ID, None, vec![assignment(1, 1)], StatusCode::CONFLICT)]
+  #[case::submission(USER_ID, Some(1), vec![], StatusCode::CONFLICT)]
+  #[tokio::test]
+  async fn test_delete_repository(
+    valid_token: String,
+    #[case] owner_id: i32,
+    #[case] assignment_id: Option<i32>,
+    #[case] assignments: Vec<Assignment>,
+    #[case] expected: StatusCode,
+  ) {
+    let mut storage = RepositoryStorage::faux();
+    faux::when!(storage.delete_repository("repo-1")).then(|_| Ok(()));
+    let client = client(storage, move |db| {
+      faux::when!(db.get_repository_by_id(1))
+        .then(move |_| Ok(Some(repository(1, owner_id, assignment_id))));
+      let assignments = assignments.clone();
+      faux::when!(db.list_repository_assignments(1)).then(move |_| Ok(assignments.clone()));
+      faux::when!(db.delete_repository(1)).then(|_| true);
+    });
+
+    let resp = client
+      .delete("/repositories/1")
+      .header("Authorization", valid_token)
+      .send()
+      .await;
+    resp.assert_status(expected);
+  }
+}
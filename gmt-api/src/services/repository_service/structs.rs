@@ -0,0 +1,211 @@
+use std::sync::{Arc, Mutex};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::AssignmentDbHandle,
+    group::GroupDbHandle,
+    repository::{Repository, RepositoryDbHandle, Repotype},
+    transaction::TransactionDbHandle,
+    user::{User, UserDbHandle},
+  },
+  error::DatabaseError,
+};
+use gmt_common::repositories::repository_storage::RepositoryStorage;
+use poem_openapi::{ApiResponse, Enum, Object};
+use serde::{Deserialize, Serialize};
+
+use crate::{error_from, security::gmt_token::TokenError};
+
+use super::super::structs::StringResponse;
+
+const DEFAULT_SSH_URL: &str = "ssh://git@localhost:2222";
+const DEFAULT_HTTP_URL: &str = "http://localhost:3001/git";
+
+pub trait DbType:
+  AssignmentDbHandle
+  + GroupDbHandle
+  + RepositoryDbHandle
+  + TransactionDbHandle
+  + UserDbHandle
+  + 'static
+{
+}
+impl<T> DbType for T where
+  T: AssignmentDbHandle
+    + GroupDbHandle
+    + RepositoryDbHandle
+    + TransactionDbHandle
+    + UserDbHandle
+    + 'static
+{
+}
+
+/// Base URLs used to build the clone URLs of the repositories
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloneUrls {
+  pub ssh: String,
+  pub http: String,
+}
+
+impl CloneUrls {
+  /// Uses the `GIT_SSH_URL` and `GIT_HTTP_URL` environment variables, defaulting to the local
+  /// servers.
+  pub fn new_from_env() -> Self {
+    Self {
+      ssh: std::env::var("GIT_SSH_URL").unwrap_or_else(|_| DEFAULT_SSH_URL.to_string()),
+      http: std::env::var("GIT_HTTP_URL").unwrap_or_else(|_| DEFAULT_HTTP_URL.to_string()),
+    }
+  }
+
+  pub fn ssh_url(&self, name: &str) -> String {
+    format!("{}/{}.git", self.ssh.trim_end_matches('/'), name)
+  }
+
+  pub fn http_url(&self, name: &str) -> String {
+    format!("{}/{}.git", self.http.trim_end_matches('/'), name)
+  }
+}
+
+pub struct RepositoryService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub db: DbPool,
+  pub storage: Arc<RepositoryStorage>,
+  pub clone_urls: CloneUrls,
+}
+
+impl<DbPool, Db> RepositoryService<DbPool, Db>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: DbType,
+  Arc<Mutex<Db>>: Send + Sync,
+{
+  pub fn new(db: DbPool, storage: Arc<RepositoryStorage>, clone_urls: CloneUrls) -> Self {
+    Self {
+      db,
+      storage,
+      clone_urls,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize)]
+pub struct CreateRepositoryRequest {
+  pub name: String,
+}
+
+#[derive(Enum, Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RepositoryType {
+  Default,
+  Ci,
+}
+
+impl From<Repotype> for RepositoryType {
+  fn from(repo_type: Repotype) -> Self {
+    match repo_type {
+      Repotype::Default => RepositoryType::Default,
+      Repotype::Ci => RepositoryType::Ci,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct RepositoryResponse {
+  pub id: i32,
+  pub name: String,
+  pub repo_type: RepositoryType,
+  pub owner_id: i32,
+  pub assignment_id: Option<i32>,
+}
+
+impl From<Repository> for RepositoryResponse {
+  fn from(repository: Repository) -> Self {
+    Self {
+      id: repository.id,
+      name: repository.name,
+      repo_type: repository.repo_type.into(),
+      owner_id: repository.owner_id,
+      assignment_id: repository.assignment_id,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct OwnerResponse {
+  pub id: i32,
+  pub username: String,
+}
+
+impl From<User> for OwnerResponse {
+  fn from(user: User) -> Self {
+    Self {
+      id: user.id,
+      username: user.username,
+    }
+  }
+}
+
+#[derive(Object, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct RepositoryDetailsResponse {
+  pub id: i32,
+  pub name: String,
+  pub repo_type: RepositoryType,
+  pub owner: OwnerResponse,
+  pub assignment_id: Option<i32>,
+  pub ssh_url: String,
+  pub http_url: String,
+}
+
+impl RepositoryDetailsResponse {
+  pub fn new(repository: Repository, owner: User, clone_urls: &CloneUrls) -> Self {
+    Self {
+      id: repository.id,
+      ssh_url: clone_urls.ssh_url(&repository.name),
+      http_url: clone_urls.http_url(&repository.name),
+      name: repository.name,
+      repo_type: repository.repo_type.into(),
+      owner: owner.into(),
+      assignment_id: repository.assignment_id,
+    }
+  }
+}
+
+#[derive(ApiResponse, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum RepositoryError {
+  #[oai(status = 400)]
+  #[error("{0}")]
+  BadRequest(StringResponse),
+  #[oai(status = 401)]
+  #[error("Invalid authentication token")]
+  Unauthorized,
+  #[oai(status = 403)]
+  #[error("You do not have access to this repository")]
+  Forbidden,
+  #[oai(status = 404)]
+  #[error("{0} not found")]
+  NotFound(StringResponse),
+  #[oai(status = 409)]
+  #[error("{0}")]
+  Conflict(StringResponse),
+  #[oai(status = 500)]
+  #[error("Internal Server Error")]
+  InternalServerError,
+}
+
+error_from!(DatabaseError, RepositoryError, InternalServerError);
+error_from!(std::io::Error, RepositoryError, InternalServerError);
+
+impl From<TokenError> for RepositoryError {
+  fn from(e: TokenError) -> Self {
+    match e {
+      TokenError::Unauthorized => RepositoryError::Unauthorized,
+      TokenError::InternalServerError => RepositoryError::InternalServerError,
+    }
+  }
+}
@@ -3,6 +3,8 @@ use rstest::fixture;
 
 use super::auth_service::{get_secret_key, user_token::UserToken};
 
+pub use database::fixtures::{cirun, repository, user};
+
 #[fixture]
 pub fn valid_token() -> String {
   let user_token = UserToken {
@@ -13,9 +15,46 @@ pub fn valid_token() -> String {
 
   let key = get_secret_key().expect("Unable to get secret key");
 
-  let token = Token::new(Header::default(), UserToken::from(user_token))
+  let token = Token::new(Header::default(), user_token)
     .sign_with_key(&key)
     .expect("Unable to generate token");
 
   token.into()
 }
+
+/// Creates a bare repository holding a single commit on `main` with the given files.
+pub fn git_fixture(files: &[(&str, &[u8])]) -> tempfile::TempDir {
+  let dir = tempfile::tempdir().expect("Unable to create temp dir");
+  let work_tree = dir.path().join("work");
+  let git_dir = dir.path().join("repo.git");
+
+  for (path, content) in files {
+    let path = work_tree.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+  }
+
+  let init = std::process::Command::new("git")
+    .args(["init", "--bare", "--quiet", "--initial-branch=main"])
+    .arg(&git_dir)
+    .status()
+    .expect("Unable to run git");
+  assert!(init.success(), "git init failed");
+
+  let git = |args: &[&str]| {
+    let output = std::process::Command::new("git")
+      .arg("--git-dir")
+      .arg(&git_dir)
+      .arg("--work-tree")
+      .arg(&work_tree)
+      .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+      .args(args)
+      .output()
+      .expect("Unable to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+  };
+  git(&["add", "--all"]);
+  git(&["commit", "--quiet", "-m", "Initial commit"]);
+
+  dir
+}
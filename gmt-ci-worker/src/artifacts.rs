@@ -0,0 +1,302 @@
+use std::{path::Path, process::Command, time::Duration};
+
+use gmt_common::{artifact_storage::ArtifactStorage, ci_pipeline::ArtifactDefinition};
+
+const MEBIBYTE: u64 = 1024 * 1024;
+/// The longest extension kept in the name of a downloaded file
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// The sizes of the artifacts kept, to protect the disk of the worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArtifactLimits {
+  /// The maximum size of an artifact. Directories are limited by the size of their files.
+  pub artifact_bytes: u64,
+  /// The maximum size of all the artifacts of a run
+  pub run_bytes: u64,
+}
+
+impl Default for ArtifactLimits {
+  fn default() -> Self {
+    ArtifactLimits {
+      artifact_bytes: 100 * MEBIBYTE,
+      run_bytes: 500 * MEBIBYTE,
+    }
+  }
+}
+
+impl ArtifactLimits {
+  /// Uses the `CI_ARTIFACT_SIZE_LIMIT` and `CI_ARTIFACTS_RUN_LIMIT` environment variables, in MiB,
+  /// keeping the default of the unset ones.
+  pub fn new_from_env() -> Self {
+    let var = |name: &str, default: u64| {
+      std::env::var(name)
+        .map(|value| {
+          value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("Invalid {}", name))
+            * MEBIBYTE
+        })
+        .unwrap_or(default)
+    };
+    let default = ArtifactLimits::default();
+    ArtifactLimits {
+      artifact_bytes: var("CI_ARTIFACT_SIZE_LIMIT", default.artifact_bytes),
+      run_bytes: var("CI_ARTIFACTS_RUN_LIMIT", default.run_bytes),
+    }
+  }
+}
+
+/// An artifact copied into the storage, which still has to be recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredArtifact {
+  pub name: String,
+  /// The name of the file when downloaded
+  pub file_name: String,
+  pub content_hash: String,
+  pub size: u64,
+  /// How long the artifact is kept after the run
+  pub expire_in: Duration,
+}
+
+/// Copies an artifact of the checkout into the storage once the steps ran, `used` being the size
+/// of the artifacts already stored for the run. Directories are stored as a `.tar.gz` archive.
+/// Paths leading outside of the checkout and artifacts over the limits are refused.
+pub fn collect_artifact(
+  workspace: &Path,
+  definition: &ArtifactDefinition,
+  storage: &ArtifactStorage,
+  limits: &ArtifactLimits,
+  used: u64,
+) -> Result<StoredArtifact, String> {
+  let path = workspace.join(&definition.path);
+  if std::fs::symlink_metadata(&path).is_err() {
+    return Err("The artifact was not found".to_string());
+  }
+  // The steps could have replaced the artifact with a link to a file of the worker
+  let (path, workspace) = match (path.canonicalize(), workspace.canonicalize()) {
+    (Ok(path), Ok(workspace)) if path.starts_with(&workspace) => (path, workspace),
+    _ => return Err("The artifact is outside of the checkout".to_string()),
+  };
+
+  let too_large = |size: u64| {
+    if size > limits.artifact_bytes {
+      return Err(format!(
+        "The artifact is larger than {} MiB",
+        limits.artifact_bytes / MEBIBYTE
+      ));
+    }
+    if used + size > limits.run_bytes {
+      return Err(format!(
+        "The artifacts of the run are larger than {} MiB",
+        limits.run_bytes / MEBIBYTE
+      ));
+    }
+    Ok(())
+  };
+  let (stored, file_name) = if path.is_dir() {
+    too_large(directory_size(&path).map_err(|e| e.to_string())?)?;
+    let archive = archive(&workspace, &path)?;
+    let stored = storage.store(archive.path());
+    (stored, format!("{}.tar.gz", definition.name))
+  } else {
+    too_large(std::fs::metadata(&path).map_err(|e| e.to_string())?.len())?;
+    (storage.store(&path), file_name(definition))
+  };
+  let (content_hash, size) = stored.map_err(|e| format!("Unable to store the artifact: {}", e))?;
+  too_large(size)?;
+
+  Ok(StoredArtifact {
+    name: definition.name.clone(),
+    file_name,
+    content_hash,
+    size,
+    expire_in: definition.expire_in,
+  })
+}
+
+/// Names the file after the artifact, keeping the extension of the file kept.
+fn file_name(definition: &ArtifactDefinition) -> String {
+  let extension = definition
+    .path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .filter(|extension| {
+      extension.len() <= MAX_EXTENSION_LENGTH
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+  match extension {
+    Some(extension) if !definition.name.ends_with(&format!(".{}", extension)) => {
+      format!("{}.{}", definition.name, extension)
+    }
+    _ => definition.name.clone(),
+  }
+}
+
+/// The size of the files of the directory, without following the links.
+fn directory_size(path: &Path) -> std::io::Result<u64> {
+  let mut size = 0;
+  for entry in std::fs::read_dir(path)? {
+    let path = entry?.path();
+    let metadata = std::fs::symlink_metadata(&path)?;
+    size += match metadata.is_dir() {
+      true => directory_size(&path)?,
+      false => metadata.len(),
+    };
+  }
+  Ok(size)
+}
+
+/// Archives a directory of the checkout with `tar`, which keeps the links it holds as they are.
+fn archive(workspace: &Path, path: &Path) -> Result<tempfile::NamedTempFile, String> {
+  let path = match path.strip_prefix(workspace) {
+    Ok(relative) if relative.as_os_str().is_empty() => Path::new("."),
+    Ok(relative) => relative,
+    Err(_) => return Err("The artifact is outside of the checkout".to_string()),
+  };
+  let archive = tempfile::NamedTempFile::new().map_err(|e| e.to_string())?;
+  let output = Command::new("tar")
+    .arg("-czf")
+    .arg(archive.path())
+    .arg("-C")
+    .arg(workspace)
+    .arg("--")
+    .arg(path)
+    .output()
+    .map_err(|e| format!("Unable to archive the artifact: {}", e))?;
+  if !output.status.success() {
+    return Err(format!(
+      "Unable to archive the artifact: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use gmt_common::ci_pipeline::{ArtifactWhen, DEFAULT_ARTIFACT_EXPIRY};
+
+  use super::*;
+
+  fn definition(name: &str, path: &str) -> ArtifactDefinition {
+    ArtifactDefinition {
+      name: name.to_string(),
+      path: PathBuf::from(path),
+      when: ArtifactWhen::Always,
+      expire_in: DEFAULT_ARTIFACT_EXPIRY,
+    }
+  }
+
+  fn collect(
+    workspace: &Path,
+    definition: &ArtifactDefinition,
+    limits: &ArtifactLimits,
+    used: u64,
+  ) -> Result<StoredArtifact, String> {
+    let storage = ArtifactStorage::new(workspace.join(".artifacts"));
+    collect_artifact(workspace, definition, &storage, limits, used)
+  }
+
+  #[test]
+  fn test_collect_files() {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(workspace.path().join("target")).unwrap();
+    std::fs::write(workspace.path().join("target/index.html"), "<html>").unwrap();
+    std::fs::write(workspace.path().join("main"), "binary").unwrap();
+
+    let limits = ArtifactLimits::default();
+    let stored = collect(
+      workspace.path(),
+      &definition("coverage", "target/index.html"),
+      &limits,
+      0,
+    )
+    .expect("Unable to collect");
+    assert_eq!(stored.file_name, "coverage.html");
+    assert_eq!(stored.size, 6);
+    assert_eq!(stored.expire_in, DEFAULT_ARTIFACT_EXPIRY);
+    let stored = collect(workspace.path(), &definition("main", "main"), &limits, 0).unwrap();
+    assert_eq!(stored.file_name, "main");
+  }
+
+  #[test]
+  fn test_collect_directories() {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(workspace.path().join("target/doc/html")).unwrap();
+    std::fs::write(
+      workspace.path().join("target/doc/html/index.html"),
+      "<html>",
+    )
+    .unwrap();
+
+    let stored = collect(
+      workspace.path(),
+      &definition("doc", "target/doc"),
+      &ArtifactLimits::default(),
+      0,
+    )
+    .expect("Unable to collect");
+    assert_eq!(stored.file_name, "doc.tar.gz");
+
+    let storage = ArtifactStorage::new(workspace.path().join(".artifacts"));
+    let archive = storage.get_path(&stored.content_hash).unwrap();
+    let output = Command::new("tar")
+      .arg("-tzf")
+      .arg(archive)
+      .output()
+      .unwrap();
+    let files = String::from_utf8_lossy(&output.stdout);
+    assert!(
+      files.lines().any(|f| f == "target/doc/html/index.html"),
+      "{}",
+      files
+    );
+  }
+
+  #[test]
+  fn test_refused_artifacts() {
+    let workspace = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    std::fs::write(outside.path().join("secret"), "secret").unwrap();
+    std::os::unix::fs::symlink(outside.path().join("secret"), workspace.path().join("link"))
+      .unwrap();
+    std::fs::write(
+      workspace.path().join("large"),
+      vec![0; MEBIBYTE as usize + 1],
+    )
+    .unwrap();
+
+    let limits = ArtifactLimits {
+      artifact_bytes: MEBIBYTE,
+      run_bytes: 2 * MEBIBYTE,
+    };
+    assert_eq!(
+      collect(workspace.path(), &definition("a", "missing"), &limits, 0),
+      Err("The artifact was not found".to_string())
+    );
+    assert_eq!(
+      collect(workspace.path(), &definition("a", "link"), &limits, 0),
+      Err("The artifact is outside of the checkout".to_string())
+    );
+    assert_eq!(
+      collect(workspace.path(), &definition("a", "large"), &limits, 0),
+      Err("The artifact is larger than 1 MiB".to_string())
+    );
+
+    // The artifacts already kept count towards the limit of the run
+    std::fs::write(workspace.path().join("small"), "0123456789").unwrap();
+    let used = 2 * MEBIBYTE - 10;
+    assert!(collect(workspace.path(), &definition("a", "small"), &limits, used).is_ok());
+    assert_eq!(
+      collect(
+        workspace.path(),
+        &definition("a", "small"),
+        &limits,
+        used + 1
+      ),
+      Err("The artifacts of the run are larger than 2 MiB".to_string())
+    );
+  }
+}
@@ -0,0 +1,149 @@
+use std::{path::Path, process::Command};
+
+use crate::error::WorkerError;
+
+/// Checks that the commit is a full object id, so that it can't be mistaken for an option or a
+/// reference name.
+fn is_valid_commit(commit: &str) -> bool {
+  matches!(commit.len(), 40 | 64) && commit.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn git(args: &[&std::ffi::OsStr]) -> Result<String, WorkerError> {
+  let output = Command::new("git").args(args).output()?;
+  if !output.status.success() {
+    return Err(WorkerError::CheckoutError(
+      String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the commit of the default branch of the bare repository.
+pub fn resolve_head(repository: &Path) -> Result<String, WorkerError> {
+  git(&[
+    "--git-dir".as_ref(),
+    repository.as_os_str(),
+    "rev-parse".as_ref(),
+    "--verify".as_ref(),
+    "--quiet".as_ref(),
+    "HEAD^{commit}".as_ref(),
+  ])
+}
+
+/// Checks out the commit of the bare repository into `destination`, which must either not exist or
+/// be empty. The checkout is detached from any branch.
+pub fn checkout(repository: &Path, commit: &str, destination: &Path) -> Result<(), WorkerError> {
+  if !is_valid_commit(commit) {
+    return Err(WorkerError::CheckoutError(format!(
+      "Invalid commit {}",
+      commit
+    )));
+  }
+
+  git(&[
+    "clone".as_ref(),
+    "--quiet".as_ref(),
+    "--no-checkout".as_ref(),
+    "--no-hardlinks".as_ref(),
+    repository.as_os_str(),
+    destination.as_os_str(),
+  ])?;
+  git(&[
+    "-C".as_ref(),
+    destination.as_os_str(),
+    "-c".as_ref(),
+    "advice.detachedHead=false".as_ref(),
+    "checkout".as_ref(),
+    "--quiet".as_ref(),
+    "--detach".as_ref(),
+    commit.as_ref(),
+  ])?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::process::Command;
+
+  use super::*;
+
+  /// Creates a bare repository with two commits, returning the ids of the commits.
+  fn repository(dir: &Path) -> (String, String) {
+    let git = |args: &[&str]| {
+      let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(dir.join("repo.git"))
+        .arg("--work-tree")
+        .arg(dir.join("work"))
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    let init = Command::new("git")
+      .args(["init", "--bare", "--quiet"])
+      .arg(dir.join("repo.git"))
+      .status()
+      .expect("Unable to run git");
+    assert!(init.success(), "git init failed");
+    std::fs::create_dir_all(dir.join("work")).unwrap();
+
+    std::fs::write(dir.join("work/file.txt"), "first").unwrap();
+    git(&["add", "--all"]);
+    git(&["commit", "--quiet", "-m", "First commit"]);
+    let first = git(&["rev-parse", "HEAD"]);
+
+    std::fs::write(dir.join("work/file.txt"), "second").unwrap();
+    git(&["add", "--all"]);
+    git(&["commit", "--quiet", "-m", "Second commit"]);
+    let second = git(&["rev-parse", "HEAD"]);
+
+    (first, second)
+  }
+
+  #[test]
+  fn test_checkout() {
+    let dir = tempfile::tempdir().unwrap();
+    let (first, second) = repository(dir.path());
+
+    for (commit, content) in [(first, "first"), (second, "second")] {
+      let destination = dir.path().join(&commit);
+      checkout(&dir.path().join("repo.git"), &commit, &destination).expect("Checkout failed");
+      let file = std::fs::read_to_string(destination.join("file.txt")).unwrap();
+      assert_eq!(file, content);
+    }
+  }
+
+  #[test]
+  fn test_resolve_head() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, second) = repository(dir.path());
+
+    let head = resolve_head(&dir.path().join("repo.git")).expect("Unable to resolve HEAD");
+    assert_eq!(head, second);
+  }
+
+  #[test]
+  fn test_checkout_unknown_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    repository(dir.path());
+
+    let commit = "0".repeat(40);
+    let result = checkout(
+      &dir.path().join("repo.git"),
+      &commit,
+      &dir.path().join("checkout"),
+    );
+    assert!(matches!(result, Err(WorkerError::CheckoutError(_))));
+  }
+
+  #[test]
+  fn test_is_valid_commit() {
+    assert!(is_valid_commit(&"a".repeat(40)));
+    assert!(is_valid_commit(&"0".repeat(64)));
+    assert!(!is_valid_commit("main"));
+    assert!(!is_valid_commit(&format!("--{}", "a".repeat(38))));
+  }
+}
@@ -0,0 +1,24 @@
+use database::error::DatabaseError;
+use git_server::objects::ObjectsError;
+use gmt_common::repositories::provisioning::ProvisionError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WorkerError {
+  #[error("Database error: {0}")]
+  DatabaseError(#[from] DatabaseError),
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
+  #[error("Repository {0} not found")]
+  RepositoryNotFound(i32),
+  #[error("Unable to check out the commit: {0}")]
+  CheckoutError(String),
+  #[error("Invalid pipeline: {0}")]
+  InvalidPipeline(String),
+  #[error("The run was cancelled")]
+  Cancelled,
+  #[error("Git error: {0}")]
+  ObjectsError(#[from] ObjectsError),
+  #[error("Provisioning error: {0}")]
+  ProvisionError(#[from] ProvisionError),
+}
@@ -0,0 +1,648 @@
+//! Runs the steps as processes of the worker host, for the machines without a container runtime.
+//!
+//! Each step runs `sh -c` in the workspace of its job with a cleared environment and a fresh home
+//! directory. Its processes share the resource limits below and a process group, killed once the
+//! step exits or times out. Where the kernel allows it, the step gets its own network namespace,
+//! holding only a loopback interface, and its own PID namespace, whose processes are all killed
+//! along with the step, including the ones which left its process group. Without PID namespace,
+//! the output of the step is only read for a short while after it exits, as the processes escaping
+//! the kill may hold it open.
+
+use std::{
+  io::{ErrorKind, Read},
+  os::unix::process::CommandExt,
+  process::{Command, Stdio},
+  sync::mpsc::{self, RecvTimeoutError},
+  thread,
+  time::{Duration, Instant},
+};
+
+use log::warn;
+
+use super::{Step, StepExecutor, StepOutcome};
+use crate::error::WorkerError;
+
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+const DEFAULT_MAX_OUTPUT: usize = 1024 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const BATCH_SIZE: usize = 16 * 1024;
+const BATCH_INTERVAL: Duration = Duration::from_millis(500);
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// The limits applied to every process of a step with `setrlimit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+  pub cpu_seconds: u64,
+  /// The maximum size of the address space
+  pub memory_bytes: u64,
+  /// The maximum size of the files written
+  pub file_size_bytes: u64,
+  /// The maximum number of processes of the user running the worker, which is therefore best run
+  /// by a dedicated user. The kernel doesn't enforce it for root.
+  pub processes: u64,
+}
+
+impl Default for ResourceLimits {
+  fn default() -> Self {
+    ResourceLimits {
+      cpu_seconds: 10 * 60,
+      memory_bytes: 2048 * MEBIBYTE,
+      file_size_bytes: 256 * MEBIBYTE,
+      processes: 512,
+    }
+  }
+}
+
+impl ResourceLimits {
+  /// Uses the `CI_CPU_LIMIT` (seconds), `CI_MEMORY_LIMIT` (MiB), `CI_FILE_SIZE_LIMIT` (MiB) and
+  /// `CI_PROCESS_LIMIT` environment variables, keeping the default of the unset ones.
+  pub fn new_from_env() -> Self {
+    let var = |name: &str, default: u64, unit: u64| {
+      std::env::var(name)
+        .map(|value| {
+          value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("Invalid {}", name))
+            * unit
+        })
+        .unwrap_or(default)
+    };
+    let default = ResourceLimits::default();
+    ResourceLimits {
+      cpu_seconds: var("CI_CPU_LIMIT", default.cpu_seconds, 1),
+      memory_bytes: var("CI_MEMORY_LIMIT", default.memory_bytes, MEBIBYTE),
+      file_size_bytes: var("CI_FILE_SIZE_LIMIT", default.file_size_bytes, MEBIBYTE),
+      processes: var("CI_PROCESS_LIMIT", default.processes, 1),
+    }
+  }
+}
+
+/// How the steps are cut from the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkIsolation {
+  /// A new network namespace, for workers allowed to create one
+  Namespace,
+  /// A new network namespace owned by a new user namespace, for unprivileged workers
+  UserNamespace,
+  /// No isolation, the steps can access the network of the host
+  None,
+}
+
+impl NetworkIsolation {
+  fn unshare_flags(self) -> libc::c_int {
+    match self {
+      NetworkIsolation::Namespace => libc::CLONE_NEWNET,
+      NetworkIsolation::UserNamespace => libc::CLONE_NEWUSER | libc::CLONE_NEWNET,
+      NetworkIsolation::None => 0,
+    }
+  }
+
+  /// Finds the strongest isolation the host supports by starting a process with each of them.
+  fn probe() -> Self {
+    for isolation in [NetworkIsolation::Namespace, NetworkIsolation::UserNamespace] {
+      if can_unshare(isolation.unshare_flags()) {
+        return isolation;
+      }
+    }
+    warn!("Network namespaces are not available, the CI steps will have access to the network");
+    NetworkIsolation::None
+  }
+}
+
+/// Whether a process can be started in the namespaces given by the `unshare` flags.
+fn can_unshare(flags: libc::c_int) -> bool {
+  let mut command = Command::new("true");
+  command.stdout(Stdio::null()).stderr(Stdio::null());
+  // SAFETY: unshare is async-signal-safe
+  unsafe {
+    command.pre_exec(move || match libc::unshare(flags) {
+      0 => Ok(()),
+      _ => Err(std::io::Error::last_os_error()),
+    });
+  }
+  matches!(command.status(), Ok(status) if status.success())
+}
+
+/// Runs the steps as local processes, see the module documentation.
+pub struct LocalExecutor {
+  limits: ResourceLimits,
+  network: NetworkIsolation,
+  pid_namespace: bool,
+  max_output: usize,
+}
+
+impl LocalExecutor {
+  /// Creates an executor with the strongest network isolation available on the host, and PID
+  /// namespaces if it allows them.
+  pub fn new(limits: ResourceLimits) -> Self {
+    let network = NetworkIsolation::probe();
+    // PID namespaces need the same privileges as network namespaces, or a user namespace
+    let pid_namespace = can_unshare(network.unshare_flags() | libc::CLONE_NEWPID);
+    if !pid_namespace {
+      warn!("PID namespaces are not available, the CI steps may leave processes behind");
+    }
+    LocalExecutor {
+      limits,
+      network,
+      pid_namespace,
+      max_output: DEFAULT_MAX_OUTPUT,
+    }
+  }
+
+  /// Passes on at most `max_output` bytes of the output of each step, 1 MiB by default.
+  pub fn with_max_output(mut self, max_output: usize) -> Self {
+    self.max_output = max_output;
+    self
+  }
+
+  pub fn network_isolation(&self) -> NetworkIsolation {
+    self.network
+  }
+
+  /// Whether each step runs in its own PID namespace.
+  pub fn has_pid_namespace(&self) -> bool {
+    self.pid_namespace
+  }
+}
+
+impl StepExecutor for LocalExecutor {
+  fn execute(
+    &self,
+    step: &Step,
+    output: &mut dyn FnMut(&[u8]),
+  ) -> Result<StepOutcome, WorkerError> {
+    let home = tempfile::Builder::new().prefix("gmt-ci-home-").tempdir()?;
+    let (mut reader, writer) = std::io::pipe()?;
+
+    let mut command = Command::new("sh");
+    command
+      .arg("-c")
+      .arg(step.command)
+      .current_dir(step.workspace)
+      .env_clear()
+      .env("PATH", DEFAULT_PATH)
+      .env("HOME", home.path())
+      .env("TMPDIR", home.path())
+      .env("CI", "true")
+      .envs(step.env)
+      .stdin(Stdio::null())
+      .stdout(writer.try_clone()?)
+      .stderr(writer)
+      .process_group(0);
+    let limits = self.limits;
+    let flags = self.network.unshare_flags();
+    let pid_namespace = self.pid_namespace;
+    // SAFETY: the closure only makes async-signal-safe calls
+    unsafe {
+      command.pre_exec(move || confine(&limits, flags, pid_namespace));
+    }
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    // The command holds the write end of the pipe, which would keep the output open
+    drop(command);
+    let max_output = self.max_output;
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || read_output(&mut reader, max_output, sender));
+
+    let mut batch = Batch::new(output);
+    let mut timed_out = false;
+    let status = loop {
+      if let Some(status) = child.try_wait()? {
+        break status;
+      }
+      if start.elapsed() >= step.timeout {
+        timed_out = true;
+        kill_group(child.id());
+        break child.wait()?;
+      }
+      match receiver.recv_timeout(POLL_INTERVAL) {
+        Ok(chunk) => batch.push(&chunk),
+        Err(RecvTimeoutError::Timeout) => batch.push(&[]),
+        // The step closed its output but keeps running
+        Err(RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+      }
+    };
+    let duration = start.elapsed();
+    // The processes left in the background by the step would otherwise keep running, and hold the
+    // output open
+    kill_group(child.id());
+
+    // The processes which escaped the kill may still hold the output open, in which case the rest
+    // of it is dropped and the reader left behind
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    let closed = loop {
+      match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(chunk) => batch.push(&chunk),
+        Err(RecvTimeoutError::Timeout) => break false,
+        Err(RecvTimeoutError::Disconnected) => break true,
+      }
+    };
+    batch.flush();
+    let truncated = match closed {
+      true => reader.join().expect("Output reader panicked")?,
+      false => {
+        warn!(
+          "Step {}: processes left behind still hold the output open",
+          step.name
+        );
+        true
+      }
+    };
+    Ok(StepOutcome {
+      exit_code: status.code(),
+      timed_out,
+      truncated,
+      duration,
+    })
+  }
+}
+
+/// Groups the output of the step, so that it is passed on in chunks of reasonable size while still
+/// showing up quickly.
+struct Batch<'a> {
+  output: &'a mut dyn FnMut(&[u8]),
+  pending: Vec<u8>,
+  last_flush: Instant,
+}
+
+impl<'a> Batch<'a> {
+  fn new(output: &'a mut dyn FnMut(&[u8])) -> Self {
+    Batch {
+      output,
+      pending: Vec::new(),
+      last_flush: Instant::now(),
+    }
+  }
+
+  fn push(&mut self, chunk: &[u8]) {
+    self.pending.extend_from_slice(chunk);
+    if self.pending.len() >= BATCH_SIZE || self.last_flush.elapsed() >= BATCH_INTERVAL {
+      self.flush();
+    }
+  }
+
+  fn flush(&mut self) {
+    if !self.pending.is_empty() {
+      (self.output)(&self.pending);
+      self.pending.clear();
+    }
+    self.last_flush = Instant::now();
+  }
+}
+
+/// Runs in the child process before executing the step, so it may only make async-signal-safe
+/// calls.
+fn confine(
+  limits: &ResourceLimits,
+  unshare_flags: libc::c_int,
+  pid_namespace: bool,
+) -> std::io::Result<()> {
+  // SAFETY: these calls only read the given values
+  unsafe {
+    let pid_flag = if pid_namespace { libc::CLONE_NEWPID } else { 0 };
+    if unshare_flags | pid_flag != 0 {
+      if libc::unshare(unshare_flags | pid_flag) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+      if unshare_flags != 0 {
+        bring_loopback_up();
+      }
+    }
+
+    for (resource, value) in [
+      (libc::RLIMIT_CPU, limits.cpu_seconds),
+      (libc::RLIMIT_AS, limits.memory_bytes),
+      (libc::RLIMIT_FSIZE, limits.file_size_bytes),
+      (libc::RLIMIT_NPROC, limits.processes),
+    ] {
+      let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+      };
+      if libc::setrlimit(resource, &limit) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+
+    if pid_namespace {
+      start_init()?;
+    }
+  }
+  Ok(())
+}
+
+/// Forks the first process of the new PID namespace, which goes on to run the step, while this
+/// one waits for it and exits the same way. The kernel kills every process of the namespace once
+/// its first one exits, and this one is killed along with the process group of the step. Only
+/// returns in the new process.
+unsafe fn start_init() -> std::io::Result<()> {
+  let pid = libc::fork();
+  if pid < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  if pid == 0 {
+    // The step may leave the process group, but still dies along with this process
+    libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+    return Ok(());
+  }
+
+  // Nobody waits for the output or for the spawn of the step to complete through this process
+  if libc::syscall(libc::SYS_close_range, 0, libc::c_uint::MAX, 0) != 0 {
+    for fd in 0..1024 {
+      libc::close(fd);
+    }
+  }
+  let mut status = 0;
+  while libc::waitpid(pid, &mut status, 0) < 0 {
+    if std::io::Error::last_os_error().kind() != ErrorKind::Interrupted {
+      libc::_exit(1);
+    }
+  }
+  if libc::WIFSIGNALED(status) {
+    libc::signal(libc::WTERMSIG(status), libc::SIG_DFL);
+    libc::raise(libc::WTERMSIG(status));
+  }
+  libc::_exit(libc::WEXITSTATUS(status))
+}
+
+/// The loopback interface of a new network namespace is down, while tests commonly rely on it. A
+/// failure only leaves it down.
+unsafe fn bring_loopback_up() {
+  let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+  if socket < 0 {
+    return;
+  }
+  let mut request: libc::ifreq = std::mem::zeroed();
+  for (destination, source) in request.ifr_name.iter_mut().zip(b"lo") {
+    *destination = *source as libc::c_char;
+  }
+  request.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_LOOPBACK) as libc::c_short;
+  libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &request);
+  libc::close(socket);
+}
+
+fn kill_group(pid: u32) {
+  // SAFETY: kill doesn't touch the memory of the process. The group is gone once all its
+  // processes exited, in which case the call fails harmlessly.
+  unsafe {
+    libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+  }
+}
+
+/// Reads the output until all the processes of the step closed it, sending the first `max_output`
+/// bytes. Returns whether the output was truncated.
+fn read_output(
+  reader: &mut impl Read,
+  max_output: usize,
+  sender: mpsc::Sender<Vec<u8>>,
+) -> std::io::Result<bool> {
+  let mut sent = 0;
+  let mut truncated = false;
+  let mut buffer = [0; 8192];
+  loop {
+    let read = match reader.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(read) => read,
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    };
+    let kept = read.min(max_output - sent);
+    if kept > 0 {
+      // The step may outlive the receiver when it times out
+      sender.send(buffer[..kept].to_vec()).ok();
+      sent += kept;
+    }
+    truncated |= kept < read;
+  }
+  Ok(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::BTreeMap, path::Path};
+
+  use super::*;
+
+  fn run(executor: &LocalExecutor, command: &str, env: &[(&str, &str)]) -> (StepOutcome, String) {
+    let workspace = tempfile::tempdir().unwrap();
+    run_in(
+      executor,
+      workspace.path(),
+      command,
+      env,
+      Duration::from_secs(30),
+    )
+  }
+
+  fn run_in(
+    executor: &LocalExecutor,
+    workspace: &Path,
+    command: &str,
+    env: &[(&str, &str)],
+    timeout: Duration,
+  ) -> (StepOutcome, String) {
+    let env: BTreeMap<String, String> = env
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect();
+    let mut output = Vec::new();
+    let outcome = executor
+      .execute(
+        &Step {
+          name: "test",
+          command,
+          env: &env,
+          workspace,
+          timeout,
+        },
+        &mut |chunk| output.extend_from_slice(chunk),
+      )
+      .expect("Unable to execute the step");
+    (outcome, String::from_utf8_lossy(&output).to_string())
+  }
+
+  #[test]
+  fn test_exit_code_and_output() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+
+    let (outcome, output) = run(&executor, "echo out; echo err >&2; exit 3", &[]);
+    assert_eq!(outcome.exit_code, Some(3));
+    assert!(!outcome.success());
+    assert_eq!(output, "out\nerr\n");
+
+    let (outcome, _) = run(&executor, "true", &[]);
+    assert!(outcome.success());
+  }
+
+  #[test]
+  fn test_runs_in_workspace() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::write(workspace.path().join("answer.txt"), "42").unwrap();
+
+    let (outcome, output) = run_in(
+      &executor,
+      workspace.path(),
+      "cat answer.txt && touch built",
+      &[],
+      Duration::from_secs(30),
+    );
+    assert!(outcome.success());
+    assert_eq!(output, "42");
+    assert!(workspace.path().join("built").exists());
+  }
+
+  #[test]
+  fn test_environment_is_cleared() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+    std::env::set_var("GMT_CI_WORKER_SECRET", "secret");
+
+    let (outcome, output) = run(
+      &executor,
+      "echo \"$GMT_CI_WORKER_SECRET|$STEP_VAR|$CI\"; test -d \"$HOME\"",
+      &[("STEP_VAR", "value")],
+    );
+    assert!(outcome.success());
+    assert_eq!(output, "|value|true\n");
+  }
+
+  #[test]
+  fn test_timeout_kills_the_process_group() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+    let workspace = tempfile::tempdir().unwrap();
+
+    let (outcome, _) = run_in(
+      &executor,
+      workspace.path(),
+      "(sleep 2; touch leaked) & sleep 30",
+      &[],
+      Duration::from_millis(200),
+    );
+    assert!(outcome.timed_out);
+    assert!(!outcome.success());
+    assert!(outcome.duration < Duration::from_secs(10));
+
+    std::thread::sleep(Duration::from_secs(3));
+    assert!(!workspace.path().join("leaked").exists());
+  }
+
+  #[test]
+  fn test_processes_leaving_the_group_do_not_block_the_step() {
+    let mut executor = LocalExecutor::new(ResourceLimits::default());
+    let mut cases = vec![false];
+    if executor.has_pid_namespace() {
+      cases.push(true);
+    }
+    for pid_namespace in cases {
+      executor.pid_namespace = pid_namespace;
+      let workspace = tempfile::tempdir().unwrap();
+
+      let start = Instant::now();
+      let (outcome, output) = run_in(
+        &executor,
+        workspace.path(),
+        "setsid sleep 30 & setsid sh -c 'touch ready; sleep 2; touch leaked' & \
+        until test -f ready; do sleep 0.1; done; echo started",
+        &[],
+        Duration::from_secs(60),
+      );
+      assert!(start.elapsed() < Duration::from_secs(10));
+      assert!(outcome.success());
+      assert_eq!(output, "started\n");
+      // Without PID namespace, the processes left behind keep the output open
+      assert_eq!(outcome.truncated, !pid_namespace);
+
+      if pid_namespace {
+        // The step is the first process of its namespace
+        assert_eq!(run(&executor, "echo $$", &[]).1, "1\n");
+        std::thread::sleep(Duration::from_secs(3));
+        assert!(!workspace.path().join("leaked").exists());
+      }
+    }
+  }
+
+  #[test]
+  fn test_output_is_streamed() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+    let workspace = tempfile::tempdir().unwrap();
+    let env = BTreeMap::new();
+
+    let mut chunks = Vec::new();
+    executor
+      .execute(
+        &Step {
+          name: "test",
+          command: "echo first; sleep 2; echo second",
+          env: &env,
+          workspace: workspace.path(),
+          timeout: Duration::from_secs(30),
+        },
+        &mut |chunk| chunks.push((Instant::now(), chunk.to_vec())),
+      )
+      .expect("Unable to execute the step");
+
+    let contents: Vec<&[u8]> = chunks.iter().map(|(_, chunk)| chunk.as_slice()).collect();
+    assert_eq!(contents, vec![&b"first\n"[..], &b"second\n"[..]]);
+    // The first line is passed on before the step completes
+    assert!(chunks[1].0 - chunks[0].0 >= Duration::from_secs(1));
+  }
+
+  #[test]
+  fn test_output_is_capped() {
+    let executor = LocalExecutor::new(ResourceLimits::default()).with_max_output(10);
+
+    let (outcome, output) = run(&executor, "printf '0123456789abcdef'", &[]);
+    assert!(outcome.success());
+    assert_eq!(output, "0123456789");
+    assert!(outcome.truncated);
+  }
+
+  #[test]
+  fn test_resource_limits() {
+    let executor = LocalExecutor::new(ResourceLimits {
+      cpu_seconds: 7,
+      memory_bytes: 512 * MEBIBYTE,
+      file_size_bytes: MEBIBYTE,
+      processes: 64,
+    });
+
+    let (_, limits) = run(&executor, "cat /proc/self/limits", &[]);
+    let limit = |name: &str| {
+      limits
+        .lines()
+        .find(|line| line.starts_with(name))
+        .unwrap_or_else(|| panic!("No {} limit", name))
+        .split_whitespace()
+        .rev()
+        .nth(1)
+        .unwrap()
+        .to_string()
+    };
+    assert_eq!(limit("Max cpu time"), "7");
+    assert_eq!(limit("Max address space"), (512 * MEBIBYTE).to_string());
+    assert_eq!(limit("Max file size"), MEBIBYTE.to_string());
+    assert_eq!(limit("Max processes"), "64");
+
+    // Writing past the file size limit kills the writer
+    let (outcome, _) = run(&executor, "head -c 2097152 /dev/zero > big", &[]);
+    assert!(!outcome.success());
+  }
+
+  #[test]
+  fn test_network_is_isolated() {
+    let executor = LocalExecutor::new(ResourceLimits::default());
+    if executor.network_isolation() == NetworkIsolation::None {
+      return;
+    }
+
+    let (_, devices) = run(&executor, "cat /proc/net/dev", &[]);
+    let interfaces: Vec<&str> = devices
+      .lines()
+      .filter_map(|line| line.split_once(':'))
+      .map(|(name, _)| name.trim())
+      .collect();
+    assert_eq!(interfaces, vec!["lo"]);
+  }
+}
@@ -0,0 +1,44 @@
+//! The execution of the steps of a pipeline. The executors isolate the commands of the steps from
+//! the worker, since they come from the repositories.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use crate::error::WorkerError;
+
+pub mod local;
+
+/// A shell command to run in the workspace of a job.
+pub struct Step<'a> {
+  pub name: &'a str,
+  pub command: &'a str,
+  /// The only environment variables of the command, along with the ones set by the executor
+  pub env: &'a BTreeMap<String, String>,
+  pub workspace: &'a Path,
+  /// The wall-clock time after which the command is killed
+  pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutcome {
+  /// The exit code of the command, none if it was killed by a signal
+  pub exit_code: Option<i32>,
+  pub timed_out: bool,
+  /// Whether the output exceeded the cap of the executor and was cut
+  pub truncated: bool,
+  pub duration: Duration,
+}
+
+impl StepOutcome {
+  pub fn success(&self) -> bool {
+    !self.timed_out && self.exit_code == Some(0)
+  }
+}
+
+/// Runs the steps of the jobs, such as on the worker host or in a container.
+pub trait StepExecutor {
+  /// Runs the step until it exits or times out, passing the interleaved standard and error outputs
+  /// of the command to `output` as they come. Errors are reserved to the failures of the executor
+  /// itself, a failing command is reported through the outcome.
+  fn execute(&self, step: &Step, output: &mut dyn FnMut(&[u8]))
+    -> Result<StepOutcome, WorkerError>;
+}
@@ -0,0 +1,10 @@
+pub mod artifacts;
+pub mod checkout;
+pub mod error;
+pub mod executor;
+pub mod pipeline;
+pub mod report;
+pub mod review;
+pub mod scoring;
+pub mod test_report;
+pub mod worker;
@@ -0,0 +1,88 @@
+use std::{sync::Arc, time::Duration};
+
+use database::connection_pool::ConnectionPool;
+use gmt_ci_worker::{
+  artifacts::ArtifactLimits,
+  executor::local::{LocalExecutor, NetworkIsolation, ResourceLimits},
+  pipeline::DefinitionPipeline,
+  worker::Worker,
+};
+use gmt_common::{
+  artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+};
+use log::info;
+
+fn main() {
+  simple_logger::SimpleLogger::new()
+    .with_level(log::LevelFilter::Info)
+    .env()
+    .init()
+    .unwrap();
+  info!("Logger initialized");
+
+  dotenvy::dotenv().ok();
+
+  let connection_pool = ConnectionPool::new_from_env().expect("Unable to connect to the database");
+  let mut executor = LocalExecutor::new(ResourceLimits::new_from_env());
+  if let Ok(log_limit) = std::env::var("CI_LOG_LIMIT") {
+    let log_limit: usize = log_limit.parse().expect("Invalid log limit");
+    executor = executor.with_max_output(log_limit * 1024);
+  }
+  if executor.network_isolation() != NetworkIsolation::None {
+    info!(
+      "Steps isolated from the network with {:?}",
+      executor.network_isolation()
+    );
+  }
+  if executor.has_pid_namespace() {
+    info!("Steps run in their own PID namespace");
+  }
+  let artifacts = ArtifactStorage::new_from_env();
+  let mut worker = Worker::new(
+    connection_pool,
+    RepositoryStorage::new_from_env(),
+    DefinitionPipeline::new(executor)
+      .with_artifacts(artifacts.clone(), ArtifactLimits::new_from_env()),
+  )
+  .with_artifacts(artifacts);
+  if let Ok(scratch_root) = std::env::var("CI_SCRATCH_ROOT") {
+    worker = worker.with_scratch_root(scratch_root.into());
+  }
+  if let Ok(id) = std::env::var("CI_WORKER_ID") {
+    worker = worker.with_id(id);
+  }
+  if let Ok(timeout) = std::env::var("CI_HEARTBEAT_TIMEOUT") {
+    let timeout = timeout.parse().expect("Invalid heartbeat timeout");
+    worker = worker.with_heartbeat_timeout(Duration::from_secs(timeout));
+  }
+  if let Ok(max_attempts) = std::env::var("CI_MAX_ATTEMPTS") {
+    worker = worker.with_max_attempts(max_attempts.parse().expect("Invalid number of attempts"));
+  }
+  let notifications = std::env::var("CI_NOTIFICATIONS")
+    .map(|enabled| enabled.parse().expect("Invalid notifications setting"))
+    .unwrap_or(true);
+  if notifications {
+    worker =
+      worker.with_notifications(std::env::var("DATABASE_URL").expect("DATABASE_URL not set"));
+  }
+
+  let workers = std::env::var("CI_WORKERS")
+    .map(|workers| workers.parse().expect("Invalid number of workers"))
+    .unwrap_or(1);
+  let poll_interval = std::env::var("CI_POLL_INTERVAL")
+    .map(|interval| interval.parse().expect("Invalid poll interval"))
+    .map(Duration::from_secs)
+    .unwrap_or(Duration::from_secs(5));
+
+  info!("Starting {} CI workers", workers);
+  let worker = Arc::new(worker);
+  let handles: Vec<_> = (0..workers)
+    .map(|_| {
+      let worker = worker.clone();
+      std::thread::spawn(move || worker.run(poll_interval))
+    })
+    .collect();
+  for handle in handles {
+    handle.join().expect("CI worker panicked");
+  }
+}
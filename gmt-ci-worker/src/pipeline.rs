@@ -0,0 +1,563 @@
+use std::{collections::BTreeMap, path::Path, time::Instant};
+
+use database::db_handle::{cirun::Cirun, cirun::Status, repository::Repository};
+use gmt_common::{
+  artifact_storage::ArtifactStorage,
+  ci_pipeline::{parse_pipeline, validation_report, PipelineDefinition, PIPELINE_FILE},
+};
+use log::{info, warn};
+
+use crate::{
+  artifacts::{collect_artifact, ArtifactLimits, StoredArtifact},
+  error::WorkerError,
+  executor::{Step, StepExecutor},
+  report::Reporter,
+  scoring::{compute_score, Score},
+  test_report::{read_reports, ReportFile},
+};
+
+/// A run to execute, along with the checkout of its commit.
+pub struct Job<'a> {
+  pub cirun: &'a Cirun,
+  pub repository: &'a Repository,
+  /// The scratch directory holding the checkout of the commit
+  pub workspace: &'a Path,
+  /// The checkout of the CI repository of the assignment, if the repository belongs to one
+  pub ci_workspace: Option<&'a Path>,
+}
+
+/// The outcome of a job.
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+  pub status: Status,
+  /// The test reports declared by the pipeline
+  pub reports: Vec<ReportFile>,
+  /// The score of the run if the pipeline grades it, or the reason it couldn't be computed
+  pub score: Option<Result<Score, String>>,
+  /// The artifacts declared by the pipeline which were kept
+  pub artifacts: Vec<StoredArtifact>,
+  /// Whether the pipeline came from the CI repository of the assignment, rather than from the
+  /// checkout which the student controls. Only those runs can grade the submissions.
+  pub from_ci_repository: bool,
+}
+
+impl From<Status> for RunResult {
+  fn from(status: Status) -> Self {
+    RunResult {
+      status,
+      reports: Vec::new(),
+      score: None,
+      artifacts: Vec::new(),
+      from_ci_repository: false,
+    }
+  }
+}
+
+/// Executes the CI of a job.
+pub trait Pipeline {
+  /// Runs the job, reporting its progress, and returns the final status of the run along with its
+  /// test reports.
+  fn run(&self, job: &Job, reporter: &mut dyn Reporter) -> Result<RunResult, WorkerError>;
+}
+
+/// Runs the steps of the `gmt-ci.toml` file with an executor. The file is read from the CI
+/// repository of the assignment, whose overlay is copied over the checkout first, or from the
+/// checkout itself without one, in which case the run can't grade a submission.
+///
+/// The steps run in order until one of them fails or the pipeline times out, after which the
+/// reports are read from the checkout, the run is scored and its artifacts are stored. The run
+/// fails if the file is missing or invalid.
+pub struct DefinitionPipeline<E: StepExecutor> {
+  executor: E,
+  /// Where the artifacts are stored, the pipelines ignoring them without one
+  artifacts: Option<(ArtifactStorage, ArtifactLimits)>,
+}
+
+impl<E: StepExecutor> DefinitionPipeline<E> {
+  pub fn new(executor: E) -> Self {
+    DefinitionPipeline {
+      executor,
+      artifacts: None,
+    }
+  }
+
+  /// Keeps the artifacts declared by the pipelines in the given storage, within the limits.
+  pub fn with_artifacts(mut self, storage: ArtifactStorage, limits: ArtifactLimits) -> Self {
+    self.artifacts = Some((storage, limits));
+    self
+  }
+}
+
+impl<E: StepExecutor> Pipeline for DefinitionPipeline<E> {
+  fn run(&self, job: &Job, reporter: &mut dyn Reporter) -> Result<RunResult, WorkerError> {
+    let source = job.ci_workspace.unwrap_or(job.workspace);
+    let content = match std::fs::read_to_string(source.join(PIPELINE_FILE)) {
+      Ok(content) => content,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        info!("Run {}: no {} found", job.cirun.id, PIPELINE_FILE);
+        reporter.fail(&format!("No {} found", PIPELINE_FILE))?;
+        return Ok(Status::Failed.into());
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let definition = match parse_pipeline(&content) {
+      Ok(definition) => definition,
+      Err(errors) => {
+        let report = validation_report(&Err(errors));
+        info!("Run {}: {}", job.cirun.id, report);
+        reporter.fail(&report)?;
+        return Ok(Status::Failed.into());
+      }
+    };
+
+    if let Some(ci_workspace) = job.ci_workspace {
+      for path in &definition.overlay {
+        copy_overlay(&ci_workspace.join(path), &job.workspace.join(path)).map_err(|e| {
+          WorkerError::InvalidPipeline(format!("overlay {}: {}", path.display(), e))
+        })?;
+      }
+    }
+
+    let status = self.run_steps(job, &definition, reporter)?;
+    let reports = read_reports(job.workspace, &definition.reports);
+    let score = definition
+      .scoring
+      .as_ref()
+      .map(|scoring| compute_score(job.workspace, scoring, &reports));
+    let artifacts = self.collect_artifacts(job, &definition, status == Status::Success);
+    Ok(RunResult {
+      status,
+      reports,
+      score,
+      artifacts,
+      from_ci_repository: job.ci_workspace.is_some(),
+    })
+  }
+}
+
+impl<E: StepExecutor> DefinitionPipeline<E> {
+  /// Stores the artifacts which apply to the outcome of the steps. The ones which can't be kept
+  /// are logged and skipped.
+  fn collect_artifacts(
+    &self,
+    job: &Job,
+    definition: &PipelineDefinition,
+    succeeded: bool,
+  ) -> Vec<StoredArtifact> {
+    let Some((storage, limits)) = &self.artifacts else {
+      return Vec::new();
+    };
+    let mut artifacts: Vec<StoredArtifact> = Vec::new();
+    for artifact in definition
+      .artifacts
+      .iter()
+      .filter(|a| a.when.applies(succeeded))
+    {
+      let used = artifacts.iter().map(|a| a.size).sum();
+      match collect_artifact(job.workspace, artifact, storage, limits, used) {
+        Ok(stored) => artifacts.push(stored),
+        Err(e) => warn!(
+          "Run {}: artifact {} not kept: {}",
+          job.cirun.id, artifact.name, e
+        ),
+      }
+    }
+    artifacts
+  }
+
+  fn run_steps(
+    &self,
+    job: &Job,
+    definition: &PipelineDefinition,
+    reporter: &mut dyn Reporter,
+  ) -> Result<Status, WorkerError> {
+    let names: Vec<&str> = definition.steps.iter().map(|s| s.name.as_str()).collect();
+    reporter.plan(&names)?;
+
+    let deadline = Instant::now() + definition.timeout;
+    for (index, step) in definition.steps.iter().enumerate() {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        info!("Run {}: the pipeline timed out", job.cirun.id);
+        reporter.fail(&format!(
+          "The pipeline timed out after {} seconds",
+          definition.timeout.as_secs()
+        ))?;
+        return Ok(Status::Failed);
+      }
+
+      let mut env: BTreeMap<String, String> = definition.env.clone();
+      env.extend(step.env.clone());
+      let timeout = step.timeout.min(remaining);
+      reporter.start(index)?;
+      let outcome = self.executor.execute(
+        &Step {
+          name: &step.name,
+          command: &step.run,
+          env: &env,
+          workspace: job.workspace,
+          timeout,
+        },
+        &mut |output| {
+          if let Err(e) = reporter.output(index, output) {
+            warn!("Run {}: unable to store the output: {}", job.cirun.id, e);
+          }
+        },
+      )?;
+      if outcome.timed_out {
+        let message = format!("\nThe step timed out after {} seconds\n", timeout.as_secs());
+        reporter.output(index, message.as_bytes())?;
+      }
+      reporter.finish(index, &outcome)?;
+
+      if !outcome.success() {
+        info!(
+          "Run {}: step {} failed (exit code {:?}, timed out: {})",
+          job.cirun.id, step.name, outcome.exit_code, outcome.timed_out
+        );
+        return Ok(Status::Failed);
+      }
+    }
+    Ok(Status::Success)
+  }
+}
+
+/// Copies a file or directory of the CI repository over the checkout, replacing the existing
+/// files. Symbolic links are skipped, so that the overlay can't expose files of the worker.
+fn copy_overlay(source: &Path, destination: &Path) -> std::io::Result<()> {
+  let metadata = std::fs::symlink_metadata(source)?;
+  if metadata.is_dir() {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+      let entry = entry?;
+      copy_overlay(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+  } else if metadata.is_file() {
+    if let Some(parent) = destination.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::symlink_metadata(destination).is_ok_and(|d| d.is_dir() || d.is_symlink()) {
+      remove(destination)?;
+    }
+    std::fs::copy(source, destination)?;
+  }
+  Ok(())
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+  match std::fs::symlink_metadata(path)?.is_dir() {
+    true => std::fs::remove_dir_all(path),
+    false => std::fs::remove_file(path),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+  use database::fixtures::{cirun, repository};
+  use rstest::rstest;
+
+  use super::*;
+  use crate::{executor::StepOutcome, report::step_status, test_report::TestOutcome};
+
+  /// The name, environment and timeout of a step
+  type Record = (String, BTreeMap<String, String>, Duration);
+
+  /// Records the steps it runs, and fails the ones whose command is `fail`.
+  #[derive(Default)]
+  struct FakeExecutor(Mutex<Vec<Record>>);
+
+  impl StepExecutor for FakeExecutor {
+    fn execute(
+      &self,
+      step: &Step,
+      output: &mut dyn FnMut(&[u8]),
+    ) -> Result<StepOutcome, WorkerError> {
+      self
+        .0
+        .lock()
+        .unwrap()
+        .push((step.name.to_string(), step.env.clone(), step.timeout));
+      output(step.command.as_bytes());
+      Ok(StepOutcome {
+        exit_code: Some(if step.command == "fail" { 1 } else { 0 }),
+        timed_out: false,
+        truncated: false,
+        duration: Duration::ZERO,
+      })
+    }
+  }
+
+  /// Describes the progress of the run as a list of events.
+  #[derive(Default)]
+  struct FakeReporter(Vec<String>);
+
+  impl Reporter for FakeReporter {
+    fn plan(&mut self, names: &[&str]) -> Result<(), WorkerError> {
+      self.0.push(format!("plan {}", names.join(",")));
+      Ok(())
+    }
+
+    fn start(&mut self, index: usize) -> Result<(), WorkerError> {
+      self.0.push(format!("start {}", index));
+      Ok(())
+    }
+
+    fn output(&mut self, index: usize, output: &[u8]) -> Result<(), WorkerError> {
+      let output = String::from_utf8_lossy(output);
+      self.0.push(format!("output {} {}", index, output));
+      Ok(())
+    }
+
+    fn finish(&mut self, index: usize, outcome: &StepOutcome) -> Result<(), WorkerError> {
+      self
+        .0
+        .push(format!("finish {} {:?}", index, step_status(outcome)));
+      Ok(())
+    }
+
+    fn fail(&mut self, message: &str) -> Result<(), WorkerError> {
+      self.0.push(format!("fail {}", message));
+      Ok(())
+    }
+
+    fn complete(&mut self) -> Result<(), WorkerError> {
+      self.0.push("complete".to_string());
+      Ok(())
+    }
+  }
+
+  fn run(
+    workspace: &Path,
+    ci_workspace: Option<&Path>,
+  ) -> (Result<RunResult, WorkerError>, Vec<Record>, Vec<String>) {
+    run_pipeline(
+      DefinitionPipeline::new(FakeExecutor::default()),
+      workspace,
+      ci_workspace,
+    )
+  }
+
+  fn run_pipeline(
+    pipeline: DefinitionPipeline<FakeExecutor>,
+    workspace: &Path,
+    ci_workspace: Option<&Path>,
+  ) -> (Result<RunResult, WorkerError>, Vec<Record>, Vec<String>) {
+    let cirun = cirun(1, 1, Status::Pending);
+    let repository = repository(1, 1, None);
+    let job = Job {
+      cirun: &cirun,
+      repository: &repository,
+      workspace,
+      ci_workspace,
+    };
+
+    let mut reporter = FakeReporter::default();
+    let result = pipeline.run(&job, &mut reporter);
+    let steps = pipeline.executor.0.into_inner().unwrap();
+    (result, steps, reporter.0)
+  }
+
+  fn names(steps: &[Record]) -> Vec<&str> {
+    steps.iter().map(|(name, _, _)| name.as_str()).collect()
+  }
+
+  #[rstest]
+  #[case::success(
+    Some("version = 1\n[[steps]]\nname = \"a\"\nrun = \"x\"\n[[steps]]\nname = \"b\"\nrun = \"y\""),
+    Status::Success,
+    vec!["a", "b"]
+  )]
+  #[case::failing_step(
+    Some("version = 1\n[[steps]]\nname = \"a\"\nrun = \"fail\"\n[[steps]]\nname = \"b\"\nrun = \"y\""),
+    Status::Failed,
+    vec!["a"]
+  )]
+  #[case::invalid(Some("version = 1"), Status::Failed, vec![])]
+  #[case::missing(None, Status::Failed, vec![])]
+  fn test_definition_pipeline(
+    #[case] definition: Option<&str>,
+    #[case] expected: Status,
+    #[case] expected_steps: Vec<&str>,
+  ) {
+    let workspace = tempfile::tempdir().unwrap();
+    if let Some(definition) = definition {
+      std::fs::write(workspace.path().join(PIPELINE_FILE), definition).unwrap();
+    }
+
+    let (result, steps, _) = run(workspace.path(), None);
+    assert_eq!(result.expect("Pipeline failed").status, expected);
+    assert_eq!(names(&steps), expected_steps);
+  }
+
+  #[rstest]
+  #[case::failing_step(
+    Some("version = 1\n[[steps]]\nname = \"a\"\nrun = \"fail\"\n[[steps]]\nname = \"b\"\nrun = \"y\""),
+    vec!["plan a,b", "start 0", "output 0 fail", "finish 0 Failed"]
+  )]
+  #[case::invalid(
+    Some("version = 1"),
+    vec!["fail gmt-ci.toml is invalid:\n  The pipeline has no step, add at least one `[[steps]]` table"]
+  )]
+  #[case::missing(None, vec!["fail No gmt-ci.toml found"])]
+  fn test_pipeline_reports_progress(#[case] definition: Option<&str>, #[case] expected: Vec<&str>) {
+    let workspace = tempfile::tempdir().unwrap();
+    if let Some(definition) = definition {
+      std::fs::write(workspace.path().join(PIPELINE_FILE), definition).unwrap();
+    }
+
+    let (_, _, events) = run(workspace.path(), None);
+    assert_eq!(events, expected);
+  }
+
+  #[test]
+  fn test_step_environment_and_timeout() {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::write(
+      workspace.path().join(PIPELINE_FILE),
+      "version = 1\ntimeout = 60\n[env]\nA = \"pipeline\"\nB = \"pipeline\"\n\
+       [[steps]]\nname = \"a\"\nrun = \"x\"\nenv = { B = \"step\" }",
+    )
+    .unwrap();
+
+    let (result, steps, _) = run(workspace.path(), None);
+    let result = result.expect("Pipeline failed");
+    assert_eq!(result.status, Status::Success);
+    assert!(!result.from_ci_repository);
+    let (_, env, timeout) = &steps[0];
+    assert_eq!(env["A"], "pipeline");
+    assert_eq!(env["B"], "step");
+    // The steps default to the timeout of the pipeline when shorter
+    assert!(*timeout <= Duration::from_secs(60));
+  }
+
+  #[test]
+  fn test_overlay_of_the_ci_repository() {
+    let workspace = tempfile::tempdir().unwrap();
+    let ci_workspace = tempfile::tempdir().unwrap();
+    // The student can't provide the pipeline, nor keep their version of the tests
+    std::fs::write(workspace.path().join(PIPELINE_FILE), "version = 1").unwrap();
+    std::fs::create_dir_all(workspace.path().join("tests")).unwrap();
+    std::fs::write(workspace.path().join("tests/test.sh"), "exit 0").unwrap();
+    std::fs::write(workspace.path().join("answer.txt"), "42").unwrap();
+
+    std::fs::write(
+      ci_workspace.path().join(PIPELINE_FILE),
+      "version = 1\noverlay = [\"tests\"]\n[[steps]]\nname = \"test\"\nrun = \"sh tests/test.sh\"",
+    )
+    .unwrap();
+    std::fs::create_dir_all(ci_workspace.path().join("tests/data")).unwrap();
+    std::fs::write(
+      ci_workspace.path().join("tests/test.sh"),
+      "test -f answer.txt",
+    )
+    .unwrap();
+    std::fs::write(ci_workspace.path().join("tests/data/input"), "input").unwrap();
+
+    let (result, steps, _) = run(workspace.path(), Some(ci_workspace.path()));
+    let result = result.expect("Pipeline failed");
+    assert_eq!(result.status, Status::Success);
+    assert!(result.from_ci_repository);
+    assert_eq!(names(&steps), vec!["test"]);
+    let test = std::fs::read_to_string(workspace.path().join("tests/test.sh")).unwrap();
+    assert_eq!(test, "test -f answer.txt");
+    assert!(workspace.path().join("tests/data/input").is_file());
+    assert!(workspace.path().join("answer.txt").is_file());
+  }
+
+  #[test]
+  fn test_missing_overlay() {
+    let workspace = tempfile::tempdir().unwrap();
+    let ci_workspace = tempfile::tempdir().unwrap();
+    std::fs::write(
+      ci_workspace.path().join(PIPELINE_FILE),
+      "version = 1\noverlay = [\"tests\"]\n[[steps]]\nname = \"test\"\nrun = \"x\"",
+    )
+    .unwrap();
+
+    let (result, steps, _) = run(workspace.path(), Some(ci_workspace.path()));
+    assert!(matches!(result, Err(WorkerError::InvalidPipeline(_))));
+    assert!(steps.is_empty());
+  }
+
+  #[test]
+  fn test_reports_are_read_after_a_failing_step() {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::write(
+      workspace.path().join(PIPELINE_FILE),
+      "version = 1\n[[steps]]\nname = \"test\"\nrun = \"fail\"\n\
+       [[reports]]\npath = \"report.tap\"\nformat = \"tap\"",
+    )
+    .unwrap();
+    std::fs::write(workspace.path().join("report.tap"), "ok 1\nnot ok 2\n").unwrap();
+
+    let (result, _, _) = run(workspace.path(), None);
+    let result = result.expect("Pipeline failed");
+    assert_eq!(result.status, Status::Failed);
+    assert_eq!(result.reports.len(), 1);
+    assert_eq!(result.reports[0].path, PathBuf::from("report.tap"));
+    let report = result.reports[0].report.as_ref().expect("Unable to read");
+    assert_eq!(report.count(TestOutcome::Failed), 1);
+    assert_eq!(report.count(TestOutcome::Passed), 1);
+    assert!(result.score.is_none());
+  }
+
+  #[test]
+  fn test_run_is_scored() {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::write(
+      workspace.path().join(PIPELINE_FILE),
+      "version = 1\n[[steps]]\nname = \"test\"\nrun = \"fail\"\n\
+       [[reports]]\npath = \"report.tap\"\nformat = \"tap\"\n\
+       [[scoring.groups]]\nname = \"Tests\"\ntests = [\"*\"]\nweight = 10",
+    )
+    .unwrap();
+    std::fs::write(
+      workspace.path().join("report.tap"),
+      "ok 1 - a\nnot ok 2 - b\n",
+    )
+    .unwrap();
+
+    let (result, _, _) = run(workspace.path(), None);
+    let score = result
+      .expect("Pipeline failed")
+      .score
+      .expect("The run wasn't scored")
+      .expect("Unable to score");
+    assert_eq!((score.score, score.max_score), (5.0, 10.0));
+  }
+
+  #[test]
+  fn test_artifacts_are_kept_after_the_steps() {
+    let workspace = tempfile::tempdir().unwrap();
+    let storage = tempfile::tempdir().unwrap();
+    std::fs::write(
+      workspace.path().join(PIPELINE_FILE),
+      "version = 1\n[[steps]]\nname = \"test\"\nrun = \"fail\"\n\
+       [[artifacts]]\nname = \"output\"\npath = \"test.log\"\n\
+       [[artifacts]]\nname = \"binary\"\npath = \"main\"\nwhen = \"on_success\"\n\
+       [[artifacts]]\nname = \"core\"\npath = \"core\"\nwhen = \"on_failure\"\n\
+       [[artifacts]]\nname = \"missing\"\npath = \"missing\"",
+    )
+    .unwrap();
+    for file in ["test.log", "main", "core"] {
+      std::fs::write(workspace.path().join(file), file).unwrap();
+    }
+
+    let pipeline = DefinitionPipeline::new(FakeExecutor::default()).with_artifacts(
+      ArtifactStorage::new(storage.path().to_path_buf()),
+      ArtifactLimits::default(),
+    );
+    let (result, _, _) = run_pipeline(pipeline, workspace.path(), None);
+    let result = result.expect("Pipeline failed");
+    assert_eq!(result.status, Status::Failed);
+    let names: Vec<&str> = result.artifacts.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, vec!["output", "core"]);
+    assert_eq!(result.artifacts[0].file_name, "output.log");
+
+    // Ignored without a storage
+    let (result, _, _) = run(workspace.path(), None);
+    assert!(result.expect("Pipeline failed").artifacts.is_empty());
+  }
+}
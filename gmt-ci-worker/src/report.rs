@@ -0,0 +1,228 @@
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::SystemTime,
+};
+
+use database::db_handle::cirun_step::{CirunStepDbHandle, StepStatus};
+
+use crate::{error::WorkerError, executor::StepOutcome};
+
+/// The step holding the problems preventing the steps of the pipeline from running.
+pub const SETUP_STEP: &str = "setup";
+
+/// Records the progress of a run as it goes, so that it can be followed while it runs.
+pub trait Reporter {
+  /// Registers the steps of the pipeline, before any of them runs
+  fn plan(&mut self, names: &[&str]) -> Result<(), WorkerError>;
+
+  fn start(&mut self, index: usize) -> Result<(), WorkerError>;
+
+  /// Appends some output to the log of the step
+  fn output(&mut self, index: usize, output: &[u8]) -> Result<(), WorkerError>;
+
+  fn finish(&mut self, index: usize, outcome: &StepOutcome) -> Result<(), WorkerError>;
+
+  /// Explains why the run failed outside of a command, such as an invalid pipeline. The message
+  /// goes to the log of the latest step, or of a setup step if none started.
+  fn fail(&mut self, message: &str) -> Result<(), WorkerError>;
+
+  /// Marks the steps which didn't run as skipped
+  fn complete(&mut self) -> Result<(), WorkerError>;
+}
+
+pub fn step_status(outcome: &StepOutcome) -> StepStatus {
+  if outcome.timed_out {
+    StepStatus::TimedOut
+  } else if outcome.success() {
+    StepStatus::Success
+  } else {
+    StepStatus::Failed
+  }
+}
+
+/// Stores the steps and their logs in the database. It should use its own connection, so that the
+/// progress is visible before the run completes.
+pub struct DbReporter<Db: CirunStepDbHandle> {
+  db: Db,
+  cirun_id: i32,
+  steps: Vec<i32>,
+  /// The step currently running
+  running: Option<usize>,
+  /// The latest step which started
+  latest: Option<usize>,
+  /// The end of the output, if it stopped in the middle of a character
+  partial: Vec<u8>,
+  /// Set once the run is cancelled, preventing the next steps from starting
+  cancelled: Arc<AtomicBool>,
+}
+
+impl<Db: CirunStepDbHandle> DbReporter<Db> {
+  pub fn new(db: Db, cirun_id: i32) -> Self {
+    DbReporter {
+      db,
+      cirun_id,
+      steps: Vec::new(),
+      running: None,
+      latest: None,
+      partial: Vec::new(),
+      cancelled: Arc::default(),
+    }
+  }
+
+  /// Stops the run before its next step once the flag is set.
+  pub fn with_cancellation(mut self, cancelled: Arc<AtomicBool>) -> Self {
+    self.cancelled = cancelled;
+    self
+  }
+
+  fn step_id(&self, index: usize) -> Result<i32, WorkerError> {
+    self
+      .steps
+      .get(index)
+      .copied()
+      .ok_or_else(|| WorkerError::InvalidPipeline(format!("Unknown step {}", index)))
+  }
+
+  fn append(&mut self, index: usize, content: &str) -> Result<(), WorkerError> {
+    if !content.is_empty() {
+      self
+        .db
+        .append_cirun_step_log(self.step_id(index)?, content)?;
+    }
+    Ok(())
+  }
+
+  fn begin(&mut self, index: usize) -> Result<(), WorkerError> {
+    self
+      .db
+      .start_cirun_step(self.step_id(index)?, SystemTime::now())?;
+    self.running = Some(index);
+    self.latest = Some(index);
+    Ok(())
+  }
+
+  fn close(
+    &mut self,
+    index: usize,
+    status: &StepStatus,
+    exit_code: Option<i32>,
+    truncated: bool,
+  ) -> Result<(), WorkerError> {
+    let rest = String::from_utf8_lossy(&std::mem::take(&mut self.partial)).to_string();
+    self.append(index, &rest)?;
+    self.db.finish_cirun_step(
+      self.step_id(index)?,
+      status,
+      exit_code,
+      truncated,
+      SystemTime::now(),
+    )?;
+    self.running = None;
+    Ok(())
+  }
+}
+
+impl<Db: CirunStepDbHandle> Reporter for DbReporter<Db> {
+  fn plan(&mut self, names: &[&str]) -> Result<(), WorkerError> {
+    let steps = self.db.create_cirun_steps(self.cirun_id, names)?;
+    self.steps = steps.into_iter().map(|step| step.id).collect();
+    Ok(())
+  }
+
+  fn start(&mut self, index: usize) -> Result<(), WorkerError> {
+    if self.cancelled.load(Ordering::SeqCst) {
+      return Err(WorkerError::Cancelled);
+    }
+    self.begin(index)
+  }
+
+  fn output(&mut self, index: usize, output: &[u8]) -> Result<(), WorkerError> {
+    self.partial.extend_from_slice(output);
+    let content = decode(&mut self.partial);
+    self.append(index, &content)
+  }
+
+  fn finish(&mut self, index: usize, outcome: &StepOutcome) -> Result<(), WorkerError> {
+    self.close(
+      index,
+      &step_status(outcome),
+      outcome.exit_code,
+      outcome.truncated,
+    )
+  }
+
+  fn fail(&mut self, message: &str) -> Result<(), WorkerError> {
+    if self.steps.is_empty() {
+      self.plan(&[SETUP_STEP])?;
+    }
+    let index = match self.latest {
+      Some(index) => index,
+      None => {
+        self.begin(0)?;
+        0
+      }
+    };
+    self.output(index, format!("{}\n", message).as_bytes())?;
+    if self.running == Some(index) {
+      self.close(index, &StepStatus::Failed, None, false)?;
+    }
+    Ok(())
+  }
+
+  fn complete(&mut self) -> Result<(), WorkerError> {
+    self.db.skip_pending_cirun_steps(self.cirun_id)?;
+    Ok(())
+  }
+}
+
+/// Decodes the complete characters of the buffer, leaving the end of a character cut in the middle
+/// for the next output. Invalid sequences are replaced.
+fn decode(buffer: &mut Vec<u8>) -> String {
+  let rest = buffer.split_off(cut_character(buffer));
+  let content = String::from_utf8_lossy(buffer).to_string();
+  *buffer = rest;
+  content
+}
+
+/// Returns where the last character of the bytes starts if it is incomplete, or their length.
+fn cut_character(bytes: &[u8]) -> usize {
+  for (back, byte) in bytes.iter().rev().take(4).enumerate() {
+    // Skips the continuation bytes, up to the first byte of the character
+    if byte & 0b1100_0000 != 0b1000_0000 {
+      let length = byte.leading_ones().max(1) as usize;
+      return match length > back + 1 {
+        true => bytes.len() - back - 1,
+        false => bytes.len(),
+      };
+    }
+  }
+  bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::rstest;
+
+  use super::*;
+
+  #[rstest]
+  #[case::ascii(&[&b"abc"[..], b"def"], "abcdef")]
+  #[case::cut_character(&[&b"caf\xc3"[..], b"\xa9!"], "caf\u{e9}!")]
+  #[case::cut_emoji(&[&b"\xf0\x9f"[..], b"\x98", b"\x80"], "\u{1F600}")]
+  #[case::invalid(&[&b"a\xffb"[..]], "a\u{FFFD}b")]
+  fn test_decode(#[case] outputs: &[&[u8]], #[case] expected: &str) {
+    let mut buffer = Vec::new();
+    let mut content = String::new();
+    for output in outputs {
+      buffer.extend_from_slice(output);
+      let decoded = decode(&mut buffer);
+      assert!(!decoded.contains('\u{FFFD}') || expected.contains('\u{FFFD}'));
+      content.push_str(&decoded);
+    }
+    assert!(buffer.is_empty());
+    assert_eq!(content, expected);
+  }
+}
@@ -0,0 +1,389 @@
+//! Posts the test reports of a run as automated comments on its commit: a summary of the run along
+//! with its score, and a comment on the file, or the line, each failing test or finding points to.
+
+use database::db_handle::{
+  cirun::{Cirun, Status},
+  comment::{CommentAnchor, CommentDbHandle, Commentauthor, Commentside},
+};
+use git_server::objects::{GitObjects, ObjectsError};
+
+use crate::{
+  pipeline::RunResult,
+  test_report::{Location, Severity, TestOutcome},
+};
+
+/// The maximum number of comments posted on the files for a run, as a broken test suite could
+/// otherwise flood the review.
+pub const MAX_FILE_COMMENTS: usize = 50;
+/// The summary lists this many problems, and only counts the others.
+const MAX_SUMMARY_ITEMS: usize = 20;
+/// Messages are cut past this length, as stack traces can be long.
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// A failing test or a finding.
+struct Remark<'a> {
+  title: String,
+  message: Option<&'a str>,
+  location: Option<&'a Location>,
+}
+
+impl Remark<'_> {
+  /// The content of the comment posted on the file
+  fn comment(&self) -> String {
+    match self.message {
+      Some(message) => format!(
+        "{}\n\n```\n{}\n```",
+        self.title,
+        truncate(message.trim(), MAX_MESSAGE_LENGTH)
+      ),
+      None => self.title.clone(),
+    }
+  }
+
+  /// The line of the remark in the summary
+  fn summary(&self) -> String {
+    let mut line = format!("- {}", self.title);
+    if let Some(location) = self.location {
+      match location.line {
+        Some(number) => line.push_str(&format!(" (`{}:{}`)", location.file, number)),
+        None => line.push_str(&format!(" (`{}`)", location.file)),
+      }
+    }
+    if let Some(first) = self
+      .message
+      .and_then(|m| m.lines().find(|l| !l.trim().is_empty()))
+    {
+      line.push_str(&format!(": {}", truncate(first.trim(), 200)));
+    }
+    line
+  }
+}
+
+fn remarks(result: &RunResult) -> Vec<Remark<'_>> {
+  let reports = result
+    .reports
+    .iter()
+    .filter_map(|file| file.report.as_ref().ok());
+  let mut remarks = Vec::new();
+  for report in reports {
+    let failures = report
+      .tests
+      .iter()
+      .filter(|test| test.outcome == TestOutcome::Failed);
+    remarks.extend(failures.map(|test| Remark {
+      title: format!("Test `{}` failed", test.name),
+      message: test.message.as_deref(),
+      location: test.location.as_ref(),
+    }));
+    remarks.extend(report.findings.iter().map(|finding| {
+      let severity = match finding.severity {
+        Severity::Error => "Error",
+        Severity::Warning => "Warning",
+        Severity::Info => "Info",
+      };
+      Remark {
+        title: match &finding.rule {
+          Some(rule) => format!("{} `{}`: {}", severity, rule, finding.message.trim()),
+          None => format!("{}: {}", severity, finding.message.trim()),
+        },
+        message: None,
+        location: finding.location.as_ref(),
+      }
+    }));
+  }
+  remarks
+}
+
+/// Describes the outcome of the run and of each of its reports.
+fn summary(cirun: &Cirun, result: &RunResult, remarks: &[Remark]) -> String {
+  let outcome = match result.status {
+    Status::Success => "passed",
+    _ => "failed",
+  };
+  let mut summary = format!("CI run #{} {}\n", cirun.id, outcome);
+
+  for file in &result.reports {
+    let description = match &file.report {
+      Ok(report) => {
+        let mut counts = vec![plural(report.tests.len(), "test")];
+        for (outcome, name) in [
+          (TestOutcome::Passed, "passed"),
+          (TestOutcome::Failed, "failed"),
+          (TestOutcome::Skipped, "skipped"),
+        ] {
+          let count = report.count(outcome);
+          if count > 0 {
+            counts.push(format!("{} {}", count, name));
+          }
+        }
+        if !report.findings.is_empty() {
+          counts.push(plural(report.findings.len(), "finding"));
+        }
+        counts.join(", ")
+      }
+      Err(e) => format!("unable to read the report: {}", e),
+    };
+    summary.push_str(&format!("\n- `{}`: {}", file.path.display(), description));
+  }
+
+  match &result.score {
+    Some(Ok(score)) => {
+      summary.push_str(&format!("\n\nScore: {}/{}", score.score, score.max_score));
+      for criterion in &score.rubric {
+        summary.push_str(&format!(
+          "\n- {}: {}/{}",
+          criterion.name, criterion.score, criterion.max_score
+        ));
+        if let Some(comment) = &criterion.comment {
+          summary.push_str(&format!(", {}", comment));
+        }
+      }
+    }
+    Some(Err(e)) => summary.push_str(&format!("\n\nUnable to compute the score: {}", e)),
+    None => {}
+  }
+
+  if !remarks.is_empty() {
+    summary.push_str("\n\nProblems:\n");
+    let lines: Vec<_> = remarks
+      .iter()
+      .take(MAX_SUMMARY_ITEMS)
+      .map(Remark::summary)
+      .collect();
+    summary.push_str(&lines.join("\n"));
+    if remarks.len() > MAX_SUMMARY_ITEMS {
+      summary.push_str(&format!(
+        "\n- and {} more",
+        remarks.len() - MAX_SUMMARY_ITEMS
+      ));
+    }
+  }
+  summary
+}
+
+/// Posts the summary of the run, then a comment for each problem pointing to a file of the commit.
+/// Nothing is posted if the pipeline neither declares reports nor scores the run.
+/// Lines which can't be found in the commit get a comment on their file instead, and files which
+/// aren't part of it, such as generated ones, are only listed in the summary.
+///
+/// Problems already commented by a previous run of the same commit aren't posted twice. Returns
+/// the number of comments posted.
+pub fn post_review<Db: CommentDbHandle>(
+  db: &mut Db,
+  objects: &GitObjects,
+  cirun: &Cirun,
+  result: &RunResult,
+) -> Result<usize, crate::error::WorkerError> {
+  if result.reports.is_empty() && result.score.is_none() {
+    return Ok(0);
+  }
+  let remarks = remarks(result);
+  let existing: Vec<_> = db
+    .list_commit_comments(cirun.repository_id, &cirun.commit, None)?
+    .into_iter()
+    .filter(|comment| comment.author_type == Commentauthor::Automated)
+    .collect();
+
+  db.add_ci_comment(
+    cirun.repository_id,
+    &cirun.commit,
+    &summary(cirun, result, &remarks),
+  )?;
+  let mut posted = 1;
+
+  let located = remarks
+    .iter()
+    .filter_map(|remark| Some((remark.location?, remark.comment())));
+  for (location, message) in located.take(MAX_FILE_COMMENTS) {
+    let duplicate = existing.iter().any(|comment| {
+      comment.file_path.as_deref() == Some(location.file.as_str())
+        && comment.start_line == location.line.map(|line| line as i32)
+        && comment.message == message
+    });
+    if !duplicate && post_remark(db, objects, cirun, location, &message)? {
+      posted += 1;
+    }
+  }
+  Ok(posted)
+}
+
+fn post_remark<Db: CommentDbHandle>(
+  db: &mut Db,
+  objects: &GitObjects,
+  cirun: &Cirun,
+  location: &Location,
+  message: &str,
+) -> Result<bool, crate::error::WorkerError> {
+  if let Some(line) = location.line {
+    match objects.hash_lines(&cirun.commit, &location.file, line as usize, line as usize) {
+      Ok(context_hash) => {
+        let anchor = CommentAnchor {
+          start_line: line as i32,
+          end_line: line as i32,
+          side: Commentside::New,
+          context_hash,
+        };
+        db.add_ci_line_comment(
+          cirun.repository_id,
+          &cirun.commit,
+          &location.file,
+          &anchor,
+          message,
+        )?;
+        return Ok(true);
+      }
+      Err(ObjectsError::GitError(e)) => return Err(ObjectsError::GitError(e).into()),
+      // The line is out of the file or the file isn't text, the comment goes on the whole file
+      Err(_) => {}
+    }
+  }
+  match objects.read_blob(&cirun.commit, &location.file, 0) {
+    Ok(_) => {
+      db.add_ci_file_comment(cirun.repository_id, &cirun.commit, &location.file, message)?;
+      Ok(true)
+    }
+    Err(ObjectsError::GitError(e)) => Err(ObjectsError::GitError(e).into()),
+    Err(_) => Ok(false),
+  }
+}
+
+fn plural(count: usize, name: &str) -> String {
+  match count {
+    1 => format!("1 {}", name),
+    _ => format!("{} {}s", count, name),
+  }
+}
+
+fn truncate(message: &str, max: usize) -> String {
+  match message.char_indices().nth(max) {
+    Some((end, _)) => format!("{}…", &message[..end]),
+    None => message.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use database::{db_handle::grade::RubricScore, fixtures::cirun};
+
+  use super::*;
+  use crate::{
+    scoring::Score,
+    test_report::{Finding, ReportFile, TestCase, TestReport},
+  };
+
+  fn test(name: &str, outcome: TestOutcome, message: Option<&str>) -> TestCase {
+    TestCase {
+      name: name.to_string(),
+      outcome,
+      message: message.map(str::to_string),
+      location: None,
+    }
+  }
+
+  #[test]
+  fn test_summary() {
+    let cirun = Cirun {
+      commit: "a".repeat(40),
+      ..cirun(3, 1, Status::Pending)
+    };
+    let mut failing = test("answer", TestOutcome::Failed, Some("\nexpected 42\nstack"));
+    failing.location = Some(Location {
+      file: "src/lib.rs".to_string(),
+      line: Some(3),
+    });
+    let result = RunResult {
+      status: Status::Failed,
+      reports: vec![
+        ReportFile {
+          path: PathBuf::from("junit.xml"),
+          report: Ok(TestReport {
+            tests: vec![
+              failing,
+              test("build", TestOutcome::Passed, None),
+              test("slow", TestOutcome::Skipped, None),
+            ],
+            findings: vec![Finding {
+              severity: Severity::Warning,
+              message: "unused variable".to_string(),
+              rule: Some("unused".to_string()),
+              location: None,
+            }],
+          }),
+        },
+        ReportFile {
+          path: PathBuf::from("report.tap"),
+          report: Err("The report was not found".to_string()),
+        },
+      ],
+      score: Some(Ok(Score {
+        score: 7.5,
+        max_score: 10.0,
+        rubric: vec![RubricScore {
+          name: "Tests".to_string(),
+          score: 7.5,
+          max_score: 10.0,
+          comment: Some("3/4 tests passed".to_string()),
+        }],
+      })),
+      artifacts: Vec::new(),
+      from_ci_repository: false,
+    };
+
+    let remarks = remarks(&result);
+    assert_eq!(
+      summary(&cirun, &result, &remarks),
+      "CI run #3 failed
+
+- `junit.xml`: 3 tests, 1 passed, 1 failed, 1 skipped, 1 finding
+- `report.tap`: unable to read the report: The report was not found
+
+Score: 7.5/10
+- Tests: 7.5/10, 3/4 tests passed
+
+Problems:
+- Test `answer` failed (`src/lib.rs:3`): expected 42
+- Warning `unused`: unused variable"
+    );
+    assert_eq!(
+      remarks[0].comment(),
+      "Test `answer` failed\n\n```\nexpected 42\nstack\n```"
+    );
+  }
+
+  #[test]
+  fn test_summary_lists_a_limited_number_of_problems() {
+    let cirun = Cirun {
+      commit: "a".repeat(40),
+      ..cirun(3, 1, Status::Pending)
+    };
+    let tests = (0..MAX_SUMMARY_ITEMS + 5)
+      .map(|i| test(&i.to_string(), TestOutcome::Failed, None))
+      .collect();
+    let result = RunResult {
+      status: Status::Failed,
+      reports: vec![ReportFile {
+        path: PathBuf::from("report.tap"),
+        report: Ok(TestReport {
+          tests,
+          findings: Vec::new(),
+        }),
+      }],
+      score: Some(Err("The score file was not found".to_string())),
+      artifacts: Vec::new(),
+      from_ci_repository: false,
+    };
+
+    let summary = summary(&cirun, &result, &remarks(&result));
+    assert!(summary.contains("\n\nUnable to compute the score: The score file was not found\n\n"));
+    assert_eq!(summary.matches("\n- Test").count(), MAX_SUMMARY_ITEMS);
+    assert!(summary.ends_with("\n- and 5 more"), "{}", summary);
+  }
+
+  #[test]
+  fn test_truncate() {
+    assert_eq!(truncate("short", 10), "short");
+    assert_eq!(truncate("café au lait", 4), "café…");
+  }
+}
@@ -0,0 +1,287 @@
+//! Computes the score of a run, either from the tests of its reports or from a score file written
+//! by the steps:
+//!
+//! ```json
+//! {
+//!   "score": 7.5,
+//!   "max_score": 10,
+//!   "rubric": [{ "name": "Tests", "score": 7.5, "max_score": 10, "comment": "3 tests failed" }]
+//! }
+//! ```
+
+use std::path::Path;
+
+use database::db_handle::grade::RubricScore;
+use gmt_common::ci_pipeline::{ScoreGroup, Scoring};
+use serde::Deserialize;
+
+use crate::test_report::{read_checkout_file, ReportFile, TestCase, TestOutcome};
+
+const MAX_CRITERION_NAME_LENGTH: usize = 255;
+
+/// The score of a run, along with its breakdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+  pub score: f64,
+  pub max_score: f64,
+  pub rubric: Vec<RubricScore>,
+}
+
+#[derive(Deserialize)]
+struct RawScore {
+  score: f64,
+  max_score: f64,
+  #[serde(default)]
+  rubric: Vec<RawCriterion>,
+}
+
+#[derive(Deserialize)]
+struct RawCriterion {
+  name: String,
+  score: f64,
+  max_score: f64,
+  comment: Option<String>,
+}
+
+/// Computes the score of the run once its steps ran. Tests missing from the reports, such as when
+/// the build failed, count as failed.
+pub fn compute_score(
+  workspace: &Path,
+  scoring: &Scoring,
+  reports: &[ReportFile],
+) -> Result<Score, String> {
+  match scoring {
+    Scoring::Groups(groups) => {
+      let tests: Vec<&TestCase> = reports
+        .iter()
+        .filter_map(|file| file.report.as_ref().ok())
+        .flat_map(|report| &report.tests)
+        .collect();
+      let rubric: Vec<RubricScore> = groups
+        .iter()
+        .map(|group| group_score(group, &tests))
+        .collect();
+      Ok(Score {
+        score: round(rubric.iter().map(|c| c.score).sum()),
+        max_score: round(rubric.iter().map(|c| c.max_score).sum()),
+        rubric,
+      })
+    }
+    Scoring::File(path) => {
+      let content = read_checkout_file(workspace, path, "score file")?;
+      parse_score(&content)
+    }
+  }
+}
+
+fn group_score(group: &ScoreGroup, tests: &[&TestCase]) -> RubricScore {
+  let matched: Vec<_> = tests
+    .iter()
+    .filter(|test| group.tests.iter().any(|p| matches(p, &test.name)))
+    .collect();
+  let passed = matched
+    .iter()
+    .filter(|test| test.outcome == TestOutcome::Passed)
+    .count();
+  let (score, comment) = match matched.len() {
+    0 => (0.0, "No test of the group ran".to_string()),
+    total => (
+      round(group.weight * passed as f64 / total as f64),
+      format!("{}/{} tests passed", passed, total),
+    ),
+  };
+  RubricScore {
+    name: group.name.clone(),
+    score,
+    max_score: group.weight,
+    comment: Some(comment),
+  }
+}
+
+fn parse_score(content: &str) -> Result<Score, String> {
+  let raw: RawScore =
+    serde_json::from_str(content).map_err(|e| format!("Invalid score file: {}", e))?;
+  check_score("the run", raw.score, raw.max_score)?;
+  let mut rubric = Vec::new();
+  for criterion in raw.rubric {
+    let name = criterion.name.trim();
+    if name.is_empty() || name.len() > MAX_CRITERION_NAME_LENGTH {
+      return Err(format!(
+        "The name of a criterion must be between 1 and {} characters long",
+        MAX_CRITERION_NAME_LENGTH
+      ));
+    }
+    check_score(name, criterion.score, criterion.max_score)?;
+    rubric.push(RubricScore {
+      name: name.to_string(),
+      score: criterion.score,
+      max_score: criterion.max_score,
+      comment: criterion.comment,
+    });
+  }
+  Ok(Score {
+    score: raw.score,
+    max_score: raw.max_score,
+    rubric,
+  })
+}
+
+/// Checks that the score is within `0..=max_score`, as the database requires.
+fn check_score(what: &str, score: f64, max_score: f64) -> Result<(), String> {
+  if !max_score.is_finite() || max_score <= 0.0 {
+    return Err(format!("The maximum score of {} must be positive", what));
+  }
+  if !(0.0..=max_score).contains(&score) {
+    return Err(format!(
+      "The score of {} must be between 0 and {}",
+      what, max_score
+    ));
+  }
+  Ok(())
+}
+
+/// Matches a test name against a pattern, `*` matching any sequence of characters.
+fn matches(pattern: &str, name: &str) -> bool {
+  let mut parts = pattern.split('*');
+  let first = parts.next().unwrap_or_default();
+  let Some(mut rest) = name.strip_prefix(first) else {
+    return false;
+  };
+  let parts: Vec<_> = parts.collect();
+  let Some((last, middle)) = parts.split_last() else {
+    return rest.is_empty();
+  };
+  for part in middle {
+    match rest.find(part) {
+      Some(index) => rest = &rest[index + part.len()..],
+      None => return false,
+    }
+  }
+  rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn round(value: f64) -> f64 {
+  (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use rstest::rstest;
+
+  use super::*;
+  use crate::test_report::TestReport;
+
+  #[rstest]
+  #[case::exact("tests.answer", "tests.answer", true)]
+  #[case::different("tests.answer", "tests.answers", false)]
+  #[case::prefix("tests.*", "tests.basics.answer", true)]
+  #[case::suffix("*.answer", "tests.basics.answer", true)]
+  #[case::middle("tests.*.answer", "tests.basics.answer", true)]
+  #[case::overlapping("a*ab", "aab", true)]
+  #[case::too_short("ab*ba", "aba", false)]
+  #[case::any("*", "anything", true)]
+  fn test_matches(#[case] pattern: &str, #[case] name: &str, #[case] expected: bool) {
+    assert_eq!(matches(pattern, name), expected);
+  }
+
+  fn report(tests: &[(&str, TestOutcome)]) -> ReportFile {
+    ReportFile {
+      path: PathBuf::from("report.xml"),
+      report: Ok(TestReport {
+        tests: tests
+          .iter()
+          .map(|(name, outcome)| TestCase {
+            name: name.to_string(),
+            outcome: *outcome,
+            message: None,
+            location: None,
+          })
+          .collect(),
+        findings: Vec::new(),
+      }),
+    }
+  }
+
+  #[test]
+  fn test_score_of_groups() {
+    let scoring = Scoring::Groups(vec![
+      ScoreGroup {
+        name: "Basics".to_string(),
+        tests: vec!["basics.*".to_string()],
+        weight: 2.0,
+      },
+      ScoreGroup {
+        name: "Advanced".to_string(),
+        tests: vec!["advanced.*".to_string()],
+        weight: 3.0,
+      },
+      ScoreGroup {
+        name: "Bonus".to_string(),
+        tests: vec!["bonus".to_string()],
+        weight: 1.0,
+      },
+    ]);
+    let reports = [report(&[
+      ("basics.add", TestOutcome::Passed),
+      ("basics.sub", TestOutcome::Passed),
+      ("advanced.a", TestOutcome::Passed),
+      ("advanced.b", TestOutcome::Failed),
+      ("advanced.c", TestOutcome::Skipped),
+    ])];
+
+    let score = compute_score(Path::new("."), &scoring, &reports).expect("Unable to score");
+    assert_eq!(score.score, 3.0);
+    assert_eq!(score.max_score, 6.0);
+    let rubric: Vec<_> = score
+      .rubric
+      .iter()
+      .map(|c| (c.name.as_str(), c.score, c.max_score, c.comment.as_deref()))
+      .collect();
+    assert_eq!(
+      rubric,
+      vec![
+        ("Basics", 2.0, 2.0, Some("2/2 tests passed")),
+        ("Advanced", 1.0, 3.0, Some("1/3 tests passed")),
+        ("Bonus", 0.0, 1.0, Some("No test of the group ran")),
+      ]
+    );
+  }
+
+  #[rstest]
+  #[case::valid(
+    r#"{ "score": 7.5, "max_score": 10, "rubric": [{ "name": "Tests", "score": 7.5, "max_score": 10 }] }"#,
+    Ok(7.5)
+  )]
+  #[case::above_maximum(
+    r#"{ "score": 11, "max_score": 10 }"#,
+    Err("The score of the run must be between 0 and 10")
+  )]
+  #[case::invalid_criterion(
+    r#"{ "score": 1, "max_score": 10, "rubric": [{ "name": "Tests", "score": 1, "max_score": 0 }] }"#,
+    Err("The maximum score of Tests must be positive")
+  )]
+  fn test_score_file(#[case] content: &str, #[case] expected: Result<f64, &str>) {
+    let workspace = tempfile::tempdir().unwrap();
+    std::fs::write(workspace.path().join("score.json"), content).unwrap();
+
+    let score = compute_score(
+      workspace.path(),
+      &Scoring::File(PathBuf::from("score.json")),
+      &[],
+    );
+    assert_eq!(score.map(|s| s.score), expected.map_err(|e| e.to_string()));
+  }
+
+  #[test]
+  fn test_missing_score_file() {
+    let workspace = tempfile::tempdir().unwrap();
+    let score = compute_score(
+      workspace.path(),
+      &Scoring::File(PathBuf::from("score.json")),
+      &[],
+    );
+    assert_eq!(score, Err("The score file was not found".to_string()));
+  }
+}
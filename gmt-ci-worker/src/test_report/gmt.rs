@@ -0,0 +1,148 @@
+//! The JSON format of gmt, for tools which don't write a standard report. Besides tests, it holds
+//! the findings of linters:
+//!
+//! ```json
+//! {
+//!   "tests": [
+//!     { "name": "answer", "status": "failed", "message": "expected 42", "file": "src/lib.rs", "line": 3 }
+//!   ],
+//!   "findings": [
+//!     { "file": "src/main.rs", "line": 12, "severity": "warning", "rule": "unused", "message": "unused variable" }
+//!   ]
+//! }
+//! ```
+//!
+//! The status of a test is one of `passed`, `failed` or `skipped`, and the severity of a finding
+//! one of `error`, `warning` (by default) or `info`.
+
+use serde::Deserialize;
+
+use super::{Finding, Location, Severity, TestCase, TestOutcome, TestReport};
+
+#[derive(Deserialize)]
+struct RawReport {
+  #[serde(default)]
+  tests: Vec<RawTest>,
+  #[serde(default)]
+  findings: Vec<RawFinding>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawStatus {
+  Passed,
+  Failed,
+  Skipped,
+}
+
+#[derive(Deserialize)]
+struct RawTest {
+  name: String,
+  status: RawStatus,
+  message: Option<String>,
+  file: Option<String>,
+  line: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawFinding {
+  message: String,
+  #[serde(default)]
+  severity: Severity,
+  rule: Option<String>,
+  file: Option<String>,
+  line: Option<u32>,
+}
+
+fn location(file: Option<String>, line: Option<u32>) -> Option<Location> {
+  file.map(|file| Location { file, line })
+}
+
+pub fn parse(content: &str) -> Result<TestReport, String> {
+  let raw: RawReport = serde_json::from_str(content).map_err(|e| format!("Invalid JSON: {}", e))?;
+  Ok(TestReport {
+    tests: raw
+      .tests
+      .into_iter()
+      .map(|test| TestCase {
+        name: test.name,
+        outcome: match test.status {
+          RawStatus::Passed => TestOutcome::Passed,
+          RawStatus::Failed => TestOutcome::Failed,
+          RawStatus::Skipped => TestOutcome::Skipped,
+        },
+        message: test.message,
+        location: location(test.file, test.line),
+      })
+      .collect(),
+    findings: raw
+      .findings
+      .into_iter()
+      .map(|finding| Finding {
+        severity: finding.severity,
+        message: finding.message,
+        rule: finding.rule,
+        location: location(finding.file, finding.line),
+      })
+      .collect(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_gmt() {
+    let content = r#"{
+      "tests": [
+        { "name": "answer", "status": "failed", "message": "expected 42", "file": "src/lib.rs", "line": 3 },
+        { "name": "build", "status": "passed" }
+      ],
+      "findings": [
+        { "file": "src/main.rs", "line": 12, "rule": "unused", "message": "unused variable" },
+        { "severity": "error", "message": "missing README" }
+      ]
+    }"#;
+    let report = parse(content).expect("Unable to parse");
+    assert_eq!(report.count(TestOutcome::Failed), 1);
+    assert_eq!(report.count(TestOutcome::Passed), 1);
+    assert_eq!(
+      report.tests[0].location,
+      Some(Location {
+        file: "src/lib.rs".to_string(),
+        line: Some(3),
+      })
+    );
+    assert_eq!(
+      report.findings,
+      vec![
+        Finding {
+          severity: Severity::Warning,
+          message: "unused variable".to_string(),
+          rule: Some("unused".to_string()),
+          location: Some(Location {
+            file: "src/main.rs".to_string(),
+            line: Some(12),
+          }),
+        },
+        Finding {
+          severity: Severity::Error,
+          message: "missing README".to_string(),
+          rule: None,
+          location: None,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_parse_invalid_gmt() {
+    let error = parse(r#"{ "tests": [{ "name": "a", "status": "broken" }] }"#).unwrap_err();
+    assert!(
+      error.starts_with("Invalid JSON: unknown variant `broken`"),
+      "{}",
+      error
+    );
+  }
+}
@@ -0,0 +1,174 @@
+//! JUnit XML, as written by most test runners: `testsuite` elements holding `testcase` elements,
+//! which hold a `failure`, `error` or `skipped` element unless they passed. The `file` and `line`
+//! attributes of the test cases are used when present.
+
+use quick_xml::{
+  events::{BytesStart, Event},
+  Reader,
+};
+
+use super::{Location, TestCase, TestOutcome, TestReport};
+
+pub fn parse(content: &str) -> Result<TestReport, String> {
+  let mut reader = Reader::from_str(content);
+  reader.config_mut().trim_text(true);
+  let invalid = |reader: &Reader<&[u8]>, e: &dyn std::fmt::Display| {
+    format!("Invalid XML at byte {}: {}", reader.error_position(), e)
+  };
+
+  let mut report = TestReport::default();
+  let mut current: Option<TestCase> = None;
+  // The text of the failure being read
+  let mut details: Option<String> = None;
+  loop {
+    let event = reader.read_event().map_err(|e| invalid(&reader, &e))?;
+    match &event {
+      Event::Start(element) | Event::Empty(element) if element.name().as_ref() == b"testcase" => {
+        let test = test_case(element).map_err(|e| invalid(&reader, &e))?;
+        match matches!(event, Event::Empty(_)) {
+          true => report.tests.push(test),
+          false => current = Some(test),
+        }
+      }
+      Event::Start(element) | Event::Empty(element) => {
+        let outcome = match element.name().as_ref() {
+          b"failure" | b"error" => TestOutcome::Failed,
+          b"skipped" => TestOutcome::Skipped,
+          _ => continue,
+        };
+        let Some(test) = current.as_mut() else {
+          continue;
+        };
+        // A test erroring after a failure stays failed
+        if test.outcome != TestOutcome::Failed {
+          test.outcome = outcome;
+        }
+        test.message = attribute(element, "message").map_err(|e| invalid(&reader, &e))?;
+        if matches!(event, Event::Start(_)) {
+          details = Some(String::new());
+        }
+      }
+      Event::Text(text) => {
+        if let Some(details) = details.as_mut() {
+          details.push_str(&text.unescape().map_err(|e| invalid(&reader, &e))?);
+        }
+      }
+      Event::CData(text) => {
+        if let Some(details) = details.as_mut() {
+          details.push_str(&String::from_utf8_lossy(text));
+        }
+      }
+      Event::End(element) => match element.name().as_ref() {
+        b"testcase" => report.tests.extend(current.take()),
+        b"failure" | b"error" | b"skipped" => {
+          let details = details.take().unwrap_or_default();
+          if let Some(test) = current.as_mut().filter(|_| !details.trim().is_empty()) {
+            test.message = Some(match test.message.take() {
+              Some(message) if !details.contains(message.as_str()) => {
+                format!("{}\n{}", message, details.trim())
+              }
+              _ => details.trim().to_string(),
+            });
+          }
+        }
+        _ => {}
+      },
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  Ok(report)
+}
+
+fn test_case(element: &BytesStart) -> Result<TestCase, quick_xml::Error> {
+  let name = attribute(element, "name")?.unwrap_or_default();
+  let name = match attribute(element, "classname")? {
+    Some(classname) if !classname.is_empty() => format!("{}.{}", classname, name),
+    _ => name,
+  };
+  let location = attribute(element, "file")?.map(|file| Location {
+    file,
+    line: attribute(element, "line")
+      .ok()
+      .flatten()
+      .and_then(|line| line.parse().ok()),
+  });
+  Ok(TestCase {
+    name,
+    outcome: TestOutcome::Passed,
+    message: None,
+    location,
+  })
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, quick_xml::Error> {
+  match element.try_get_attribute(name)? {
+    Some(attribute) => Ok(Some(attribute.unescape_value()?.to_string())),
+    None => Ok(None),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_junit() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="tests" tests="4" failures="1" errors="1" skipped="1">
+    <testcase classname="tests.test_answer" name="test_value" file="answer.py" line="12">
+      <failure message="assert 41 == 42">def test_value():
+&gt;   assert answer() == 42</failure>
+    </testcase>
+    <testcase classname="tests.test_answer" name="test_type">
+      <error message="TypeError"><![CDATA[Traceback <here>]]></error>
+    </testcase>
+    <testcase name="test_slow"><skipped message="too slow"/></testcase>
+    <testcase name="test_ok" time="0.1"/>
+  </testsuite>
+</testsuites>"#;
+
+    let report = parse(content).expect("Unable to parse");
+    assert_eq!(
+      report.tests,
+      vec![
+        TestCase {
+          name: "tests.test_answer.test_value".to_string(),
+          outcome: TestOutcome::Failed,
+          message: Some(
+            "assert 41 == 42\ndef test_value():\n>   assert answer() == 42".to_string()
+          ),
+          location: Some(Location {
+            file: "answer.py".to_string(),
+            line: Some(12),
+          }),
+        },
+        TestCase {
+          name: "tests.test_answer.test_type".to_string(),
+          outcome: TestOutcome::Failed,
+          message: Some("TypeError\nTraceback <here>".to_string()),
+          location: None,
+        },
+        TestCase {
+          name: "test_slow".to_string(),
+          outcome: TestOutcome::Skipped,
+          message: Some("too slow".to_string()),
+          location: None,
+        },
+        TestCase {
+          name: "test_ok".to_string(),
+          outcome: TestOutcome::Passed,
+          message: None,
+          location: None,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_parse_invalid_junit() {
+    let error = parse("<testsuite><testcase name=\"a\"></testsuite>").unwrap_err();
+    assert!(error.starts_with("Invalid XML at byte"), "{}", error);
+  }
+}
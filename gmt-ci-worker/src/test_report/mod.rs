@@ -0,0 +1,235 @@
+//! Reads the test reports written by the steps of a pipeline, whichever their format, so that
+//! their results can be posted as comments on the commit.
+
+use std::path::{Component, Path, PathBuf};
+
+use gmt_common::ci_pipeline::{ReportDefinition, ReportFormat};
+use serde::Deserialize;
+
+mod gmt;
+mod junit;
+mod tap;
+
+/// Reports and score files larger than this are ignored rather than read in memory.
+pub const MAX_REPORT_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+  Passed,
+  Failed,
+  Skipped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  Error,
+  #[default]
+  Warning,
+  Info,
+}
+
+/// Where a test or a finding points to in the checkout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+  /// The path of the file, relative to the root of the repository
+  pub file: String,
+  /// The line, starting at 1
+  pub line: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+  pub name: String,
+  pub outcome: TestOutcome,
+  /// Why the test failed or was skipped
+  pub message: Option<String>,
+  pub location: Option<Location>,
+}
+
+/// A problem reported by a linter or a static analysis tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+  pub severity: Severity,
+  pub message: String,
+  /// The identifier of the rule, as given by the tool
+  pub rule: Option<String>,
+  pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TestReport {
+  pub tests: Vec<TestCase>,
+  pub findings: Vec<Finding>,
+}
+
+impl TestReport {
+  pub fn count(&self, outcome: TestOutcome) -> usize {
+    self.tests.iter().filter(|t| t.outcome == outcome).count()
+  }
+}
+
+/// A report declared by the pipeline, along with its content or the reason it couldn't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportFile {
+  pub path: PathBuf,
+  pub report: Result<TestReport, String>,
+}
+
+pub fn parse_report(format: ReportFormat, content: &str) -> Result<TestReport, String> {
+  match format {
+    ReportFormat::Junit => junit::parse(content),
+    ReportFormat::Tap => tap::parse(content),
+    ReportFormat::Gmt => gmt::parse(content),
+  }
+}
+
+/// Reads the reports from the checkout once the steps ran. The locations they point to are made
+/// relative to the checkout, and dropped when they point outside of it.
+pub fn read_reports(workspace: &Path, definitions: &[ReportDefinition]) -> Vec<ReportFile> {
+  definitions
+    .iter()
+    .map(|definition| ReportFile {
+      path: definition.path.clone(),
+      report: read_report(workspace, definition).map(|mut report| {
+        relocate(workspace, &mut report);
+        report
+      }),
+    })
+    .collect()
+}
+
+fn read_report(workspace: &Path, definition: &ReportDefinition) -> Result<TestReport, String> {
+  let content = read_checkout_file(workspace, &definition.path, "report")?;
+  parse_report(definition.format, &content)
+}
+
+/// Reads a file written by the steps, `kind` describing it in errors. Files larger than
+/// `MAX_REPORT_SIZE` or outside of the checkout are refused.
+pub fn read_checkout_file(workspace: &Path, path: &Path, kind: &str) -> Result<String, String> {
+  let path = workspace.join(path);
+  if !path.exists() {
+    return Err(format!("The {} was not found", kind));
+  }
+  // The steps could have replaced the file with a link to a file of the worker
+  let inside = match (path.canonicalize(), workspace.canonicalize()) {
+    (Ok(path), Ok(workspace)) => path.starts_with(workspace),
+    _ => false,
+  };
+  if !inside || !path.is_file() {
+    return Err(format!("The {} is not a file of the checkout", kind));
+  }
+  let size = path.metadata().map_err(|e| e.to_string())?.len();
+  if size > MAX_REPORT_SIZE {
+    return Err(format!(
+      "The {} is too large ({} bytes, at most {} allowed)",
+      kind, size, MAX_REPORT_SIZE
+    ));
+  }
+  std::fs::read_to_string(&path).map_err(|e| e.to_string())
+}
+
+fn relocate(workspace: &Path, report: &mut TestReport) {
+  let locations = report
+    .tests
+    .iter_mut()
+    .map(|t| &mut t.location)
+    .chain(report.findings.iter_mut().map(|f| &mut f.location));
+  for location in locations {
+    *location = location.take().and_then(|mut location| {
+      location.file = relative_file(workspace, &location.file)?;
+      Some(location)
+    });
+  }
+}
+
+/// Makes the path of a file relative to the checkout, as tools often report absolute paths.
+fn relative_file(workspace: &Path, file: &str) -> Option<String> {
+  let path = Path::new(file);
+  let path = match path.is_absolute() {
+    true => path
+      .strip_prefix(workspace)
+      .ok()
+      .or_else(|| path.strip_prefix(workspace.canonicalize().ok()?).ok())?,
+    false => path,
+  };
+
+  let mut parts = Vec::new();
+  for component in path.components() {
+    match component {
+      Component::Normal(part) => parts.push(part.to_str()?),
+      Component::CurDir => {}
+      _ => return None,
+    }
+  }
+  match parts.is_empty() {
+    true => None,
+    false => Some(parts.join("/")),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::rstest;
+
+  use super::*;
+
+  #[rstest]
+  #[case::relative("src/lib.rs", Some("src/lib.rs"))]
+  #[case::current_directory("./src/lib.rs", Some("src/lib.rs"))]
+  #[case::absolute("{workspace}/src/lib.rs", Some("src/lib.rs"))]
+  #[case::outside("/usr/lib/python3/unittest.py", None)]
+  #[case::parent("../secret", None)]
+  #[case::empty("", None)]
+  fn test_relative_file(#[case] file: &str, #[case] expected: Option<&str>) {
+    let workspace = tempfile::tempdir().unwrap();
+    let file = file.replace("{workspace}", &workspace.path().to_string_lossy());
+    assert_eq!(relative_file(workspace.path(), &file).as_deref(), expected);
+  }
+
+  #[test]
+  fn test_read_reports() {
+    let workspace = tempfile::tempdir().unwrap();
+    let report = format!(
+      "not ok 1 - answer\n  ---\n  at: {}/answer.py:3\n  ...\n",
+      workspace.path().display()
+    );
+    std::fs::write(workspace.path().join("report.tap"), report).unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    std::fs::write(outside.path().join("secret"), "ok 1").unwrap();
+    std::os::unix::fs::symlink(
+      outside.path().join("secret"),
+      workspace.path().join("link.tap"),
+    )
+    .unwrap();
+    let definition = |path: &str| ReportDefinition {
+      path: PathBuf::from(path),
+      format: ReportFormat::Tap,
+    };
+
+    let reports = read_reports(
+      workspace.path(),
+      &[
+        definition("report.tap"),
+        definition("missing.tap"),
+        definition("link.tap"),
+      ],
+    );
+    let report = reports[0].report.as_ref().expect("Unable to read");
+    assert_eq!(
+      report.tests[0].location,
+      Some(Location {
+        file: "answer.py".to_string(),
+        line: Some(3),
+      })
+    );
+    assert_eq!(
+      reports[1].report,
+      Err("The report was not found".to_string())
+    );
+    assert_eq!(
+      reports[2].report,
+      Err("The report is not a file of the checkout".to_string())
+    );
+  }
+}
@@ -0,0 +1,254 @@
+//! The Test Anything Protocol: one `ok` or `not ok` line per test, optionally followed by an
+//! indented YAML block describing the failure. The `message`, `file`, `line` and `at` keys of the
+//! block are used, `at` being either a `file:line` string or a mapping.
+
+use super::{Location, TestCase, TestOutcome, TestReport};
+
+pub fn parse(content: &str) -> Result<TestReport, String> {
+  let mut report = TestReport::default();
+  let mut planned = None;
+  let mut lines = content.lines().peekable();
+
+  while let Some(line) = lines.next() {
+    if let Some(plan) = line.strip_prefix("1..") {
+      let count = plan.split_whitespace().next().unwrap_or_default();
+      planned = Some(
+        count
+          .parse::<usize>()
+          .map_err(|_| format!("Invalid plan `{}`", line))?,
+      );
+      continue;
+    }
+    if let Some(reason) = line.strip_prefix("Bail out!") {
+      report.tests.push(TestCase {
+        name: "Bail out!".to_string(),
+        outcome: TestOutcome::Failed,
+        message: Some(reason.trim().to_string()).filter(|r| !r.is_empty()),
+        location: None,
+      });
+      return Ok(report);
+    }
+    let Some(mut test) = test_line(line) else {
+      continue;
+    };
+
+    // The diagnostics of the test, up to the end of the block
+    if lines.peek().is_some_and(|next| is_block_start(next)) {
+      let start = lines.next().unwrap_or_default();
+      let indent = start.len() - start.trim_start().len();
+      let mut block = Vec::new();
+      for line in lines.by_ref() {
+        if line.trim() == "..." {
+          break;
+        }
+        block.push(line.get(indent..).unwrap_or(line.trim_start()));
+      }
+      diagnostics(&mut test, &block);
+    }
+    report.tests.push(test);
+  }
+
+  if let Some(planned) = planned.filter(|planned| *planned > report.tests.len()) {
+    report.tests.push(TestCase {
+      name: "Missing tests".to_string(),
+      outcome: TestOutcome::Failed,
+      message: Some(format!(
+        "{} tests were planned, only {} ran",
+        planned,
+        report.tests.len()
+      )),
+      location: None,
+    });
+  }
+  Ok(report)
+}
+
+fn is_block_start(line: &str) -> bool {
+  line.starts_with(char::is_whitespace) && line.trim() == "---"
+}
+
+/// Reads a test line, such as `not ok 3 - name # TODO reason`. Indented lines belong to subtests,
+/// whose result is summarized by their parent, and are ignored.
+fn test_line(line: &str) -> Option<TestCase> {
+  let (passed, rest) = match line.strip_prefix("not ok") {
+    Some(rest) => (false, rest),
+    None => (true, line.strip_prefix("ok")?),
+  };
+  if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+    return None;
+  }
+  let rest = rest.trim_start();
+  let rest = rest
+    .trim_start_matches(|c: char| c.is_ascii_digit())
+    .trim_start();
+  let rest = rest.strip_prefix('-').unwrap_or(rest).trim_start();
+
+  let (name, directive) = match rest.split_once(" # ") {
+    Some((name, directive)) => (name, Some(directive.trim())),
+    None => match rest.strip_prefix("# ") {
+      Some(directive) => ("", Some(directive.trim())),
+      None => (rest, None),
+    },
+  };
+  let directive = directive.and_then(|directive| {
+    let upper = directive.to_ascii_uppercase();
+    ["SKIP", "TODO"]
+      .iter()
+      .find(|keyword| upper.starts_with(*keyword))
+      .map(|keyword| directive[keyword.len()..].trim().to_string())
+  });
+
+  // Failing tests marked as to do are expected to fail
+  let (outcome, message) = match directive {
+    Some(reason) => (TestOutcome::Skipped, Some(reason).filter(|r| !r.is_empty())),
+    None if passed => (TestOutcome::Passed, None),
+    None => (TestOutcome::Failed, None),
+  };
+  Some(TestCase {
+    name: name.trim().to_string(),
+    outcome,
+    message,
+    location: None,
+  })
+}
+
+/// Reads the keys of the YAML block of a test, without going as far as parsing YAML.
+fn diagnostics(test: &mut TestCase, block: &[&str]) {
+  let mut file = None;
+  let mut line = None;
+  let mut lines = block.iter().peekable();
+  while let Some(entry) = lines.next() {
+    let Some((key, value)) = entry.split_once(':') else {
+      continue;
+    };
+    let (key, value) = (key.trim(), unquote(value.trim()));
+    match key {
+      "message" if value.starts_with(['|', '>']) => {
+        // A block scalar, made of the lines indented deeper than its key
+        let indent = entry.len() - entry.trim_start().len();
+        let mut message = Vec::new();
+        while let Some(next) = lines
+          .next_if(|next| next.trim().is_empty() || next.len() - next.trim_start().len() > indent)
+        {
+          message.push(next.trim());
+        }
+        test.message = Some(message.join("\n").trim().to_string());
+      }
+      "message" => test.message = Some(value.to_string()),
+      "file" => file = Some(value.to_string()),
+      "line" => line = value.parse().ok(),
+      "at" if !value.is_empty() => {
+        let mut parts = value.rsplitn(3, ':').collect::<Vec<_>>();
+        parts.reverse();
+        // Either `file:line` or `file:line:column`
+        while parts.len() > 1 && parts.last().is_some_and(|p| p.parse::<u32>().is_ok()) {
+          let part = parts.pop().unwrap_or_default();
+          line = part.parse().ok().or(line);
+        }
+        file = Some(parts.join(":"));
+      }
+      _ => {}
+    }
+  }
+  test.location = file.map(|file| Location { file, line });
+}
+
+fn unquote(value: &str) -> &str {
+  for quote in ['"', '\''] {
+    if let Some(value) = value
+      .strip_prefix(quote)
+      .and_then(|value| value.strip_suffix(quote))
+    {
+      return value;
+    }
+  }
+  value
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_tap() {
+    let content = "TAP version 13
+1..6
+ok 1 - builds
+not ok 2 - answer is 42
+  ---
+  message: expected 42, got 41
+  at: src/answer.js:12:5
+  ...
+not ok 3 - handles errors
+  ---
+  message: |-
+    first line
+    second line
+  at:
+    line: 7
+    column: 3
+    file: src/errors.js
+  ...
+ok 4 - slow test # SKIP too slow
+not ok 5 # TODO not implemented
+    ok 1 - subtest
+# a comment
+";
+    let report = parse(content).expect("Unable to parse");
+    let summary: Vec<_> = report
+      .tests
+      .iter()
+      .map(|t| (t.name.as_str(), t.outcome, t.message.as_deref()))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        ("builds", TestOutcome::Passed, None),
+        (
+          "answer is 42",
+          TestOutcome::Failed,
+          Some("expected 42, got 41")
+        ),
+        (
+          "handles errors",
+          TestOutcome::Failed,
+          Some("first line\nsecond line")
+        ),
+        ("slow test", TestOutcome::Skipped, Some("too slow")),
+        ("", TestOutcome::Skipped, Some("not implemented")),
+        (
+          "Missing tests",
+          TestOutcome::Failed,
+          Some("6 tests were planned, only 5 ran")
+        ),
+      ]
+    );
+    assert_eq!(
+      report.tests[1].location,
+      Some(Location {
+        file: "src/answer.js".to_string(),
+        line: Some(12),
+      })
+    );
+    assert_eq!(
+      report.tests[2].location,
+      Some(Location {
+        file: "src/errors.js".to_string(),
+        line: Some(7),
+      })
+    );
+  }
+
+  #[test]
+  fn test_parse_tap_bail_out() {
+    let report = parse("1..3\nok 1\nBail out! database down\nok 2").expect("Unable to parse");
+    assert_eq!(report.tests.len(), 2);
+    assert_eq!(report.tests[1].outcome, TestOutcome::Failed);
+    assert_eq!(report.tests[1].message.as_deref(), Some("database down"));
+  }
+
+  #[test]
+  fn test_parse_invalid_plan() {
+    assert_eq!(parse("1..many"), Err("Invalid plan `1..many`".to_string()));
+  }
+}
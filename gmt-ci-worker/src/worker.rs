@@ -0,0 +1,727 @@
+use std::{
+  collections::HashSet,
+  path::PathBuf,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, RecvTimeoutError},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant, SystemTime},
+};
+
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    artifact::ArtifactDbHandle,
+    assignment::AssignmentDbHandle,
+    cirun::{Cirun, CirunDbHandle, Status},
+    cirun_step::CirunStepDbHandle,
+    comment::CommentDbHandle,
+    grade::GradeDbHandle,
+    group::GroupDbHandle,
+    push::PushDbHandle,
+    repository::{Repository, RepositoryDbHandle},
+    submission::SubmissionDbHandle,
+    transaction::TransactionDbHandle,
+  },
+  notifications::{CirunListener, CIRUN_QUEUE_CHANNEL},
+};
+use git_server::objects::GitObjects;
+use gmt_common::{
+  artifact_storage::ArtifactStorage,
+  deadlines::find_commit_push,
+  repositories::{
+    provisioning::provision_released_assignments, repository_storage::RepositoryStorage,
+  },
+};
+use log::{error, info, warn};
+
+use crate::{
+  artifacts::StoredArtifact,
+  checkout::{checkout, resolve_head},
+  error::WorkerError,
+  pipeline::{Job, Pipeline, RunResult},
+  report::{DbReporter, Reporter},
+  review::post_review,
+  scoring::Score,
+};
+
+/// How often the expired artifacts are purged
+const ARTIFACT_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+/// How long a stored artifact is kept before it is recorded, while its run completes
+const ARTIFACT_GRACE_PERIOD: Duration = Duration::from_secs(24 * 3600);
+/// How often the submission repositories of the assignments released since are created
+const PROVISION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Processes the pending CI runs, one at a time.
+pub struct Worker<DbPool, Db, P>
+where
+  DbPool: ConnectionProvider<Connection = Db>,
+  Db: ArtifactDbHandle
+    + AssignmentDbHandle
+    + CirunDbHandle
+    + CirunStepDbHandle
+    + CommentDbHandle
+    + GradeDbHandle
+    + GroupDbHandle
+    + PushDbHandle
+    + RepositoryDbHandle
+    + SubmissionDbHandle
+    + TransactionDbHandle,
+  P: Pipeline,
+{
+  db: DbPool,
+  storage: RepositoryStorage,
+  pipeline: P,
+  scratch_root: PathBuf,
+  /// The name of the worker, recorded on the runs it claims
+  id: String,
+  /// How long a run can go without a heartbeat before its worker is considered dead
+  heartbeat_timeout: Duration,
+  /// How many times a run is attempted before it fails, when its workers keep dying
+  max_attempts: i32,
+  /// The database to listen to for the runs queued, instead of only polling it
+  notifications_url: Option<String>,
+  /// The storage of the artifacts, whose expired artifacts are purged
+  artifacts: Option<ArtifactStorage>,
+  /// When the expired artifacts were last purged by one of the threads of the worker
+  last_purge: Mutex<Option<Instant>>,
+  /// When the released assignments were last provisioned by one of the threads of the worker
+  last_provision: Mutex<Option<Instant>>,
+}
+
+impl<DbPool, Db, P> Worker<DbPool, Db, P>
+where
+  DbPool: ConnectionProvider<Connection = Db> + Sync,
+  Db: ArtifactDbHandle
+    + AssignmentDbHandle
+    + CirunDbHandle
+    + CirunStepDbHandle
+    + CommentDbHandle
+    + GradeDbHandle
+    + GroupDbHandle
+    + PushDbHandle
+    + RepositoryDbHandle
+    + SubmissionDbHandle
+    + TransactionDbHandle,
+  P: Pipeline,
+{
+  pub fn new(db: DbPool, storage: RepositoryStorage, pipeline: P) -> Self {
+    Worker {
+      db,
+      storage,
+      pipeline,
+      scratch_root: std::env::temp_dir(),
+      id: default_worker_id(),
+      heartbeat_timeout: Duration::from_secs(60),
+      max_attempts: 3,
+      notifications_url: None,
+      artifacts: None,
+      last_purge: Mutex::new(None),
+      last_provision: Mutex::new(None),
+    }
+  }
+
+  /// Names the worker on the runs it claims, instead of the host name and the process id.
+  pub fn with_id(mut self, id: String) -> Self {
+    self.id = id;
+    self
+  }
+
+  /// Releases the runs whose worker didn't send a heartbeat for the given time. The heartbeats
+  /// are sent four times as often.
+  pub fn with_heartbeat_timeout(mut self, heartbeat_timeout: Duration) -> Self {
+    self.heartbeat_timeout = heartbeat_timeout;
+    self
+  }
+
+  /// Fails the runs released after the given number of attempts, instead of 3.
+  pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+    self.max_attempts = max_attempts;
+    self
+  }
+
+  /// Listens to the notifications of the given database while no run is pending, so that the runs
+  /// queued start right away. The database is still polled, in case a notification is missed.
+  pub fn with_notifications(mut self, database_url: String) -> Self {
+    self.notifications_url = Some(database_url);
+    self
+  }
+
+  /// Purges the expired artifacts of the runs from the given storage, along with the files no
+  /// artifact refers to anymore. The pipeline stores the artifacts of the runs.
+  pub fn with_artifacts(mut self, storage: ArtifactStorage) -> Self {
+    self.artifacts = Some(storage);
+    self
+  }
+
+  /// Uses the given directory to check out the commits instead of the temporary directory.
+  pub fn with_scratch_root(mut self, scratch_root: PathBuf) -> Self {
+    self.scratch_root = scratch_root;
+    self
+  }
+
+  /// Claims the oldest pending run and executes it, returning the run with its final status. Returns
+  /// none if no run is pending.
+  ///
+  /// The run is marked as running while it executes, and the worker sends heartbeats until it
+  /// completes, so that the run can be released if the worker dies. A run cancelled in the
+  /// meantime stops before its next step, and keeps its cancelled status. The artifacts, the
+  /// comments of the test reports and the grade are recorded along with the final status.
+  pub fn run_next(&self) -> Result<Option<Cirun>, WorkerError> {
+    let mut db = self.db.get_connection()?;
+    let Some(cirun) = db.claim_pending_cirun(&self.id)? else {
+      return Ok(None);
+    };
+    info!(
+      "Run {}: starting attempt {} on commit {}",
+      cirun.id, cirun.attempt, cirun.commit
+    );
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let mut reporter =
+      DbReporter::new(self.db.get_connection()?, cirun.id).with_cancellation(cancelled.clone());
+    let result = std::thread::scope(|scope| {
+      let (stop, stopped) = mpsc::channel::<()>();
+      let (pool, interval) = (&self.db, self.heartbeat_timeout / 4);
+      let (cirun, cancelled) = (&cirun, &cancelled);
+      scope.spawn(move || send_heartbeats(pool, cirun, interval, cancelled, stopped));
+      let result = self.execute(&mut db, cirun, &mut reporter);
+      drop(stop);
+      result
+    });
+    let result = match result {
+      Ok(result) => result,
+      Err(e) => {
+        warn!("Run {}: {}", cirun.id, e);
+        if let Err(e) = reporter.fail(&e.to_string()) {
+          warn!("Run {}: unable to record the failure: {}", cirun.id, e);
+        }
+        Status::Failed.into()
+      }
+    };
+    if let Err(e) = reporter.complete() {
+      warn!(
+        "Run {}: unable to record the skipped steps: {}",
+        cirun.id, e
+      );
+    }
+
+    db.transaction(|db| {
+      let Some(finished) = db.finish_cirun(cirun.id, cirun.attempt, &result.status)? else {
+        info!("Run {}: released before it completed", cirun.id);
+        return Ok(db.get_cirun_by_id(cirun.id)?);
+      };
+      // Savepoints keep the status of the run if the artifacts or the results can't be recorded
+      if !result.artifacts.is_empty() {
+        if let Err(e) = db.transaction(|db| record_artifacts(db, &cirun, &result.artifacts)) {
+          warn!("Run {}: unable to record the artifacts: {}", cirun.id, e);
+        }
+      }
+      if !result.reports.is_empty() || result.score.is_some() {
+        if let Err(e) = db.transaction(|db| self.publish(db, &cirun, &result)) {
+          warn!("Run {}: unable to record the results: {}", cirun.id, e);
+        }
+      }
+      info!("Run {}: {:?}", cirun.id, result.status);
+      Ok(Some(finished))
+    })
+  }
+
+  /// Releases the runs whose worker seems dead, so that they can run again.
+  pub fn reap(&self) -> Result<Vec<Cirun>, WorkerError> {
+    let mut db = self.db.get_connection()?;
+    let reaped = db.reap_ciruns(
+      SystemTime::now() - self.heartbeat_timeout,
+      self.max_attempts,
+    )?;
+    for cirun in &reaped {
+      warn!(
+        "Run {}: worker {} stopped sending heartbeats, {}",
+        cirun.id,
+        cirun.worker_id.as_deref().unwrap_or("unknown"),
+        match cirun.status {
+          Status::Pending => "queued again",
+          _ => "giving up",
+        }
+      );
+    }
+    Ok(reaped)
+  }
+
+  /// Deletes the expired artifacts, then removes the stored files which no artifact refers to.
+  /// Returns the number of files removed.
+  pub fn purge_artifacts(&self) -> Result<usize, WorkerError> {
+    let Some(storage) = &self.artifacts else {
+      return Ok(0);
+    };
+    let mut db = self.db.get_connection()?;
+    let expired = db.delete_expired_artifacts(SystemTime::now())?;
+    let used: HashSet<String> = db.list_artifact_hashes()?.into_iter().collect();
+    let removed = storage.collect_garbage(&used, ARTIFACT_GRACE_PERIOD)?;
+    if !expired.is_empty() || removed > 0 {
+      info!(
+        "{} artifact(s) expired, {} file(s) removed",
+        expired.len(),
+        removed
+      );
+    }
+    Ok(removed)
+  }
+
+  /// Purges the artifacts if no thread of the worker did for `ARTIFACT_PURGE_INTERVAL`.
+  fn purge_artifacts_if_due(&self) {
+    {
+      let mut last_purge = self.last_purge.lock().unwrap_or_else(|e| e.into_inner());
+      if last_purge.is_some_and(|last| last.elapsed() < ARTIFACT_PURGE_INTERVAL) {
+        return;
+      }
+      *last_purge = Some(Instant::now());
+    }
+    if let Err(e) = self.purge_artifacts() {
+      error!("Unable to purge the expired artifacts: {}", e);
+    }
+  }
+
+  /// Creates the missing submission repositories of the assignments released by now. Returns the
+  /// number of repositories created.
+  pub fn provision_released_assignments(&self) -> Result<usize, WorkerError> {
+    let mut db = self.db.get_connection()?;
+    let created = provision_released_assignments(&mut db, &self.storage, SystemTime::now())?;
+    if !created.is_empty() {
+      info!("Provisioned {} submission repositories", created.len());
+    }
+    Ok(created.len())
+  }
+
+  /// Provisions the released assignments if no thread of the worker did for
+  /// `PROVISION_INTERVAL`.
+  fn provision_if_due(&self) {
+    {
+      let mut last_provision = self
+        .last_provision
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+      if last_provision.is_some_and(|last| last.elapsed() < PROVISION_INTERVAL) {
+        return;
+      }
+      *last_provision = Some(Instant::now());
+    }
+    if let Err(e) = self.provision_released_assignments() {
+      error!("Unable to provision the released assignments: {}", e);
+    }
+  }
+
+  fn execute(
+    &self,
+    db: &mut Db,
+    cirun: &Cirun,
+    reporter: &mut dyn Reporter,
+  ) -> Result<RunResult, WorkerError> {
+    let repository = db
+      .get_repository_by_id(cirun.repository_id)?
+      .ok_or(WorkerError::RepositoryNotFound(cirun.repository_id))?;
+
+    std::fs::create_dir_all(&self.scratch_root)?;
+    let workspace = self.scratch_dir(cirun)?;
+    checkout(
+      &self.storage.get_path(&repository.name),
+      &cirun.commit,
+      workspace.path(),
+    )?;
+
+    // The pipeline of an assignment comes from the latest version of its CI repository
+    let ci_repository = match repository.assignment_id {
+      Some(assignment_id) => db
+        .get_assignment_test_repo(assignment_id)?
+        .filter(|ci_repository| ci_repository.id != repository.id),
+      None => None,
+    };
+    let ci_workspace = match ci_repository {
+      Some(ci_repository) => {
+        let path = self.storage.get_path(&ci_repository.name);
+        let ci_workspace = self.scratch_dir(cirun)?;
+        checkout(&path, &resolve_head(&path)?, ci_workspace.path())?;
+        Some(ci_workspace)
+      }
+      None => None,
+    };
+
+    self.pipeline.run(
+      &Job {
+        cirun,
+        repository: &repository,
+        workspace: workspace.path(),
+        ci_workspace: ci_workspace.as_ref().map(|dir| dir.path()),
+      },
+      reporter,
+    )
+  }
+
+  /// Posts the test reports and grades the submission of the commit.
+  fn publish(&self, db: &mut Db, cirun: &Cirun, result: &RunResult) -> Result<(), WorkerError> {
+    let repository = db
+      .get_repository_by_id(cirun.repository_id)?
+      .ok_or(WorkerError::RepositoryNotFound(cirun.repository_id))?;
+    let objects = GitObjects::open(self.storage.get_path(&repository.name))?;
+    let posted = post_review(db, &objects, cirun, result)?;
+    info!("Run {}: {} comment(s) posted", cirun.id, posted);
+
+    if let Some(Ok(score)) = &result.score {
+      if result.from_ci_repository {
+        self.grade(db, cirun, &repository, &objects, score)?;
+      } else if repository.assignment_id.is_some() {
+        warn!(
+          "Run {}: not graded, the pipeline doesn't come from the CI repository of the assignment",
+          cirun.id
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Records the score as a grade of the submission of the commit, submitting the commit if it
+  /// wasn't already, as late if the server recorded its push as late. Only submission
+  /// repositories are graded, by the pipelines of their CI repository.
+  fn grade(
+    &self,
+    db: &mut Db,
+    cirun: &Cirun,
+    repository: &Repository,
+    objects: &GitObjects,
+    score: &Score,
+  ) -> Result<(), WorkerError> {
+    if repository.assignment_id.is_none() {
+      return Ok(());
+    }
+    let submission = match db.get_submission_by_commit(repository.id, &cirun.commit)? {
+      Some(submission) => submission,
+      None => {
+        let pushes = db.list_repository_pushes(repository.id)?;
+        let push = find_commit_push(&pushes, objects, &cirun.commit)?;
+        db.create_submission(
+          repository.id,
+          &cirun.commit,
+          SystemTime::now(),
+          push.is_some_and(|push| push.late),
+          push.map(|push| push.pushed_at),
+        )?
+      }
+    };
+    // A run queued again replaces the grade of its previous attempt
+    if let Some(grade) = db.get_cirun_grade(cirun.id)? {
+      db.delete_grade(grade.id)?;
+    }
+    let grade = db.add_ci_grade(
+      submission.id,
+      cirun.id,
+      score.score,
+      score.max_score,
+      &score.rubric,
+    )?;
+    info!(
+      "Run {}: graded submission {} with {}/{}",
+      cirun.id, submission.id, grade.score, grade.max_score
+    );
+    Ok(())
+  }
+
+  fn scratch_dir(&self, cirun: &Cirun) -> Result<tempfile::TempDir, WorkerError> {
+    Ok(
+      tempfile::Builder::new()
+        .prefix(&format!("gmt-ci-{}-", cirun.id))
+        .tempdir_in(&self.scratch_root)?,
+    )
+  }
+
+  /// Processes the runs as they come, waiting for `poll_interval` whenever no run is pending, or
+  /// until a run is queued when listening to notifications. The runs of dead workers are released
+  /// between the runs, the expired artifacts are purged every hour, and the repositories of the
+  /// students are created every minute for the assignments released since.
+  pub fn run(&self, poll_interval: Duration) {
+    let mut listener = None;
+    loop {
+      if let Err(e) = self.reap() {
+        error!("Unable to release the runs of dead workers: {}", e);
+      }
+      self.purge_artifacts_if_due();
+      self.provision_if_due();
+      match self.run_next() {
+        Ok(Some(_)) => continue,
+        Ok(None) => {}
+        Err(e) => error!("Unable to process the pending runs: {}", e),
+      }
+      self.wait_for_runs(&mut listener, poll_interval);
+    }
+  }
+
+  /// Waits up to `poll_interval` for a run to be queued. The listener is connected on first use,
+  /// and again after an error, the worker only polling the database in the meantime.
+  fn wait_for_runs(&self, listener: &mut Option<CirunListener>, poll_interval: Duration) {
+    let Some(database_url) = &self.notifications_url else {
+      std::thread::sleep(poll_interval);
+      return;
+    };
+    if listener.is_none() {
+      match CirunListener::connect(database_url, &[CIRUN_QUEUE_CHANNEL]) {
+        Ok(connected) => *listener = Some(connected),
+        Err(e) => warn!("Unable to listen to the runs queued: {}", e),
+      }
+    }
+    match listener
+      .as_mut()
+      .map(|listener| listener.wait(poll_interval))
+    {
+      Some(Ok(_)) => {}
+      Some(Err(e)) => {
+        warn!("Stopped listening to the runs queued: {}", e);
+        *listener = None;
+        std::thread::sleep(poll_interval);
+      }
+      None => std::thread::sleep(poll_interval),
+    }
+  }
+}
+
+/// Records the artifacts stored by the pipeline, which expire from now on.
+fn record_artifacts<Db: ArtifactDbHandle>(
+  db: &mut Db,
+  cirun: &Cirun,
+  artifacts: &[StoredArtifact],
+) -> Result<(), WorkerError> {
+  let now = SystemTime::now();
+  for artifact in artifacts {
+    db.create_artifact(
+      cirun.id,
+      &artifact.name,
+      &artifact.file_name,
+      &artifact.content_hash,
+      artifact.size as i64,
+      now + artifact.expire_in,
+    )?;
+  }
+  info!("Run {}: {} artifact(s) kept", cirun.id, artifacts.len());
+  Ok(())
+}
+
+/// Sends the heartbeats of the run until `stopped` is signaled. If the run isn't running anymore,
+/// because it was cancelled or released, sets `cancelled` and stops.
+fn send_heartbeats<DbPool, Db>(
+  pool: &DbPool,
+  cirun: &Cirun,
+  interval: Duration,
+  cancelled: &AtomicBool,
+  stopped: mpsc::Receiver<()>,
+) where
+  DbPool: ConnectionProvider<Connection = Db>,
+  Db: CirunDbHandle,
+{
+  let mut db: Option<Db> = None;
+  while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+    let beat = match &mut db {
+      Some(db) => db.heartbeat_cirun(cirun.id, cirun.attempt),
+      None => pool
+        .get_connection()
+        .and_then(|conn| db.insert(conn).heartbeat_cirun(cirun.id, cirun.attempt)),
+    };
+    match beat {
+      Ok(true) => {}
+      Ok(false) => {
+        info!(
+          "Run {}: not running anymore, stopping before the next step",
+          cirun.id
+        );
+        cancelled.store(true, Ordering::SeqCst);
+        return;
+      }
+      Err(e) => warn!("Run {}: unable to send a heartbeat: {}", cirun.id, e),
+    }
+  }
+}
+
+/// Names the worker after the host and the process.
+fn default_worker_id() -> String {
+  let mut name = [0u8; 256];
+  // SAFETY: the buffer is valid for its whole length
+  let host = match unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } {
+    0 => {
+      let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+      String::from_utf8_lossy(&name[..end]).to_string()
+    }
+    _ => "localhost".to_string(),
+  };
+  format!("{}-{}", host, std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      cirun_step::{CirunLogChunk, CirunStep, StepStatus},
+      repository::Repository,
+    },
+    error::DatabaseError,
+    fixtures::{cirun, repository},
+    DbHandle,
+  };
+
+  use super::*;
+
+  type Jobs = Arc<Mutex<Vec<i32>>>;
+  type Logs = Arc<Mutex<Vec<String>>>;
+
+  /// Records the jobs it runs, and fails them.
+  struct FakePipeline(Jobs);
+
+  impl Pipeline for FakePipeline {
+    fn run(&self, job: &Job, _: &mut dyn Reporter) -> Result<RunResult, WorkerError> {
+      self.0.lock().unwrap().push(job.cirun.id);
+      Ok(Status::Failed.into())
+    }
+  }
+
+  /// The run of repository 2 claimed by the worker
+  fn claimed_run(status: Status) -> Cirun {
+    Cirun {
+      commit: "a".repeat(40),
+      worker_id: Some("worker".to_string()),
+      attempt: 1,
+      ..cirun(1, 2, status)
+    }
+  }
+
+  fn step(id: i32, name: &str, status: StepStatus) -> CirunStep {
+    CirunStep {
+      id,
+      cirun_id: 1,
+      position: id - 1,
+      name: name.to_string(),
+      status,
+      started_at: None,
+      finished_at: None,
+      exit_code: None,
+      log_size: 0,
+      log_truncated: false,
+    }
+  }
+
+  /// Records the names of the steps created, their logs and their final status.
+  fn setup_steps(db: &mut DbHandle, logs: Logs) {
+    let created = logs.clone();
+    faux::when!(db.create_cirun_steps).then(move |(cirun_id, names)| {
+      assert_eq!(cirun_id, 1);
+      created.lock().unwrap().push(names.join(","));
+      Ok(vec![step(1, names[0], StepStatus::Pending)])
+    });
+    faux::when!(db.start_cirun_step).then(|(id, _)| Ok(step(id, "", StepStatus::Running)));
+    let appended = logs.clone();
+    faux::when!(db.append_cirun_step_log).then(move |(step_id, content)| {
+      appended.lock().unwrap().push(content.to_string());
+      Ok(CirunLogChunk {
+        id: 1,
+        step_id,
+        content: content.to_string(),
+      })
+    });
+    faux::when!(db.finish_cirun_step).then(move |(id, status, _, _, _)| {
+      logs.lock().unwrap().push(format!("{:?}", status));
+      Ok(step(id, "", *status))
+    });
+    faux::when!(db.skip_pending_cirun_steps).then(|_| Ok(0));
+  }
+
+  fn worker<F>(setup: F) -> (Worker<ConnectionPool, DbHandle, FakePipeline>, Jobs)
+  where
+    F: Fn(&mut DbHandle) + Send + Sync + 'static,
+  {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.begin_transaction).then(|_| Ok(()));
+      faux::when!(db.commit_transaction).then(|_| Ok(()));
+      setup(&mut db);
+      Ok(db)
+    });
+    let jobs = Arc::new(Mutex::new(Vec::new()));
+    let worker = Worker::new(
+      pool,
+      RepositoryStorage::new(PathBuf::from("repositories")),
+      FakePipeline(jobs.clone()),
+    );
+    (worker, jobs)
+  }
+
+  #[test]
+  fn test_run_next_without_pending_run() {
+    let (worker, jobs) = worker(|db| {
+      faux::when!(db.claim_pending_cirun).then(|_| Ok(None));
+    });
+
+    assert!(worker.run_next().expect("Unable to run").is_none());
+    assert!(jobs.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_run_next_with_missing_repository_fails_the_run() {
+    let logs = Logs::default();
+    let steps = logs.clone();
+    let (worker, jobs) = worker(move |db| {
+      setup_steps(db, steps.clone());
+      faux::when!(db.claim_pending_cirun).then(|_| Ok(Some(claimed_run(Status::Running))));
+      faux::when!(db.get_repository_by_id(2)).then(|_| Ok(None));
+      faux::when!(db.finish_cirun).then(|(id, attempt, status)| {
+        assert_eq!((id, attempt), (1, 1));
+        assert_eq!(*status, Status::Failed);
+        Ok(Some(claimed_run(Status::Failed)))
+      });
+    });
+
+    let cirun = worker.run_next().expect("Unable to run");
+    assert_eq!(cirun.map(|c| c.status), Some(Status::Failed));
+    assert!(jobs.lock().unwrap().is_empty());
+    // The reason of the failure is shown in a setup step
+    assert_eq!(
+      *logs.lock().unwrap(),
+      vec!["setup", "Repository 2 not found\n", "Failed"]
+    );
+  }
+
+  #[test]
+  fn test_run_next_keeps_the_status_of_released_runs() {
+    let (worker, _) = worker(|db| {
+      setup_steps(db, Logs::default());
+      faux::when!(db.claim_pending_cirun).then(|_| Ok(Some(claimed_run(Status::Running))));
+      faux::when!(db.get_repository_by_id(2)).then(|_| Ok(None));
+      // The run was cancelled while it ran
+      faux::when!(db.finish_cirun).then(|_| Ok(None));
+      faux::when!(db.get_cirun_by_id(1)).then(|_| Ok(Some(claimed_run(Status::Cancelled))));
+    });
+
+    let cirun = worker.run_next().expect("Unable to run");
+    assert_eq!(cirun.map(|c| c.status), Some(Status::Cancelled));
+  }
+
+  #[test]
+  fn test_run_next_rolls_back_on_database_error() {
+    let (worker, _) = worker(|db| {
+      setup_steps(db, Logs::default());
+      faux::when!(db.claim_pending_cirun).then(|_| Ok(Some(claimed_run(Status::Running))));
+      faux::when!(db.get_repository_by_id(2)).then(|_| {
+        Ok(Some(Repository {
+          name: "missing".to_string(),
+          ..repository(2, 1, None)
+        }))
+      });
+      faux::when!(db.finish_cirun).then(|_| Err(DatabaseError::NotFound));
+      faux::when!(db.rollback_transaction).once().then(|_| Ok(()));
+    });
+
+    assert!(matches!(
+      worker.run_next(),
+      Err(WorkerError::DatabaseError(DatabaseError::NotFound))
+    ));
+  }
+}
@@ -0,0 +1,712 @@
+//! Runs the worker against the database described in the .env file and local bare repositories.
+
+use std::{
+  path::Path,
+  process::Command,
+  sync::Mutex,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use database::{
+  connection_pool::{ConnectionPool, ConnectionProvider},
+  db_handle::{
+    artifact::ArtifactDbHandle,
+    assignment::AssignmentDbHandle,
+    cirun::{CirunDbHandle, Status},
+    cirun_step::{CirunStepDbHandle, StepStatus},
+    comment::{CommentDbHandle, Commentauthor},
+    grade::{GradeDbHandle, Gradesource},
+    group::{Group, GroupDbHandle},
+    push::PushDbHandle,
+    repository::{Repository, RepositoryDbHandle, Repotype},
+    submission::SubmissionDbHandle,
+    transaction::TransactionDbHandle,
+    user::{User, UserDbHandle},
+  },
+  notifications::{CirunListener, CirunNotification, CIRUN_QUEUE_CHANNEL, CIRUN_STATUS_CHANNEL},
+  DbHandle,
+};
+use gmt_ci_worker::{
+  artifacts::ArtifactLimits,
+  executor::local::{LocalExecutor, ResourceLimits},
+  pipeline::DefinitionPipeline,
+  worker::Worker,
+};
+use gmt_common::{
+  artifact_storage::ArtifactStorage, repositories::repository_storage::RepositoryStorage,
+};
+
+/// The tests share the queue of the database, so they can't run concurrently.
+static QUEUE: Mutex<()> = Mutex::new(());
+
+/// A user owning a repository, removed from the database when dropped.
+struct Fixture {
+  pool: ConnectionPool,
+  dir: tempfile::TempDir,
+  user: User,
+  repository: Repository,
+  /// The group and the base repository of the assignment of the repository, if any
+  assignment: Option<(Group, Repository)>,
+  /// The CI repository of the assignment, if it has one
+  ci_repository: Option<Repository>,
+}
+
+impl Fixture {
+  fn new() -> Self {
+    Self::create(false, false)
+  }
+
+  /// Creates the repository as the submission repository of an assignment, which has a CI
+  /// repository if `with_ci` is set.
+  fn with_assignment(with_ci: bool) -> Self {
+    Self::create(true, with_ci)
+  }
+
+  fn create(with_assignment: bool, with_ci: bool) -> Self {
+    dotenvy::dotenv().ok();
+    let pool = ConnectionPool::new_from_env().expect("Unable to connect to the database");
+    pool.run_migrations().expect("Unable to run migrations");
+
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_nanos();
+    let name = format!("ci-worker-{}-{}", std::process::id(), nanos);
+    let mut db = pool.get_connection().unwrap();
+    let user = db
+      .create_user(&name, &format!("{}@test.com", name), "password", None)
+      .unwrap();
+    let ci_repository = with_ci.then(|| {
+      db.create_repository(&format!("{}-ci", name), &Repotype::Ci, user.id, None)
+        .unwrap()
+    });
+    let assignment = with_assignment.then(|| {
+      let group = db.create_group(&name, Some(user.id)).unwrap();
+      let base = db
+        .create_repository(&format!("{}-base", name), &Repotype::Default, user.id, None)
+        .unwrap();
+      let assignment = match &ci_repository {
+        Some(ci) => db.create_assignment_with_ci(group.id, base.id, ci.id),
+        None => db.create_assignment(group.id, base.id),
+      }
+      .unwrap();
+      (group, base, assignment.id)
+    });
+    let repository = db
+      .create_repository(
+        &name,
+        &Repotype::Default,
+        user.id,
+        assignment.as_ref().map(|(_, _, id)| *id),
+      )
+      .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    storage.create_repository(&name).unwrap();
+    if let Some(ci) = &ci_repository {
+      storage.create_repository(&ci.name).unwrap();
+    }
+
+    Fixture {
+      pool,
+      dir,
+      user,
+      repository,
+      assignment: assignment.map(|(group, base, _)| (group, base)),
+      ci_repository,
+    }
+  }
+
+  fn storage(&self) -> RepositoryStorage {
+    RepositoryStorage::new(self.dir.path().to_path_buf())
+  }
+
+  /// Commits the files on top of the previous commit, returning the id of the new commit.
+  fn commit(&self, files: &[(&str, &str)]) -> String {
+    self.commit_to(&self.repository.name, files)
+  }
+
+  /// Commits the files to the CI repository of the assignment.
+  fn commit_ci(&self, files: &[(&str, &str)]) -> String {
+    let ci = self.ci_repository.as_ref().expect("No CI repository");
+    self.commit_to(&ci.name, files)
+  }
+
+  fn commit_to(&self, repository: &str, files: &[(&str, &str)]) -> String {
+    let work_tree = self.dir.path().join(format!("work-{}", repository));
+    std::fs::create_dir_all(&work_tree).unwrap();
+    for (path, content) in files {
+      std::fs::write(work_tree.join(path), content).unwrap();
+    }
+    let git_dir = self.storage().get_path(repository);
+    let git = |args: &[&str]| git(&git_dir, &work_tree, args);
+    git(&["add", "--all"]);
+    git(&["commit", "--quiet", "-m", "Commit"]);
+    git(&["rev-parse", "HEAD"])
+  }
+}
+
+impl Drop for Fixture {
+  fn drop(&mut self) {
+    let mut db = self.pool.get_connection().unwrap();
+    db.delete_repository(self.repository.id);
+    if let Some((group, base)) = &self.assignment {
+      db.delete_group(group.id);
+      db.delete_repository(base.id);
+    }
+    if let Some(ci) = &self.ci_repository {
+      db.delete_repository(ci.id);
+    }
+    db.delete_user(self.user.id).ok();
+  }
+}
+
+fn git(git_dir: &Path, work_tree: &Path, args: &[&str]) -> String {
+  let output = Command::new("git")
+    .arg("--git-dir")
+    .arg(git_dir)
+    .arg("--work-tree")
+    .arg(work_tree)
+    .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+    .args(args)
+    .output()
+    .expect("Unable to run git");
+  assert!(output.status.success(), "git {:?} failed", args);
+  String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Runs the jobs of the fixture until the run isn't pending anymore.
+fn run_pending(db: &mut DbHandle, fixture: &Fixture, cirun_id: i32) {
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default())),
+  );
+  for _ in 0..100 {
+    if db.get_cirun_by_id(cirun_id).unwrap().unwrap().status != Status::Pending {
+      break;
+    }
+    worker.run_next().expect("Unable to run the next job");
+  }
+}
+
+/// A pipeline giving full marks to whoever runs it.
+fn self_graded_pipeline() -> String {
+  "version = 1\n[[steps]]\nname = \"test\"\nrun = \"echo 'ok 1 - all' > report.tap\"\n\
+   [[reports]]\npath = \"report.tap\"\nformat = \"tap\"\n\
+   [[scoring.groups]]\nname = \"All\"\ntests = [\"*\"]"
+    .to_string()
+}
+
+#[test]
+fn test_worker_runs_pending_ciruns() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let pipeline = |run: &str| format!("version = 1\n[[steps]]\nname = \"test\"\nrun = \"{}\"", run);
+  let passing = fixture.commit(&[
+    ("gmt-ci.toml", &pipeline("cat answer.txt")),
+    ("answer.txt", "42"),
+  ]);
+  let failing = fixture.commit(&[("gmt-ci.toml", &pipeline("exit 1"))]);
+  let unknown = "0".repeat(40);
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  let ids: Vec<i32> = [&passing, &failing, &unknown]
+    .iter()
+    .map(|commit| db.create_cirun(fixture.repository.id, commit).unwrap().id)
+    .collect();
+  // Only the pending runs are processed
+  let cancelled = db
+    .create_cirun_with_status(fixture.repository.id, &passing, &Status::Cancelled)
+    .unwrap();
+
+  let scratch = tempfile::tempdir().unwrap();
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default())),
+  )
+  .with_scratch_root(scratch.path().to_path_buf());
+
+  let statuses = |db: &mut DbHandle| -> Vec<Status> {
+    ids
+      .iter()
+      .map(|id| db.get_cirun_by_id(*id).unwrap().unwrap().status)
+      .collect()
+  };
+  // Other pending runs of the database may be processed along the way
+  for _ in 0..100 {
+    if !statuses(&mut db).contains(&Status::Pending) {
+      break;
+    }
+    worker.run_next().expect("Unable to run the next job");
+  }
+
+  assert_eq!(
+    statuses(&mut db),
+    vec![Status::Success, Status::Failed, Status::Failed]
+  );
+  let cancelled = db.get_cirun_by_id(cancelled.id).unwrap().unwrap();
+  assert_eq!(cancelled.status, Status::Cancelled);
+  for id in &ids {
+    let cirun = db.get_cirun_by_id(*id).unwrap().unwrap();
+    assert_eq!(cirun.attempt, 1);
+    assert!(cirun.worker_id.is_some());
+    assert!(cirun.started_at.is_some() && cirun.finished_at >= cirun.started_at);
+  }
+
+  // The steps are recorded along with their log
+  let steps: Vec<_> = ids
+    .iter()
+    .map(|id| {
+      let steps = db.list_cirun_steps(*id).unwrap();
+      assert_eq!(steps.len(), 1);
+      let log = db.get_cirun_step_log(steps[0].id).unwrap();
+      (
+        steps[0].name.clone(),
+        steps[0].status,
+        steps[0].exit_code,
+        log,
+      )
+    })
+    .collect();
+  assert_eq!(
+    steps[0],
+    ("test".into(), StepStatus::Success, Some(0), "42".into())
+  );
+  assert_eq!(
+    steps[1],
+    ("test".into(), StepStatus::Failed, Some(1), "".into())
+  );
+  assert_eq!(steps[2].0, "setup");
+  assert_eq!(steps[2].1, StepStatus::Failed);
+  assert!(steps[2].3.starts_with("Unable to check out the commit"));
+  // The checkouts are cleaned up
+  assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_worker_posts_test_reports() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let tap = "1..2\nok 1 - builds\nnot ok 2 - answer\n  ---\n  message: expected 42\n  at: answer.py:2\n  ...\n";
+  let gmt = r#"{ "findings": [
+    { "file": "answer.py", "rule": "style", "message": "missing docstring" },
+    { "file": "generated.py", "line": 1, "message": "generated file" }
+  ] }"#;
+  let commit = fixture.commit(&[
+    (
+      "gmt-ci.toml",
+      "version = 1\n[[steps]]\nname = \"test\"\nrun = \"sh test.sh\"\n\
+       [[reports]]\npath = \"report.tap\"\nformat = \"tap\"\n\
+       [[reports]]\npath = \"lint.json\"\nformat = \"gmt\"",
+    ),
+    (
+      "test.sh",
+      &format!(
+        "printf '{}' > report.tap\ncat > lint.json <<EOF\n{}\nEOF\nexit 1",
+        tap, gmt
+      ),
+    ),
+    ("answer.py", "def answer():\n  return 41\n"),
+  ]);
+
+  // The second run of the commit doesn't comment the same problems again
+  let mut db = fixture.pool.get_connection().unwrap();
+  let ids: Vec<i32> = (0..2)
+    .map(|_| db.create_cirun(fixture.repository.id, &commit).unwrap().id)
+    .collect();
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default())),
+  );
+  for _ in 0..100 {
+    let pending = ids
+      .iter()
+      .any(|id| db.get_cirun_by_id(*id).unwrap().unwrap().status == Status::Pending);
+    if !pending {
+      break;
+    }
+    worker.run_next().expect("Unable to run the next job");
+  }
+
+  let comments = db
+    .list_commit_comments(fixture.repository.id, &commit, None)
+    .unwrap();
+  assert!(comments
+    .iter()
+    .all(|c| c.author_type == Commentauthor::Automated));
+  let summaries: Vec<_> = comments.iter().filter(|c| c.file_path.is_none()).collect();
+  assert_eq!(summaries.len(), 2);
+  assert!(
+    summaries[0].message.starts_with(&format!(
+      "CI run #{} failed\n\n- `report.tap`: 2 tests, 1 passed, 1 failed\n- `lint.json`: 0 tests, 2 findings",
+      ids[0]
+    )),
+    "{}",
+    summaries[0].message
+  );
+  assert!(summaries[0]
+    .message
+    .contains("- Warning: generated file (`generated.py:1`)"));
+
+  // The failing test points to its line, the finding without a line to its file, and the finding
+  // on a file which isn't part of the commit is only listed in the summary
+  let mut files: Vec<_> = comments
+    .iter()
+    .filter_map(|c| Some((c.file_path.as_deref()?, c.start_line, c.message.as_str())))
+    .collect();
+  files.sort();
+  assert_eq!(
+    files,
+    vec![
+      ("answer.py", None, "Warning `style`: missing docstring"),
+      (
+        "answer.py",
+        Some(2),
+        "Test `answer` failed\n\n```\nexpected 42\n```"
+      ),
+    ]
+  );
+}
+
+#[test]
+fn test_worker_grades_submissions() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::with_assignment(true);
+
+  fixture.commit_ci(&[(
+    "gmt-ci.toml",
+    "version = 1\n[[steps]]\nname = \"test\"\nrun = \"sh test.sh\"\n\
+     [[reports]]\npath = \"report.tap\"\nformat = \"tap\"\n\
+     [[scoring.groups]]\nname = \"Basics\"\ntests = [\"basics *\"]\nweight = 4\n\
+     [[scoring.groups]]\nname = \"Bonus\"\ntests = [\"bonus\"]",
+  )]);
+  let commit = fixture.commit(&[
+    // Ignored, the pipeline comes from the CI repository
+    ("gmt-ci.toml", &self_graded_pipeline()),
+    (
+      "test.sh",
+      "printf 'ok 1 - basics add\\nok 2 - basics sub\\nnot ok 3 - basics div\\nnot ok 4 - bonus\\n' > report.tap",
+    ),
+  ]);
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  // The server recorded the push as late, long before the run
+  db.create_push_record(
+    fixture.repository.id,
+    Some(fixture.user.id),
+    "refs/heads/main",
+    &commit,
+    UNIX_EPOCH,
+    true,
+  )
+  .unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, &commit).unwrap();
+  run_pending(&mut db, &fixture, cirun.id);
+
+  // The commit is submitted by the CI, and graded with the score of the run
+  let submission = db
+    .get_submission_by_commit(fixture.repository.id, &commit)
+    .unwrap()
+    .expect("The commit wasn't submitted");
+  assert!(submission.late);
+  assert!(submission.pushed_at.is_some());
+  let grade = db
+    .get_cirun_grade(cirun.id)
+    .unwrap()
+    .expect("The run didn't grade the submission");
+  assert_eq!(grade.submission_id, submission.id);
+  assert_eq!(grade.source, Gradesource::Automated);
+  assert_eq!((grade.score, grade.max_score), (2.67, 5.0));
+  let rubric: Vec<_> = db
+    .list_grade_criteria(grade.id)
+    .unwrap()
+    .into_iter()
+    .map(|c| (c.name, c.score, c.max_score))
+    .collect();
+  assert_eq!(
+    rubric,
+    vec![
+      ("Basics".to_string(), 2.67, 4.0),
+      ("Bonus".to_string(), 0.0, 1.0)
+    ]
+  );
+  assert_eq!(
+    db.get_latest_ci_grade(fixture.repository.id).unwrap(),
+    Some(grade)
+  );
+
+  let comments = db
+    .list_commit_comments(fixture.repository.id, &commit, None)
+    .unwrap();
+  assert!(comments[0]
+    .message
+    .contains("\n\nScore: 2.67/5\n- Basics: 2.67/4, 2/3 tests passed"));
+}
+
+#[test]
+fn test_worker_refuses_self_graded_submissions() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  // Without a CI repository, the pipeline of the student runs but can't grade
+  let fixture = Fixture::with_assignment(false);
+  let commit = fixture.commit(&[("gmt-ci.toml", &self_graded_pipeline())]);
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, &commit).unwrap();
+  run_pending(&mut db, &fixture, cirun.id);
+
+  let cirun = db.get_cirun_by_id(cirun.id).unwrap().unwrap();
+  assert_eq!(cirun.status, Status::Success);
+  assert!(db.get_cirun_grade(cirun.id).unwrap().is_none());
+  assert!(db
+    .get_submission_by_commit(fixture.repository.id, &commit)
+    .unwrap()
+    .is_none());
+}
+
+#[test]
+fn test_worker_keeps_artifacts() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let commit = fixture.commit(&[
+    (
+      "gmt-ci.toml",
+      "version = 1\n[[steps]]\nname = \"build\"\nrun = \"sh build.sh\"\n\
+       [[artifacts]]\nname = \"binary\"\npath = \"main\"\nexpire_in = 7\n\
+       [[artifacts]]\nname = \"coverage\"\npath = \"coverage\"\n\
+       [[artifacts]]\nname = \"core\"\npath = \"core\"\nwhen = \"on_failure\"",
+    ),
+    (
+      "build.sh",
+      "printf 'binary' > main\nmkdir coverage\nprintf '<html>' > coverage/index.html\ntouch core",
+    ),
+  ]);
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, &commit).unwrap();
+  let storage = ArtifactStorage::new(fixture.dir.path().join("artifacts"));
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default()))
+      .with_artifacts(storage.clone(), ArtifactLimits::default()),
+  )
+  .with_artifacts(storage.clone());
+  for _ in 0..100 {
+    if db.get_cirun_by_id(cirun.id).unwrap().unwrap().status != Status::Pending {
+      break;
+    }
+    worker.run_next().expect("Unable to run the next job");
+  }
+
+  let artifacts = db.list_cirun_artifacts(cirun.id).unwrap();
+  let files: Vec<_> = artifacts
+    .iter()
+    .map(|a| (a.name.as_str(), a.file_name.as_str()))
+    .collect();
+  assert_eq!(
+    files,
+    vec![("binary", "binary"), ("coverage", "coverage.tar.gz")]
+  );
+  let binary = &artifacts[0];
+  assert_eq!(binary.size, 6);
+  let kept_for = binary.expires_at.duration_since(binary.created_at).unwrap();
+  assert!(kept_for.abs_diff(Duration::from_secs(7 * 24 * 3600)) < Duration::from_secs(60));
+  let path = storage.get_path(&binary.content_hash).unwrap();
+  assert_eq!(std::fs::read_to_string(&path).unwrap(), "binary");
+
+  // The expired artifacts are deleted, along with the files no artifact refers to anymore
+  let file = fixture.dir.path().join("expired");
+  std::fs::write(&file, "expired").unwrap();
+  let (hash, size) = storage.store(&file).unwrap();
+  let expired = db
+    .create_artifact(
+      cirun.id,
+      "expired",
+      "expired",
+      &hash,
+      size as i64,
+      SystemTime::now() - Duration::from_secs(1),
+    )
+    .unwrap();
+  let old = SystemTime::now() - Duration::from_secs(2 * 24 * 3600);
+  for path in [storage.get_path(&hash).unwrap(), path.clone()] {
+    std::fs::File::options()
+      .write(true)
+      .open(path)
+      .unwrap()
+      .set_modified(old)
+      .unwrap();
+  }
+
+  assert_eq!(worker.purge_artifacts().expect("Unable to purge"), 1);
+  assert!(db.get_artifact_by_id(expired.id).unwrap().is_none());
+  assert!(!storage.get_path(&hash).unwrap().exists());
+  assert!(path.exists());
+}
+
+#[test]
+fn test_workers_claim_distinct_ciruns() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  db.create_cirun(fixture.repository.id, "first").unwrap();
+  db.create_cirun(fixture.repository.id, "second").unwrap();
+
+  let mut first_worker = fixture.pool.get_connection().unwrap();
+  let mut second_worker = fixture.pool.get_connection().unwrap();
+  first_worker.begin_transaction().unwrap();
+  second_worker.begin_transaction().unwrap();
+
+  // A run being claimed is skipped by the other workers
+  let first = first_worker.claim_pending_cirun("first").unwrap().unwrap();
+  let second = second_worker
+    .claim_pending_cirun("second")
+    .unwrap()
+    .unwrap();
+  assert_ne!(first.id, second.id);
+
+  // Once the claim is rolled back, the run can be claimed again
+  first_worker.rollback_transaction().unwrap();
+  first_worker.begin_transaction().unwrap();
+  let again = first_worker.claim_pending_cirun("first").unwrap().unwrap();
+  assert_eq!(again.id, first.id);
+  first_worker.rollback_transaction().unwrap();
+  second_worker.rollback_transaction().unwrap();
+}
+
+#[test]
+fn test_worker_reaps_the_runs_of_dead_workers() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let commit = fixture.commit(&[(
+    "gmt-ci.toml",
+    "version = 1\n[[steps]]\nname = \"test\"\nrun = \"true\"",
+  )]);
+  let mut db = fixture.pool.get_connection().unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, &commit).unwrap();
+
+  // A worker claims the run, then dies
+  let claimed = db.claim_pending_cirun("dead").unwrap().unwrap();
+  assert_eq!(claimed.id, cirun.id);
+
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default())),
+  )
+  .with_id("alive".to_string())
+  .with_heartbeat_timeout(Duration::from_millis(400));
+  assert!(worker.reap().unwrap().is_empty());
+  std::thread::sleep(Duration::from_millis(500));
+  let reaped = worker.reap().unwrap();
+  assert_eq!(
+    reaped.iter().map(|c| c.id).collect::<Vec<_>>(),
+    vec![cirun.id]
+  );
+  assert_eq!(reaped[0].status, Status::Pending);
+
+  let cirun = worker
+    .run_next()
+    .unwrap()
+    .expect("The run should be pending");
+  assert_eq!(cirun.status, Status::Success);
+  assert_eq!(cirun.worker_id.as_deref(), Some("alive"));
+  assert_eq!(cirun.attempt, 2);
+}
+
+#[test]
+fn test_worker_stops_cancelled_runs() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let commit = fixture.commit(&[(
+    "gmt-ci.toml",
+    "version = 1\n[[steps]]\nname = \"slow\"\nrun = \"sleep 1\"\n\
+     [[steps]]\nname = \"next\"\nrun = \"true\"",
+  )]);
+  let mut db = fixture.pool.get_connection().unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, &commit).unwrap();
+
+  let worker = Worker::new(
+    ConnectionPool::new_from_env().unwrap(),
+    fixture.storage(),
+    DefinitionPipeline::new(LocalExecutor::new(ResourceLimits::default())),
+  )
+  .with_heartbeat_timeout(Duration::from_millis(200));
+  let canceller = std::thread::spawn(move || {
+    for _ in 0..100 {
+      if db.get_cirun_by_id(cirun.id).unwrap().unwrap().status == Status::Running {
+        return db.cancel_cirun(cirun.id).unwrap();
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    None
+  });
+
+  let finished = worker
+    .run_next()
+    .unwrap()
+    .expect("The run should be pending");
+  let cancelled = canceller.join().unwrap().expect("The run wasn't cancelled");
+  assert_eq!(cancelled.id, finished.id);
+  // The worker doesn't override the status of the run, and skips the next steps
+  assert_eq!(finished.status, Status::Cancelled);
+  let mut db = fixture.pool.get_connection().unwrap();
+  let steps: Vec<_> = db
+    .list_cirun_steps(finished.id)
+    .unwrap()
+    .into_iter()
+    .map(|step| step.status)
+    .collect();
+  assert_eq!(steps, vec![StepStatus::Success, StepStatus::Skipped]);
+}
+
+#[test]
+fn test_ciruns_notify_their_changes() {
+  let _queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  let fixture = Fixture::new();
+
+  let database_url = std::env::var("DATABASE_URL").unwrap();
+  let mut listener =
+    CirunListener::connect(&database_url, &[CIRUN_QUEUE_CHANNEL, CIRUN_STATUS_CHANNEL]).unwrap();
+
+  let mut db = fixture.pool.get_connection().unwrap();
+  let cirun = db.create_cirun(fixture.repository.id, "commit").unwrap();
+  db.claim_pending_cirun("worker").unwrap().unwrap();
+  // Heartbeats don't change the status
+  db.heartbeat_cirun(cirun.id, 1).unwrap();
+  db.finish_cirun(cirun.id, 1, &Status::Failed).unwrap();
+  db.requeue_cirun(cirun.id).unwrap();
+
+  let status = |status| CirunNotification::StatusChanged {
+    cirun_id: cirun.id,
+    repository_id: fixture.repository.id,
+    status,
+  };
+  let queued = CirunNotification::Queued { cirun_id: cirun.id };
+  let expected = vec![
+    queued.clone(),
+    status(Status::Pending),
+    status(Status::Running),
+    status(Status::Failed),
+    queued,
+    status(Status::Pending),
+  ];
+  let mut received = Vec::new();
+  for _ in 0..50 {
+    received.extend(listener.wait(Duration::from_millis(100)).unwrap());
+    if received.len() >= expected.len() {
+      break;
+    }
+  }
+  assert_eq!(received, expected);
+}
@@ -0,0 +1,198 @@
+//! The artifacts of the CI runs, stored on the filesystem under the SHA-256 of their content.
+//!
+//! The same file kept by several runs is only stored once. The blobs are never removed while an
+//! artifact refers to them, [`ArtifactStorage::collect_garbage`] removing the others.
+
+use std::{
+  collections::HashSet,
+  fs::File,
+  io::{Error, ErrorKind, Read, Write},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+const DEFAULT_ARTIFACTS_ROOT: &str = "artifacts";
+/// Files being stored, which are never collected
+const TEMPORARY_PREFIX: &str = "tmp-";
+
+/// Checks that a hash is the lowercase hexadecimal SHA-256 of a blob.
+pub fn is_valid_content_hash(hash: &str) -> bool {
+  hash.len() == 64 && hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+#[derive(Clone)]
+pub struct ArtifactStorage {
+  root: PathBuf,
+}
+
+impl ArtifactStorage {
+  pub fn new(root: PathBuf) -> Self {
+    ArtifactStorage { root }
+  }
+
+  /// Uses the `ARTIFACTS_ROOT` environment variable, defaulting to `./artifacts`.
+  pub fn new_from_env() -> Self {
+    let root =
+      std::env::var("ARTIFACTS_ROOT").unwrap_or_else(|_| DEFAULT_ARTIFACTS_ROOT.to_string());
+    ArtifactStorage::new(PathBuf::from(root))
+  }
+
+  /// Returns the path of the blob on disk, whether it exists or not. Returns `None` if the hash is
+  /// invalid.
+  pub fn get_path(&self, content_hash: &str) -> Option<PathBuf> {
+    if !is_valid_content_hash(content_hash) {
+      return None;
+    }
+    Some(self.root.join(&content_hash[..2]).join(content_hash))
+  }
+
+  /// Copies the file into the storage, returning the hash and the size of its content.
+  pub fn store(&self, file: &Path) -> Result<(String, u64), Error> {
+    std::fs::create_dir_all(&self.root)?;
+    let temporary = self.root.join(format!(
+      "{}{:016x}",
+      TEMPORARY_PREFIX,
+      rand::random::<u64>()
+    ));
+    let result = self.copy_hashed(file, &temporary).and_then(|(hash, size)| {
+      let path = self
+        .get_path(&hash)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid content hash"))?;
+      std::fs::create_dir_all(path.parent().unwrap_or(&self.root))?;
+      // Replacing an existing blob is harmless, their content being the same
+      std::fs::rename(&temporary, &path)?;
+      Ok((hash, size))
+    });
+    if result.is_err() {
+      std::fs::remove_file(&temporary).ok();
+    }
+    result
+  }
+
+  fn copy_hashed(&self, source: &Path, destination: &Path) -> Result<(String, u64), Error> {
+    let mut source = File::open(source)?;
+    let mut destination = File::create(destination)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+    loop {
+      let read = source.read(&mut buffer)?;
+      if read == 0 {
+        break;
+      }
+      hasher.update(&buffer[..read]);
+      destination.write_all(&buffer[..read])?;
+      size += read as u64;
+    }
+    destination.sync_all()?;
+    let hash = hasher
+      .finalize()
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect();
+    Ok((hash, size))
+  }
+
+  /// Removes the blobs which aren't in `used`, unless they were stored less than `grace` ago and
+  /// may belong to a run which isn't recorded yet. Returns the number of blobs removed.
+  pub fn collect_garbage(&self, used: &HashSet<String>, grace: Duration) -> Result<usize, Error> {
+    let entries = match std::fs::read_dir(&self.root) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+      Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries {
+      let entry = entry?;
+      if !entry.file_type()?.is_dir() {
+        continue;
+      }
+      for blob in std::fs::read_dir(entry.path())? {
+        let blob = blob?;
+        let name = blob.file_name().to_string_lossy().to_string();
+        if !is_valid_content_hash(&name) || used.contains(&name) {
+          continue;
+        }
+        let age = blob
+          .metadata()?
+          .modified()
+          .ok()
+          .and_then(|modified| now.duration_since(modified).ok())
+          .unwrap_or_default();
+        if age < grace {
+          continue;
+        }
+        // Another worker may be collecting the same blob
+        match std::fs::remove_file(blob.path()) {
+          Ok(()) => removed += 1,
+          Err(e) if e.kind() == ErrorKind::NotFound => {}
+          Err(e) => return Err(e),
+        }
+      }
+    }
+    Ok(removed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HELLO_HASH: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+  #[test]
+  fn test_store_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = ArtifactStorage::new(dir.path().join("artifacts"));
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, "hello").unwrap();
+
+    assert_eq!(storage.store(&file).unwrap(), (HELLO_HASH.to_string(), 5));
+    // Stored once
+    assert_eq!(storage.store(&file).unwrap(), (HELLO_HASH.to_string(), 5));
+    let path = storage.get_path(HELLO_HASH).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+    assert_eq!(
+      std::fs::read_dir(dir.path().join("artifacts"))
+        .unwrap()
+        .count(),
+      1
+    );
+
+    assert!(storage.store(&dir.path().join("missing")).is_err());
+    assert_eq!(storage.get_path("../hello.txt"), None);
+  }
+
+  #[test]
+  fn test_collect_garbage() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = ArtifactStorage::new(dir.path().to_path_buf());
+    assert_eq!(
+      storage
+        .collect_garbage(&HashSet::new(), Duration::ZERO)
+        .unwrap(),
+      0
+    );
+
+    let file = dir.path().join("file");
+    std::fs::write(&file, "hello").unwrap();
+    storage.store(&file).unwrap();
+    std::fs::write(&file, "unused").unwrap();
+    let (unused, _) = storage.store(&file).unwrap();
+
+    let used = HashSet::from([HELLO_HASH.to_string()]);
+    // Too recent
+    assert_eq!(
+      storage
+        .collect_garbage(&used, Duration::from_secs(3600))
+        .unwrap(),
+      0
+    );
+    assert_eq!(storage.collect_garbage(&used, Duration::ZERO).unwrap(), 1);
+    assert!(storage.get_path(HELLO_HASH).unwrap().exists());
+    assert!(!storage.get_path(&unused).unwrap().exists());
+  }
+}
@@ -0,0 +1,959 @@
+//! The definition of the CI pipeline of an assignment, read from the `gmt-ci.toml` file of its CI
+//! repository.
+//!
+//! ```toml
+//! version = 1
+//! # Maximum duration of the whole pipeline, in seconds
+//! timeout = 600
+//! # Files and directories of the CI repository copied over the student checkout
+//! overlay = ["tests", "Makefile"]
+//!
+//! [env]
+//! LANG = "C.UTF-8"
+//!
+//! [[steps]]
+//! name = "build"
+//! run = "make"
+//!
+//! [[steps]]
+//! name = "test"
+//! run = "make test"
+//! timeout = 120
+//! env = { VERBOSE = "1" }
+//!
+//! # Test reports written by the steps, posted as comments on the commit
+//! [[reports]]
+//! path = "target/junit.xml"
+//! format = "junit"
+//!
+//! # Files kept once the steps ran, which can be downloaded from the API. Directories are archived
+//! [[artifacts]]
+//! name = "coverage"
+//! path = "target/coverage"
+//! # Whether to keep it `always`, `on_success` or `on_failure` of the steps, defaults to always
+//! when = "on_success"
+//! # How long the artifact is kept, in days
+//! expire_in = 7
+//!
+//! # Grades the submissions with weighted groups of tests, or with `file = "score.json"`
+//! [[scoring.groups]]
+//! name = "Basics"
+//! tests = ["tests.test_basics.*"]
+//! weight = 2
+//! ```
+//!
+//! Validation reports every problem of the file at once, each with the line it was found on, so
+//! that teachers can fix them in one go.
+
+use std::{
+  collections::{BTreeMap, HashSet},
+  fmt::Display,
+  ops::Range,
+  path::{Component, PathBuf},
+  time::Duration,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+/// The file holding the pipeline, at the root of the CI repository.
+pub const PIPELINE_FILE: &str = "gmt-ci.toml";
+
+/// The versions of the format this module understands.
+pub const SUPPORTED_VERSIONS: &[i64] = &[1];
+
+pub const DEFAULT_PIPELINE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+pub const MAX_PIPELINE_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+pub const DEFAULT_ARTIFACT_EXPIRY: Duration = Duration::from_secs(30 * 24 * 3600);
+pub const MAX_ARTIFACT_EXPIRY: Duration = Duration::from_secs(365 * 24 * 3600);
+
+const MAX_STEP_NAME_LENGTH: usize = 64;
+const MAX_GROUP_NAME_LENGTH: usize = 255;
+const MAX_ARTIFACT_NAME_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineDefinition {
+  pub version: i64,
+  /// Maximum duration of the whole pipeline
+  pub timeout: Duration,
+  /// Paths of the CI repository copied over the student checkout before running the steps
+  pub overlay: Vec<PathBuf>,
+  /// Environment variables set for every step
+  pub env: BTreeMap<String, String>,
+  pub steps: Vec<StepDefinition>,
+  /// Test reports read from the checkout once the steps ran
+  pub reports: Vec<ReportDefinition>,
+  /// Files and directories of the checkout kept once the steps ran
+  pub artifacts: Vec<ArtifactDefinition>,
+  /// How the submissions are graded, if they are
+  pub scoring: Option<Scoring>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepDefinition {
+  pub name: String,
+  /// The shell command of the step
+  pub run: String,
+  pub timeout: Duration,
+  /// Environment variables of the step, overriding the ones of the pipeline
+  pub env: BTreeMap<String, String>,
+}
+
+/// The formats of test reports the worker understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+  /// JUnit XML, as written by most test runners
+  Junit,
+  /// Test Anything Protocol
+  Tap,
+  /// The JSON format of gmt, which can also hold linter findings
+  Gmt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportDefinition {
+  /// The path of the report, relative to the checkout
+  pub path: PathBuf,
+  pub format: ReportFormat,
+}
+
+/// The outcomes of the steps after which an artifact is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactWhen {
+  #[default]
+  Always,
+  OnSuccess,
+  OnFailure,
+}
+
+impl ArtifactWhen {
+  /// Whether the artifact is kept when the steps did or didn't succeed
+  pub fn applies(&self, succeeded: bool) -> bool {
+    match self {
+      ArtifactWhen::Always => true,
+      ArtifactWhen::OnSuccess => succeeded,
+      ArtifactWhen::OnFailure => !succeeded,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactDefinition {
+  /// The name of the artifact, which can be used as a file name
+  pub name: String,
+  /// The file or directory kept, relative to the checkout
+  pub path: PathBuf,
+  pub when: ArtifactWhen,
+  /// How long the artifact is kept after the run
+  pub expire_in: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scoring {
+  /// Weighted groups of tests, each worth its weight times the share of its tests which passed
+  Groups(Vec<ScoreGroup>),
+  /// A JSON file written by the steps, relative to the checkout
+  File(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreGroup {
+  pub name: String,
+  /// Patterns matching the names of the tests in the reports, `*` matching any characters
+  pub tests: Vec<String>,
+  pub weight: f64,
+}
+
+/// A problem found in the pipeline file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineError {
+  /// The line and column the problem was found on, starting at 1
+  pub position: Option<(usize, usize)>,
+  pub message: String,
+}
+
+impl Display for PipelineError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.position {
+      Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+      None => write!(f, "{}", self.message),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct RawVersion {
+  version: Option<Spanned<toml::Value>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPipeline {
+  /// Checked beforehand
+  #[serde(rename = "version")]
+  _version: toml::Value,
+  timeout: Option<Spanned<i64>>,
+  #[serde(default)]
+  overlay: Vec<Spanned<String>>,
+  #[serde(default)]
+  env: BTreeMap<Spanned<String>, String>,
+  #[serde(default)]
+  steps: Vec<RawStep>,
+  #[serde(default)]
+  reports: Vec<RawReport>,
+  #[serde(default)]
+  artifacts: Vec<RawArtifact>,
+  scoring: Option<Spanned<RawScoring>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStep {
+  name: Spanned<String>,
+  run: Spanned<String>,
+  timeout: Option<Spanned<i64>>,
+  #[serde(default)]
+  env: BTreeMap<Spanned<String>, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawReport {
+  path: Spanned<String>,
+  format: ReportFormat,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawArtifact {
+  name: Spanned<String>,
+  path: Spanned<String>,
+  #[serde(default)]
+  when: ArtifactWhen,
+  expire_in: Option<Spanned<i64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawScoring {
+  file: Option<Spanned<String>>,
+  #[serde(default)]
+  groups: Vec<RawScoreGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawScoreGroup {
+  name: Spanned<String>,
+  tests: Spanned<Vec<String>>,
+  weight: Option<Spanned<f64>>,
+}
+
+/// Collects the problems of the file, along with their position.
+struct Validator<'a> {
+  content: &'a str,
+  errors: Vec<PipelineError>,
+}
+
+impl Validator<'_> {
+  fn position(&self, offset: usize) -> (usize, usize) {
+    let before = &self.content[..offset.min(self.content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+  }
+
+  fn error(&mut self, span: Option<Range<usize>>, message: String) {
+    let position = span.map(|span| self.position(span.start));
+    self.errors.push(PipelineError { position, message });
+  }
+
+  /// Checks a timeout in seconds, returning it as a duration.
+  fn timeout(&mut self, timeout: &Spanned<i64>, max: Duration) -> Duration {
+    let seconds = *timeout.get_ref();
+    if seconds <= 0 || seconds as u64 > max.as_secs() {
+      self.error(
+        Some(timeout.span()),
+        format!(
+          "The timeout must be between 1 and {} seconds, got {}",
+          max.as_secs(),
+          seconds
+        ),
+      );
+      return max;
+    }
+    Duration::from_secs(seconds as u64)
+  }
+
+  fn env(&mut self, env: BTreeMap<Spanned<String>, String>) -> BTreeMap<String, String> {
+    env
+      .into_iter()
+      .filter_map(|(name, value)| {
+        let valid = name
+          .get_ref()
+          .chars()
+          .enumerate()
+          .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        if !valid || name.get_ref().is_empty() {
+          self.error(
+            Some(name.span()),
+            format!(
+              "Invalid environment variable name `{}`, only letters, digits and underscores are allowed",
+              name.get_ref()
+            ),
+          );
+          return None;
+        }
+        Some((name.into_inner(), value))
+      })
+      .collect()
+  }
+
+  /// Checks that the path stays inside the repository, `kind` and `root` describing it in errors.
+  fn relative_path(&mut self, path: Spanned<String>, kind: &str, root: &str) -> Option<PathBuf> {
+    let span = path.span();
+    let path = PathBuf::from(path.into_inner());
+    let escapes = path
+      .components()
+      .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir) || c.as_os_str() == ".git");
+    if path.as_os_str().is_empty() || escapes {
+      self.error(
+        Some(span),
+        format!(
+          "Invalid {} path `{}`, paths must be relative to the {} and stay inside it",
+          kind,
+          path.display(),
+          root
+        ),
+      );
+      return None;
+    }
+    Some(path)
+  }
+
+  fn step(
+    &mut self,
+    step: RawStep,
+    names: &mut HashSet<String>,
+    max_timeout: Duration,
+  ) -> Option<StepDefinition> {
+    let errors = self.errors.len();
+
+    let name = step.name.get_ref().trim();
+    if name.is_empty() || name.len() > MAX_STEP_NAME_LENGTH {
+      self.error(
+        Some(step.name.span()),
+        format!(
+          "Step names must be between 1 and {} characters long",
+          MAX_STEP_NAME_LENGTH
+        ),
+      );
+    } else if !names.insert(name.to_string()) {
+      self.error(
+        Some(step.name.span()),
+        format!("Duplicate step name `{}`", name),
+      );
+    }
+    if step.run.get_ref().trim().is_empty() {
+      self.error(
+        Some(step.run.span()),
+        "The command of a step can't be empty".to_string(),
+      );
+    }
+    let timeout = match &step.timeout {
+      Some(timeout) => self.timeout(timeout, max_timeout),
+      None => DEFAULT_STEP_TIMEOUT.min(max_timeout),
+    };
+    let env = self.env(step.env);
+
+    if self.errors.len() > errors {
+      return None;
+    }
+    Some(StepDefinition {
+      name: name.to_string(),
+      run: step.run.into_inner(),
+      timeout,
+      env,
+    })
+  }
+}
+
+impl Validator<'_> {
+  fn artifact(
+    &mut self,
+    artifact: RawArtifact,
+    names: &mut HashSet<String>,
+  ) -> Option<ArtifactDefinition> {
+    let errors = self.errors.len();
+
+    let name = artifact.name.get_ref();
+    let valid = name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if name.is_empty() || name.len() > MAX_ARTIFACT_NAME_LENGTH || !valid || name.starts_with('.') {
+      self.error(
+        Some(artifact.name.span()),
+        format!(
+          "Invalid artifact name `{}`, names must be between 1 and {} letters, digits, `-`, `_` or `.` and can't start with `.`",
+          name, MAX_ARTIFACT_NAME_LENGTH
+        ),
+      );
+    } else if !names.insert(name.to_string()) {
+      self.error(
+        Some(artifact.name.span()),
+        format!("Duplicate artifact name `{}`", name),
+      );
+    }
+    let path = self.relative_path(artifact.path, "artifact", "checkout");
+    let expire_in = match &artifact.expire_in {
+      Some(days) => {
+        let max_days = MAX_ARTIFACT_EXPIRY.as_secs() / (24 * 3600);
+        if *days.get_ref() <= 0 || *days.get_ref() as u64 > max_days {
+          self.error(
+            Some(days.span()),
+            format!(
+              "The expiry of an artifact must be between 1 and {} days, got {}",
+              max_days,
+              days.get_ref()
+            ),
+          );
+        }
+        Duration::from_secs(*days.get_ref() as u64 * 24 * 3600)
+      }
+      None => DEFAULT_ARTIFACT_EXPIRY,
+    };
+
+    if self.errors.len() > errors {
+      return None;
+    }
+    Some(ArtifactDefinition {
+      name: artifact.name.into_inner(),
+      path: path?,
+      when: artifact.when,
+      expire_in,
+    })
+  }
+
+  fn scoring(&mut self, scoring: Spanned<RawScoring>) -> Option<Scoring> {
+    let span = scoring.span();
+    let scoring = scoring.into_inner();
+    match (scoring.file, scoring.groups.is_empty()) {
+      (Some(file), true) => self
+        .relative_path(file, "score file", "checkout")
+        .map(Scoring::File),
+      (None, false) => {
+        let errors = self.errors.len();
+        let mut names = HashSet::new();
+        let groups: Vec<_> = scoring
+          .groups
+          .into_iter()
+          .map(|group| self.score_group(group, &mut names))
+          .collect();
+        match self.errors.len() > errors {
+          true => None,
+          false => Some(Scoring::Groups(groups)),
+        }
+      }
+      _ => {
+        self.error(
+          Some(span),
+          "The scoring needs either a `file` or `[[scoring.groups]]` tables, but not both"
+            .to_string(),
+        );
+        None
+      }
+    }
+  }
+
+  fn score_group(&mut self, group: RawScoreGroup, names: &mut HashSet<String>) -> ScoreGroup {
+    let name = group.name.get_ref().trim();
+    if name.is_empty() || name.len() > MAX_GROUP_NAME_LENGTH {
+      self.error(
+        Some(group.name.span()),
+        format!(
+          "Group names must be between 1 and {} characters long",
+          MAX_GROUP_NAME_LENGTH
+        ),
+      );
+    } else if !names.insert(name.to_string()) {
+      self.error(
+        Some(group.name.span()),
+        format!("Duplicate group name `{}`", name),
+      );
+    }
+    if group.tests.get_ref().iter().all(|t| t.trim().is_empty()) {
+      self.error(
+        Some(group.tests.span()),
+        format!("The group `{}` has no test pattern", name),
+      );
+    }
+    let weight = match &group.weight {
+      Some(weight) if !weight.get_ref().is_finite() || *weight.get_ref() <= 0.0 => {
+        self.error(
+          Some(weight.span()),
+          format!("The weight must be positive, got {}", weight.get_ref()),
+        );
+        1.0
+      }
+      Some(weight) => *weight.get_ref(),
+      None => 1.0,
+    };
+
+    ScoreGroup {
+      name: name.to_string(),
+      tests: group
+        .tests
+        .into_inner()
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect(),
+      weight,
+    }
+  }
+}
+
+/// Parses and validates the content of a pipeline file, returning every problem found.
+pub fn parse_pipeline(content: &str) -> Result<PipelineDefinition, Vec<PipelineError>> {
+  let mut validator = Validator {
+    content,
+    errors: Vec::new(),
+  };
+  let syntax_error = |validator: &mut Validator, e: toml::de::Error| {
+    validator.error(e.span(), e.message().trim().to_string());
+    Err(std::mem::take(&mut validator.errors))
+  };
+
+  // The version is checked first, as the rest of the file depends on it
+  let version = match toml::from_str::<RawVersion>(content) {
+    Ok(RawVersion { version }) => version,
+    Err(e) => return syntax_error(&mut validator, e),
+  };
+  let supported = SUPPORTED_VERSIONS
+    .iter()
+    .map(|v| v.to_string())
+    .collect::<Vec<_>>()
+    .join(", ");
+  let version = match version {
+    None => {
+      validator.error(
+        None,
+        format!(
+          "Missing `version`, the supported versions are: {}",
+          supported
+        ),
+      );
+      return Err(validator.errors);
+    }
+    Some(version) => match version.get_ref().as_integer() {
+      Some(v) if SUPPORTED_VERSIONS.contains(&v) => v,
+      _ => {
+        validator.error(
+          Some(version.span()),
+          format!(
+            "Unsupported version {}, the supported versions are: {}",
+            version.get_ref(),
+            supported
+          ),
+        );
+        return Err(validator.errors);
+      }
+    },
+  };
+
+  let raw = match toml::from_str::<RawPipeline>(content) {
+    Ok(raw) => raw,
+    Err(e) => return syntax_error(&mut validator, e),
+  };
+
+  let timeout = match &raw.timeout {
+    Some(timeout) => validator.timeout(timeout, MAX_PIPELINE_TIMEOUT),
+    None => DEFAULT_PIPELINE_TIMEOUT,
+  };
+  let overlay = raw
+    .overlay
+    .into_iter()
+    .filter_map(|path| validator.relative_path(path, "overlay", "CI repository"))
+    .collect();
+  let env = validator.env(raw.env);
+
+  if raw.steps.is_empty() {
+    validator.error(
+      None,
+      "The pipeline has no step, add at least one `[[steps]]` table".to_string(),
+    );
+  }
+  let mut names = HashSet::new();
+  let steps = raw
+    .steps
+    .into_iter()
+    .filter_map(|step| validator.step(step, &mut names, timeout))
+    .collect();
+  let reports = raw
+    .reports
+    .into_iter()
+    .filter_map(|report| {
+      let path = validator.relative_path(report.path, "report", "checkout")?;
+      Some(ReportDefinition {
+        path,
+        format: report.format,
+      })
+    })
+    .collect();
+  let mut names = HashSet::new();
+  let artifacts = raw
+    .artifacts
+    .into_iter()
+    .filter_map(|artifact| validator.artifact(artifact, &mut names))
+    .collect();
+  let scoring = raw.scoring.and_then(|scoring| validator.scoring(scoring));
+
+  if !validator.errors.is_empty() {
+    return Err(validator.errors);
+  }
+  Ok(PipelineDefinition {
+    version,
+    timeout,
+    overlay,
+    env,
+    steps,
+    reports,
+    artifacts,
+    scoring,
+  })
+}
+
+/// Describes the result of the validation of a pipeline, as shown to the teacher.
+pub fn validation_report(result: &Result<PipelineDefinition, Vec<PipelineError>>) -> String {
+  match result {
+    Ok(pipeline) => format!(
+      "{} is valid: {} step(s), {}",
+      PIPELINE_FILE,
+      pipeline.steps.len(),
+      pipeline
+        .steps
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+    ),
+    Err(errors) => {
+      let mut report = format!("{} is invalid:", PIPELINE_FILE);
+      for error in errors {
+        report.push_str(&format!("\n  {}", error));
+      }
+      report
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn errors(content: &str) -> Vec<String> {
+    parse_pipeline(content)
+      .expect_err("Expected the pipeline to be invalid")
+      .iter()
+      .map(|e| e.to_string())
+      .collect()
+  }
+
+  #[test]
+  fn test_parse_pipeline() {
+    let content = r#"
+version = 1
+timeout = 600
+overlay = ["tests", "./Makefile"]
+
+[env]
+LANG = "C.UTF-8"
+
+[[steps]]
+name = "build"
+run = "make"
+
+[[steps]]
+name = "test"
+run = "make test"
+timeout = 120
+env = { VERBOSE = "1" }
+
+[[reports]]
+path = "target/junit.xml"
+format = "junit"
+
+[[artifacts]]
+name = "coverage"
+path = "target/coverage"
+when = "on_success"
+expire_in = 7
+
+[[artifacts]]
+name = "main"
+path = "main"
+
+[[scoring.groups]]
+name = "Basics"
+tests = ["tests.basics.*", "tests.test_answer"]
+weight = 2
+
+[[scoring.groups]]
+name = "Bonus"
+tests = ["tests.bonus.*"]
+"#;
+    let pipeline = parse_pipeline(content).expect("Expected a valid pipeline");
+    assert_eq!(
+      pipeline,
+      PipelineDefinition {
+        version: 1,
+        timeout: Duration::from_secs(600),
+        overlay: vec![PathBuf::from("tests"), PathBuf::from("./Makefile")],
+        env: BTreeMap::from([("LANG".to_string(), "C.UTF-8".to_string())]),
+        steps: vec![
+          StepDefinition {
+            name: "build".to_string(),
+            run: "make".to_string(),
+            timeout: DEFAULT_STEP_TIMEOUT,
+            env: BTreeMap::new(),
+          },
+          StepDefinition {
+            name: "test".to_string(),
+            run: "make test".to_string(),
+            timeout: Duration::from_secs(120),
+            env: BTreeMap::from([("VERBOSE".to_string(), "1".to_string())]),
+          },
+        ],
+        reports: vec![ReportDefinition {
+          path: PathBuf::from("target/junit.xml"),
+          format: ReportFormat::Junit,
+        }],
+        artifacts: vec![
+          ArtifactDefinition {
+            name: "coverage".to_string(),
+            path: PathBuf::from("target/coverage"),
+            when: ArtifactWhen::OnSuccess,
+            expire_in: Duration::from_secs(7 * 24 * 3600),
+          },
+          ArtifactDefinition {
+            name: "main".to_string(),
+            path: PathBuf::from("main"),
+            when: ArtifactWhen::Always,
+            expire_in: DEFAULT_ARTIFACT_EXPIRY,
+          },
+        ],
+        scoring: Some(Scoring::Groups(vec![
+          ScoreGroup {
+            name: "Basics".to_string(),
+            tests: vec![
+              "tests.basics.*".to_string(),
+              "tests.test_answer".to_string()
+            ],
+            weight: 2.0,
+          },
+          ScoreGroup {
+            name: "Bonus".to_string(),
+            tests: vec!["tests.bonus.*".to_string()],
+            weight: 1.0,
+          },
+        ])),
+      }
+    );
+  }
+
+  #[test]
+  fn test_step_timeout_defaults_to_pipeline_timeout() {
+    let pipeline =
+      parse_pipeline("version = 1\ntimeout = 60\n[[steps]]\nname = \"a\"\nrun = \"true\"")
+        .expect("Expected a valid pipeline");
+    assert_eq!(pipeline.steps[0].timeout, Duration::from_secs(60));
+  }
+
+  #[test]
+  fn test_version_errors() {
+    assert_eq!(
+      errors("[[steps]]\nname = \"a\"\nrun = \"true\""),
+      vec!["Missing `version`, the supported versions are: 1"]
+    );
+    assert_eq!(
+      errors("version = 2\nunknown = true"),
+      vec!["line 1, column 11: Unsupported version 2, the supported versions are: 1"]
+    );
+    assert_eq!(
+      errors("version = \"1\""),
+      vec!["line 1, column 11: Unsupported version \"1\", the supported versions are: 1"]
+    );
+  }
+
+  #[test]
+  fn test_syntax_errors() {
+    let found = errors("version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\nretries = 3");
+    assert_eq!(found.len(), 1);
+    assert!(
+      found[0].starts_with("line 5, column 1: unknown field `retries`"),
+      "{}",
+      found[0]
+    );
+
+    let found = errors("version = 1\n[[steps]\n");
+    assert_eq!(found.len(), 1);
+    assert!(found[0].starts_with("line 2"), "{}", found[0]);
+  }
+
+  #[test]
+  fn test_validation_errors_are_all_reported() {
+    let content = r#"version = 1
+timeout = 100000
+overlay = ["../secrets", "/etc", ".git/config", "tests"]
+env = { "1PATH" = "x" }
+
+[[steps]]
+name = "build"
+run = ""
+
+[[steps]]
+name = "build"
+run = "make"
+timeout = 0
+"#;
+    assert_eq!(
+      errors(content),
+      vec![
+        "line 2, column 11: The timeout must be between 1 and 7200 seconds, got 100000",
+        "line 3, column 12: Invalid overlay path `../secrets`, paths must be relative to the CI repository and stay inside it",
+        "line 3, column 26: Invalid overlay path `/etc`, paths must be relative to the CI repository and stay inside it",
+        "line 3, column 34: Invalid overlay path `.git/config`, paths must be relative to the CI repository and stay inside it",
+        "line 4, column 9: Invalid environment variable name `1PATH`, only letters, digits and underscores are allowed",
+        "line 8, column 7: The command of a step can't be empty",
+        "line 11, column 8: Duplicate step name `build`",
+        "line 13, column 11: The timeout must be between 1 and 7200 seconds, got 0",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_report_errors() {
+    let found = errors(
+      "version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\n\
+       [[reports]]\npath = \"../report.xml\"\nformat = \"junit\"",
+    );
+    assert_eq!(
+      found,
+      vec!["line 6, column 8: Invalid report path `../report.xml`, paths must be relative to the checkout and stay inside it"]
+    );
+
+    let found = errors(
+      "version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\n\
+       [[reports]]\npath = \"report.xml\"\nformat = \"xunit\"",
+    );
+    assert_eq!(found.len(), 1);
+    assert!(
+      found[0].starts_with("line 7, column 10: unknown variant `xunit`"),
+      "{}",
+      found[0]
+    );
+  }
+
+  #[test]
+  fn test_artifact_errors() {
+    let steps = "version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\n";
+    assert_eq!(
+      errors(&format!(
+        "{}[[artifacts]]\nname = \"bin\"\npath = \"/usr/bin\"\n\
+         [[artifacts]]\nname = \"bin\"\npath = \"bin\"\nexpire_in = 0\n\
+         [[artifacts]]\nname = \"../out\"\npath = \"out\"",
+        steps
+      )),
+      vec![
+        "line 7, column 8: Invalid artifact path `/usr/bin`, paths must be relative to the checkout and stay inside it",
+        "line 9, column 8: Duplicate artifact name `bin`",
+        "line 11, column 13: The expiry of an artifact must be between 1 and 365 days, got 0",
+        "line 13, column 8: Invalid artifact name `../out`, names must be between 1 and 64 letters, digits, `-`, `_` or `.` and can't start with `.`",
+      ]
+    );
+
+    let found = errors(&format!(
+      "{}[[artifacts]]\nname = \"bin\"\npath = \"bin\"\nwhen = \"never\"",
+      steps
+    ));
+    assert_eq!(found.len(), 1);
+    assert!(
+      found[0].starts_with("line 8, column 8: unknown variant `never`"),
+      "{}",
+      found[0]
+    );
+  }
+
+  #[test]
+  fn test_artifact_when() {
+    assert!(ArtifactWhen::Always.applies(true) && ArtifactWhen::Always.applies(false));
+    assert!(ArtifactWhen::OnSuccess.applies(true) && !ArtifactWhen::OnSuccess.applies(false));
+    assert!(!ArtifactWhen::OnFailure.applies(true) && ArtifactWhen::OnFailure.applies(false));
+  }
+
+  #[test]
+  fn test_scoring_file() {
+    let pipeline = parse_pipeline(
+      "version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\n[scoring]\nfile = \"score.json\"",
+    )
+    .expect("Expected a valid pipeline");
+    assert_eq!(
+      pipeline.scoring,
+      Some(Scoring::File(PathBuf::from("score.json")))
+    );
+  }
+
+  #[test]
+  fn test_scoring_errors() {
+    let steps = "version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"\n";
+    assert_eq!(
+      errors(&format!("{}[scoring]\n", steps)),
+      vec!["line 5, column 1: The scoring needs either a `file` or `[[scoring.groups]]` tables, but not both"]
+    );
+    assert_eq!(
+      errors(&format!(
+        "{}[scoring]\nfile = \"/score.json\"\n",
+        steps
+      )),
+      vec!["line 6, column 8: Invalid score file path `/score.json`, paths must be relative to the checkout and stay inside it"]
+    );
+    assert_eq!(
+      errors(&format!(
+        "{}[[scoring.groups]]\nname = \"a\"\ntests = []\nweight = 0\n\
+         [[scoring.groups]]\nname = \"a\"\ntests = [\"*\"]\n",
+        steps
+      )),
+      vec![
+        "line 7, column 9: The group `a` has no test pattern",
+        "line 8, column 10: The weight must be positive, got 0",
+        "line 10, column 8: Duplicate group name `a`",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_pipeline_without_steps() {
+    assert_eq!(
+      errors("version = 1"),
+      vec!["The pipeline has no step, add at least one `[[steps]]` table"]
+    );
+  }
+
+  #[test]
+  fn test_validation_report() {
+    let valid = parse_pipeline("version = 1\n[[steps]]\nname = \"a\"\nrun = \"true\"");
+    assert_eq!(
+      validation_report(&valid),
+      "gmt-ci.toml is valid: 1 step(s), a"
+    );
+    let invalid = parse_pipeline("version = 1");
+    assert_eq!(
+      validation_report(&invalid),
+      "gmt-ci.toml is invalid:\n  The pipeline has no step, add at least one `[[steps]]` table"
+    );
+  }
+}
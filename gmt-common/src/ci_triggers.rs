@@ -0,0 +1,148 @@
+//! Queues the CI runs of the branches updated by a push.
+//!
+//! A commit is only run once per repository, whichever branch it is pushed to, and a new head
+//! supersedes the runs of the branch which are still waiting for a worker, unless another branch
+//! still points to their commit.
+
+use std::collections::HashMap;
+
+use database::{
+  db_handle::cirun::{Cirun, CirunDbHandle, Status},
+  error::DatabaseError,
+};
+use git_server::push::RefUpdate;
+
+/// The push option skipping the CI, as in `git push -o ci.skip`
+pub const SKIP_CI_OPTION: &str = "ci.skip";
+
+/// Queues a run for the new head of each branch updated by a push of `triggered_by`, cancelling
+/// the pending runs of the previous heads which are not the head of another branch of `branches`,
+/// mapping the branches to their head once pushed. Deleted branches and other references are
+/// ignored, as are the commits which already have a run. Returns the runs queued.
+pub fn queue_push_runs<Db: CirunDbHandle>(
+  db: &mut Db,
+  repository_id: i32,
+  triggered_by: Option<i32>,
+  updates: &[RefUpdate],
+  branches: &HashMap<String, String>,
+) -> Result<Vec<Cirun>, DatabaseError> {
+  let mut runs = db.list_repository_ciruns(repository_id)?;
+  let mut queued: Vec<Cirun> = Vec::new();
+
+  for update in updates {
+    if update.is_delete() || update.branch().is_none() {
+      continue;
+    }
+
+    for run in runs.iter_mut().filter(|run| {
+      run.status == Status::Pending
+        && run.ref_name.as_deref() == Some(update.name.as_str())
+        && run.commit != update.new
+        && !branches.values().any(|head| *head == run.commit)
+    }) {
+      if let Some(cancelled) = db.cancel_cirun(run.id)? {
+        *run = cancelled;
+      }
+    }
+
+    let has_run = runs
+      .iter()
+      .chain(queued.iter())
+      .any(|run| run.commit == update.new && run.status != Status::Cancelled);
+    if has_run {
+      continue;
+    }
+    queued.push(db.create_cirun_for_ref(repository_id, &update.new, &update.name, triggered_by)?);
+  }
+
+  Ok(queued)
+}
+
+#[cfg(test)]
+mod tests {
+  use database::{fixtures::cirun, DbHandle};
+
+  use super::*;
+
+  /// A run queued by user 2 pushing to the branch of repository 1
+  fn pushed_run(id: i32, commit: &str, ref_name: &str, status: Status) -> Cirun {
+    Cirun {
+      commit: commit.to_string(),
+      ref_name: Some(ref_name.to_string()),
+      triggered_by: Some(2),
+      ..cirun(id, 1, status)
+    }
+  }
+
+  fn update(old: &str, new: &str, name: &str) -> RefUpdate {
+    RefUpdate {
+      old: old.to_string(),
+      new: new.to_string(),
+      name: name.to_string(),
+    }
+  }
+
+  #[test]
+  fn test_queue_push_runs() {
+    let mut db = DbHandle::faux();
+    faux::when!(db.list_repository_ciruns(1)).then(|_| {
+      Ok(vec![
+        pushed_run(1, "a", "refs/heads/main", Status::Pending),
+        pushed_run(2, "x", "refs/heads/other", Status::Pending),
+        pushed_run(3, "c", "refs/heads/old", Status::Success),
+        pushed_run(4, "d", "refs/heads/old", Status::Cancelled),
+        pushed_run(5, "f", "refs/heads/shared", Status::Pending),
+      ])
+    });
+    faux::when!(db.cancel_cirun(1))
+      .once()
+      .then_return(Ok(Some(pushed_run(
+        1,
+        "a",
+        "refs/heads/main",
+        Status::Cancelled,
+      ))));
+    faux::when!(db.create_cirun_for_ref(1, "b", "refs/heads/main", Some(2)))
+      .once()
+      .then_return(Ok(pushed_run(6, "b", "refs/heads/main", Status::Pending)));
+    faux::when!(db.create_cirun_for_ref(1, "d", "refs/heads/retry", Some(2)))
+      .once()
+      .then_return(Ok(pushed_run(7, "d", "refs/heads/retry", Status::Pending)));
+    faux::when!(db.create_cirun_for_ref(1, "g", "refs/heads/shared", Some(2)))
+      .once()
+      .then_return(Ok(pushed_run(8, "g", "refs/heads/shared", Status::Pending)));
+
+    let zero = "0".repeat(40);
+    let queued = queue_push_runs(
+      &mut db,
+      1,
+      Some(2),
+      &[
+        // Supersedes the pending run of the branch
+        update("a", "b", "refs/heads/main"),
+        // Already queued by the update of main
+        update(&zero, "b", "refs/heads/feature"),
+        // Already ran
+        update("c", "c", "refs/heads/copy"),
+        // Only ran cancelled
+        update(&zero, "d", "refs/heads/retry"),
+        update(&zero, "e", "refs/tags/v1"),
+        update("x", &zero, "refs/heads/other"),
+        // The run of f is kept for the branch still pointing to it, as cancelling it isn't mocked
+        update("f", "g", "refs/heads/shared"),
+      ],
+      &HashMap::from([
+        ("main".to_string(), "b".to_string()),
+        ("feature".to_string(), "b".to_string()),
+        ("copy".to_string(), "c".to_string()),
+        ("retry".to_string(), "d".to_string()),
+        ("shared".to_string(), "g".to_string()),
+        ("kept".to_string(), "f".to_string()),
+      ]),
+    )
+    .unwrap();
+
+    let ids: Vec<i32> = queued.iter().map(|run| run.id).collect();
+    assert_eq!(ids, vec![6, 7, 8]);
+  }
+}
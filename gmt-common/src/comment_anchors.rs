@@ -0,0 +1,210 @@
+//! Keeps line comments anchored to their code as new commits are pushed.
+//!
+//! A line comment stores a hash of the lines it refers to. When a branch is pushed, the comments
+//! made on its history are looked up in its new head: the ones whose lines moved follow them,
+//! and the ones whose lines can't be found anymore are marked as outdated, the same way code
+//! review tools do. A comment keeps the commit and lines it was made on, the place its lines
+//! moved to is stored apart.
+
+use database::{
+  db_handle::comment::{CommentDbHandle, Commentside},
+  error::DatabaseError,
+};
+use git_server::objects::{GitObjects, ObjectsError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AnchorError {
+  #[error("Database error: {0}")]
+  DatabaseError(#[from] DatabaseError),
+  #[error("Git error: {0}")]
+  ObjectsError(#[from] ObjectsError),
+}
+
+/// The line comments changed by a refresh
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RefreshedAnchors {
+  /// The comments whose lines were found at another place, now also anchored to the new head
+  pub moved: Vec<i32>,
+  /// The comments whose lines can't be found anymore
+  pub outdated: Vec<i32>,
+}
+
+/// Checks the line comments anchored to the history of `head`, the new head of a pushed branch.
+/// Comments whose lines moved are anchored to their new place in `head`, and the ones whose lines
+/// can't be found anymore are marked as outdated. Comments anchored to other branches, including
+/// the ones whose lines last moved to another branch, are left as they are.
+///
+/// Only comments on the new side of a diff are checked: the old side refers to lines which were
+/// already removed when the comment was made.
+pub fn refresh_comment_anchors<Db: CommentDbHandle>(
+  db: &mut Db,
+  objects: &GitObjects,
+  repository_id: i32,
+  head: &str,
+) -> Result<RefreshedAnchors, AnchorError> {
+  let mut refreshed = RefreshedAnchors::default();
+
+  for comment in db.list_anchored_comments(repository_id)? {
+    let (Some((commit, anchor)), Some(file_path)) =
+      (comment.current_anchor(), comment.file_path.as_deref())
+    else {
+      continue;
+    };
+    if anchor.side != Commentside::New || commit == head || !objects.is_ancestor(commit, head)? {
+      continue;
+    }
+
+    let count = (anchor.end_line - anchor.start_line + 1) as usize;
+    let found = objects.find_lines(
+      head,
+      file_path,
+      &anchor.context_hash,
+      count,
+      anchor.start_line as usize,
+    )?;
+    match found {
+      None => {
+        db.set_comment_outdated(comment.id, true)?;
+        refreshed.outdated.push(comment.id);
+      }
+      Some(start) if start as i32 != anchor.start_line => {
+        let start = start as i32;
+        db.move_comment_anchor(comment.id, head, start, start + count as i32 - 1)?;
+        refreshed.moved.push(comment.id);
+      }
+      // Still at the same place
+      Some(_) => {}
+    }
+  }
+
+  Ok(refreshed)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::Path, process::Command};
+
+  use database::{
+    db_handle::comment::{Comment, CommentAnchor, Commentauthor},
+    DbHandle,
+  };
+
+  use super::*;
+
+  fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+      .current_dir(dir)
+      .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+      .args(args)
+      .output()
+      .expect("Unable to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+  }
+
+  fn commit(dir: &Path, content: &str) -> String {
+    std::fs::write(dir.join("main.rs"), content).unwrap();
+    git(dir, &["add", "--all"]);
+    git(dir, &["commit", "--quiet", "-m", "commit"]);
+    git(dir, &["rev-parse", "HEAD"])
+  }
+
+  fn comment(id: i32, commit: &str, side: Commentside, anchor: &CommentAnchor) -> Comment {
+    Comment {
+      id,
+      repository_id: 1,
+      commit_hash: commit.to_string(),
+      respond_to: None,
+      file_path: Some("main.rs".to_string()),
+      message: "message".to_string(),
+      author_type: Commentauthor::User,
+      author_id: Some(1),
+      date: std::time::SystemTime::now(),
+      start_line: Some(anchor.start_line),
+      end_line: Some(anchor.end_line),
+      side: Some(side),
+      context_hash: Some(anchor.context_hash.clone()),
+      outdated: false,
+      edited_at: None,
+      resolved: false,
+      resolved_by: None,
+      resolved_at: None,
+      anchor_commit: None,
+      anchor_start_line: None,
+      anchor_end_line: None,
+    }
+  }
+
+  #[test]
+  fn test_refresh_comment_anchors() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    git(dir.path(), &["init", "--quiet", "--initial-branch=main"]);
+    let first = commit(dir.path(), "fn main() {\n  todo!()\n}\n\nfn helper() {}\n");
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let anchor = |start: usize, end: usize| CommentAnchor {
+      start_line: start as i32,
+      end_line: end as i32,
+      side: Commentside::New,
+      context_hash: objects.hash_lines(&first, "main.rs", start, end).unwrap(),
+    };
+    let (moved, changed, kept, old) = (anchor(5, 5), anchor(1, 3), anchor(4, 4), anchor(2, 2));
+
+    // A branch which isn't pushed
+    git(dir.path(), &["checkout", "--quiet", "-b", "other"]);
+    let other = commit(dir.path(), "fn main() {}\n");
+    git(dir.path(), &["checkout", "--quiet", "main"]);
+    let other_anchor = CommentAnchor {
+      start_line: 1,
+      end_line: 1,
+      side: Commentside::New,
+      context_hash: objects.hash_lines(&other, "main.rs", 1, 1).unwrap(),
+    };
+
+    let head = commit(
+      dir.path(),
+      "// Entry point\nfn main() {\n  println!(\"Hello\")\n\n}\nfn helper() {}\n",
+    );
+
+    let mut db = DbHandle::faux();
+    let changed_comment = comment(2, &first, Commentside::New, &changed);
+    let (first_commit, other_commit) = (first.clone(), other.clone());
+    faux::when!(db.list_anchored_comments(1)).then(move |_| {
+      Ok(vec![
+        comment(1, &first_commit, Commentside::New, &moved),
+        comment(2, &first_commit, Commentside::New, &changed),
+        comment(3, &first_commit, Commentside::Old, &old),
+        comment(4, &first_commit, Commentside::New, &kept),
+        comment(5, &other_commit, Commentside::New, &other_anchor),
+        // Made on the history of head, but its lines last moved to the other branch
+        Comment {
+          anchor_commit: Some(other_commit.clone()),
+          anchor_start_line: Some(1),
+          anchor_end_line: Some(1),
+          ..comment(6, &first_commit, Commentside::New, &other_anchor)
+        },
+      ])
+    });
+    faux::when!(db.set_comment_outdated(2, true))
+      .once()
+      .then_return(Ok(changed_comment));
+    let moved_comment = comment(1, &head, Commentside::New, &anchor(5, 5));
+    let expected_head = head.clone();
+    faux::when!(db.move_comment_anchor(1, _, 6, 6))
+      .once()
+      .then(move |(_, commit, _, _)| {
+        assert_eq!(commit, expected_head);
+        Ok(moved_comment)
+      });
+
+    let refreshed = refresh_comment_anchors(&mut db, &objects, 1, &head).unwrap();
+    assert_eq!(
+      refreshed,
+      RefreshedAnchors {
+        moved: vec![1],
+        outdated: vec![2],
+      }
+    );
+  }
+}
@@ -0,0 +1,342 @@
+//! Release and due date rules of the assignments.
+//!
+//! The current time is always given by the caller, so that the rules can be checked against a
+//! fixed clock.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use database::{
+  db_handle::{
+    assignment::{AssignmentDbHandle, AssignmentMetadata, Latepolicy},
+    push::PushRecord,
+    repository::Repository,
+    submission::Submission,
+  },
+  error::DatabaseError,
+};
+use git_server::objects::{GitObjects, ObjectsError};
+
+/// What to do with a push to a submission repository.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PushDecision {
+  /// The push happened before the due date
+  OnTime,
+  /// The push happened after the due date, but within the grace period
+  Grace,
+  /// The push is late but accepted, `flagged` telling whether it should be recorded as late
+  Late { flagged: bool },
+  /// The push is late and the assignment doesn't accept late submissions
+  Rejected,
+}
+
+impl PushDecision {
+  pub fn is_accepted(&self) -> bool {
+    !matches!(self, PushDecision::Rejected)
+  }
+
+  /// Whether a submission made at that time is recorded as late. Rejected submissions only get in
+  /// through the teacher or the CI, and are late as well.
+  pub fn is_late(&self) -> bool {
+    matches!(
+      self,
+      PushDecision::Late { flagged: true } | PushDecision::Rejected
+    )
+  }
+
+  /// The message explaining the decision to the student, if there is anything to say.
+  pub fn message(&self, metadata: &AssignmentMetadata) -> Option<String> {
+    let due = metadata.due_date.map(|due| {
+      DateTime::<Utc>::from(due)
+        .format("%Y-%m-%d %H:%M UTC")
+        .to_string()
+    })?;
+    match self {
+      PushDecision::OnTime => None,
+      PushDecision::Grace => Some(format!(
+        "The assignment was due on {}, this push is accepted within the grace period.",
+        due
+      )),
+      PushDecision::Late { flagged: false } => Some(format!(
+        "The assignment was due on {}, this push is late but accepted.",
+        due
+      )),
+      PushDecision::Late { flagged: true } => Some(format!(
+        "The assignment was due on {}, this push is accepted but recorded as late.",
+        due
+      )),
+      PushDecision::Rejected => Some(format!(
+        "The assignment was due on {}, late pushes are not accepted.",
+        due
+      )),
+    }
+  }
+}
+
+/// Whether the assignment has been released, meaning its base repository is visible to the
+/// students.
+pub fn is_released(metadata: &AssignmentMetadata, now: SystemTime) -> bool {
+  metadata.release_date.is_none_or(|release| release <= now)
+}
+
+/// The moment after which pushes are late, taking the grace period into account.
+pub fn late_after(metadata: &AssignmentMetadata) -> Option<SystemTime> {
+  let grace = Duration::from_secs(metadata.grace_period.max(0) as u64 * 60);
+  metadata.due_date.map(|due| due + grace)
+}
+
+/// Applies the late policy of the assignment to a push happening at `now`.
+pub fn push_decision(metadata: &AssignmentMetadata, now: SystemTime) -> PushDecision {
+  match (metadata.due_date, late_after(metadata)) {
+    (_, Some(deadline)) if now > deadline => match metadata.late_policy {
+      Latepolicy::Accept => PushDecision::Late { flagged: false },
+      Latepolicy::Flag => PushDecision::Late { flagged: true },
+      Latepolicy::Reject => PushDecision::Rejected,
+    },
+    (Some(due), _) if now > due => PushDecision::Grace,
+    _ => PushDecision::OnTime,
+  }
+}
+
+/// Applies the late policy to a push of the user to the repository, returning the decision along
+/// with the message for the user. Only the pushes of the owner of a submission repository are
+/// subject to the due date, so that the teacher can still push afterwards.
+pub fn check_submission_push<Db: AssignmentDbHandle>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+  now: SystemTime,
+) -> Result<(PushDecision, Option<String>), DatabaseError> {
+  let assignment = match repository.assignment_id {
+    Some(assignment_id) if repository.owner_id == user_id => {
+      db.get_assignment_by_id(assignment_id)?
+    }
+    _ => None,
+  };
+  Ok(match assignment {
+    Some(assignment) => {
+      let decision = push_decision(&assignment.metadata, now);
+      (decision, decision.message(&assignment.metadata))
+    }
+    None => (PushDecision::OnTime, None),
+  })
+}
+
+/// Finds the push which handed the commit in, among the pushes of its repository, oldest first:
+/// the first one whose branch contained the commit. Commits no push contained, such as the ones
+/// of a submission repository nobody pushed to yet, have none.
+pub fn find_commit_push<'a>(
+  pushes: &'a [PushRecord],
+  objects: &GitObjects,
+  commit: &str,
+) -> Result<Option<&'a PushRecord>, ObjectsError> {
+  for push in pushes {
+    if objects.is_ancestor(commit, &push.commit_hash)? {
+      return Ok(Some(push));
+    }
+  }
+  Ok(None)
+}
+
+/// The percentage of the score removed from a submission: the late penalty of the assignment for
+/// every started day between the end of the grace period and the push of the commit, up to the
+/// whole score. Only the submissions recorded as late are penalized, the ones whose push time is
+/// unknown counting as one day late.
+pub fn late_penalty(metadata: &AssignmentMetadata, submission: &Submission) -> i32 {
+  const DAY: u64 = 24 * 60 * 60;
+
+  if !submission.late {
+    return 0;
+  }
+  let late = match (late_after(metadata), submission.pushed_at) {
+    (Some(deadline), Some(pushed_at)) => pushed_at.duration_since(deadline).unwrap_or_default(),
+    _ => Duration::ZERO,
+  };
+  let days = late.as_secs().div_ceil(DAY).max(1);
+  (metadata.late_penalty.max(0) as u64 * days).min(100) as i32
+}
+
+/// Removes a late penalty, as a percentage, from a score, rounded to two decimals like the CI
+/// scores.
+pub fn penalized_score(score: f64, penalty: i32) -> f64 {
+  (score * f64::from(100 - penalty)).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::Path, process::Command};
+
+  use super::*;
+
+  fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+  }
+
+  fn metadata(late_policy: Latepolicy) -> AssignmentMetadata {
+    AssignmentMetadata {
+      release_date: Some(at(1_000)),
+      due_date: Some(at(10_000)),
+      late_policy,
+      grace_period: 5,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_is_released() {
+    assert!(!is_released(&metadata(Latepolicy::Flag), at(999)));
+    assert!(is_released(&metadata(Latepolicy::Flag), at(1_000)));
+    assert!(is_released(&AssignmentMetadata::default(), at(0)));
+  }
+
+  #[test]
+  fn test_push_decision() {
+    let cases = [
+      (Latepolicy::Accept, PushDecision::Late { flagged: false }),
+      (Latepolicy::Flag, PushDecision::Late { flagged: true }),
+      (Latepolicy::Reject, PushDecision::Rejected),
+    ];
+    for (policy, late) in cases {
+      let metadata = metadata(policy);
+      assert_eq!(push_decision(&metadata, at(9_999)), PushDecision::OnTime);
+      assert_eq!(push_decision(&metadata, at(10_000)), PushDecision::OnTime);
+      // within the 5 minutes grace period
+      assert_eq!(push_decision(&metadata, at(10_300)), PushDecision::Grace);
+      assert_eq!(push_decision(&metadata, at(10_301)), late);
+    }
+  }
+
+  #[test]
+  fn test_push_decision_without_due_date() {
+    let metadata = AssignmentMetadata {
+      late_policy: Latepolicy::Reject,
+      ..Default::default()
+    };
+    assert_eq!(
+      push_decision(&metadata, at(u32::MAX as u64)),
+      PushDecision::OnTime
+    );
+  }
+
+  #[test]
+  fn test_push_decision_message() {
+    let metadata = metadata(Latepolicy::Reject);
+    assert_eq!(PushDecision::OnTime.message(&metadata), None);
+    assert_eq!(
+      PushDecision::Rejected.message(&metadata).unwrap(),
+      "The assignment was due on 1970-01-01 02:46 UTC, late pushes are not accepted."
+    );
+    assert_eq!(
+      PushDecision::Rejected.message(&AssignmentMetadata::default()),
+      None
+    );
+  }
+
+  fn submission(late: bool, pushed_at: Option<SystemTime>) -> Submission {
+    Submission {
+      id: 1,
+      repository_id: 1,
+      commit_hash: "commit".to_string(),
+      submitted_at: at(100_000),
+      late,
+      pushed_at,
+    }
+  }
+
+  #[test]
+  fn test_late_penalty() {
+    const DAY: u64 = 24 * 60 * 60;
+    let metadata = AssignmentMetadata {
+      late_penalty: 30,
+      ..metadata(Latepolicy::Flag)
+    };
+    // Late by the 5 minutes grace period
+    let deadline = 10_300;
+
+    assert_eq!(
+      late_penalty(&metadata, &submission(false, Some(at(deadline + DAY)))),
+      0
+    );
+    assert_eq!(
+      late_penalty(&metadata, &submission(true, Some(at(deadline + 1)))),
+      30
+    );
+    assert_eq!(
+      late_penalty(&metadata, &submission(true, Some(at(deadline + DAY)))),
+      30
+    );
+    assert_eq!(
+      late_penalty(&metadata, &submission(true, Some(at(deadline + DAY + 1)))),
+      60
+    );
+    assert_eq!(
+      late_penalty(&metadata, &submission(true, Some(at(deadline + 5 * DAY)))),
+      100
+    );
+    assert_eq!(late_penalty(&metadata, &submission(true, None)), 30);
+    assert_eq!(
+      late_penalty(&AssignmentMetadata::default(), &submission(true, None)),
+      0
+    );
+  }
+
+  #[test]
+  fn test_penalized_score() {
+    assert_eq!(penalized_score(8.0, 0), 8.0);
+    assert_eq!(penalized_score(8.0, 25), 6.0);
+    assert_eq!(penalized_score(3.33, 10), 3.0);
+    assert_eq!(penalized_score(8.0, 100), 0.0);
+  }
+
+  fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+      .current_dir(dir)
+      .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+      .args(args)
+      .output()
+      .expect("Unable to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+  }
+
+  #[test]
+  fn test_find_commit_push() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    git(dir.path(), &["init", "--quiet", "--initial-branch=main"]);
+    let commit = |message: &str| {
+      git(
+        dir.path(),
+        &["commit", "--quiet", "--allow-empty", "-m", message],
+      );
+      git(dir.path(), &["rev-parse", "HEAD"])
+    };
+    let base = commit("base");
+    let (first, second) = (commit("first"), commit("second"));
+    let third = commit("third");
+    let objects = GitObjects::open(dir.path()).unwrap();
+
+    let push = |id: i32, commit: &str, late: bool| PushRecord {
+      id,
+      repository_id: 1,
+      user_id: Some(1),
+      ref_name: "refs/heads/main".to_string(),
+      commit_hash: commit.to_string(),
+      pushed_at: at(id as u64),
+      late,
+    };
+    // `first` and `second` were pushed together
+    let pushes = [push(1, &second, false), push(2, &third, true)];
+
+    let found = |commit: &str| {
+      find_commit_push(&pushes, &objects, commit)
+        .unwrap()
+        .map(|push| push.id)
+    };
+    assert_eq!(found(&first), Some(1));
+    assert_eq!(found(&second), Some(1));
+    assert_eq!(found(&third), Some(2));
+    // Every push contains the commits of the base repository
+    assert_eq!(found(&base), Some(1));
+    assert_eq!(find_commit_push(&[], &objects, &base).unwrap(), None);
+  }
+}
@@ -5,10 +5,9 @@ use ssh_server::user::User;
 pub enum GmtUser {
   /// A user which does not exist in the database
   Public,
-  /// A connected user
-  /// TODO: Add DB user data here
+  /// A connected user, identified by its id in the database
   #[allow(dead_code)]
-  Connected(),
+  Connected(i32),
   /// An admin user, with full access to the system.
   /// This field might be used in the long run to provide access to a bash accesspoint or other ssh functions.
   #[allow(dead_code)]
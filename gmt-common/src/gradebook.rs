@@ -0,0 +1,362 @@
+//! Gathers the grades of a group into a gradebook, one row per student and one column per
+//! assignment, so that they can be exported to other tools.
+//!
+//! Assignments are ordered by id and students by username, so that the columns and rows of an
+//! export stay in the same order from one export to the next.
+
+use std::collections::HashMap;
+
+use database::{
+  db_handle::{
+    assignment::{Assignment, AssignmentDbHandle},
+    cirun::{CirunDbHandle, Status},
+    grade::{Grade, GradeDbHandle},
+    group::GroupDbHandle,
+    submission::{Submission, SubmissionDbHandle},
+    user::User,
+  },
+  error::DatabaseError,
+};
+
+use crate::deadlines::{late_penalty, penalized_score};
+
+/// The state of the work of a student on an assignment.
+#[derive(Debug, Default, PartialEq)]
+pub struct GradebookEntry {
+  /// The submission repository of the student, none if it wasn't provisioned
+  pub repository_id: Option<i32>,
+  /// The latest submission of the student
+  pub submission: Option<Submission>,
+  /// The latest grade of the latest submission
+  pub grade: Option<Grade>,
+  /// The percentage removed from the grade by the late penalty of the assignment
+  pub late_penalty: i32,
+  /// The status of the CI on the latest submission, or on the latest run without submission
+  pub ci_status: Option<Status>,
+}
+
+impl GradebookEntry {
+  /// The latest grade once the late penalty removed
+  pub fn penalized_score(&self) -> Option<f64> {
+    self
+      .grade
+      .as_ref()
+      .map(|grade| penalized_score(grade.score, self.late_penalty))
+  }
+}
+
+pub struct GradebookRow {
+  pub student: User,
+  /// The entries of the student, in the same order as the assignments of the gradebook
+  pub entries: Vec<GradebookEntry>,
+}
+
+pub struct Gradebook {
+  pub assignments: Vec<Assignment>,
+  pub rows: Vec<GradebookRow>,
+}
+
+/// Builds the gradebook of the group.
+pub fn build_gradebook<Db>(db: &mut Db, group_id: i32) -> Result<Gradebook, DatabaseError>
+where
+  Db: AssignmentDbHandle + CirunDbHandle + GradeDbHandle + GroupDbHandle + SubmissionDbHandle,
+{
+  let mut assignments = db.list_group_assignments(group_id)?;
+  assignments.sort_by_key(|a| a.id);
+  let mut students = db.list_students(group_id)?;
+  students.sort_by(|a, b| a.username.cmp(&b.username).then(a.id.cmp(&b.id)));
+
+  // Entries of each assignment, by student id
+  let mut columns = Vec::new();
+  for assignment in &assignments {
+    let mut latest: HashMap<i32, Submission> = HashMap::new();
+    for submission in db.list_assignment_submissions(assignment.id)? {
+      // Submissions are listed oldest first
+      latest.insert(submission.repository_id, submission);
+    }
+
+    let mut entries = HashMap::new();
+    for repository in db.get_assignment_submission_repos(assignment.id)? {
+      let submission = latest.remove(&repository.id);
+      let (grade, cirun) = match &submission {
+        Some(submission) => (
+          db.list_submission_grades(submission.id)?.pop(),
+          db.get_cirun_by_commit(repository.id, &submission.commit_hash)?,
+        ),
+        None => (
+          None,
+          db.list_repository_ciruns(repository.id)?
+            .into_iter()
+            .max_by_key(|c| c.id),
+        ),
+      };
+      let entry = GradebookEntry {
+        repository_id: Some(repository.id),
+        late_penalty: submission.as_ref().map_or(0, |submission| {
+          late_penalty(&assignment.metadata, submission)
+        }),
+        submission,
+        grade,
+        ci_status: cirun.map(|c| c.status),
+      };
+      entries.insert(repository.owner_id, entry);
+    }
+    columns.push(entries);
+  }
+
+  let rows = students
+    .into_iter()
+    .map(|student| GradebookRow {
+      entries: columns
+        .iter_mut()
+        .map(|entries| entries.remove(&student.id).unwrap_or_default())
+        .collect(),
+      student,
+    })
+    .collect();
+
+  Ok(Gradebook { assignments, rows })
+}
+
+/// The name of the status, as used in the exports.
+pub fn ci_status_name(status: &Status) -> &'static str {
+  match status {
+    Status::Success => "success",
+    Status::Pending => "pending",
+    Status::Running => "running",
+    Status::Cancelled => "cancelled",
+    Status::Failed => "failed",
+  }
+}
+
+/// The name of the assignment in the header of the exports. The id is included since names aren't
+/// unique.
+pub fn assignment_label(assignment: &Assignment) -> String {
+  match assignment.metadata.name.as_str() {
+    "" => format!("Assignment #{}", assignment.id),
+    name => format!("{} #{}", name, assignment.id),
+  }
+}
+
+/// Quotes the field if needed, following RFC 4180. Fields which a spreadsheet would evaluate as a
+/// formula, such as usernames starting with `=`, are prefixed with a single quote.
+fn csv_field(value: &str) -> String {
+  let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+    format!("'{}", value)
+  } else {
+    value.to_string()
+  };
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value
+  }
+}
+
+fn csv_line(fields: Vec<String>) -> String {
+  let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+  fields.join(",") + "\r\n"
+}
+
+impl Gradebook {
+  /// Exports the gradebook as CSV. The student columns come first, then the score, score once the
+  /// late penalty removed, maximum score, late flag and CI status of each assignment. Cells are
+  /// left empty when there is no value.
+  pub fn to_csv(&self) -> String {
+    let mut header = vec![
+      "student_id".to_string(),
+      "username".to_string(),
+      "email".to_string(),
+    ];
+    for assignment in &self.assignments {
+      let label = assignment_label(assignment);
+      for column in ["score", "penalized_score", "max_score", "late", "ci_status"] {
+        header.push(format!("{} {}", label, column));
+      }
+    }
+
+    let mut csv = csv_line(header);
+    for row in &self.rows {
+      let mut line = vec![
+        row.student.id.to_string(),
+        row.student.username.clone(),
+        row.student.email.clone(),
+      ];
+      for entry in &row.entries {
+        line.push(
+          entry
+            .grade
+            .as_ref()
+            .map_or(String::new(), |g| g.score.to_string()),
+        );
+        line.push(
+          entry
+            .penalized_score()
+            .map_or(String::new(), |score| score.to_string()),
+        );
+        line.push(
+          entry
+            .grade
+            .as_ref()
+            .map_or(String::new(), |g| g.max_score.to_string()),
+        );
+        line.push(
+          entry
+            .submission
+            .as_ref()
+            .map_or(String::new(), |s| s.late.to_string()),
+        );
+        line.push(
+          entry
+            .ci_status
+            .as_ref()
+            .map_or("", ci_status_name)
+            .to_string(),
+        );
+      }
+      csv.push_str(&csv_line(line));
+    }
+    csv
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::SystemTime;
+
+  use database::{
+    db_handle::{assignment::AssignmentMetadata, grade::Gradesource},
+    fixtures::{cirun, repository, user},
+    DbHandle,
+  };
+
+  use super::*;
+
+  fn assignment(id: i32, name: &str) -> Assignment {
+    Assignment {
+      id,
+      group_id: 1,
+      base_repo_id: 100 + id,
+      test_repo_id: None,
+      correction_repo_id: None,
+      metadata: AssignmentMetadata {
+        name: name.to_string(),
+        late_penalty: 10,
+        ..Default::default()
+      },
+    }
+  }
+
+  fn submission(id: i32, repository_id: i32, late: bool) -> Submission {
+    Submission {
+      id,
+      repository_id,
+      commit_hash: format!("commit-{}", id),
+      submitted_at: SystemTime::UNIX_EPOCH,
+      late,
+      pushed_at: None,
+    }
+  }
+
+  fn grade(id: i32, submission_id: i32, score: f64) -> Grade {
+    Grade {
+      id,
+      submission_id,
+      score,
+      max_score: 20.0,
+      source: Gradesource::Manual,
+      grader_id: Some(1),
+      date: SystemTime::UNIX_EPOCH,
+      cirun_id: None,
+    }
+  }
+
+  /// Two assignments with a late penalty of 10% per day, listed out of order. Bob submitted twice
+  /// to the first one, late the second time; Alice only has a CI run on the second one and Carol
+  /// has no repository at all.
+  fn db() -> DbHandle {
+    let mut db = DbHandle::faux();
+    faux::when!(db.list_group_assignments(1))
+      .then(|_| Ok(vec![assignment(2, ""), assignment(1, "Linked, lists")]));
+    faux::when!(db.list_students(1))
+      .then(|_| Ok(vec![user(3, "carol"), user(2, "bob"), user(1, "alice")]));
+    faux::when!(db.list_assignment_submissions(1))
+      .then(|_| Ok(vec![submission(1, 10, false), submission(2, 10, true)]));
+    faux::when!(db.list_assignment_submissions(2)).then(|_| Ok(vec![]));
+    faux::when!(db.get_assignment_submission_repos(1))
+      .then(|_| Ok(vec![repository(10, 2, Some(1))]));
+    faux::when!(db.get_assignment_submission_repos(2))
+      .then(|_| Ok(vec![repository(20, 1, Some(2))]));
+    faux::when!(db.list_submission_grades(2))
+      .then(|_| Ok(vec![grade(1, 2, 12.0), grade(2, 2, 15.5)]));
+    faux::when!(db.get_cirun_by_commit(10, "commit-2"))
+      .then(|_| Ok(Some(cirun(5, 10, Status::Success))));
+    faux::when!(db.list_repository_ciruns(20)).then(|_| {
+      Ok(vec![
+        cirun(6, 20, Status::Success),
+        cirun(7, 20, Status::Failed),
+      ])
+    });
+    db
+  }
+
+  #[test]
+  fn test_build_gradebook() {
+    let gradebook = build_gradebook(&mut db(), 1).expect("Unable to build gradebook");
+
+    let ids: Vec<i32> = gradebook.assignments.iter().map(|a| a.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    let usernames: Vec<&str> = gradebook
+      .rows
+      .iter()
+      .map(|r| r.student.username.as_str())
+      .collect();
+    assert_eq!(usernames, vec!["alice", "bob", "carol"]);
+
+    let bob = &gradebook.rows[1].entries;
+    assert_eq!(bob[0].submission, Some(submission(2, 10, true)));
+    assert_eq!(bob[0].grade, Some(grade(2, 2, 15.5)));
+    // Late by an unknown time, counted as a day
+    assert_eq!(bob[0].late_penalty, 10);
+    assert_eq!(bob[0].penalized_score(), Some(13.95));
+    assert_eq!(bob[0].ci_status, Some(Status::Success));
+    assert_eq!(bob[1], GradebookEntry::default());
+
+    let alice = &gradebook.rows[0].entries;
+    assert_eq!(alice[1].repository_id, Some(20));
+    assert_eq!(alice[1].submission, None);
+    assert_eq!(alice[1].ci_status, Some(Status::Failed));
+  }
+
+  #[test]
+  fn test_gradebook_to_csv() {
+    let gradebook = build_gradebook(&mut db(), 1).expect("Unable to build gradebook");
+
+    let expected = [
+      "student_id,username,email,\
+        \"Linked, lists #1 score\",\"Linked, lists #1 penalized_score\",\
+        \"Linked, lists #1 max_score\",\"Linked, lists #1 late\",\"Linked, lists #1 ci_status\",\
+        Assignment #2 score,Assignment #2 penalized_score,Assignment #2 max_score,\
+        Assignment #2 late,Assignment #2 ci_status",
+      "1,alice,alice@test.com,,,,,,,,,,failed",
+      "2,bob,bob@test.com,15.5,13.95,20,true,success,,,,,",
+      "3,carol,carol@test.com,,,,,,,,,,",
+      "",
+    ];
+    assert_eq!(gradebook.to_csv(), expected.join("\r\n"));
+  }
+
+  #[test]
+  fn test_csv_field() {
+    assert_eq!(csv_field("plain"), "plain");
+    assert_eq!(csv_field("a \"quote\""), "\"a \"\"quote\"\"\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+    assert_eq!(csv_field("+1"), "'+1");
+    assert_eq!(csv_field("-1"), "'-1");
+    assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    assert_eq!(csv_field("\tcell"), "'\tcell");
+    assert_eq!(csv_field("\rcell"), "\"'\rcell\"");
+    assert_eq!(csv_field("a=b"), "a=b");
+  }
+}
@@ -1,4 +1,12 @@
+pub mod artifact_storage;
+pub mod ci_pipeline;
+pub mod ci_triggers;
+pub mod comment_anchors;
+pub mod deadlines;
 pub mod gmt_user;
+pub mod gradebook;
+pub mod permissions;
 pub mod repositories;
+pub mod student_import;
 
 pub mod password;
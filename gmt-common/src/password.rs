@@ -1,3 +1,5 @@
+use sha2::{Digest, Sha256};
+
 pub trait PasswordAuth {
   fn generate_hash(password: impl AsRef<[u8]>) -> String;
   fn verify_password(password: impl AsRef<[u8]>, hash: &str) -> bool;
@@ -20,10 +22,33 @@ impl PasswordAuth for PasswordAuthImpl {
   }
 }
 
+/// Generates a random token to send to a user, such as an invitation to choose a password.
+pub fn generate_token() -> String {
+  let bytes: [u8; 32] = rand::random();
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes a token before storing it, so that the stored tokens can't be used if they leak.
+pub fn hash_token(token: &str) -> String {
+  Sha256::digest(token.as_bytes())
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  #[test]
+  fn test_generate_token() {
+    let token = generate_token();
+    assert_eq!(token.len(), 64);
+    assert_ne!(token, generate_token());
+    assert_eq!(hash_token(&token).len(), 64);
+    assert_eq!(hash_token(&token), hash_token(&token));
+  }
+
   #[test]
   fn test_generate_hash() {
     let password = "password";
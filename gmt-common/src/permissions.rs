@@ -0,0 +1,87 @@
+//! Access rules shared by the API and the git server, so that a repository can be reached in the
+//! same way whichever way it is accessed.
+
+use std::time::SystemTime;
+
+use database::{
+  db_handle::{
+    assignment::AssignmentDbHandle,
+    group::{Group, GroupDbHandle},
+    repository::Repository,
+  },
+  error::DatabaseError,
+};
+use git_server::repository::RepositoryPermission;
+
+use crate::deadlines::is_released;
+
+/// Whether the user is the teacher of the group.
+pub fn is_teacher(group: &Group, user_id: i32) -> bool {
+  group.teacher_id == Some(user_id)
+}
+
+/// Whether the user is either the teacher or one of the students of the group.
+pub fn is_group_member<Db: GroupDbHandle>(
+  db: &mut Db,
+  group: &Group,
+  user_id: i32,
+) -> Result<bool, DatabaseError> {
+  Ok(is_teacher(group, user_id) || db.list_students(group.id)?.iter().any(|s| s.id == user_id))
+}
+
+/// Whether the user can push to the repository: its owner, or the teacher of the group the
+/// repository was submitted to.
+pub fn can_write_repository<Db: AssignmentDbHandle + GroupDbHandle>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+) -> Result<bool, DatabaseError> {
+  if repository.owner_id == user_id {
+    return Ok(true);
+  }
+  match repository.assignment_id {
+    Some(assignment_id) => Ok(
+      db.get_assignment_group(assignment_id)?
+        .is_some_and(|group| is_teacher(&group, user_id)),
+    ),
+    None => Ok(false),
+  }
+}
+
+/// Whether the user can read the repository. On top of the writers, the members of a group can
+/// read the base repositories of the group's assignments once they are released.
+pub fn can_read_repository<Db: AssignmentDbHandle + GroupDbHandle>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+) -> Result<bool, DatabaseError> {
+  if can_write_repository(db, repository, user_id)? {
+    return Ok(true);
+  }
+  for assignment in db.list_repository_assignments(repository.id)? {
+    let Some(group) = db.get_group_by_id(assignment.group_id)? else {
+      continue;
+    };
+    if is_teacher(&group, user_id)
+      || (assignment.base_repo_id == repository.id
+        && is_released(&assignment.metadata, SystemTime::now())
+        && is_group_member(db, &group, user_id)?)
+    {
+      return Ok(true);
+    }
+  }
+  Ok(false)
+}
+
+/// Checks the permission through the rules above.
+pub fn has_repository_permission<Db: AssignmentDbHandle + GroupDbHandle>(
+  db: &mut Db,
+  repository: &Repository,
+  user_id: i32,
+  permission: RepositoryPermission,
+) -> Result<bool, DatabaseError> {
+  match permission {
+    RepositoryPermission::Read => can_read_repository(db, repository, user_id),
+    RepositoryPermission::Write => can_write_repository(db, repository, user_id),
+  }
+}
@@ -1,59 +1,536 @@
-use git_server::repository::{Repository, RepositoryPermission};
+use std::{collections::HashMap, io::Error, process::Command};
 
-use crate::gmt_user::GmtUser;
+use database::{db_handle::cirun::Cirun, error::DatabaseError};
+use git_server::{
+  push::{Push, RefUpdate},
+  repository::{PushCheck, Repository, RepositoryPermission},
+};
+use log::error;
 
+use crate::{
+  ci_pipeline::{parse_pipeline, validation_report, PIPELINE_FILE},
+  ci_triggers::SKIP_CI_OPTION,
+  comment_anchors::AnchorError,
+  deadlines::PushDecision,
+  gmt_user::GmtUser,
+};
+
+/// Queues the CI runs of the branches updated by a push, given the heads of all the branches of
+/// the repository, returning the runs queued.
+pub type CiTrigger = Box<
+  dyn Fn(&[RefUpdate], &HashMap<String, String>) -> Result<Vec<Cirun>, DatabaseError> + Send + Sync,
+>;
+
+/// Refreshes the anchors of the line comments made on the branches updated by a push.
+pub type AnchorRefresh = Box<dyn Fn(&[RefUpdate]) -> Result<(), AnchorError> + Send + Sync>;
+
+/// Records the branches updated by a push in the database, along with the deadline decision.
+pub type PushRecorder = Box<dyn Fn(&[RefUpdate]) -> Result<(), DatabaseError> + Send + Sync>;
+
+/// A repository found in the database, along with the permissions of the connected user on it.
 pub struct DbRepository {
-  // TODO: Add inner fields backed by DB
+  path: String,
+  can_read: bool,
+  can_write: bool,
+  push_decision: PushDecision,
+  push_message: Option<String>,
+  /// Whether the repository holds a CI pipeline, validated after each push
+  holds_pipeline: bool,
+  /// Queues the CI of the pushes, for the repositories whose assignment has a CI repository
+  ci_trigger: Option<CiTrigger>,
+  anchor_refresh: Option<AnchorRefresh>,
+  /// Keeps the evidence of when the commits of a submission repository were pushed
+  push_recorder: Option<PushRecorder>,
+}
+
+impl DbRepository {
+  pub fn new(path: String, can_read: bool, can_write: bool) -> Self {
+    DbRepository {
+      path,
+      can_read,
+      can_write,
+      push_decision: PushDecision::OnTime,
+      push_message: None,
+      holds_pipeline: false,
+      ci_trigger: None,
+      anchor_refresh: None,
+      push_recorder: None,
+    }
+  }
+
+  /// Marks the repository as the CI repository of an assignment, so that its pipeline gets
+  /// validated after each push.
+  pub fn with_pipeline(mut self, holds_pipeline: bool) -> Self {
+    self.holds_pipeline = holds_pipeline;
+    self
+  }
+
+  /// Runs the CI of the branches updated by each push through the given trigger.
+  pub fn with_ci_trigger(mut self, trigger: CiTrigger) -> Self {
+    self.ci_trigger = Some(trigger);
+    self
+  }
+
+  /// Keeps the line comments anchored to the code of the branches updated by each push.
+  pub fn with_anchor_refresh(mut self, refresh: AnchorRefresh) -> Self {
+    self.anchor_refresh = Some(refresh);
+    self
+  }
+
+  /// Records the branches updated by each push, before anything else runs.
+  pub fn with_push_recorder(mut self, recorder: PushRecorder) -> Self {
+    self.push_recorder = Some(recorder);
+    self
+  }
+
+  /// Sets the deadline decision applying to the pushes of the connected user.
+  pub fn with_push_decision(mut self, decision: PushDecision, message: Option<String>) -> Self {
+    self.push_decision = decision;
+    self.push_message = message;
+    self
+  }
+
+  fn git(&self) -> Command {
+    let mut command = Command::new("git");
+    command.arg("--git-dir").arg(&self.path);
+    command
+  }
+
+  /// Lists the branches of the repository along with the commit they point to.
+  fn list_branches(&self) -> Result<HashMap<String, String>, Error> {
+    let output = self
+      .git()
+      .args([
+        "for-each-ref",
+        "--format=%(refname:short) %(objectname)",
+        "refs/heads",
+      ])
+      .output()?;
+    if !output.status.success() {
+      return Err(Error::other(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(
+      String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, commit)| (name.to_string(), commit.to_string()))
+        .collect(),
+    )
+  }
+
+  /// Validates the pipeline file of the default branch, returning the report shown to the client.
+  fn check_pipeline(&self) -> Result<String, Error> {
+    let output = self
+      .git()
+      .args(["show", &format!("HEAD:{}", PIPELINE_FILE)])
+      .output()?;
+    if !output.status.success() {
+      return Ok(format!(
+        "No {} found on the default branch, the CI of the assignment won't run",
+        PIPELINE_FILE
+      ));
+    }
+    let content = String::from_utf8_lossy(&output.stdout);
+    Ok(validation_report(&parse_pipeline(&content)))
+  }
+
+  /// Lists the branches the push created or moved, leaving out the updates git rejected.
+  fn updated_branches(&self, push: &Push) -> Result<Vec<RefUpdate>, Error> {
+    let mut updates: Vec<RefUpdate> = push
+      .updates
+      .iter()
+      .filter(|update| update.branch().is_some() && !update.is_delete())
+      .cloned()
+      .collect();
+    if updates.is_empty() {
+      return Ok(updates);
+    }
+
+    let branches = self.list_branches()?;
+    updates.retain(|update| branches.get(update.branch().unwrap_or_default()) == Some(&update.new));
+    Ok(updates)
+  }
+
+  /// Queues the CI of the branches updated by the push, returning the report shown to the client.
+  fn queue_ci(
+    &self,
+    trigger: &CiTrigger,
+    push: &Push,
+    updates: &[RefUpdate],
+  ) -> Result<Option<String>, Error> {
+    if updates.is_empty() {
+      return Ok(None);
+    }
+    if push.has_option(SKIP_CI_OPTION) {
+      return Ok(Some(format!(
+        "CI skipped, as requested by the {} push option",
+        SKIP_CI_OPTION
+      )));
+    }
+
+    let branches = self.list_branches()?;
+    let runs = trigger(updates, &branches).map_err(Error::other)?;
+    let report: Vec<String> = runs
+      .iter()
+      .map(|run| {
+        let branch = run.ref_name.as_deref().unwrap_or_default();
+        format!(
+          "CI run #{} queued for {}",
+          run.id,
+          branch.strip_prefix("refs/heads/").unwrap_or(branch)
+        )
+      })
+      .collect();
+    Ok((!report.is_empty()).then(|| report.join("\n")))
+  }
 }
 
 impl Repository for DbRepository {
   type User = GmtUser;
 
-  fn has_permission(&self, user: &Self::User, _permission: RepositoryPermission) -> bool {
+  fn has_permission(&self, user: &Self::User, permission: RepositoryPermission) -> bool {
     match user {
       GmtUser::Admin => true,
-      GmtUser::Connected() => true,
+      GmtUser::Connected(_) => match permission {
+        RepositoryPermission::Read => self.can_read,
+        RepositoryPermission::Write => self.can_write,
+      },
       GmtUser::Public => false,
     }
   }
 
   fn get_path(&self) -> &str {
-    todo!()
+    &self.path
+  }
+
+  fn check_push(&self, _user: &Self::User) -> PushCheck {
+    match (self.push_decision, self.push_message.clone()) {
+      (PushDecision::Rejected, message) => {
+        PushCheck::Reject(message.unwrap_or_else(|| "Late pushes are not accepted".to_string()))
+      }
+      (_, Some(message)) => PushCheck::Notify(message),
+      (_, None) => PushCheck::Accept,
+    }
+  }
+
+  fn after_push(&self, push: &Push) -> Option<String> {
+    let updates = self.updated_branches(push).unwrap_or_else(|e| {
+      error!("Unable to list the branches of {}: {}", self.path, e);
+      vec![]
+    });
+    if let (Some(recorder), false) = (&self.push_recorder, updates.is_empty()) {
+      if let Err(e) = recorder(&updates) {
+        error!("Unable to record the push to {}: {}", self.path, e);
+      }
+    }
+    if let (Some(refresh), false) = (&self.anchor_refresh, updates.is_empty()) {
+      if let Err(e) = refresh(&updates) {
+        error!(
+          "Unable to refresh the comment anchors of {}: {}",
+          self.path, e
+        );
+      }
+    }
+
+    let mut messages = Vec::new();
+    if self.holds_pipeline {
+      match self.check_pipeline() {
+        Ok(report) => messages.push(report),
+        Err(e) => error!("Unable to check the pipeline of {}: {}", self.path, e),
+      }
+    }
+    if let Some(trigger) = &self.ci_trigger {
+      match self.queue_ci(trigger, push, &updates) {
+        Ok(report) => messages.extend(report),
+        Err(e) => {
+          error!("Unable to queue the CI of {}: {}", self.path, e);
+          messages.push("The CI couldn't be queued for this push".to_string());
+        }
+      }
+    }
+    (!messages.is_empty()).then(|| messages.join("\n"))
   }
 }
 
 #[cfg(test)]
 mod test {
+  use std::{sync::Mutex, time::SystemTime};
+
   use super::*;
 
   #[test]
   fn test_get_permissions() {
-    let repo = DbRepository {};
+    let repo = DbRepository::new("repo.git".to_string(), true, false);
+
+    assert!(repo.has_permission(&GmtUser::Admin, RepositoryPermission::Read));
+    assert!(repo.has_permission(&GmtUser::Connected(1), RepositoryPermission::Read));
+    assert!(!repo.has_permission(&GmtUser::Public, RepositoryPermission::Read));
+
+    assert!(repo.has_permission(&GmtUser::Admin, RepositoryPermission::Write));
+    assert!(!repo.has_permission(&GmtUser::Connected(1), RepositoryPermission::Write));
+    assert!(!repo.has_permission(&GmtUser::Public, RepositoryPermission::Write));
+  }
+
+  #[test]
+  fn test_check_push() {
+    let repo = |decision, message: Option<&str>| {
+      DbRepository::new("repo.git".to_string(), true, true)
+        .with_push_decision(decision, message.map(str::to_string))
+    };
+    let user = GmtUser::Connected(1);
 
     assert_eq!(
-      repo.has_permission(&GmtUser::Admin, RepositoryPermission::Read),
-      true
+      repo(PushDecision::OnTime, None).check_push(&user),
+      PushCheck::Accept
     );
     assert_eq!(
-      repo.has_permission(&GmtUser::Connected(), RepositoryPermission::Read),
-      true
+      repo(PushDecision::Grace, Some("grace")).check_push(&user),
+      PushCheck::Notify("grace".to_string())
     );
     assert_eq!(
-      repo.has_permission(&GmtUser::Public, RepositoryPermission::Read),
-      false
+      repo(PushDecision::Late { flagged: true }, Some("late")).check_push(&user),
+      PushCheck::Notify("late".to_string())
     );
+    assert_eq!(
+      repo(PushDecision::Rejected, Some("too late")).check_push(&user),
+      PushCheck::Reject("too late".to_string())
+    );
+  }
 
+  #[test]
+  fn test_push_is_recorded() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let path = dir.path().join("repo.git");
+    let git = |args: &[&str]| {
+      let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(&path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init", "--bare", "--quiet"]);
+    let tree = git(&["mktree"]);
+    let first = git(&["commit-tree", &tree, "-m", "first"]);
+    git(&["update-ref", "refs/heads/main", &first]);
+
+    let recorded = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let recorder: PushRecorder = {
+      let recorded = recorded.clone();
+      Box::new(move |updates: &[RefUpdate]| {
+        recorded.lock().unwrap().extend_from_slice(updates);
+        Ok(())
+      })
+    };
+    let repo = DbRepository::new(path.to_string_lossy().to_string(), true, true)
+      .with_push_recorder(recorder);
+    let update = |new: &str, name: &str| RefUpdate {
+      old: "0".repeat(40),
+      new: new.to_string(),
+      name: name.to_string(),
+    };
+    let push = Push {
+      updates: vec![
+        update(&first, "refs/heads/main"),
+        // Rejected by git
+        update(&"1".repeat(40), "refs/heads/other"),
+        update(&first, "refs/tags/v1"),
+      ],
+      options: vec![],
+    };
+    assert_eq!(repo.after_push(&push), None);
     assert_eq!(
-      repo.has_permission(&GmtUser::Admin, RepositoryPermission::Write),
-      true
+      *recorded.lock().unwrap(),
+      vec![update(&first, "refs/heads/main")]
+    );
+
+    // Deleting a branch isn't recorded
+    let push = Push {
+      updates: vec![RefUpdate {
+        old: first.clone(),
+        new: "0".repeat(40),
+        name: "refs/heads/main".to_string(),
+      }],
+      options: vec![],
+    };
+    assert_eq!(repo.after_push(&push), None);
+    assert_eq!(recorded.lock().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_pipeline_is_checked_after_push() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let path = dir.path().join("repo.git");
+    let git = |args: &[&str], input: Option<&str>| {
+      let mut child = Command::new("git")
+        .arg("--git-dir")
+        .arg(&path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Unable to run git");
+      if let Some(input) = input {
+        use std::io::Write;
+        child
+          .stdin
+          .take()
+          .unwrap()
+          .write_all(input.as_bytes())
+          .unwrap();
+      }
+      let output = child.wait_with_output().unwrap();
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(
+      &["init", "--bare", "--quiet", "--initial-branch=main"],
+      None,
     );
+    let repo =
+      DbRepository::new(path.to_string_lossy().to_string(), true, true).with_pipeline(true);
+    let commit = |content: &str| {
+      let blob = git(&["hash-object", "-w", "--stdin"], Some(content));
+      let tree = git(
+        &["mktree"],
+        Some(&format!("100644 blob {}\t{}\n", blob, PIPELINE_FILE)),
+      );
+      let commit = git(&["commit-tree", &tree, "-m", "pipeline"], None);
+      git(&["update-ref", "refs/heads/main", &commit], None);
+    };
+
     assert_eq!(
-      repo.has_permission(&GmtUser::Connected(), RepositoryPermission::Write),
-      true
+      repo.after_push(&Push::default()),
+      Some(
+        "No gmt-ci.toml found on the default branch, the CI of the assignment won't run"
+          .to_string()
+      )
     );
+
+    commit("version = 1\n[[steps]]\nname = \"test\"\nrun = \"make test\"\n");
+    assert_eq!(
+      repo.after_push(&Push::default()),
+      Some("gmt-ci.toml is valid: 1 step(s), test".to_string())
+    );
+
+    commit("version = 1\n");
+    assert_eq!(
+      repo.after_push(&Push::default()),
+      Some(
+        "gmt-ci.toml is invalid:\n  The pipeline has no step, add at least one `[[steps]]` table"
+          .to_string()
+      )
+    );
+
+    // Other repositories aren't checked
+    let repo = DbRepository::new(path.to_string_lossy().to_string(), true, true);
+    assert_eq!(repo.after_push(&Push::default()), None);
+  }
+
+  #[test]
+  fn test_ci_is_queued_after_push() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let path = dir.path().join("repo.git");
+    let git = |args: &[&str]| {
+      let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(&path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init", "--bare", "--quiet"]);
+    let tree = git(&["mktree"]);
+    let commit = git(&["commit-tree", &tree, "-m", "first"]);
+    git(&["update-ref", "refs/heads/main", &commit]);
+
+    let triggered = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let trigger: CiTrigger = {
+      let triggered = triggered.clone();
+      Box::new(move |updates: &[RefUpdate], _: &HashMap<String, String>| {
+        triggered.lock().unwrap().extend_from_slice(updates);
+        Ok(
+          updates
+            .iter()
+            .enumerate()
+            .map(|(i, update)| Cirun {
+              id: i as i32 + 1,
+              repository_id: 1,
+              commit: update.new.clone(),
+              status: database::db_handle::cirun::Status::Pending,
+              created_at: SystemTime::UNIX_EPOCH,
+              started_at: None,
+              finished_at: None,
+              heartbeat_at: None,
+              ref_name: Some(update.name.clone()),
+              triggered_by: None,
+              worker_id: None,
+              attempt: 0,
+            })
+            .collect(),
+        )
+      })
+    };
+    let refreshed = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let refresh: AnchorRefresh = {
+      let refreshed = refreshed.clone();
+      Box::new(move |updates: &[RefUpdate]| {
+        refreshed.lock().unwrap().extend_from_slice(updates);
+        Ok(())
+      })
+    };
+    let repo = DbRepository::new(path.to_string_lossy().to_string(), true, true)
+      .with_ci_trigger(trigger)
+      .with_anchor_refresh(refresh);
+    let update = |new: &str, name: &str| RefUpdate {
+      old: "0".repeat(40),
+      new: new.to_string(),
+      name: name.to_string(),
+    };
+    let mut push = Push {
+      updates: vec![
+        update(&commit, "refs/heads/main"),
+        // Rejected by git
+        update(&"1".repeat(40), "refs/heads/other"),
+        update(&commit, "refs/tags/v1"),
+      ],
+      options: vec![],
+    };
+
+    assert_eq!(
+      repo.after_push(&push),
+      Some("CI run #1 queued for main".to_string())
+    );
+    assert_eq!(
+      *triggered.lock().unwrap(),
+      vec![update(&commit, "refs/heads/main")]
+    );
+    // The comments of the pushed branches follow their code
     assert_eq!(
-      repo.has_permission(&GmtUser::Public, RepositoryPermission::Write),
-      false
+      *refreshed.lock().unwrap(),
+      vec![update(&commit, "refs/heads/main")]
     );
+
+    push.options.push(SKIP_CI_OPTION.to_string());
+    assert_eq!(
+      repo.after_push(&push),
+      Some("CI skipped, as requested by the ci.skip push option".to_string())
+    );
+    assert_eq!(triggered.lock().unwrap().len(), 1);
+
+    // Deleting a branch doesn't run anything
+    let push = Push {
+      updates: vec![update(&"0".repeat(40), "refs/heads/main")],
+      options: vec![],
+    };
+    assert_eq!(repo.after_push(&push), None);
   }
 }
@@ -1,25 +1,504 @@
-use git_server::repository::RepositoryProvider;
+use std::{sync::Arc, time::SystemTime};
 
-use crate::gmt_user::GmtUser;
+use database::{
+  connection_pool::ConnectionProvider,
+  db_handle::{
+    assignment::AssignmentDbHandle,
+    cirun::CirunDbHandle,
+    comment::CommentDbHandle,
+    group::GroupDbHandle,
+    push::PushDbHandle,
+    repository::{RepositoryDbHandle, Repotype},
+  },
+};
+use git_server::{
+  objects::GitObjects,
+  repository::{RepositoryPermission, RepositoryProvider},
+};
+use log::error;
 
-use super::db_repository::DbRepository;
+use crate::{
+  ci_triggers::queue_push_runs,
+  comment_anchors::refresh_comment_anchors,
+  deadlines::{check_submission_push, PushDecision},
+  gmt_user::GmtUser,
+  permissions::has_repository_permission,
+};
 
-pub struct DbRepositoryProvider {
-  // db_handle: DbConnection,
+use super::{
+  db_repository::DbRepository,
+  repository_storage::{is_valid_repository_name, RepositoryStorage},
+};
+
+/// Finds the repositories in the database, checking the permissions with the same rules as the API.
+pub struct DbRepositoryProvider<DbPool> {
+  db: Arc<DbPool>,
+  storage: RepositoryStorage,
+  /// Gives the current time, when checking the due dates of the pushes
+  clock: fn() -> SystemTime,
 }
 
-impl DbRepositoryProvider {
-  #[allow(clippy::new_without_default)] // TODO: remove this when adding the database
-  pub fn new() -> Self {
-    DbRepositoryProvider {}
+impl<DbPool, Db> DbRepositoryProvider<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db> + Send + Sync + 'static,
+  Db: AssignmentDbHandle
+    + CirunDbHandle
+    + CommentDbHandle
+    + GroupDbHandle
+    + PushDbHandle
+    + RepositoryDbHandle,
+{
+  pub fn new(db: DbPool, storage: RepositoryStorage) -> Self {
+    DbRepositoryProvider {
+      db: Arc::new(db),
+      storage,
+      clock: SystemTime::now,
+    }
+  }
+
+  /// Replaces the clock used to check the due dates, to freeze the time in tests.
+  pub fn with_clock(mut self, clock: fn() -> SystemTime) -> Self {
+    self.clock = clock;
+    self
   }
 }
 
-impl RepositoryProvider for DbRepositoryProvider {
+/// Extracts the repository name from the path given to git, such as `/my-repo.git`.
+fn repository_name(path: &str) -> Option<&str> {
+  let name = path.trim_matches('/');
+  let name = name.strip_suffix(".git").unwrap_or(name);
+  is_valid_repository_name(name).then_some(name)
+}
+
+impl<DbPool, Db> RepositoryProvider for DbRepositoryProvider<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db> + Send + Sync + 'static,
+  Db: AssignmentDbHandle
+    + CirunDbHandle
+    + CommentDbHandle
+    + GroupDbHandle
+    + PushDbHandle
+    + RepositoryDbHandle,
+{
   type User = GmtUser;
   type Repository = DbRepository;
 
-  fn find_repository(&self, _user: &Self::User, _path: &str) -> Option<Self::Repository> {
-    todo!("Get repository from database")
+  fn find_repository(&self, user: &Self::User, path: &str) -> Option<Self::Repository> {
+    let name = repository_name(path)?;
+    let mut db = self
+      .db
+      .get_connection()
+      .map_err(|e| error!("Unable to get a database connection: {}", e))
+      .ok()?;
+    let repository = db
+      .get_repository_by_name(name)
+      .map_err(|e| error!("Unable to find repository {}: {}", name, e))
+      .ok()??;
+
+    let mut has_permission = |permission| match user {
+      GmtUser::Admin => true,
+      GmtUser::Public => false,
+      GmtUser::Connected(user_id) => {
+        has_repository_permission(&mut db, &repository, *user_id, permission)
+          .map_err(|e| error!("Unable to check permissions on {}: {}", name, e))
+          .unwrap_or(false)
+      }
+    };
+    let can_read = has_permission(RepositoryPermission::Read);
+    let mut can_write = has_permission(RepositoryPermission::Write);
+
+    let now = (self.clock)();
+    let mut push_decision = (PushDecision::OnTime, None);
+    if let (GmtUser::Connected(user_id), true) = (user, can_write) {
+      match check_submission_push(&mut db, &repository, *user_id, now) {
+        Ok(decision) => push_decision = decision,
+        Err(e) => {
+          // The due date can't be checked, so the push is refused
+          error!("Unable to check the due date of {}: {}", name, e);
+          can_write = false;
+        }
+      }
+    }
+
+    // The pushes to the repositories whose assignment has a CI repository run it
+    let mut runs_ci = false;
+    if let (Some(assignment_id), true) = (repository.assignment_id, can_write) {
+      match db.get_assignment_test_repo(assignment_id) {
+        Ok(test_repo) => runs_ci = test_repo.is_some_and(|test_repo| test_repo.id != repository.id),
+        Err(e) => error!("Unable to find the CI repository of {}: {}", name, e),
+      }
+    }
+
+    let path = self.storage.get_path(name).to_string_lossy().to_string();
+    let (decision, message) = push_decision;
+    let mut found = DbRepository::new(path.clone(), can_read, can_write)
+      .with_push_decision(decision, message)
+      .with_pipeline(repository.repo_type == Repotype::Ci);
+    let pushed_by = match user {
+      GmtUser::Connected(user_id) => Some(*user_id),
+      _ => None,
+    };
+    // The server keeps the evidence of when the submissions were pushed, and whether they were
+    // late, as the history of the repository is in the hands of the student
+    if let (Some(_), true) = (repository.assignment_id, can_write) {
+      let pool = self.db.clone();
+      let repository_id = repository.id;
+      found = found.with_push_recorder(Box::new(move |updates| {
+        let mut db = pool.get_connection()?;
+        for update in updates {
+          db.create_push_record(
+            repository_id,
+            pushed_by,
+            &update.name,
+            &update.new,
+            now,
+            decision.is_late(),
+          )?;
+        }
+        Ok(())
+      }));
+    }
+    if can_write {
+      let pool = self.db.clone();
+      let repository_id = repository.id;
+      found = found.with_anchor_refresh(Box::new(move |updates| {
+        let mut db = pool.get_connection()?;
+        let objects = GitObjects::open(&path)?;
+        for update in updates {
+          refresh_comment_anchors(&mut db, &objects, repository_id, &update.new)?;
+        }
+        Ok(())
+      }));
+    }
+    if runs_ci {
+      let pool = self.db.clone();
+      let repository_id = repository.id;
+      found = found.with_ci_trigger(Box::new(move |updates, branches| {
+        let mut db = pool.get_connection()?;
+        queue_push_runs(&mut db, repository_id, pushed_by, updates, branches)
+      }));
+    }
+    Some(found)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use database::{
+    connection_pool::ConnectionPool,
+    db_handle::{
+      assignment::{Assignment, AssignmentMetadata, Latepolicy},
+      cirun::{Cirun, Status},
+      group::Group,
+      push::PushRecord,
+      repository::{Repository as DbRepositoryRow, Repotype},
+    },
+    fixtures::{cirun, user},
+    DbHandle,
+  };
+  use git_server::{
+    push::{Push, RefUpdate},
+    repository::{PushCheck, Repository},
+  };
+  use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+  };
+
+  const OWNER_ID: i32 = 1;
+  const STUDENT_ID: i32 = 2;
+
+  fn provider() -> DbRepositoryProvider<ConnectionPool> {
+    provider_with_assignments(vec![])
+  }
+
+  /// A provider where `repo` is the base repository of the given assignments, in a group with one
+  /// student
+  fn provider_with_assignments(
+    assignments: Vec<Assignment>,
+  ) -> DbRepositoryProvider<ConnectionPool> {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.get_repository_by_name).then(|name| match name {
+        "repo" => Ok(Some(DbRepositoryRow {
+          id: 1,
+          name: "repo".to_string(),
+          repo_type: Repotype::Default,
+          owner_id: OWNER_ID,
+          assignment_id: None,
+        })),
+        _ => Ok(None),
+      });
+      let assignments = assignments.clone();
+      faux::when!(db.list_repository_assignments(1)).then(move |_| Ok(assignments.clone()));
+      faux::when!(db.get_group_by_id).then(|id| {
+        Ok(Some(Group {
+          id,
+          name: "group".to_string(),
+          teacher_id: Some(OWNER_ID),
+        }))
+      });
+      faux::when!(db.list_students).then(|_| Ok(vec![user(STUDENT_ID, "student")]));
+      Ok(db)
+    });
+    DbRepositoryProvider::new(pool, RepositoryStorage::new(PathBuf::from("/repositories")))
+  }
+
+  #[test]
+  fn test_repository_name() {
+    assert_eq!(repository_name("/repo.git"), Some("repo"));
+    assert_eq!(repository_name("repo"), Some("repo"));
+    assert_eq!(repository_name("/../repo.git"), None);
+  }
+
+  #[test]
+  fn test_find_repository() {
+    let provider = provider();
+
+    let repository = provider
+      .find_repository(&GmtUser::Connected(OWNER_ID), "/repo.git")
+      .expect("Repository not found");
+    assert_eq!(repository.get_path(), "/repositories/repo.git");
+    assert!(repository.has_permission(&GmtUser::Connected(OWNER_ID), RepositoryPermission::Write));
+
+    let repository = provider
+      .find_repository(&GmtUser::Connected(2), "/repo.git")
+      .expect("Repository not found");
+    assert!(!repository.has_permission(&GmtUser::Connected(2), RepositoryPermission::Read));
+
+    assert!(provider
+      .find_repository(&GmtUser::Connected(OWNER_ID), "/missing.git")
+      .is_none());
+  }
+
+  #[test]
+  fn test_base_repository_visible_once_released() {
+    let assignment = |release_date| Assignment {
+      id: 1,
+      group_id: 1,
+      base_repo_id: 1,
+      test_repo_id: None,
+      correction_repo_id: None,
+      metadata: AssignmentMetadata {
+        release_date,
+        ..Default::default()
+      },
+    };
+    let next_year = SystemTime::now() + Duration::from_secs(365 * 24 * 3600);
+    let student = GmtUser::Connected(STUDENT_ID);
+
+    for (release_date, visible) in [(None, true), (Some(next_year), false)] {
+      let provider = provider_with_assignments(vec![assignment(release_date)]);
+      let repository = provider
+        .find_repository(&student, "/repo.git")
+        .expect("Repository not found");
+      assert_eq!(
+        repository.has_permission(&student, RepositoryPermission::Read),
+        visible
+      );
+      assert!(!repository.has_permission(&student, RepositoryPermission::Write));
+    }
+  }
+
+  /// A provider where `submission` is the repository of the student for an assignment due at
+  /// `DUE`, with the time frozen at `DUE + 1h`
+  fn provider_with_late_policy(late_policy: Latepolicy) -> DbRepositoryProvider<ConnectionPool> {
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.get_repository_by_name).then(|name| {
+        Ok((name == "submission").then(|| DbRepositoryRow {
+          id: 2,
+          name: "submission".to_string(),
+          repo_type: Repotype::Default,
+          owner_id: STUDENT_ID,
+          assignment_id: Some(1),
+        }))
+      });
+      faux::when!(db.get_assignment_by_id(1)).then(move |_| {
+        Ok(Some(Assignment {
+          id: 1,
+          group_id: 1,
+          base_repo_id: 1,
+          test_repo_id: None,
+          correction_repo_id: None,
+          metadata: AssignmentMetadata {
+            due_date: Some(SystemTime::UNIX_EPOCH + DUE),
+            late_policy,
+            ..Default::default()
+          },
+        }))
+      });
+      faux::when!(db.get_assignment_group(1)).then(|_| {
+        Ok(Some(Group {
+          id: 1,
+          name: "group".to_string(),
+          teacher_id: Some(OWNER_ID),
+        }))
+      });
+      faux::when!(db.get_assignment_test_repo(1)).then(|_| Ok(None));
+      Ok(db)
+    });
+    DbRepositoryProvider::new(pool, RepositoryStorage::new(PathBuf::from("/repositories")))
+      .with_clock(|| SystemTime::UNIX_EPOCH + DUE + Duration::from_secs(3600))
+  }
+
+  const DUE: Duration = Duration::from_secs(1_700_000_000);
+
+  #[test]
+  fn test_late_push_checks() {
+    let student = GmtUser::Connected(STUDENT_ID);
+    let due = "The assignment was due on 2023-11-14 22:13 UTC";
+    let cases = [
+      (
+        Latepolicy::Accept,
+        PushCheck::Notify(format!("{}, this push is late but accepted.", due)),
+      ),
+      (
+        Latepolicy::Flag,
+        PushCheck::Notify(format!(
+          "{}, this push is accepted but recorded as late.",
+          due
+        )),
+      ),
+      (
+        Latepolicy::Reject,
+        PushCheck::Reject(format!("{}, late pushes are not accepted.", due)),
+      ),
+    ];
+
+    for (late_policy, check) in cases {
+      let repository = provider_with_late_policy(late_policy)
+        .find_repository(&student, "/submission.git")
+        .expect("Repository not found");
+      assert!(repository.has_permission(&student, RepositoryPermission::Write));
+      assert_eq!(repository.check_push(&student), check);
+    }
+  }
+
+  #[test]
+  fn test_teacher_push_after_due_date() {
+    let teacher = GmtUser::Connected(OWNER_ID);
+    let repository = provider_with_late_policy(Latepolicy::Reject)
+      .find_repository(&teacher, "/submission.git")
+      .expect("Repository not found");
+    assert_eq!(repository.check_push(&teacher), PushCheck::Accept);
+  }
+
+  #[test]
+  fn test_push_queues_ci_runs() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let path = dir.path().join("submission.git");
+    let git = |args: &[&str]| {
+      let output = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(&path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init", "--bare", "--quiet"]);
+    let tree = git(&["mktree"]);
+    let commit = git(&["commit-tree", &tree, "-m", "first"]);
+    git(&["update-ref", "refs/heads/main", &commit]);
+
+    let mut pool = ConnectionPool::faux();
+    let head = commit.clone();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      faux::when!(db.get_repository_by_name).then(|name| {
+        Ok((name == "submission").then(|| DbRepositoryRow {
+          id: 2,
+          name: "submission".to_string(),
+          repo_type: Repotype::Default,
+          owner_id: STUDENT_ID,
+          assignment_id: Some(1),
+        }))
+      });
+      faux::when!(db.get_assignment_by_id(1)).then(|_| {
+        Ok(Some(Assignment {
+          id: 1,
+          group_id: 1,
+          base_repo_id: 1,
+          test_repo_id: Some(3),
+          correction_repo_id: None,
+          metadata: AssignmentMetadata {
+            due_date: Some(SystemTime::UNIX_EPOCH + DUE),
+            late_policy: Latepolicy::Flag,
+            ..Default::default()
+          },
+        }))
+      });
+      faux::when!(db.get_assignment_test_repo(1)).then(|_| {
+        Ok(Some(DbRepositoryRow {
+          id: 3,
+          name: "tests".to_string(),
+          repo_type: Repotype::Ci,
+          owner_id: OWNER_ID,
+          assignment_id: None,
+        }))
+      });
+      faux::when!(db.list_repository_ciruns(2)).then(|_| Ok(vec![]));
+      faux::when!(db.list_anchored_comments(2)).then(|_| Ok(vec![]));
+      // The push is recorded as late by the server
+      let pushed = head.clone();
+      faux::when!(db.create_push_record(2, Some(STUDENT_ID), "refs/heads/main", _, _, true))
+        .once()
+        .then(
+          move |(repository_id, user_id, ref_name, commit, pushed_at, late)| {
+            assert_eq!(commit, pushed);
+            assert_eq!(
+              pushed_at,
+              SystemTime::UNIX_EPOCH + DUE + Duration::from_secs(3600)
+            );
+            Ok(PushRecord {
+              id: 1,
+              repository_id,
+              user_id,
+              ref_name: ref_name.to_string(),
+              commit_hash: commit.to_string(),
+              pushed_at,
+              late,
+            })
+          },
+        );
+      let head = head.clone();
+      faux::when!(db.create_cirun_for_ref(2, _, "refs/heads/main", Some(STUDENT_ID))).then(
+        move |(_, commit, ref_name, triggered_by)| {
+          assert_eq!(commit, head);
+          Ok(Cirun {
+            commit: commit.to_string(),
+            ref_name: Some(ref_name.to_string()),
+            triggered_by,
+            ..cirun(7, 2, Status::Pending)
+          })
+        },
+      );
+      Ok(db)
+    });
+    let provider = DbRepositoryProvider::new(pool, RepositoryStorage::new(dir.path().into()))
+      .with_clock(|| SystemTime::UNIX_EPOCH + DUE + Duration::from_secs(3600));
+
+    let student = GmtUser::Connected(STUDENT_ID);
+    let repository = provider
+      .find_repository(&student, "/submission.git")
+      .expect("Repository not found");
+    let push = Push {
+      updates: vec![RefUpdate {
+        old: "0".repeat(40),
+        new: commit,
+        name: "refs/heads/main".to_string(),
+      }],
+      options: vec![],
+    };
+    assert_eq!(
+      repository.after_push(&push),
+      Some("CI run #7 queued for main".to_string())
+    );
   }
 }
@@ -1,2 +1,4 @@
 pub mod db_repository;
 pub mod db_repository_provider;
+pub mod provisioning;
+pub mod repository_storage;
@@ -0,0 +1,416 @@
+//! Creates the submission repositories of the students, forked from the base repository of the
+//! assignment.
+//!
+//! Provisioning is idempotent: students who already have a repository for the assignment are
+//! skipped, so it can be run again whenever a student joins the group late. The assignments
+//! released later on are provisioned by the periodic sweep of `provision_released_assignments`.
+
+use std::time::SystemTime;
+
+use database::{
+  db_handle::{
+    assignment::{Assignment, AssignmentDbHandle},
+    group::GroupDbHandle,
+    repository::{
+      Repository, RepositoryDbHandle, Repotype, REPOSITORY_NAME_CONSTRAINT,
+      SUBMISSION_REPOSITORY_CONSTRAINT,
+    },
+    transaction::TransactionDbHandle,
+    user::User,
+  },
+  error::DatabaseError,
+};
+use thiserror::Error;
+
+use crate::deadlines::is_released;
+
+use super::repository_storage::{is_valid_repository_name, RepositoryStorage};
+
+#[derive(Error, Debug)]
+pub enum ProvisionError {
+  #[error("Database error: {0}")]
+  DatabaseError(#[from] DatabaseError),
+  #[error("Storage error: {0}")]
+  IoError(#[from] std::io::Error),
+  #[error("The base repository of the assignment doesn't exist")]
+  BaseRepositoryNotFound,
+  #[error("No repository name available for {0}")]
+  NameUnavailable(String),
+}
+
+/// The name of the submission repository of a student, such as `linked-lists-alice`. Characters
+/// of the username which can't be used in a repository name are replaced by dashes.
+pub fn submission_repository_name(base_name: &str, username: &str) -> String {
+  let username: String = username
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+        c
+      } else {
+        '-'
+      }
+    })
+    .collect();
+  let mut name = format!("{}-{}", base_name, username);
+  name.truncate(255);
+  name
+}
+
+/// Creates the missing submission repositories of all the students of the group. Nothing is done
+/// before the release of the assignment. Returns the repositories which were created.
+pub fn provision_assignment<Db>(
+  db: &mut Db,
+  storage: &RepositoryStorage,
+  assignment: &Assignment,
+  now: SystemTime,
+) -> Result<Vec<Repository>, ProvisionError>
+where
+  Db: AssignmentDbHandle + GroupDbHandle + RepositoryDbHandle + TransactionDbHandle,
+{
+  if !is_released(&assignment.metadata, now) {
+    return Ok(vec![]);
+  }
+
+  let mut created = Vec::new();
+  for student in db.list_students(assignment.group_id)? {
+    if let Some(repository) = provision_student(db, storage, assignment, &student, now)? {
+      created.push(repository);
+    }
+  }
+  Ok(created)
+}
+
+/// Provisions the assignments released by `now` which still have students without a submission
+/// repository. A failure is only logged, so that the other assignments are still provisioned and
+/// the next sweep tries again. Returns the repositories which were created.
+pub fn provision_released_assignments<Db>(
+  db: &mut Db,
+  storage: &RepositoryStorage,
+  now: SystemTime,
+) -> Result<Vec<Repository>, ProvisionError>
+where
+  Db: AssignmentDbHandle + GroupDbHandle + RepositoryDbHandle + TransactionDbHandle,
+{
+  let mut created = Vec::new();
+  for assignment in db.list_unprovisioned_assignments(now)? {
+    match provision_assignment(db, storage, &assignment, now) {
+      Ok(repositories) => created.extend(repositories),
+      Err(e) => log::warn!("Unable to provision assignment {}: {}", assignment.id, e),
+    }
+  }
+  Ok(created)
+}
+
+/// Creates the submission repository of the student if it doesn't have one yet and the assignment
+/// has been released. Returns the repository if it was created.
+pub fn provision_student<Db>(
+  db: &mut Db,
+  storage: &RepositoryStorage,
+  assignment: &Assignment,
+  student: &User,
+  now: SystemTime,
+) -> Result<Option<Repository>, ProvisionError>
+where
+  Db: AssignmentDbHandle + GroupDbHandle + RepositoryDbHandle + TransactionDbHandle,
+{
+  if !is_released(&assignment.metadata, now)
+    || db
+      .get_assignment_submission_repos(assignment.id)?
+      .iter()
+      .any(|r| r.owner_id == student.id)
+  {
+    return Ok(None);
+  }
+  let base = db
+    .get_repository_by_id(assignment.base_repo_id)?
+    .ok_or(ProvisionError::BaseRepositoryNotFound)?;
+
+  // Falls back on the user id when the name is already taken
+  let name = submission_repository_name(&base.name, &student.username);
+  for candidate in [name.clone(), format!("{}-{}", name, student.id)] {
+    if !is_valid_repository_name(&candidate) || db.get_repository_by_name(&candidate)?.is_some() {
+      continue;
+    }
+    match fork_base_repository(db, storage, assignment, student, &base, &candidate) {
+      Ok(repository) => return Ok(Some(repository)),
+      Err(ProvisionError::DatabaseError(e)) => match e.unique_constraint() {
+        // Provisioned concurrently for the same student, nothing left to do
+        Some(SUBMISSION_REPOSITORY_CONSTRAINT) => return Ok(None),
+        // The name was taken concurrently by another repository
+        Some(REPOSITORY_NAME_CONSTRAINT) => continue,
+        _ => return Err(e.into()),
+      },
+      Err(e) => return Err(e),
+    }
+  }
+  Err(ProvisionError::NameUnavailable(student.username.clone()))
+}
+
+/// Creates the submission repository of the student under the given name, forked from the base
+/// repository. Nothing is left behind on failure.
+fn fork_base_repository<Db>(
+  db: &mut Db,
+  storage: &RepositoryStorage,
+  assignment: &Assignment,
+  student: &User,
+  base: &Repository,
+  name: &str,
+) -> Result<Repository, ProvisionError>
+where
+  Db: RepositoryDbHandle + TransactionDbHandle,
+{
+  let mut forked = false;
+  let repository = db.transaction(|db| {
+    let repository =
+      db.create_repository(name, &Repotype::Default, student.id, Some(assignment.id))?;
+    storage.fork_repository(&base.name, name)?;
+    forked = true;
+    Ok::<_, ProvisionError>(repository)
+  });
+
+  // Left on disk, the fork would prevent the student from being provisioned again
+  if repository.is_err() && forked {
+    if let Err(e) = storage.delete_repository(name) {
+      log::warn!("Unable to remove repository {} after failure: {}", name, e);
+    }
+  }
+  repository
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
+
+  use database::{
+    db_handle::assignment::AssignmentMetadata,
+    fixtures::{repository, unique_violation, user},
+    DbHandle,
+  };
+
+  use super::*;
+
+  fn git(path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+      .arg("--git-dir")
+      .arg(path)
+      .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+      .args(args)
+      .output()
+      .expect("Unable to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+  }
+
+  fn assignment(release_date: Option<SystemTime>) -> Assignment {
+    Assignment {
+      id: 1,
+      group_id: 1,
+      base_repo_id: 10,
+      test_repo_id: None,
+      correction_repo_id: None,
+      metadata: AssignmentMetadata {
+        release_date,
+        ..Default::default()
+      },
+    }
+  }
+
+  fn named_repository(
+    id: i32,
+    name: &str,
+    owner_id: i32,
+    assignment_id: Option<i32>,
+  ) -> Repository {
+    Repository {
+      name: name.to_string(),
+      ..repository(id, owner_id, assignment_id)
+    }
+  }
+
+  #[test]
+  fn test_submission_repository_name() {
+    assert_eq!(submission_repository_name("base", "alice"), "base-alice");
+    assert_eq!(
+      submission_repository_name("base", "bob smith@home"),
+      "base-bob-smith-home"
+    );
+  }
+
+  #[test]
+  fn test_provision_assignment() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    let base = storage.create_repository("base").unwrap();
+    let tree = git(&base, &["mktree"]);
+    let commit = git(&base, &["commit-tree", &tree, "-m", "initial"]);
+    git(&base, &["update-ref", "refs/heads/main", &commit]);
+
+    // alice already has a repository, and bob's default name is taken
+    let created_repos = Arc::new(Mutex::new(Vec::new()));
+    let mut db = DbHandle::faux();
+    faux::when!(db.list_students(1))
+      .then(|_| Ok(vec![user(1, "alice"), user(2, "bob"), user(3, "carol")]));
+    faux::when!(db.get_assignment_submission_repos(1))
+      .then(|_| Ok(vec![named_repository(20, "base-alice", 1, Some(1))]));
+    faux::when!(db.get_repository_by_id(10))
+      .then(|_| Ok(Some(named_repository(10, "base", 4, None))));
+    faux::when!(db.get_repository_by_name)
+      .then(|name| Ok((name == "base-bob").then(|| named_repository(21, "base-bob", 4, None))));
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.commit_transaction).then(|_| Ok(()));
+    let created = created_repos.clone();
+    faux::when!(db.create_repository).then(move |(name, _, owner_id, assignment_id)| {
+      created.lock().unwrap().push(name.to_string());
+      Ok(named_repository(
+        30 + owner_id,
+        name,
+        owner_id,
+        assignment_id,
+      ))
+    });
+
+    let repositories =
+      provision_assignment(&mut db, &storage, &assignment(None), SystemTime::now())
+        .expect("Unable to provision repositories");
+    let names: Vec<&str> = repositories.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["base-bob-2", "base-carol"]);
+    assert!(repositories.iter().all(|r| r.assignment_id == Some(1)));
+    assert_eq!(
+      *created_repos.lock().unwrap(),
+      vec!["base-bob-2", "base-carol"]
+    );
+    assert_eq!(
+      storage.resolve_branch("base-carol", "main").unwrap(),
+      Some(commit)
+    );
+  }
+
+  #[test]
+  fn test_provision_released_assignments() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    storage.create_repository("base").unwrap();
+    let release_date = SystemTime::now() + Duration::from_secs(3600);
+
+    let mut db = DbHandle::faux();
+    faux::when!(db.list_unprovisioned_assignments)
+      .then(move |_| Ok(vec![assignment(Some(release_date))]));
+    faux::when!(db.list_students(1)).then(|_| Ok(vec![user(1, "alice")]));
+    faux::when!(db.get_assignment_submission_repos(1)).then(|_| Ok(vec![]));
+    faux::when!(db.get_repository_by_id(10))
+      .then(|_| Ok(Some(named_repository(10, "base", 4, None))));
+    faux::when!(db.get_repository_by_name).then(|_| Ok(None));
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.commit_transaction).then(|_| Ok(()));
+    faux::when!(db.create_repository).then(|(name, _, owner_id, assignment_id)| {
+      Ok(named_repository(30, name, owner_id, assignment_id))
+    });
+
+    let repositories =
+      provision_released_assignments(&mut db, &storage, release_date - Duration::from_secs(60))
+        .expect("Unable to provision repositories");
+    assert!(repositories.is_empty());
+    assert!(!storage.get_path("base-alice").exists());
+
+    let repositories = provision_released_assignments(&mut db, &storage, release_date)
+      .expect("Unable to provision repositories");
+    let names: Vec<&str> = repositories.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["base-alice"]);
+    assert!(storage.get_path("base-alice").exists());
+  }
+
+  #[test]
+  fn test_provision_student_commit_failure() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    storage.create_repository("base").unwrap();
+
+    let mut db = DbHandle::faux();
+    faux::when!(db.get_assignment_submission_repos(1)).then(|_| Ok(vec![]));
+    faux::when!(db.get_repository_by_id(10))
+      .then(|_| Ok(Some(named_repository(10, "base", 4, None))));
+    faux::when!(db.get_repository_by_name).then(|_| Ok(None));
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.commit_transaction).then(|_| Err(DatabaseError::NotFound));
+    faux::when!(db.create_repository).then(|(name, _, owner_id, assignment_id)| {
+      Ok(named_repository(30, name, owner_id, assignment_id))
+    });
+
+    let res = provision_student(
+      &mut db,
+      &storage,
+      &assignment(None),
+      &user(1, "alice"),
+      SystemTime::now(),
+    );
+    assert!(res.is_err());
+    assert!(!storage.get_path("base-alice").exists());
+  }
+
+  #[test]
+  fn test_provision_student_concurrently() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    storage.create_repository("base").unwrap();
+
+    let mut db = DbHandle::faux();
+    faux::when!(db.get_assignment_submission_repos(1)).then(|_| Ok(vec![]));
+    faux::when!(db.get_repository_by_id(10))
+      .then(|_| Ok(Some(named_repository(10, "base", 4, None))));
+    faux::when!(db.get_repository_by_name).then(|_| Ok(None));
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.commit_transaction).then(|_| Ok(()));
+    faux::when!(db.rollback_transaction).then(|_| Ok(()));
+    // alice's default name is taken by another repository in the meantime
+    faux::when!(db.create_repository).then(|(name, _, owner_id, assignment_id)| match name {
+      "base-alice" => Err(unique_violation(REPOSITORY_NAME_CONSTRAINT)),
+      "base-bob" => Err(unique_violation(SUBMISSION_REPOSITORY_CONSTRAINT)),
+      _ => Ok(named_repository(30, name, owner_id, assignment_id)),
+    });
+
+    let repository = provision_student(
+      &mut db,
+      &storage,
+      &assignment(None),
+      &user(1, "alice"),
+      SystemTime::now(),
+    )
+    .expect("Unable to provision repository");
+    assert_eq!(repository.map(|r| r.name), Some("base-alice-1".to_string()));
+    assert!(storage.get_path("base-alice-1").exists());
+
+    // bob's repository is provisioned in the meantime
+    let repository = provision_student(
+      &mut db,
+      &storage,
+      &assignment(None),
+      &user(2, "bob"),
+      SystemTime::now(),
+    )
+    .expect("Unable to provision repository");
+    assert!(repository.is_none());
+    assert!(!storage.get_path("base-bob").exists());
+  }
+
+  #[test]
+  fn test_provision_unreleased_assignment() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    let now = SystemTime::now();
+
+    let mut db = DbHandle::faux();
+    let repositories = provision_assignment(
+      &mut db,
+      &storage,
+      &assignment(Some(now + Duration::from_secs(60))),
+      now,
+    )
+    .expect("Unable to provision repositories");
+    assert!(repositories.is_empty());
+  }
+}
@@ -0,0 +1,343 @@
+use std::{
+  io::{Error, ErrorKind},
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+const DEFAULT_REPOSITORIES_ROOT: &str = "repositories";
+
+/// Checks that a repository name can safely be used as a directory name.
+pub fn is_valid_repository_name(name: &str) -> bool {
+  !name.is_empty()
+    && name.len() <= 255
+    && !name.starts_with('.')
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Checks that a branch name is a plain name, following the rules of `git check-ref-format`.
+/// Revision syntax such as `main~3` or `main@{1}` is refused.
+pub fn is_valid_branch_name(branch: &str) -> bool {
+  !branch.is_empty()
+    && branch.len() <= 255
+    && branch != "@"
+    && !branch.starts_with('-')
+    && !branch.ends_with('.')
+    && !branch.contains("..")
+    && !branch.contains("@{")
+    && branch
+      .split('/')
+      .all(|part| !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock"))
+    && branch.chars().all(|c| {
+      !c.is_ascii_control() && !matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+    })
+}
+
+/// Manages the bare repositories stored on the server's filesystem.
+#[cfg_attr(feature = "mock", faux::create)]
+pub struct RepositoryStorage {
+  root: PathBuf,
+}
+
+#[cfg_attr(feature = "mock", faux::methods)]
+impl RepositoryStorage {
+  pub fn new(root: PathBuf) -> Self {
+    RepositoryStorage { root }
+  }
+
+  /// Uses the `REPOSITORIES_ROOT` environment variable, defaulting to `./repositories`.
+  pub fn new_from_env() -> Self {
+    let root =
+      std::env::var("REPOSITORIES_ROOT").unwrap_or_else(|_| DEFAULT_REPOSITORIES_ROOT.to_string());
+    RepositoryStorage::new(PathBuf::from(root))
+  }
+
+  /// Returns the path of the repository on disk, whether it exists or not.
+  pub fn get_path(&self, name: &str) -> PathBuf {
+    self.root.join(format!("{}.git", name))
+  }
+
+  /// Initializes a new bare repository. Fails if the repository already exists.
+  pub fn create_repository(&self, name: &str) -> Result<PathBuf, Error> {
+    if !is_valid_repository_name(name) {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Invalid repository name",
+      ));
+    }
+    let path = self.get_path(name);
+    if path.exists() {
+      return Err(Error::new(
+        ErrorKind::AlreadyExists,
+        "Repository already exists",
+      ));
+    }
+    std::fs::create_dir_all(&self.root)?;
+
+    let output = Command::new("git")
+      .arg("init")
+      .arg("--bare")
+      .arg("--quiet")
+      .arg(&path)
+      .output()?;
+    if !output.status.success() {
+      return Err(Error::other(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(path)
+  }
+
+  /// Creates a new bare repository holding a copy of the history of `source`. Fails if `source`
+  /// doesn't exist or if `name` already does.
+  pub fn fork_repository(&self, source: &str, name: &str) -> Result<PathBuf, Error> {
+    if !is_valid_repository_name(source) || !is_valid_repository_name(name) {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Invalid repository name",
+      ));
+    }
+    let source_path = self.get_path(source);
+    if !source_path.exists() {
+      return Err(Error::new(ErrorKind::NotFound, "Repository not found"));
+    }
+    let path = self.get_path(name);
+    if path.exists() {
+      return Err(Error::new(
+        ErrorKind::AlreadyExists,
+        "Repository already exists",
+      ));
+    }
+
+    // A partial clone would prevent forking the repository again under the same name
+    let res = clone_bare(&source_path, &path);
+    if res.is_err() {
+      if let Err(e) = std::fs::remove_dir_all(&path) {
+        if e.kind() != ErrorKind::NotFound {
+          log::warn!("Unable to remove {} after failure: {}", path.display(), e);
+        }
+      }
+    }
+    res.map(|_| path)
+  }
+
+  /// Returns the commit the branch points to, or `None` if the branch doesn't exist.
+  pub fn resolve_branch(&self, name: &str, branch: &str) -> Result<Option<String>, Error> {
+    if !is_valid_repository_name(name) {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Invalid repository name",
+      ));
+    }
+    if !is_valid_branch_name(branch) {
+      return Err(Error::new(ErrorKind::InvalidInput, "Invalid branch name"));
+    }
+
+    let output = Command::new("git")
+      .arg("--git-dir")
+      .arg(self.get_path(name))
+      .arg("rev-parse")
+      .arg("--verify")
+      .arg("--quiet")
+      .arg(format!("refs/heads/{}^{{commit}}", branch))
+      .output()?;
+    if !output.status.success() {
+      return Ok(None);
+    }
+
+    Ok(Some(
+      String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+  }
+
+  /// Removes the repository from the disk. Removing a missing repository is not an error.
+  pub fn delete_repository(&self, name: &str) -> Result<(), Error> {
+    if !is_valid_repository_name(name) {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Invalid repository name",
+      ));
+    }
+    match std::fs::remove_dir_all(self.get_path(name)) {
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+      res => res,
+    }
+  }
+}
+
+/// Clones the source repository as a standalone bare repository.
+fn clone_bare(source_path: &Path, path: &Path) -> Result<(), Error> {
+  let output = Command::new("git")
+    .arg("clone")
+    .arg("--bare")
+    .arg("--quiet")
+    .arg(source_path)
+    .arg(path)
+    .output()?;
+  if !output.status.success() {
+    return Err(Error::other(String::from_utf8_lossy(&output.stderr)));
+  }
+
+  // The fork is standalone, it shouldn't keep track of where it comes from
+  let output = Command::new("git")
+    .arg("--git-dir")
+    .arg(path)
+    .args(["remote", "remove", "origin"])
+    .output()?;
+  if !output.status.success() {
+    return Err(Error::other(String::from_utf8_lossy(&output.stderr)));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_valid_repository_name() {
+    assert!(is_valid_repository_name("my-repo_1.0"));
+    assert!(!is_valid_repository_name(""));
+    assert!(!is_valid_repository_name(".hidden"));
+    assert!(!is_valid_repository_name("../escape"));
+    assert!(!is_valid_repository_name("with/slash"));
+    assert!(!is_valid_repository_name("with space"));
+  }
+
+  #[test]
+  fn test_is_valid_branch_name() {
+    assert!(is_valid_branch_name("main"));
+    assert!(is_valid_branch_name("feature/login-page_2"));
+    assert!(!is_valid_branch_name(""));
+    assert!(!is_valid_branch_name("main~3"));
+    assert!(!is_valid_branch_name("main^"));
+    assert!(!is_valid_branch_name("main@{1}"));
+    assert!(!is_valid_branch_name("a..b"));
+    assert!(!is_valid_branch_name("-main"));
+    assert!(!is_valid_branch_name("feature/.hidden"));
+    assert!(!is_valid_branch_name("feature//login"));
+    assert!(!is_valid_branch_name("main.lock"));
+  }
+
+  #[test]
+  fn test_create_and_delete_repository() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+
+    let path = storage
+      .create_repository("repo")
+      .expect("Unable to create repository");
+    assert_eq!(path, dir.path().join("repo.git"));
+    assert!(path.join("HEAD").exists(), "Expected a bare repository");
+
+    let err = storage
+      .create_repository("repo")
+      .expect_err("Expected duplicated repository to fail");
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    storage
+      .delete_repository("repo")
+      .expect("Unable to delete repository");
+    assert!(!path.exists());
+    storage
+      .delete_repository("repo")
+      .expect("Deleting a missing repository should succeed");
+  }
+
+  #[test]
+  fn test_resolve_branch() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    let path = storage
+      .create_repository("repo")
+      .expect("Unable to create repository");
+
+    assert_eq!(storage.resolve_branch("repo", "main").unwrap(), None);
+
+    let git = |args: &[&str]| {
+      let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(&path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    let tree = git(&["mktree"]);
+    let commit = git(&["commit-tree", &tree, "-m", "initial"]);
+    git(&["update-ref", "refs/heads/main", &commit]);
+
+    assert_eq!(
+      storage.resolve_branch("repo", "main").unwrap(),
+      Some(commit)
+    );
+    assert_eq!(storage.resolve_branch("repo", "other").unwrap(), None);
+    let err = storage
+      .resolve_branch("repo", "main~1")
+      .expect_err("Expected revision syntax to fail");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn test_fork_repository() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+    let path = storage
+      .create_repository("base")
+      .expect("Unable to create repository");
+
+    let git = |path: &PathBuf, args: &[&str]| {
+      let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(path)
+        .args(["-c", "user.name=test", "-c", "user.email=test@test.com"])
+        .args(args)
+        .output()
+        .expect("Unable to run git");
+      assert!(output.status.success(), "git {:?} failed", args);
+      String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    let tree = git(&path, &["mktree"]);
+    let commit = git(&path, &["commit-tree", &tree, "-m", "initial"]);
+    git(&path, &["update-ref", "refs/heads/main", &commit]);
+
+    let fork = storage
+      .fork_repository("base", "fork")
+      .expect("Unable to fork repository");
+    assert_eq!(fork, dir.path().join("fork.git"));
+    assert_eq!(
+      storage.resolve_branch("fork", "main").unwrap(),
+      Some(commit)
+    );
+    assert_eq!(git(&fork, &["remote"]), "");
+
+    let err = storage
+      .fork_repository("base", "fork")
+      .expect_err("Expected duplicated repository to fail");
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    let err = storage
+      .fork_repository("missing", "other")
+      .expect_err("Expected missing source to fail");
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    std::fs::create_dir(storage.get_path("broken")).unwrap();
+    storage
+      .fork_repository("broken", "other")
+      .expect_err("Expected invalid source to fail");
+    assert!(!storage.get_path("other").exists());
+  }
+
+  #[test]
+  fn test_create_invalid_repository_name() {
+    let dir = tempfile::tempdir().expect("Unable to create temp dir");
+    let storage = RepositoryStorage::new(dir.path().to_path_buf());
+
+    let err = storage
+      .create_repository("../repo")
+      .expect_err("Expected invalid name to fail");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+}
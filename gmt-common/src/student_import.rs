@@ -0,0 +1,386 @@
+//! Imports the students of a group from a CSV file holding a username, an email and an optional
+//! public key per line.
+//!
+//! Students are matched with the existing users by username. Unknown students get an account
+//! without password, along with a token letting them choose one. Invalid rows are reported and
+//! skipped, while all the valid ones are imported in a single transaction.
+
+use std::time::{Duration, SystemTime};
+
+use database::{
+  db_handle::{group::GroupDbHandle, transaction::TransactionDbHandle, user::UserDbHandle},
+  error::DatabaseError,
+};
+use thiserror::Error;
+
+use crate::password::{generate_token, hash_token};
+
+/// How long the invitation of a new user stays valid.
+pub const INVITATION_VALIDITY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+  #[error("Database error: {0}")]
+  DatabaseError(#[from] DatabaseError),
+  #[error("Invalid CSV: {0}")]
+  InvalidCsv(String),
+}
+
+/// What happened to a row of the import.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+  /// A new user was created, who can choose a password with the token
+  Created { user_id: i32, token: String },
+  /// An existing user was added to the group, or already belonged to it
+  Linked { user_id: i32 },
+  /// The row was skipped
+  Failed { reason: String },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportRow {
+  /// The line of the row in the file, starting at 1
+  pub line: usize,
+  pub username: String,
+  pub email: String,
+  pub outcome: ImportOutcome,
+}
+
+/// A row of the file which passed validation.
+struct StudentRecord {
+  username: String,
+  email: String,
+  pubkey: Option<String>,
+}
+
+/// Splits the content into records of fields, following RFC 4180. Returns the line each record
+/// starts on along with its fields; blank lines are skipped.
+pub fn parse_csv(content: &str) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+  let mut records = Vec::new();
+  let mut fields = Vec::new();
+  let mut field = String::new();
+  let (mut line, mut start) = (1, 1);
+  let mut quoted = false;
+  let mut chars = content.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match (quoted, c) {
+      (true, '"') if chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      }
+      (true, '"') => quoted = false,
+      (true, c) => {
+        if c == '\n' {
+          line += 1;
+        }
+        field.push(c);
+      }
+      (false, '"') if field.is_empty() => quoted = true,
+      (false, ',') => fields.push(std::mem::take(&mut field)),
+      (false, '\r') if chars.peek() == Some(&'\n') => {}
+      (false, '\n') => {
+        fields.push(std::mem::take(&mut field));
+        if fields.iter().any(|f| !f.trim().is_empty()) {
+          records.push((start, std::mem::take(&mut fields)));
+        }
+        fields.clear();
+        line += 1;
+        start = line;
+      }
+      (false, c) => field.push(c),
+    }
+  }
+  if quoted {
+    return Err(ImportError::InvalidCsv(format!(
+      "Unterminated quoted field starting on line {}",
+      start
+    )));
+  }
+  fields.push(field);
+  if fields.iter().any(|f| !f.trim().is_empty()) {
+    records.push((start, fields));
+  }
+  Ok(records)
+}
+
+/// Checks the fields of a row.
+fn validate_record(fields: &[String]) -> Result<StudentRecord, String> {
+  let fields: Vec<&str> = fields.iter().map(|f| f.trim()).collect();
+  let (username, email, pubkey) = match fields.as_slice() {
+    [username, email] => (*username, *email, None),
+    [username, email, pubkey] => (*username, *email, Some(*pubkey).filter(|k| !k.is_empty())),
+    _ => return Err("Expected a username, an email and an optional public key".to_string()),
+  };
+
+  if username.is_empty() || username.len() > 255 || username.contains(char::is_whitespace) {
+    return Err("Invalid username".to_string());
+  }
+  match email.split_once('@') {
+    Some((local, domain)) if !local.is_empty() && domain.contains('.') => {}
+    _ => return Err("Invalid email".to_string()),
+  }
+  if let Some(pubkey) = pubkey {
+    let key = pubkey.split_whitespace().nth(1).unwrap_or_default();
+    if russh_keys::parse_public_key_base64(key).is_err() {
+      return Err("Invalid public key".to_string());
+    }
+  }
+
+  Ok(StudentRecord {
+    username: username.to_string(),
+    email: email.to_string(),
+    pubkey: pubkey.map(str::to_string),
+  })
+}
+
+/// Imports the students listed in the CSV content into the group. A header line starting with
+/// `username` is skipped. Returns the report of each row, in the order of the file.
+pub fn import_students<Db>(
+  db: &mut Db,
+  group_id: i32,
+  content: &str,
+  now: SystemTime,
+) -> Result<Vec<ImportRow>, ImportError>
+where
+  Db: GroupDbHandle + TransactionDbHandle + UserDbHandle,
+{
+  let mut records = parse_csv(content)?;
+  if records
+    .first()
+    .and_then(|(_, fields)| fields.first())
+    .is_some_and(|f| f.trim().eq_ignore_ascii_case("username"))
+  {
+    records.remove(0);
+  }
+
+  db.transaction(|db| {
+    let mut members: Vec<i32> = db.list_students(group_id)?.iter().map(|s| s.id).collect();
+    let mut seen = Vec::new();
+    let mut report = Vec::new();
+    for (line, fields) in records {
+      let (username, email) = (
+        fields.first().cloned().unwrap_or_default(),
+        fields.get(1).cloned().unwrap_or_default(),
+      );
+      let outcome = match validate_record(&fields) {
+        Ok(record) if seen.contains(&record.username) => ImportOutcome::Failed {
+          reason: "Duplicate username in the file".to_string(),
+        },
+        Ok(record) => {
+          seen.push(record.username.clone());
+          import_student(db, group_id, &record, &mut members, now)?
+        }
+        Err(reason) => ImportOutcome::Failed { reason },
+      };
+      report.push(ImportRow {
+        line,
+        username: username.trim().to_string(),
+        email: email.trim().to_string(),
+        outcome,
+      });
+    }
+    Ok(report)
+  })
+}
+
+fn import_student<Db: GroupDbHandle + UserDbHandle>(
+  db: &mut Db,
+  group_id: i32,
+  record: &StudentRecord,
+  members: &mut Vec<i32>,
+  now: SystemTime,
+) -> Result<ImportOutcome, DatabaseError> {
+  let failed = |reason: &str| {
+    Ok(ImportOutcome::Failed {
+      reason: reason.to_string(),
+    })
+  };
+
+  let (user_id, outcome) = match db.get_user_by_username(&record.username)? {
+    Some(user) if user.email != record.email => {
+      return failed("The username belongs to a user with another email");
+    }
+    Some(user) => (user.id, ImportOutcome::Linked { user_id: user.id }),
+    None => {
+      if db.get_user_by_email(&record.email)?.is_some() {
+        return failed("The email belongs to a user with another username");
+      }
+      // Without password, the user can't log in before using the token
+      let pubkey = record.pubkey.as_deref().map(|k| vec![k]);
+      let user = db.create_user(&record.username, &record.email, "", pubkey)?;
+      let token = generate_token();
+      db.create_password_token(user.id, &hash_token(&token), now + INVITATION_VALIDITY)?;
+      let outcome = ImportOutcome::Created {
+        user_id: user.id,
+        token,
+      };
+      (user.id, outcome)
+    }
+  };
+
+  if !members.contains(&user_id) {
+    db.add_student(group_id, user_id)?;
+    members.push(user_id);
+  }
+  Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use database::{db_handle::user::User, fixtures::user, DbHandle};
+
+  use super::*;
+
+  const PUBKEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ alice@laptop";
+
+  #[test]
+  fn test_parse_csv() {
+    let records = parse_csv("a,b\r\n\n\"quoted, \"\"field\"\"\",\"two\nlines\"\nlast").unwrap();
+    assert_eq!(
+      records,
+      vec![
+        (1, vec!["a".to_string(), "b".to_string()]),
+        (
+          3,
+          vec!["quoted, \"field\"".to_string(), "two\nlines".to_string()]
+        ),
+        (5, vec!["last".to_string()]),
+      ]
+    );
+    assert!(parse_csv("a,\"b").is_err());
+  }
+
+  #[test]
+  fn test_validate_record() {
+    let record =
+      |fields: &[&str]| validate_record(&fields.iter().map(|f| f.to_string()).collect::<Vec<_>>());
+    assert!(record(&["alice", "alice@test.com"]).is_ok());
+    assert!(record(&["alice", "alice@test.com", ""]).is_ok_and(|r| r.pubkey.is_none()));
+    assert!(record(&["alice", "alice@test.com", PUBKEY]).is_ok_and(|r| r.pubkey.is_some()));
+    assert!(record(&["alice"]).is_err());
+    assert!(record(&["al ice", "alice@test.com"]).is_err());
+    assert!(record(&["alice", "alice"]).is_err());
+    assert!(record(&["alice", "alice@test.com", "ssh-ed25519 invalid"]).is_err());
+  }
+
+  #[test]
+  fn test_import_students() {
+    let mut db = DbHandle::faux();
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.commit_transaction).then(|_| Ok(()));
+    // bob already belongs to the group, carol exists but isn't a member yet
+    faux::when!(db.list_students(1)).then(|_| Ok(vec![user(2, "bob")]));
+    faux::when!(db.get_user_by_username).then(|username| match username {
+      "bob" => Ok(Some(user(2, "bob"))),
+      "carol" => Ok(Some(user(3, "carol"))),
+      "dave" => Ok(Some(user(4, "dave"))),
+      _ => Ok(None),
+    });
+    faux::when!(db.get_user_by_email).then(|email| match email {
+      "dave@test.com" => Ok(Some(user(4, "dave"))),
+      _ => Ok(None),
+    });
+    faux::when!(db.create_user).then(|(username, email, password, pubkey)| {
+      assert_eq!(password, "");
+      assert_eq!(pubkey, Some(vec![PUBKEY]));
+      Ok(User {
+        email: email.to_string(),
+        ..user(5, username)
+      })
+    });
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+    let stored = tokens.clone();
+    faux::when!(db.create_password_token).then(move |(user_id, token_hash, _)| {
+      stored
+        .lock()
+        .unwrap()
+        .push((user_id, token_hash.to_string()));
+      Ok(())
+    });
+    let added = Arc::new(Mutex::new(Vec::new()));
+    let students = added.clone();
+    faux::when!(db.add_student).then(move |(group_id, student_id)| {
+      assert_eq!(group_id, 1);
+      students.lock().unwrap().push(student_id);
+      Ok(())
+    });
+
+    let csv = format!(
+      "Username,Email,Public key\n\
+      alice,alice@test.com,{}\n\
+      bob,bob@test.com\n\
+      carol,carol@test.com\n\
+      carol,carol@test.com\n\
+      dave,other@test.com\n\
+      eve,dave@test.com\n\
+      invalid\n",
+      PUBKEY
+    );
+    let report = import_students(&mut db, 1, &csv, SystemTime::now()).unwrap();
+
+    let outcomes: Vec<(usize, &str, &ImportOutcome)> = report
+      .iter()
+      .map(|r| (r.line, r.username.as_str(), &r.outcome))
+      .collect();
+    let ImportOutcome::Created { token, .. } = &report[0].outcome else {
+      panic!("Expected alice to be created, got {:?}", report[0].outcome);
+    };
+    let failed = |reason: &str| ImportOutcome::Failed {
+      reason: reason.to_string(),
+    };
+    assert_eq!(
+      outcomes,
+      vec![
+        (
+          2,
+          "alice",
+          &ImportOutcome::Created {
+            user_id: 5,
+            token: token.clone()
+          }
+        ),
+        (3, "bob", &ImportOutcome::Linked { user_id: 2 }),
+        (4, "carol", &ImportOutcome::Linked { user_id: 3 }),
+        (5, "carol", &failed("Duplicate username in the file")),
+        (
+          6,
+          "dave",
+          &failed("The username belongs to a user with another email")
+        ),
+        (
+          7,
+          "eve",
+          &failed("The email belongs to a user with another username")
+        ),
+        (
+          8,
+          "invalid",
+          &failed("Expected a username, an email and an optional public key")
+        ),
+      ]
+    );
+    assert_eq!(*added.lock().unwrap(), vec![5, 3]);
+    assert_eq!(*tokens.lock().unwrap(), vec![(5, hash_token(token))]);
+  }
+
+  #[test]
+  fn test_import_students_rolls_back_on_error() {
+    let mut db = DbHandle::faux();
+    faux::when!(db.begin_transaction).then(|_| Ok(()));
+    faux::when!(db.rollback_transaction).once().then(|_| Ok(()));
+    faux::when!(db.list_students(1)).then(|_| Ok(vec![]));
+    faux::when!(db.get_user_by_username).then(|_| Ok(None));
+    faux::when!(db.get_user_by_email).then(|_| Ok(None));
+    faux::when!(db.create_user).then(|_| Err(DatabaseError::NotFound));
+
+    let result = import_students(&mut db, 1, "alice,alice@test.com", SystemTime::now());
+    assert!(matches!(
+      result,
+      Err(ImportError::DatabaseError(DatabaseError::NotFound))
+    ));
+  }
+}
@@ -1,63 +1,118 @@
+use database::{connection_pool::ConnectionProvider, db_handle::user::UserDbHandle};
 use gmt_common::gmt_user::GmtUser;
+use log::error;
+use russh_keys::PublicKeyBase64;
 use ssh_server::{authenticator::Authenticator, error::SshError};
 
 const GIT_USER: &str = "git";
 
 /// The authenticator object, allowing to connect users based on their public keys
-pub struct DbAuthenticator {
-  // db_handle: DatabaseConnection <- TODO: add interface to make the whole stuff easily mockable or replaceable in future iterations
+pub struct DbAuthenticator<DbPool> {
+  db: DbPool,
 }
 
-impl DbAuthenticator {
-  pub fn new() -> Self {
-    DbAuthenticator {}
+impl<DbPool, Db> DbAuthenticator<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: UserDbHandle,
+{
+  pub fn new(db: DbPool) -> Self {
+    DbAuthenticator { db }
   }
 }
 
-impl Authenticator for DbAuthenticator {
+impl<DbPool, Db> Authenticator for DbAuthenticator<DbPool>
+where
+  DbPool: ConnectionProvider<Connection = Db> + 'static,
+  Db: UserDbHandle,
+{
   type User = GmtUser;
 
+  /// Connects the user owning the key. Unknown keys are refused, so that the pushes are always
+  /// made on behalf of a known user.
   fn validate_public_key(
     &self,
     user: &str,
-    _key: &russh_keys::key::PublicKey,
+    key: &russh_keys::key::PublicKey,
   ) -> Result<Option<Self::User>, SshError> {
     if user != GIT_USER {
       return Ok(None);
     }
 
-    // TODO: Add Connected user check
+    let owner = self
+      .db
+      .get_connection()
+      .and_then(|mut db| db.get_user_by_public_key(&key.public_key_base64()))
+      .map_err(|e| {
+        error!("Unable to find the owner of a public key: {}", e);
+        SshError::Unknown
+      })?;
 
-    Ok(Some(GmtUser::Public))
+    Ok(owner.map(|owner| GmtUser::Connected(owner.id)))
   }
 }
 
 #[cfg(test)]
 mod test {
+  use database::{connection_pool::ConnectionPool, db_handle::user::User, DbHandle};
   use russh_keys::key::{KeyPair, PublicKey};
 
   use super::*;
 
-  fn unknown_key() -> PublicKey {
+  const USER_ID: i32 = 1;
+
+  fn key() -> PublicKey {
     let key = KeyPair::generate_ed25519().unwrap();
     key.clone_public_key().unwrap()
   }
 
+  /// An authenticator where `known` belongs to the user `USER_ID`
+  fn authenticator(known: &PublicKey) -> DbAuthenticator<ConnectionPool> {
+    let known = known.public_key_base64();
+    let mut pool = ConnectionPool::faux();
+    faux::when!(pool.get_connection).then(move |_| {
+      let mut db = DbHandle::faux();
+      let known = known.clone();
+      faux::when!(db.get_user_by_public_key).then(move |key| {
+        Ok((key == known).then(|| User {
+          id: USER_ID,
+          username: "user".to_string(),
+          email: "user@test.com".to_string(),
+          password: "password".to_string(),
+          pubkey: vec![Some(format!("ssh-ed25519 {} user@host", known))],
+        }))
+      });
+      Ok(db)
+    });
+    DbAuthenticator::new(pool)
+  }
+
   #[test]
-  fn given_non_git_user_and_unknown_key_then_user_is_none() {
-    let auth = DbAuthenticator::new();
+  fn given_non_git_user_and_known_key_then_user_is_none() {
+    let key = key();
+    let auth = authenticator(&key);
 
-    let user = auth.validate_public_key("unknown", &unknown_key());
+    let user = auth.validate_public_key("unknown", &key);
     let user = user.expect("No error should be returned");
     assert_eq!(user, None);
   }
 
   #[test]
   fn given_git_user_and_unknown_key_then_user_is_none() {
-    let auth = DbAuthenticator::new();
+    let auth = authenticator(&key());
+
+    let user = auth.validate_public_key("git", &key());
+    let user = user.expect("No error should be returned");
+    assert_eq!(user, None);
+  }
+
+  #[test]
+  fn given_git_user_and_known_key_then_user_is_connected() {
+    let key = key();
+    let auth = authenticator(&key);
 
-    let user = auth.validate_public_key("git", &unknown_key());
+    let user = auth.validate_public_key("git", &key);
     let user = user.expect("No error should be returned");
-    assert_eq!(user, Some(GmtUser::Public));
+    assert_eq!(user, Some(GmtUser::Connected(USER_ID)));
   }
 }
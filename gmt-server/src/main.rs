@@ -1,5 +1,10 @@
+use std::sync::Arc;
+
+use database::connection_pool::ConnectionPool;
 use git_server::{GitHandler, GitHandlerConfig};
-use gmt_common::repositories::db_repository_provider::DbRepositoryProvider;
+use gmt_common::repositories::{
+  db_repository_provider::DbRepositoryProvider, repository_storage::RepositoryStorage,
+};
 use log::info;
 use ssh_server::SshServer;
 
@@ -18,8 +23,11 @@ async fn main() {
 
   dotenvy::dotenv().ok();
 
-  let auth = DbAuthenticator::new();
-  let repository_provider = DbRepositoryProvider::new();
+  let connection_pool =
+    Arc::new(ConnectionPool::new_from_env().expect("Unable to connect to the database"));
+  let auth = DbAuthenticator::new(connection_pool.clone());
+  let repository_provider =
+    DbRepositoryProvider::new(connection_pool, RepositoryStorage::new_from_env());
   let config = GitHandlerConfig {
     use_git_command: true,
   };